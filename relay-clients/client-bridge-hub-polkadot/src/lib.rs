@@ -55,6 +55,19 @@ pub type BridgeKusamaMessagesCall = runtime_types::pallet_bridge_messages::palle
 pub type BridgePolkadotBulletinGrandpaCall = runtime_types::pallet_bridge_grandpa::pallet::Call;
 pub type BridgeKusamaGrandpaCall = runtime_types::pallet_bridge_grandpa::pallet::Call;
 pub type BridgeParachainCall = runtime_types::pallet_bridge_parachains::pallet::Call;
+pub type BalancesCall = runtime_types::pallet_balances::pallet::Call;
+
+/// Returns the `Balances::transfer_keep_alive` call.
+pub fn transfer_keep_alive_call(
+	dest: bp_bridge_hub_polkadot::AccountId,
+	value: bp_bridge_hub_polkadot::Balance,
+) -> RuntimeCall {
+	RuntimeCall::Balances(BalancesCall::transfer_keep_alive {
+		dest: subxt::ext::subxt_core::utils::MultiAddress::Id(dest),
+		value,
+	})
+}
+
 type UncheckedExtrinsic = bp_bridge_hub_polkadot::UncheckedExtrinsic<RuntimeCall, SignedExtension>;
 type UtilityCall = runtime_types::pallet_utility::pallet::Call;
 
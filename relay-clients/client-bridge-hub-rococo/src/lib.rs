@@ -56,6 +56,22 @@ pub type BridgeGrandpaCall = runtime_types::pallet_bridge_grandpa::pallet::Call;
 // TODO: https://github.com/paritytech/parity-bridges-common/issues/2547 - regenerate when ready - shuold be Call2
 pub type BridgeBulletinGrandpaCall = runtime_types::pallet_bridge_grandpa::pallet::Call;
 pub type BridgeParachainCall = runtime_types::pallet_bridge_parachains::pallet::Call;
+pub type BalancesCall = runtime_types::pallet_balances::pallet::Call;
+
+/// Returns the `Balances::transfer_keep_alive` call.
+pub fn transfer_keep_alive_call(
+	dest: bp_bridge_hub_rococo::AccountId,
+	value: bp_bridge_hub_rococo::Balance,
+) -> RuntimeCall {
+	RuntimeCall::Balances(BalancesCall::transfer_keep_alive {
+		dest: subxt::ext::subxt_core::utils::MultiAddress::Id(dest),
+		value,
+	})
+}
+
+/// Name of the relayers pallet instance that is used by permissionless lanes at Rococo Bridge Hub.
+pub const WITH_BRIDGE_HUB_ROCOCO_PERMISSIONLESS_LANES_RELAYERS_PALLET_NAME: &str =
+	"BridgeRelayersForPermissionlessLanes";
 type UncheckedExtrinsic = bp_bridge_hub_rococo::UncheckedExtrinsic<RuntimeCall, SignedExtension>;
 type UtilityCall = runtime_types::pallet_utility::pallet::Call;
 
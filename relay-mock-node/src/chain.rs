@@ -0,0 +1,519 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Simulated chain, served by the mock node.
+
+use futures::channel::mpsc::UnboundedSender;
+use serde_json::{json, Value};
+use sp_core::{storage::StateVersion, H256};
+use sp_runtime::{
+	traits::{BlakeTwo256, Header as HeaderT},
+	ConsensusEngineId, Digest,
+};
+use sp_state_machine::InMemoryBackend;
+use sp_trie::{LayoutV1, TrieConfiguration};
+use std::collections::{BTreeMap, HashMap};
+
+/// Block number of the mock chain.
+pub type BlockNumber = u32;
+/// Hash of the mock chain.
+pub type Hash = H256;
+/// Header of the mock chain.
+pub type Header = sp_runtime::generic::Header<BlockNumber, BlakeTwo256>;
+/// Storage of the mock chain.
+pub type Storage = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// Runtime API implementation. Receives storage at the block and encoded arguments and returns
+/// encoded result.
+pub type RuntimeApi = Box<dyn Fn(&Storage, &[u8]) -> Result<Vec<u8>, String> + Send>;
+/// Extrinsic handler. Called when the extrinsic is included into the block and may alter the
+/// storage to simulate pallets. Extrinsics that are rejected by the handler are dropped.
+pub type ExtrinsicHandler = Box<dyn FnMut(&mut Storage, &[u8]) -> Result<(), String> + Send>;
+
+/// GRANDPA consensus engine identifier.
+pub const GRANDPA_ENGINE_ID: ConsensusEngineId = *b"FRNK";
+
+/// Kind of the subscription.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum SubscriptionKind {
+	NewHeads,
+	FinalizedHeads,
+	Justifications,
+	Extrinsic,
+}
+
+impl SubscriptionKind {
+	/// Method of subscription notifications.
+	fn notification_method(&self) -> &'static str {
+		match *self {
+			SubscriptionKind::NewHeads => "chain_newHead",
+			SubscriptionKind::FinalizedHeads => "chain_finalizedHead",
+			SubscriptionKind::Justifications => "grandpa_justifications",
+			SubscriptionKind::Extrinsic => "author_extrinsicUpdate",
+		}
+	}
+}
+
+/// Active subscription.
+struct Subscription {
+	kind: SubscriptionKind,
+	sink: UnboundedSender<String>,
+}
+
+/// Block of the mock chain.
+struct MockBlock {
+	header: Header,
+	extrinsics: Vec<Vec<u8>>,
+	justification: Option<Vec<u8>>,
+	/// Storage after the block is applied.
+	storage: Storage,
+	/// Subscriptions to statuses of extrinsics, included into the block.
+	watchers: Vec<String>,
+}
+
+/// Extrinsic that is waiting for the next block.
+struct PendingExtrinsic {
+	extrinsic: Vec<u8>,
+	watcher: Option<String>,
+}
+
+/// Simulated chain, served by the mock node.
+///
+/// Blocks are only produced and finalized when the test asks for it.
+pub struct MockChain {
+	name: String,
+	blocks: Vec<MockBlock>,
+	best_finalized: usize,
+	/// Storage of the next block.
+	storage: Storage,
+	pending: Vec<PendingExtrinsic>,
+	submitted: Vec<Vec<u8>>,
+	runtime_version: Value,
+	runtime_apis: HashMap<String, RuntimeApi>,
+	extrinsic_handler: Option<ExtrinsicHandler>,
+	account_nonces: HashMap<String, u64>,
+	subscriptions: BTreeMap<String, Subscription>,
+	next_subscription_id: u64,
+}
+
+impl MockChain {
+	/// Create chain with the genesis block and empty storage.
+	pub fn new(name: impl Into<String>) -> Self {
+		let storage = Storage::new();
+		let genesis = Header::new(
+			0,
+			extrinsics_root(&[]),
+			state_root(&storage),
+			Default::default(),
+			Digest::default(),
+		);
+		MockChain {
+			name: name.into(),
+			blocks: vec![MockBlock {
+				header: genesis,
+				extrinsics: Vec::new(),
+				justification: None,
+				storage: storage.clone(),
+				watchers: Vec::new(),
+			}],
+			best_finalized: 0,
+			storage,
+			pending: Vec::new(),
+			submitted: Vec::new(),
+			runtime_version: runtime_version(0, 0),
+			runtime_apis: HashMap::new(),
+			extrinsic_handler: None,
+			account_nonces: HashMap::new(),
+			subscriptions: BTreeMap::new(),
+			next_subscription_id: 0,
+		}
+	}
+
+	/// Chain name.
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	/// Number of the best block.
+	pub fn best_block_number(&self) -> BlockNumber {
+		self.best().header.number
+	}
+
+	/// Number of the best finalized block.
+	pub fn best_finalized_block_number(&self) -> BlockNumber {
+		self.best_finalized as BlockNumber
+	}
+
+	/// Header of the block with given number.
+	pub fn header(&self, number: BlockNumber) -> Option<&Header> {
+		self.blocks.get(number as usize).map(|block| &block.header)
+	}
+
+	/// Storage value of the next block.
+	pub fn storage(&self, key: &[u8]) -> Option<&Vec<u8>> {
+		self.storage.get(key)
+	}
+
+	/// Set storage value of the next block.
+	pub fn set_storage(&mut self, key: Vec<u8>, value: Vec<u8>) {
+		self.storage.insert(key, value);
+	}
+
+	/// Remove storage value of the next block.
+	pub fn remove_storage(&mut self, key: &[u8]) {
+		self.storage.remove(key);
+	}
+
+	/// Set runtime version, reported by the node.
+	pub fn set_runtime_version(&mut self, spec_version: u32, transaction_version: u32) {
+		self.runtime_version = runtime_version(spec_version, transaction_version);
+	}
+
+	/// Set implementation of the runtime API method (e.g. `BridgeRococoGrandpaApi_best_finalized`).
+	pub fn set_runtime_api(
+		&mut self,
+		method: impl Into<String>,
+		api: impl Fn(&Storage, &[u8]) -> Result<Vec<u8>, String> + Send + 'static,
+	) {
+		self.runtime_apis.insert(method.into(), Box::new(api));
+	}
+
+	/// Set handler of extrinsics that are included into blocks.
+	pub fn set_extrinsic_handler(
+		&mut self,
+		handler: impl FnMut(&mut Storage, &[u8]) -> Result<(), String> + Send + 'static,
+	) {
+		self.extrinsic_handler = Some(Box::new(handler));
+	}
+
+	/// Set next transaction index of the account with given SS58 address.
+	pub fn set_account_next_index(&mut self, address: impl Into<String>, index: u64) {
+		self.account_nonces.insert(address.into(), index);
+	}
+
+	/// All extrinsics that have been submitted to the node.
+	pub fn submitted_extrinsics(&self) -> &[Vec<u8>] {
+		&self.submitted
+	}
+
+	/// Produce new block with all pending extrinsics and return its hash.
+	pub fn produce_block(&mut self) -> Hash {
+		let mut extrinsics = Vec::new();
+		let mut watchers = Vec::new();
+		for pending in std::mem::take(&mut self.pending) {
+			let result = match self.extrinsic_handler {
+				Some(ref mut handler) => handler(&mut self.storage, &pending.extrinsic),
+				None => Ok(()),
+			};
+			match result {
+				Ok(()) => {
+					extrinsics.push(pending.extrinsic);
+					watchers.extend(pending.watcher);
+				},
+				Err(e) => {
+					log::debug!(target: "bridge", "Mock {} has rejected extrinsic: {}", self.name, e);
+					if let Some(watcher) = pending.watcher {
+						self.notify(&watcher, json!("invalid"));
+						self.subscriptions.remove(&watcher);
+					}
+				},
+			}
+		}
+
+		let best = self.best();
+		let header = Header::new(
+			best.header.number + 1,
+			extrinsics_root(&extrinsics),
+			state_root(&self.storage),
+			best.header.hash(),
+			Digest::default(),
+		);
+		let hash = header.hash();
+		for watcher in &watchers {
+			self.notify(watcher, json!({ "inBlock": hash }));
+		}
+		self.notify_all(SubscriptionKind::NewHeads, json!(header));
+		self.blocks.push(MockBlock {
+			header,
+			extrinsics,
+			justification: None,
+			storage: self.storage.clone(),
+			watchers,
+		});
+		hash
+	}
+
+	/// Finalize block with given number, optionally providing its GRANDPA justification.
+	pub fn finalize(&mut self, number: BlockNumber, justification: Option<Vec<u8>>) {
+		let number = number as usize;
+		assert!(
+			number >= self.best_finalized && number < self.blocks.len(),
+			"Mock {} can't finalize block {}",
+			self.name,
+			number,
+		);
+
+		let previous_finalized = std::mem::replace(&mut self.best_finalized, number);
+		let hash = self.blocks[number].header.hash();
+		let mut watchers = Vec::new();
+		for block in &mut self.blocks[previous_finalized + 1..=number] {
+			watchers.append(&mut block.watchers);
+		}
+		for watcher in watchers {
+			self.notify(&watcher, json!({ "finalized": hash }));
+			self.subscriptions.remove(&watcher);
+		}
+
+		self.notify_all(SubscriptionKind::FinalizedHeads, json!(self.blocks[number].header));
+		if let Some(justification) = justification {
+			self.notify_all(SubscriptionKind::Justifications, json!(hex(&justification)));
+			self.blocks[number].justification = Some(justification);
+		}
+	}
+
+	/// Produce new block and finalize it.
+	pub fn produce_finalized_block(&mut self, justification: Option<Vec<u8>>) -> Hash {
+		let hash = self.produce_block();
+		self.finalize(self.best_block_number(), justification);
+		hash
+	}
+
+	/// Hash of the block with given number (best block by default).
+	pub(crate) fn block_hash(&self, number: Option<BlockNumber>) -> Option<Hash> {
+		match number {
+			Some(number) => self.header(number).map(|header| header.hash()),
+			None => Some(self.best().header.hash()),
+		}
+	}
+
+	/// Hash of the best finalized block.
+	pub(crate) fn finalized_hash(&self) -> Hash {
+		self.blocks[self.best_finalized].header.hash()
+	}
+
+	/// Header of the block with given hash (best block by default).
+	pub(crate) fn header_json(&self, at: Option<Hash>) -> Option<Value> {
+		self.block(at).map(|block| json!(block.header))
+	}
+
+	/// Block with given hash (best block by default).
+	pub(crate) fn block_json(&self, at: Option<Hash>) -> Option<Value> {
+		self.block(at).map(|block| {
+			json!({
+				"block": {
+					"header": block.header,
+					"extrinsics": block.extrinsics.iter().map(|e| hex(e)).collect::<Vec<_>>(),
+				},
+				"justifications": block
+					.justification
+					.as_ref()
+					.map(|justification| json!([[GRANDPA_ENGINE_ID, justification]])),
+			})
+		})
+	}
+
+	/// Runtime version.
+	pub(crate) fn runtime_version(&self) -> Value {
+		self.runtime_version.clone()
+	}
+
+	/// Next transaction index of the account.
+	pub(crate) fn account_next_index(&self, address: &str) -> u64 {
+		self.account_nonces.get(address).copied().unwrap_or(0)
+	}
+
+	/// Storage value at given block.
+	pub(crate) fn storage_at(
+		&self,
+		key: &[u8],
+		at: Option<Hash>,
+	) -> Result<Option<Vec<u8>>, String> {
+		Ok(self.existing_block(at)?.storage.get(key).cloned())
+	}
+
+	/// Storage proof of given keys at given block.
+	pub(crate) fn read_proof(
+		&self,
+		keys: Vec<Vec<u8>>,
+		at: Option<Hash>,
+	) -> Result<(Hash, Vec<Vec<u8>>), String> {
+		let block = self.existing_block(at)?;
+		let proof = sp_state_machine::prove_read(trie_backend(&block.storage), keys)
+			.map_err(|e| format!("Failed to generate storage proof: {}", e))?;
+		Ok((block.header.hash(), proof.into_iter_nodes().collect()))
+	}
+
+	/// Call runtime API method at given block.
+	pub(crate) fn call(
+		&self,
+		method: &str,
+		args: &[u8],
+		at: Option<Hash>,
+	) -> Result<Vec<u8>, String> {
+		let block = self.existing_block(at)?;
+		let api = self
+			.runtime_apis
+			.get(method)
+			.ok_or_else(|| format!("Runtime API method {} is not mocked", method))?;
+		api(&block.storage, args)
+	}
+
+	/// Submit extrinsic. It'll be included into the next block.
+	pub(crate) fn submit(&mut self, extrinsic: Vec<u8>, watcher: Option<String>) -> Hash {
+		let hash = Hash::from(sp_core::hashing::blake2_256(&extrinsic));
+		self.submitted.push(extrinsic.clone());
+		self.pending.push(PendingExtrinsic { extrinsic, watcher });
+		hash
+	}
+
+	/// Extrinsics that are waiting for the next block.
+	pub(crate) fn pending_extrinsics(&self) -> Vec<String> {
+		self.pending.iter().map(|pending| hex(&pending.extrinsic)).collect()
+	}
+
+	/// Register new subscription.
+	pub(crate) fn subscribe(
+		&mut self,
+		kind: SubscriptionKind,
+		sink: UnboundedSender<String>,
+	) -> String {
+		self.next_subscription_id += 1;
+		let id = format!("mock-{}", self.next_subscription_id);
+		self.subscriptions.insert(id.clone(), Subscription { kind, sink });
+		id
+	}
+
+	/// Send initial notifications of the subscription. Must be called after the subscription
+	/// identifier is sent to the client.
+	pub(crate) fn on_subscribed(&mut self, id: &str) {
+		let kind = match self.subscriptions.get(id) {
+			Some(subscription) => subscription.kind,
+			None => return,
+		};
+		match kind {
+			SubscriptionKind::NewHeads => self.notify(id, json!(self.best().header)),
+			SubscriptionKind::FinalizedHeads =>
+				self.notify(id, json!(self.blocks[self.best_finalized].header)),
+			SubscriptionKind::Justifications => (),
+			SubscriptionKind::Extrinsic => self.notify(id, json!("ready")),
+		}
+	}
+
+	/// Remove subscription.
+	pub(crate) fn unsubscribe(&mut self, id: &str) -> bool {
+		self.subscriptions.remove(id).is_some()
+	}
+
+	fn best(&self) -> &MockBlock {
+		self.blocks.last().expect("genesis block is always present; qed")
+	}
+
+	fn block(&self, at: Option<Hash>) -> Option<&MockBlock> {
+		match at {
+			Some(at) => self.blocks.iter().find(|block| block.header.hash() == at),
+			None => Some(self.best()),
+		}
+	}
+
+	fn existing_block(&self, at: Option<Hash>) -> Result<&MockBlock, String> {
+		self.block(at).ok_or_else(|| format!("Unknown block {:?}", at))
+	}
+
+	fn notify(&mut self, id: &str, result: Value) {
+		let is_sent = match self.subscriptions.get(id) {
+			Some(subscription) => subscription
+				.sink
+				.unbounded_send(notification(subscription.kind, id, result))
+				.is_ok(),
+			None => return,
+		};
+		// the connection has been closed
+		if !is_sent {
+			self.subscriptions.remove(id);
+		}
+	}
+
+	fn notify_all(&mut self, kind: SubscriptionKind, result: Value) {
+		self.subscriptions.retain(|id, subscription| {
+			subscription.kind != kind ||
+				subscription.sink.unbounded_send(notification(kind, id, result.clone())).is_ok()
+		});
+	}
+}
+
+fn notification(kind: SubscriptionKind, id: &str, result: Value) -> String {
+	json!({
+		"jsonrpc": "2.0",
+		"method": kind.notification_method(),
+		"params": { "subscription": id, "result": result },
+	})
+	.to_string()
+}
+
+fn runtime_version(spec_version: u32, transaction_version: u32) -> Value {
+	json!({
+		"specName": "mock",
+		"implName": "mock",
+		"authoringVersion": 0,
+		"specVersion": spec_version,
+		"implVersion": 0,
+		"apis": [],
+		"transactionVersion": transaction_version,
+		"stateVersion": 1,
+	})
+}
+
+fn trie_backend(storage: &Storage) -> InMemoryBackend<BlakeTwo256> {
+	let collection = storage.iter().map(|(key, value)| (key.clone(), Some(value.clone())));
+	InMemoryBackend::from((vec![(None, collection.collect())], StateVersion::V1))
+}
+
+fn state_root(storage: &Storage) -> Hash {
+	*trie_backend(storage).root()
+}
+
+fn extrinsics_root(extrinsics: &[Vec<u8>]) -> Hash {
+	LayoutV1::<BlakeTwo256>::ordered_trie_root(extrinsics)
+}
+
+pub(crate) fn hex(data: &[u8]) -> String {
+	format!("0x{}", hex::encode(data))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn read_proof_is_verified_against_header_state_root() {
+		let mut chain = MockChain::new("mock");
+		chain.set_storage(b"key".to_vec(), b"value".to_vec());
+		let hash = chain.produce_finalized_block(None);
+		chain.remove_storage(b"key");
+		chain.produce_block();
+
+		let (at, proof) = chain.read_proof(vec![b"key".to_vec()], Some(hash)).unwrap();
+		assert_eq!(at, hash);
+		let values = sp_state_machine::read_proof_check::<BlakeTwo256, _>(
+			chain.header(1).unwrap().state_root,
+			sp_trie::StorageProof::new(proof),
+			[b"key"],
+		)
+		.unwrap();
+		assert_eq!(values.get(&b"key"[..]), Some(&Some(b"value".to_vec())));
+		assert_eq!(chain.storage_at(b"key", None), Ok(None));
+	}
+}
@@ -0,0 +1,183 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! In-process mock of the Substrate node RPC server, used in relay tests.
+//!
+//! The mock node serves websocket JSON-RPC connections on the localhost and answers requests
+//! using the simulated [`MockChain`]. Tests are scripting the chain: they produce and finalize
+//! blocks (optionally with GRANDPA justifications), alter storage, implement runtime API methods
+//! and handle submitted extrinsics to simulate pallets. Storage proofs are real trie proofs,
+//! which are verifiable against state roots of mock headers.
+//!
+//! To run the relay against two mock nodes, start them and pass their ports to the relay:
+//! `--source-host 127.0.0.1 --source-port <source.port()> --target-host 127.0.0.1
+//! --target-port <target.port()>`.
+
+#![warn(missing_docs)]
+
+mod chain;
+mod rpc;
+
+pub use chain::{
+	BlockNumber, ExtrinsicHandler, Hash, Header, MockChain, RuntimeApi, Storage, GRANDPA_ENGINE_ID,
+};
+
+use async_std::net::{TcpListener, TcpStream};
+use async_tungstenite::tungstenite::Message;
+use futures::{FutureExt, SinkExt, StreamExt};
+use parking_lot::{Mutex, MutexGuard};
+use std::{net::SocketAddr, sync::Arc};
+
+/// Host, where mock nodes are listening.
+pub const MOCK_NODE_HOST: &str = "127.0.0.1";
+
+/// Running mock node.
+///
+/// The node serves connections until the test process exits.
+pub struct MockNode {
+	chain: Arc<Mutex<MockChain>>,
+	address: SocketAddr,
+}
+
+impl MockNode {
+	/// Start serving the chain at random local port.
+	pub async fn start(chain: MockChain) -> anyhow::Result<Self> {
+		let listener = TcpListener::bind((MOCK_NODE_HOST, 0)).await?;
+		let address = listener.local_addr()?;
+		let chain = Arc::new(Mutex::new(chain));
+
+		let served_chain = chain.clone();
+		async_std::task::spawn(async move {
+			let mut incoming = listener.incoming();
+			while let Some(stream) = incoming.next().await {
+				let chain = served_chain.clone();
+				async_std::task::spawn(async move {
+					let result = match stream {
+						Ok(stream) => serve_connection(stream, &chain).await,
+						Err(e) => Err(e.into()),
+					};
+					if let Err(e) = result {
+						log::debug!(target: "bridge", "Mock node connection has failed: {}", e);
+					}
+				});
+			}
+		});
+
+		Ok(MockNode { chain, address })
+	}
+
+	/// Port of the node RPC server.
+	pub fn port(&self) -> u16 {
+		self.address.port()
+	}
+
+	/// URI of the node RPC server.
+	pub fn uri(&self) -> String {
+		format!("ws://{}", self.address)
+	}
+
+	/// Access the served chain.
+	pub fn chain(&self) -> MutexGuard<MockChain> {
+		self.chain.lock()
+	}
+}
+
+/// Serve single client connection.
+async fn serve_connection(stream: TcpStream, chain: &Mutex<MockChain>) -> anyhow::Result<()> {
+	let client = async_tungstenite::accept_async(stream).await?;
+	let (mut client_tx, mut client_rx) = client.split();
+	let (sink, mut outgoing) = futures::channel::mpsc::unbounded();
+	loop {
+		futures::select! {
+			message = client_rx.next().fuse() => match message {
+				Some(Ok(Message::Text(request))) => rpc::handle(chain, &sink, &request),
+				Some(Ok(Message::Close(_))) | None => return Ok(()),
+				Some(Ok(_)) => (),
+				Some(Err(e)) => return Err(e.into()),
+			},
+			message = outgoing.next() => match message {
+				Some(message) => client_tx.send(Message::Text(message)).await?,
+				None => return Ok(()),
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::{json, Value};
+
+	async fn call(
+		client: &mut async_tungstenite::WebSocketStream<
+			async_tungstenite::async_std::ConnectStream,
+		>,
+		id: u64,
+		method: &str,
+		params: Value,
+	) -> Value {
+		let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+		client.send(Message::Text(request.to_string())).await.unwrap();
+		next_message(client).await
+	}
+
+	async fn next_message(
+		client: &mut async_tungstenite::WebSocketStream<
+			async_tungstenite::async_std::ConnectStream,
+		>,
+	) -> Value {
+		match client.next().await {
+			Some(Ok(Message::Text(message))) => serde_json::from_str(&message).unwrap(),
+			message => panic!("Unexpected message: {:?}", message),
+		}
+	}
+
+	#[async_std::test]
+	async fn submitted_extrinsic_is_handled_and_finalized() {
+		let mut chain = MockChain::new("mock");
+		chain.set_extrinsic_handler(|storage, extrinsic| {
+			storage.insert(b"last".to_vec(), extrinsic.to_vec());
+			Ok(())
+		});
+		chain.set_runtime_api("Test_last", |storage, _| {
+			Ok(storage.get(&b"last"[..]).cloned().unwrap_or_default())
+		});
+		let node = MockNode::start(chain).await.unwrap();
+		let (mut client, _) =
+			async_tungstenite::async_std::connect_async(node.uri()).await.unwrap();
+
+		let response =
+			call(&mut client, 1, "author_submitAndWatchExtrinsic", json!(["0x0102"])).await;
+		let subscription = response["result"].clone();
+		assert_eq!(next_message(&mut client).await["params"]["result"], "ready");
+
+		let hash = node.chain().produce_finalized_block(Some(vec![42]));
+		assert_eq!(
+			next_message(&mut client).await["params"],
+			json!({ "subscription": subscription, "result": { "inBlock": hash } }),
+		);
+		assert_eq!(
+			next_message(&mut client).await["params"],
+			json!({ "subscription": subscription, "result": { "finalized": hash } }),
+		);
+
+		let response = call(&mut client, 2, "state_call", json!(["Test_last", "0x"])).await;
+		assert_eq!(response["result"], "0x0102");
+		let response = call(&mut client, 3, "chain_getBlock", json!([hash])).await;
+		assert_eq!(response["result"]["block"]["extrinsics"], json!(["0x0102"]));
+		assert_eq!(response["result"]["justifications"], json!([[[70, 82, 78, 75], [42]]]));
+	}
+}
@@ -0,0 +1,232 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! JSON-RPC methods of the mock node.
+
+use crate::chain::{hex, BlockNumber, MockChain, SubscriptionKind};
+
+use futures::channel::mpsc::UnboundedSender;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use sp_core::Bytes;
+
+/// Invalid JSON was received.
+const PARSE_ERROR: i64 = -32700;
+/// The method does not exist.
+const METHOD_NOT_FOUND: i64 = -32601;
+/// Invalid method parameters.
+const INVALID_PARAMS: i64 = -32602;
+/// Error, reported by the node.
+const NODE_ERROR: i64 = -32000;
+
+/// All supported methods.
+const METHODS: &[&str] = &[
+	"author_pendingExtrinsics",
+	"author_submitAndWatchExtrinsic",
+	"author_submitExtrinsic",
+	"author_unwatchExtrinsic",
+	"chain_getBlock",
+	"chain_getBlockHash",
+	"chain_getFinalizedHead",
+	"chain_getHeader",
+	"chain_getRuntimeVersion",
+	"chain_subscribeAllHeads",
+	"chain_subscribeFinalizedHeads",
+	"chain_subscribeNewHeads",
+	"chain_unsubscribeAllHeads",
+	"chain_unsubscribeFinalizedHeads",
+	"chain_unsubscribeNewHeads",
+	"grandpa_subscribeJustifications",
+	"grandpa_unsubscribeJustifications",
+	"rpc_methods",
+	"state_call",
+	"state_getMetadata",
+	"state_getReadProof",
+	"state_getRuntimeVersion",
+	"state_getStorage",
+	"system_accountNextIndex",
+	"system_chain",
+	"system_health",
+	"system_name",
+	"system_properties",
+	"system_version",
+];
+
+/// Successful method output.
+enum Output {
+	/// Method result.
+	Result(Value),
+	/// New subscription identifier.
+	Subscription(String),
+}
+
+/// Method error.
+type Error = (i64, String);
+
+/// Process client request. Response and all subscription notifications are sent to the sink.
+pub(crate) fn handle(chain: &Mutex<MockChain>, sink: &UnboundedSender<String>, request: &str) {
+	let request: Value = match serde_json::from_str(request) {
+		Ok(request) => request,
+		Err(e) => {
+			let _ = sink.unbounded_send(error(Value::Null, (PARSE_ERROR, e.to_string())));
+			return
+		},
+	};
+	let id = request["id"].clone();
+	let method = request["method"].as_str().unwrap_or_default();
+
+	// hold the lock until the response is sent, so that notifications are never sent before
+	// the subscription identifier
+	let mut chain = chain.lock();
+	match dispatch(&mut chain, sink, method, &request["params"]) {
+		Ok(Output::Result(result)) => {
+			let _ = sink.unbounded_send(response(id, result));
+		},
+		Ok(Output::Subscription(subscription)) => {
+			let _ = sink.unbounded_send(response(id, json!(subscription)));
+			chain.on_subscribed(&subscription);
+		},
+		Err(e) => {
+			log::debug!(target: "bridge", "Mock {} has failed to process {}: {}", chain.name(), method, e.1);
+			let _ = sink.unbounded_send(error(id, e));
+		},
+	}
+}
+
+fn dispatch(
+	chain: &mut MockChain,
+	sink: &UnboundedSender<String>,
+	method: &str,
+	params: &Value,
+) -> Result<Output, Error> {
+	let result = match method {
+		"rpc_methods" => json!({ "version": 1, "methods": METHODS }),
+		"system_health" => json!({ "peers": 1, "isSyncing": false, "shouldHavePeers": true }),
+		"system_name" => json!("relay-mock-node"),
+		"system_version" => json!(env!("CARGO_PKG_VERSION")),
+		"system_chain" => json!(chain.name()),
+		"system_properties" => json!({}),
+		"system_accountNextIndex" => {
+			let address: String = required_param(params, 0)?;
+			json!(chain.account_next_index(&address))
+		},
+		"chain_getBlockHash" => json!(chain.block_hash(block_number_param(params, 0)?)),
+		"chain_getFinalizedHead" => json!(chain.finalized_hash()),
+		"chain_getHeader" => json!(chain.header_json(param(params, 0)?)),
+		"chain_getBlock" => json!(chain.block_json(param(params, 0)?)),
+		"state_getRuntimeVersion" | "chain_getRuntimeVersion" => chain.runtime_version(),
+		"state_getStorage" => {
+			let key: Bytes = required_param(params, 0)?;
+			let value = chain.storage_at(&key, param(params, 1)?).map_err(node_error)?;
+			json!(value.map(|value| hex(&value)))
+		},
+		"state_getReadProof" => {
+			let keys: Vec<Bytes> = required_param(params, 0)?;
+			let keys = keys.into_iter().map(|key| key.0).collect();
+			let (at, proof) = chain.read_proof(keys, param(params, 1)?).map_err(node_error)?;
+			json!({ "at": at, "proof": proof.iter().map(|node| hex(node)).collect::<Vec<_>>() })
+		},
+		"state_call" => {
+			let method: String = required_param(params, 0)?;
+			let args: Bytes = required_param(params, 1)?;
+			json!(hex(&chain.call(&method, &args, param(params, 2)?).map_err(node_error)?))
+		},
+		"state_getMetadata" => json!(hex(&chain
+			.call("Metadata_metadata", &[], param(params, 0)?)
+			.map_err(node_error)?)),
+		"author_submitExtrinsic" => {
+			let extrinsic: Bytes = required_param(params, 0)?;
+			json!(chain.submit(extrinsic.0, None))
+		},
+		"author_submitAndWatchExtrinsic" => {
+			let extrinsic: Bytes = required_param(params, 0)?;
+			let subscription = chain.subscribe(SubscriptionKind::Extrinsic, sink.clone());
+			chain.submit(extrinsic.0, Some(subscription.clone()));
+			return Ok(Output::Subscription(subscription))
+		},
+		"author_pendingExtrinsics" => json!(chain.pending_extrinsics()),
+		"chain_subscribeNewHeads" | "chain_subscribeAllHeads" =>
+			return Ok(Output::Subscription(
+				chain.subscribe(SubscriptionKind::NewHeads, sink.clone()),
+			)),
+		"chain_subscribeFinalizedHeads" =>
+			return Ok(Output::Subscription(
+				chain.subscribe(SubscriptionKind::FinalizedHeads, sink.clone()),
+			)),
+		"grandpa_subscribeJustifications" =>
+			return Ok(Output::Subscription(
+				chain.subscribe(SubscriptionKind::Justifications, sink.clone()),
+			)),
+		"chain_unsubscribeNewHeads" |
+		"chain_unsubscribeAllHeads" |
+		"chain_unsubscribeFinalizedHeads" |
+		"grandpa_unsubscribeJustifications" |
+		"author_unwatchExtrinsic" => {
+			let subscription: String = required_param(params, 0)?;
+			json!(chain.unsubscribe(&subscription))
+		},
+		_ => return Err((METHOD_NOT_FOUND, format!("Method {} is not supported", method))),
+	};
+	Ok(Output::Result(result))
+}
+
+/// Returns optional positional parameter.
+fn param<T: serde::de::DeserializeOwned>(params: &Value, index: usize) -> Result<Option<T>, Error> {
+	match params.get(index) {
+		Some(Value::Null) | None => Ok(None),
+		Some(value) => serde_json::from_value(value.clone())
+			.map(Some)
+			.map_err(|e| (INVALID_PARAMS, format!("Invalid parameter {}: {}", index, e))),
+	}
+}
+
+/// Returns required positional parameter.
+fn required_param<T: serde::de::DeserializeOwned>(
+	params: &Value,
+	index: usize,
+) -> Result<T, Error> {
+	param(params, index)?.ok_or_else(|| (INVALID_PARAMS, format!("Missing parameter {}", index)))
+}
+
+/// Returns optional block number parameter, which may be either number, or hex string.
+fn block_number_param(params: &Value, index: usize) -> Result<Option<BlockNumber>, Error> {
+	let invalid = || (INVALID_PARAMS, format!("Invalid block number parameter {}", index));
+	match params.get(index) {
+		Some(Value::Null) | None => Ok(None),
+		Some(Value::Number(number)) => number
+			.as_u64()
+			.and_then(|number| number.try_into().ok())
+			.map(Some)
+			.ok_or_else(invalid),
+		Some(Value::String(number)) =>
+			BlockNumber::from_str_radix(number.trim_start_matches("0x"), 16)
+				.map(Some)
+				.map_err(|_| invalid()),
+		Some(_) => Err(invalid()),
+	}
+}
+
+fn node_error(e: String) -> Error {
+	(NODE_ERROR, e)
+}
+
+fn response(id: Value, result: Value) -> String {
+	json!({ "jsonrpc": "2.0", "id": id, "result": result }).to_string()
+}
+
+fn error(id: Value, (code, message): Error) -> String {
+	json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }).to_string()
+}
@@ -0,0 +1,218 @@
+#!/usr/bin/env python3
+
+# Generates fixtures for golden tests of calls, built by the relay
+# (`substrate-relay/src/bridges/fixtures/runtime_calls.json`).
+#
+# Pallet and call indices are extracted from the metadata of live runtimes, so the fixtures don't
+# depend on the `codegen_runtime.rs` files that the relay is compiled against. Only pallets and
+# calls that are used by the relay are included.
+#
+# Usage:
+#
+#   generate_call_fixtures.py <runtime>=<source> [<runtime>=<source> ...]
+#
+# where `<runtime>` is the fixture name (e.g. `bridge-hub-polkadot`) and `<source>` is either the
+# HTTP RPC endpoint of the chain node, or the file with the hex-encoded result of the
+# `state_getMetadata` RPC call. Fixtures of other runtimes are left untouched.
+
+import json
+import os
+import sys
+import urllib.request
+
+ROOT = os.path.join(os.path.dirname(os.path.abspath(__file__)), '..')
+FIXTURES = os.path.join(ROOT, 'substrate-relay', 'src', 'bridges', 'fixtures', 'runtime_calls.json')
+
+RUNTIMES = [
+	'bridge-hub-kusama',
+	'bridge-hub-polkadot',
+	'bridge-hub-rococo',
+	'bridge-hub-westend',
+	'kusama',
+	'polkadot',
+	'polkadot-bulletin',
+	'rococo',
+	'westend',
+]
+
+CALLS = {
+	'pallet_bridge_grandpa': ['initialize', 'submit_finality_proof', 'submit_finality_proof_ex'],
+	'pallet_bridge_parachains': ['submit_parachain_heads', 'submit_parachain_heads_ex'],
+	'pallet_bridge_messages': ['receive_messages_proof', 'receive_messages_delivery_proof'],
+	'pallet_grandpa': ['report_equivocation'],
+	'pallet_sudo': ['sudo'],
+}
+
+METADATA_MAGIC = b'meta'
+
+
+class Input:
+	"""SCALE decoder of the metadata subset that we need."""
+
+	def __init__(self, data):
+		self.data = data
+		self.position = 0
+
+	def bytes(self, length):
+		value = self.data[self.position:self.position + length]
+		if len(value) != length:
+			raise ValueError('unexpected end of metadata')
+		self.position += length
+		return value
+
+	def u8(self):
+		return self.bytes(1)[0]
+
+	def u32(self):
+		return int.from_bytes(self.bytes(4), 'little')
+
+	def compact(self):
+		first = self.u8()
+		mode = first & 0b11
+		if mode == 0:
+			return first >> 2
+		if mode == 1:
+			return (first | self.u8() << 8) >> 2
+		if mode == 2:
+			return (first | int.from_bytes(self.bytes(3), 'little') << 8) >> 2
+		return int.from_bytes(self.bytes((first >> 2) + 4), 'little')
+
+	def string(self):
+		return self.bytes(self.compact()).decode()
+
+	def vec(self, item):
+		return [item() for _ in range(self.compact())]
+
+	def option(self, item):
+		return item() if self.u8() else None
+
+
+def decode_field(data):
+	data.option(data.string)
+	ty = data.compact()
+	data.option(data.string)
+	data.vec(data.string)
+	return ty
+
+
+def decode_type_def(data):
+	kind = data.u8()
+	if kind == 0:
+		data.vec(lambda: decode_field(data))
+	elif kind == 1:
+		def variant():
+			name = data.string()
+			data.vec(lambda: decode_field(data))
+			index = data.u8()
+			data.vec(data.string)
+			return name, index
+		return dict(data.vec(variant))
+	elif kind in (2, 6):
+		data.compact()
+	elif kind == 3:
+		data.u32()
+		data.compact()
+	elif kind == 4:
+		data.vec(data.compact)
+	elif kind == 5:
+		data.u8()
+	elif kind == 7:
+		data.compact()
+		data.compact()
+	else:
+		raise ValueError('unknown type definition: %s' % kind)
+	return None
+
+
+def decode_type(data):
+	ty = data.compact()
+	path = data.vec(data.string)
+	data.vec(lambda: (data.string(), data.option(data.compact)))
+	variants = decode_type_def(data)
+	data.vec(data.string)
+	return ty, (path, variants)
+
+
+def decode_storage_entry(data):
+	data.string()
+	data.u8()
+	if data.u8() == 0:
+		data.compact()
+	else:
+		data.vec(data.u8)
+		data.compact()
+		data.compact()
+	data.vec(data.u8)
+	data.vec(data.string)
+
+
+def decode_pallet(data, version):
+	name = data.string()
+	data.option(lambda: (data.string(), data.vec(lambda: decode_storage_entry(data))))
+	calls = data.option(data.compact)
+	data.option(data.compact)
+	data.vec(lambda: (data.string(), data.compact(), data.vec(data.u8), data.vec(data.string)))
+	data.option(data.compact)
+	index = data.u8()
+	if version >= 15:
+		data.vec(data.string)
+	return name, calls, index
+
+
+def runtime_fixture(metadata):
+	data = Input(metadata)
+	if data.bytes(4) != METADATA_MAGIC:
+		raise ValueError('invalid metadata magic')
+	version = data.u8()
+	if version not in (14, 15):
+		raise ValueError('unsupported metadata version: %s' % version)
+
+	types = dict(data.vec(lambda: decode_type(data)))
+	pallets = {}
+	for name, calls, index in data.vec(lambda: decode_pallet(data, version)):
+		if calls is None:
+			continue
+		path, variants = types[calls]
+		pallet_module = path[0] if path else None
+		if pallet_module in CALLS:
+			pallets[name] = {
+				'index': index,
+				'calls': {call: variants[call] for call in CALLS[pallet_module] if call in variants},
+			}
+	return pallets
+
+
+def read_metadata(source):
+	if source.startswith('http://') or source.startswith('https://'):
+		request = urllib.request.Request(
+			source,
+			data=json.dumps({'jsonrpc': '2.0', 'id': 1, 'method': 'state_getMetadata', 'params': []}).encode(),
+			headers={'Content-Type': 'application/json'},
+		)
+		with urllib.request.urlopen(request) as response:
+			encoded = json.load(response)['result']
+	else:
+		with open(source) as f:
+			encoded = f.read().strip()
+	return bytes.fromhex(encoded[2:] if encoded.startswith('0x') else encoded)
+
+
+def main():
+	if len(sys.argv) < 2:
+		sys.exit('Usage: %s <runtime>=<rpc-url-or-metadata-file> ...' % sys.argv[0])
+
+	with open(FIXTURES) as f:
+		fixtures = json.load(f)
+	for argument in sys.argv[1:]:
+		runtime, _, source = argument.partition('=')
+		if runtime not in RUNTIMES or not source:
+			sys.exit('Invalid argument: %s. Known runtimes: %s' % (argument, ', '.join(RUNTIMES)))
+		fixtures[runtime] = runtime_fixture(read_metadata(source))
+
+	with open(FIXTURES, 'w') as f:
+		json.dump(fixtures, f, indent='\t', sort_keys=True)
+		f.write('\n')
+
+
+if __name__ == '__main__':
+	main()
@@ -37,4 +37,17 @@ cargo +nightly fmt --all
 # cargo run
 # cargo run --bin runtime-codegen -- --from-node-url "ws://127.0.0.1:9944" > ../../relay-clients/client-polkadot-bulletin/src/codegen_runtime.rs
 
+# Pallet and call indices for golden tests of relay calls. They are read from the runtime metadata
+# directly, so the tests catch errors in the generated code. For the Polkadot Bulletin Chain, add
+# "polkadot-bulletin=http://127.0.0.1:9944"
+python3 scripts/generate_call_fixtures.py \
+    "bridge-hub-rococo=https://rococo-bridge-hub-rpc.polkadot.io" \
+    "rococo=https://rococo-rpc.polkadot.io" \
+    "westend=https://westend-rpc.polkadot.io" \
+    "bridge-hub-westend=https://westend-bridge-hub-rpc.polkadot.io" \
+    "kusama=https://kusama-rpc.polkadot.io" \
+    "bridge-hub-kusama=https://kusama-bridge-hub-rpc.polkadot.io" \
+    "polkadot=https://dot-rpc.stakeworld.io" \
+    "bridge-hub-polkadot=https://polkadot-bridge-hub-rpc.polkadot.io"
+
 cargo check --workspace
\ No newline at end of file
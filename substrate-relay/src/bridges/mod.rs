@@ -20,3 +20,6 @@ pub mod kusama_polkadot;
 pub mod polkadot_bulletin;
 pub mod rococo_bulletin;
 pub mod rococo_westend;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,396 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Limits of messages delivery transactions at the Polkadot Bulletin chain.
+//!
+//! Bulletin chain is missing the `TransactionPayment` runtime API (as well as the transaction
+//! payment pallet itself), so limits can't be estimated the way they're estimated for other
+//! chains. Instead, the relay dry-runs `receive_messages_proof` calls of increasing size against
+//! the best block, using the `BlockBuilder_apply_extrinsic` runtime API. Candidate calls have
+//! fake storage proofs, so they always fail at dispatch. But transactions that exhaust block
+//! resources (because of their weight or size) are rejected before dispatch, and calls with too
+//! many messages are failing with a different dispatch error.
+//!
+//! Estimated limits are cached per runtime version of the Bulletin chain. The relay is watching
+//! the runtime version and limits are re-estimated when it changes. Messages relay loops are
+//! reading limits once, when they are started, so the relay is stopped when re-estimated limits
+//! differ from limits that loops are using. It is restarted by the supervisor and starts loops
+//! with new limits.
+
+use crate::cli::{offline_signing::account_nonce, LOG_TARGET};
+
+use bp_messages::{target_chain::FromBridgedChainMessagesProof, MessageNonce};
+use bp_runtime::Chain as _;
+use codec::Decode;
+use frame_support::weights::Weight;
+use futures::{future::Either, Future};
+use relay_polkadot_bulletin_client::PolkadotBulletin;
+use relay_substrate_client::{
+	AccountIdOf, AccountKeyPairOf, Chain, ChainWithTransactions, Client, HashOf, NonceOf,
+	SignParam, SimpleRuntimeVersion, UnsignedTransaction,
+};
+use sp_core::Pair;
+use sp_runtime::{
+	traits::{Header as _, TrailingZeroInput},
+	transaction_validity::{InvalidTransaction, TransactionValidityError},
+	ApplyExtrinsicResult, DispatchError,
+};
+use std::{collections::BTreeMap, sync::Mutex};
+use substrate_relay_helper::messages::{
+	MessagesRelayLimits, ReceiveMessagesProofCallBuilder, SubstrateMessageLane,
+};
+
+/// Name of the runtime method, used to dry-run transactions.
+const APPLY_EXTRINSIC_METHOD: &str = "BlockBuilder_apply_extrinsic";
+/// Maximal number of messages in the candidate call.
+const MAX_MESSAGES_IN_CANDIDATE_CALL: MessageNonce = 4096;
+/// Precision of the weight limit search (the maximal extrinsic weight is split into that many
+/// parts).
+const WEIGHT_PARTS: u64 = 1000;
+
+/// Limits, estimated for the runtime version `(spec_version, transaction_version)`.
+#[derive(Default)]
+struct EstimatedLimits {
+	/// Current runtime version of the Bulletin chain.
+	active: Option<(u32, u32)>,
+	limits: BTreeMap<(u32, u32), (MessageNonce, Weight)>,
+}
+
+static ESTIMATED_LIMITS: Mutex<EstimatedLimits> =
+	Mutex::new(EstimatedLimits { active: None, limits: BTreeMap::new() });
+
+/// Returns limits of messages delivery transactions at the Bulletin chain.
+///
+/// If limits have not been estimated yet, safe **underestimations** are returned. Normally, we
+/// are able to deliver and dispatch thousands of messages in the same transaction.
+pub fn messages_limits() -> MessagesRelayLimits {
+	match active_limits() {
+		Some((max_messages, max_weight)) => MessagesRelayLimits {
+			max_messages_in_single_batch: max_messages,
+			max_messages_weight_in_single_batch: max_weight,
+		},
+		None => MessagesRelayLimits {
+			max_messages_in_single_batch: 128,
+			max_messages_weight_in_single_batch:
+				bp_polkadot_bulletin::PolkadotBulletin::max_extrinsic_weight() / 20,
+		},
+	}
+}
+
+/// Returns estimated limits for the current runtime version of the Bulletin chain.
+fn active_limits() -> Option<(MessageNonce, Weight)> {
+	let estimated = ESTIMATED_LIMITS.lock().unwrap_or_else(|e| e.into_inner());
+	estimated.active.and_then(|version| estimated.limits.get(&version).cloned())
+}
+
+/// Returns loop, that is estimating limits of messages delivery transactions, submitted by the
+/// `signer`, whenever the runtime version of the Bulletin chain changes.
+///
+/// Limits for the current runtime version are estimated before the loop is returned, so messages
+/// relay loops that are started after this call are using them. The loop stops when limits for
+/// the new runtime version differ from them (see [`run_until_limits_change`]).
+pub async fn messages_limits_loop<L>(
+	client: impl Client<PolkadotBulletin>,
+	signer: AccountKeyPairOf<PolkadotBulletin>,
+	lane: L::LaneId,
+) -> impl Future<Output = ()>
+where
+	L: SubstrateMessageLane<TargetChain = PolkadotBulletin>,
+{
+	update_messages_limits::<L>(&client, &signer, lane.clone()).await;
+	let used_limits = active_limits();
+	async move {
+		loop {
+			async_std::task::sleep(PolkadotBulletin::AVERAGE_BLOCK_INTERVAL).await;
+			update_messages_limits::<L>(&client, &signer, lane.clone()).await;
+			if active_limits() != used_limits {
+				return
+			}
+		}
+	}
+}
+
+/// Run the relay until limits of messages delivery transactions change.
+///
+/// Messages relay loops are reading limits once, when they are started, so the relay must be
+/// restarted to use new limits. Returns error if limits have changed.
+pub async fn run_until_limits_change(
+	relay: impl Future<Output = anyhow::Result<()>>,
+	limits_loop: impl Future<Output = ()>,
+) -> anyhow::Result<()> {
+	futures::pin_mut!(relay, limits_loop);
+	match futures::future::select(relay, limits_loop).await {
+		Either::Left((result, _)) => result,
+		Either::Right(_) => Err(anyhow::format_err!(
+			"Limits of messages delivery transactions at {} have changed: {:?}. Restart the \
+			relay to use new limits",
+			PolkadotBulletin::NAME,
+			active_limits(),
+		)),
+	}
+}
+
+/// Estimate limits of messages delivery transactions if the runtime version of the Bulletin
+/// chain has changed since the last call.
+///
+/// If estimation fails, the relay is using safe underestimations until the next call.
+async fn update_messages_limits<L>(
+	client: &impl Client<PolkadotBulletin>,
+	signer: &AccountKeyPairOf<PolkadotBulletin>,
+	lane: L::LaneId,
+) where
+	L: SubstrateMessageLane<TargetChain = PolkadotBulletin>,
+{
+	let result = match client.simple_runtime_version().await {
+		Ok(runtime_version) =>
+			estimate_messages_limits::<L>(client, signer, lane, runtime_version).await,
+		Err(e) => Err(e.into()),
+	};
+	if let Err(e) = result {
+		log::warn!(
+			target: LOG_TARGET,
+			"Failed to estimate limits of messages delivery transactions at {}: {}. Using \
+			default limits",
+			PolkadotBulletin::NAME,
+			e,
+		);
+	}
+}
+
+/// Estimate limits of messages delivery transactions, submitted by the `signer`, at given
+/// runtime version of the Bulletin chain, unless they are already estimated.
+async fn estimate_messages_limits<L>(
+	client: &impl Client<PolkadotBulletin>,
+	signer: &AccountKeyPairOf<PolkadotBulletin>,
+	lane: L::LaneId,
+	runtime_version: SimpleRuntimeVersion,
+) -> anyhow::Result<()>
+where
+	L: SubstrateMessageLane<TargetChain = PolkadotBulletin>,
+{
+	let version = (runtime_version.spec_version, runtime_version.transaction_version);
+	{
+		// limits of the previous runtime version are never used after the upgrade
+		let mut estimated = ESTIMATED_LIMITS.lock().unwrap_or_else(|e| e.into_inner());
+		estimated.active = Some(version);
+		if estimated.limits.contains_key(&version) {
+			return Ok(())
+		}
+	}
+
+	let at = client.best_header().await?.hash();
+	let signer_id: AccountIdOf<PolkadotBulletin> = signer.public().into();
+	let mut estimator = Estimator::<L, _> {
+		client,
+		signer,
+		lane,
+		version,
+		at,
+		nonce: account_nonce(client, &signer_id, at).await?,
+		baseline: None,
+	};
+
+	// the call with single message and empty proof must fit
+	let baseline = match estimator.dry_run(1, Weight::zero(), 0).await? {
+		Ok(dispatch_result) => dispatch_result,
+		Err(e) =>
+			return Err(anyhow::format_err!(
+				"Minimal messages delivery transaction is rejected by {}: {:?}",
+				PolkadotBulletin::NAME,
+				e,
+			)),
+	};
+	estimator.baseline = Some(baseline);
+
+	let estimator = &estimator;
+	let max_extrinsic_size: u64 =
+		bp_polkadot_bulletin::PolkadotBulletin::max_extrinsic_size().into();
+	let max_proof_size = max_fitting(0, max_extrinsic_size, move |proof_size| {
+		estimator.fits(1, Weight::zero(), proof_size)
+	})
+	.await?;
+	// messages relay is building proofs of up to that size, so limits are estimated for the
+	// largest proof that the relay may attach to the delivery transaction
+	let relay_proof_size = std::cmp::min(max_extrinsic_size / 3, max_proof_size);
+	if relay_proof_size < max_extrinsic_size / 3 {
+		log::warn!(
+			target: LOG_TARGET,
+			"Delivery transactions with more than {} bytes of proof are rejected by {}. Large \
+			messages may be undeliverable",
+			max_proof_size,
+			PolkadotBulletin::NAME,
+		);
+	}
+	let max_messages = max_fitting(1, MAX_MESSAGES_IN_CANDIDATE_CALL, move |messages| {
+		estimator.fits(messages, Weight::zero(), relay_proof_size)
+	})
+	.await?;
+	let max_weight_parts = max_fitting(0, WEIGHT_PARTS, move |parts| {
+		estimator.fits(max_messages, weight_parts(parts), relay_proof_size)
+	})
+	.await?;
+	let max_weight = weight_parts(max_weight_parts);
+
+	log::info!(
+		target: LOG_TARGET,
+		"Estimated limits of messages delivery transactions at {} (spec_version: {}, \
+		transaction_version: {}): {} messages, {:?} dispatch weight, {} bytes of proof",
+		PolkadotBulletin::NAME,
+		version.0,
+		version.1,
+		max_messages,
+		max_weight,
+		max_proof_size,
+	);
+
+	ESTIMATED_LIMITS
+		.lock()
+		.unwrap_or_else(|e| e.into_inner())
+		.limits
+		.insert(version, (max_messages, max_weight));
+	Ok(())
+}
+
+/// Dry-runs candidate delivery transactions.
+struct Estimator<'a, L: SubstrateMessageLane, C> {
+	client: &'a C,
+	signer: &'a AccountKeyPairOf<PolkadotBulletin>,
+	lane: L::LaneId,
+	version: (u32, u32),
+	at: HashOf<PolkadotBulletin>,
+	nonce: NonceOf<PolkadotBulletin>,
+	/// Dispatch result of the minimal candidate call.
+	baseline: Option<Result<(), DispatchError>>,
+}
+
+impl<'a, L, C> Estimator<'a, L, C>
+where
+	L: SubstrateMessageLane<TargetChain = PolkadotBulletin>,
+	C: Client<PolkadotBulletin>,
+{
+	/// Returns true if the candidate call fits the limits.
+	async fn fits(
+		&self,
+		messages: MessageNonce,
+		dispatch_weight: Weight,
+		proof_size: u64,
+	) -> anyhow::Result<bool> {
+		Ok(match self.dry_run(messages, dispatch_weight, proof_size).await? {
+			Ok(dispatch_result) => Some(dispatch_result) == self.baseline,
+			Err(TransactionValidityError::Invalid(InvalidTransaction::ExhaustsResources)) => false,
+			Err(e) =>
+				return Err(anyhow::format_err!(
+					"Messages delivery transaction is rejected by {}: {:?}",
+					PolkadotBulletin::NAME,
+					e,
+				)),
+		})
+	}
+
+	/// Dry-run the candidate call. Returns dispatch result if transaction is valid.
+	async fn dry_run(
+		&self,
+		messages: MessageNonce,
+		dispatch_weight: Weight,
+		proof_size: u64,
+	) -> anyhow::Result<Result<Result<(), DispatchError>, TransactionValidityError>> {
+		// nonces are far from the actual lane state, so the candidate is never obsolete
+		let nonces_start = MessageNonce::MAX / 2;
+		let proof = FromBridgedChainMessagesProof {
+			bridged_header_hash: Default::default(),
+			storage_proof: vec![vec![0; proof_size as usize]],
+			lane: self.lane.clone(),
+			nonces_start,
+			nonces_end: nonces_start + messages - 1,
+		};
+		let relayer_id_at_source = Decode::decode(&mut TrailingZeroInput::zeroes())?;
+		let call = L::ReceiveMessagesProofCallBuilder::build_receive_messages_proof_call(
+			relayer_id_at_source,
+			(Weight::zero(), proof),
+			messages as u32,
+			dispatch_weight,
+			false,
+		);
+		let transaction = PolkadotBulletin::sign_transaction(
+			SignParam {
+				spec_version: self.version.0,
+				transaction_version: self.version.1,
+				genesis_hash: self.client.genesis_hash(),
+				signer: self.signer.clone(),
+			},
+			UnsignedTransaction::new(call.into(), self.nonce),
+		)?;
+
+		let result: ApplyExtrinsicResult = self
+			.client
+			.state_call(self.at, APPLY_EXTRINSIC_METHOD.into(), transaction)
+			.await?;
+		Ok(result)
+	}
+}
+
+/// Given part of the maximal extrinsic weight.
+fn weight_parts(parts: u64) -> Weight {
+	let max_weight = bp_polkadot_bulletin::PolkadotBulletin::max_extrinsic_weight();
+	Weight::from_parts(
+		max_weight.ref_time().saturating_mul(parts) / WEIGHT_PARTS,
+		max_weight.proof_size().saturating_mul(parts) / WEIGHT_PARTS,
+	)
+}
+
+/// Find the maximal value in the `min..=max` range that fits the limits. The `min` must fit.
+async fn max_fitting<F, Fut>(min: u64, max: u64, mut fits: F) -> anyhow::Result<u64>
+where
+	F: FnMut(u64) -> Fut,
+	Fut: Future<Output = anyhow::Result<bool>>,
+{
+	let (mut fitting, mut exceeding) = (min, max.saturating_add(1));
+	while exceeding - fitting > 1 {
+		let candidate = fitting + (exceeding - fitting) / 2;
+		if fits(candidate).await? {
+			fitting = candidate;
+		} else {
+			exceeding = candidate;
+		}
+	}
+	Ok(fitting)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[async_std::test]
+	fn max_fitting_value_is_found() {
+		for limit in [0, 1, 127, 128, 4095, 4096] {
+			let found = max_fitting(0, 4096, move |value| async move { Ok(value <= limit) }).await;
+			assert_eq!(found.unwrap(), limit);
+		}
+		let found = max_fitting(1, 4096, move |value| async move { Ok(value <= 10_000) }).await;
+		assert_eq!(found.unwrap(), 4096);
+	}
+
+	#[async_std::test]
+	async fn relay_is_stopped_when_limits_change() {
+		let relay = futures::future::pending();
+		let limits_loop = futures::future::ready(());
+		assert!(run_until_limits_change(relay, limits_loop).await.is_err());
+
+		let relay = futures::future::ready(Ok(()));
+		let limits_loop = futures::future::pending();
+		assert!(run_until_limits_change(relay, limits_loop).await.is_ok());
+	}
+}
@@ -17,6 +17,7 @@
 //! Declaration of all bridges between Polkadot Bulletin Chain and Polkadot Bridge Hub.
 
 pub mod bridge_hub_polkadot_messages_to_polkadot_bulletin;
+pub mod messages_limits;
 pub mod polkadot_bulletin_headers_to_bridge_hub_polkadot;
 pub mod polkadot_bulletin_messages_to_bridge_hub_polkadot;
 pub mod polkadot_headers_to_polkadot_bulletin;
@@ -19,7 +19,6 @@
 use super::{BridgeHubRococoAsBridgeHubPolkadot, RococoAsPolkadot};
 
 use bp_polkadot_core::parachains::{ParaHash, ParaHeadsProof, ParaId};
-use bp_runtime::Chain;
 use relay_substrate_client::{CallOf, HeaderIdOf};
 use substrate_relay_helper::{
 	cli::bridge::{CliBridgeBase, MessagesCliBridge, ParachainToRelayHeadersCliBridge},
@@ -77,16 +76,8 @@ impl MessagesCliBridge for RococoToRococoBulletinCliBridge {
 		crate::bridges::rococo_bulletin::bridge_hub_rococo_messages_to_rococo_bulletin::BridgeHubRococoMessagesToRococoBulletinMessageLane;
 
 	fn maybe_messages_limits() -> Option<MessagesRelayLimits> {
-		// Rococo Bulletin chain is missing the `TransactionPayment` runtime API (as well as the
-		// transaction payment pallet itself), so we can't estimate limits using runtime calls.
-		// Let's do it here.
-		//
-		// Folloiung constants are just safe **underestimations**. Normally, we are able to deliver
-		// and dispatch thousands of messages in the same transaction.
-		Some(MessagesRelayLimits {
-			max_messages_in_single_batch: 128,
-			max_messages_weight_in_single_batch:
-				bp_polkadot_bulletin::PolkadotBulletin::max_extrinsic_weight() / 20,
-		})
+		// Rococo Bulletin chain is missing the `TransactionPayment` runtime API, so limits are
+		// estimated by dry-running delivery transactions when the relay starts
+		Some(crate::bridges::polkadot_bulletin::messages_limits::messages_limits())
 	}
 }
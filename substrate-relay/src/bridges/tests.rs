@@ -0,0 +1,513 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Golden tests of calls, built by the relay.
+//!
+//! Every call is encoded and compared with the expected encoding, which starts with pallet and
+//! call indices from `fixtures/runtime_calls.json` and is followed by encoded call arguments.
+//! Fixtures are generated from the metadata of live runtimes (`state_getMetadata` RPC) by
+//! `scripts/generate_call_fixtures.py`, so they don't depend on the generated `codegen_runtime.rs`
+//! files that the relay is using.
+
+use crate::bridges::{
+	kusama_polkadot::{
+		bridge_hub_kusama_messages_to_bridge_hub_polkadot::BridgeHubKusamaMessagesToBridgeHubPolkadotMessageLane,
+		bridge_hub_polkadot_messages_to_bridge_hub_kusama::BridgeHubPolkadotMessagesToBridgeHubKusamaMessageLane,
+		kusama_headers_to_bridge_hub_polkadot::{
+			KusamaFinalityToBridgeHubPolkadot, KusamaToBridgeHubPolkadotCliBridge,
+		},
+		kusama_parachains_to_bridge_hub_polkadot::BridgeHubKusamaToBridgeHubPolkadot,
+		polkadot_headers_to_bridge_hub_kusama::{
+			PolkadotFinalityToBridgeHubKusama, PolkadotToBridgeHubKusamaCliBridge,
+		},
+		polkadot_parachains_to_bridge_hub_kusama::BridgeHubPolkadotToBridgeHubKusama,
+	},
+	polkadot_bulletin::{
+		bridge_hub_polkadot_messages_to_polkadot_bulletin::BridgeHubPolkadotMessagesToPolkadotBulletinMessageLane,
+		polkadot_bulletin_headers_to_bridge_hub_polkadot::{
+			PolkadotBulletinFinalityToBridgeHubPolkadot,
+			PolkadotBulletinToBridgeHubPolkadotCliBridge,
+		},
+		polkadot_bulletin_messages_to_bridge_hub_polkadot::PolkadotBulletinMessagesToBridgeHubPolkadotMessageLane,
+		polkadot_headers_to_polkadot_bulletin::{
+			PolkadotFinalityToPolkadotBulletin, PolkadotToPolkadotBulletinCliBridge,
+		},
+		polkadot_parachains_to_polkadot_bulletin::PolkadotToPolkadotBulletin,
+	},
+	rococo_bulletin::{
+		bridge_hub_rococo_messages_to_rococo_bulletin::BridgeHubRococoMessagesToRococoBulletinMessageLane,
+		rococo_bulletin_headers_to_bridge_hub_rococo::{
+			RococoBulletinFinalityToBridgeHubRococo, RococoBulletinToBridgeHubRococoCliBridge,
+		},
+		rococo_bulletin_messages_to_bridge_hub_rococo::RococoBulletinMessagesToBridgeHubRococoMessageLane,
+		rococo_headers_to_rococo_bulletin::{
+			RococoFinalityToRococoBulletin, RococoToRococoBulletinCliBridge,
+		},
+		rococo_parachains_to_rococo_bulletin::RococoToRococoBulletin,
+	},
+	rococo_westend::{
+		bridge_hub_rococo_messages_to_bridge_hub_westend::BridgeHubRococoMessagesToBridgeHubWestendMessageLane,
+		bridge_hub_westend_messages_to_bridge_hub_rococo::BridgeHubWestendMessagesToBridgeHubRococoMessageLane,
+		rococo_headers_to_bridge_hub_westend::{
+			RococoFinalityToBridgeHubWestend, RococoToBridgeHubWestendCliBridge,
+		},
+		rococo_parachains_to_bridge_hub_westend::BridgeHubRococoToBridgeHubWestend,
+		westend_headers_to_bridge_hub_rococo::{
+			WestendFinalityToBridgeHubRococo, WestendToBridgeHubRococoCliBridge,
+		},
+		westend_parachains_to_bridge_hub_rococo::BridgeHubWestendToBridgeHubRococo,
+	},
+};
+
+use bp_header_chain::{justification::JustificationVerificationContext, InitializationData};
+use bp_polkadot_core::parachains::{ParaHash, ParaHeadsProof, ParaId};
+use bp_runtime::BasicOperatingMode;
+use codec::{Decode, Encode};
+use frame_support::weights::Weight;
+use relay_utils::HeaderId;
+use serde_json::Value;
+use sp_runtime::{
+	traits::{BlakeTwo256, TrailingZeroInput},
+	AccountId32,
+};
+use substrate_relay_helper::{
+	cli::init_bridge::BridgeInitializer,
+	equivocation::{ReportEquivocationCallBuilder, SubstrateEquivocationDetectionPipeline},
+	finality::{SubmitFinalityProofCallBuilder, SubstrateFinalitySyncPipeline},
+	finality_base::SyncHeader,
+	messages::{
+		ReceiveMessagesDeliveryProofCallBuilder, ReceiveMessagesProofCallBuilder,
+		SubstrateMessageLane,
+	},
+	parachains::{SubmitParachainHeadsCallBuilder, SubstrateParachainsPipeline},
+};
+
+/// Header type of all bridged chains.
+type Header = sp_runtime::generic::Header<u32, BlakeTwo256>;
+
+/// Authority set id, used in tests.
+const SET_ID: u64 = 7;
+/// Encoded `sp_session::MembershipProof { session: 3, trie_nodes: vec![], validator_count: 5 }`.
+const KEY_OWNER_PROOF: [u8; 9] = [3, 0, 0, 0, 0, 5, 0, 0, 0];
+
+/// Returns pallet and call indices of the call, if it is present in the runtime.
+fn expected_call_index(runtime: &str, pallet: &str, call: &str) -> Option<(u8, u8)> {
+	let fixtures: Value =
+		serde_json::from_str(include_str!("fixtures/runtime_calls.json")).unwrap();
+	let pallet_fixture = &fixtures[runtime][pallet];
+	let pallet_index = pallet_fixture["index"].as_u64()?;
+	let call_index = pallet_fixture["calls"][call].as_u64()?;
+	Some((pallet_index as u8, call_index as u8))
+}
+
+/// Returns expected encoding of the call.
+fn expected_call(runtime: &str, pallet: &str, call: &str, args: &[u8]) -> Vec<u8> {
+	let (pallet_index, call_index) =
+		expected_call_index(runtime, pallet, call).unwrap_or_else(|| {
+			panic!("Call {}::{} is missing from the {} runtime", pallet, call, runtime)
+		});
+	[vec![pallet_index, call_index], args.to_vec()].concat()
+}
+
+/// Returns value, decoded from zero bytes.
+fn zeroed<T: Decode>() -> T {
+	T::decode(&mut TrailingZeroInput::zeroes()).unwrap()
+}
+
+fn header() -> Header {
+	bp_test_utils::test_header(42)
+}
+
+fn justification() -> bp_header_chain::justification::GrandpaJustification<Header> {
+	bp_test_utils::make_default_justification(&header())
+}
+
+fn verification_context() -> JustificationVerificationContext {
+	JustificationVerificationContext {
+		voter_set: finality_grandpa::voter_set::VoterSet::new(bp_test_utils::authority_list())
+			.unwrap(),
+		authority_set_id: SET_ID,
+	}
+}
+
+fn relayer() -> AccountId32 {
+	AccountId32::new([1u8; 32])
+}
+
+macro_rules! assert_init_bridge_call {
+	($bridge:ty, $runtime:expr, $pallet:expr) => {{
+		let init_data = InitializationData {
+			header: Box::new(header()),
+			authority_list: bp_test_utils::authority_list(),
+			set_id: SET_ID,
+			operating_mode: BasicOperatingMode::Normal,
+		};
+		let expected = expected_call($runtime, $pallet, "initialize", &init_data.encode());
+		assert_eq!(
+			<$bridge>::encode_init_bridge(init_data).encode(),
+			expected,
+			stringify!($bridge)
+		);
+	}};
+	($bridge:ty, $runtime:expr, $pallet:expr, sudo) => {{
+		let init_data = InitializationData {
+			header: Box::new(header()),
+			authority_list: bp_test_utils::authority_list(),
+			set_id: SET_ID,
+			operating_mode: BasicOperatingMode::Normal,
+		};
+		let initialize = expected_call($runtime, $pallet, "initialize", &init_data.encode());
+		let expected = expected_call($runtime, "Sudo", "sudo", &initialize);
+		assert_eq!(
+			<$bridge>::encode_init_bridge(init_data).encode(),
+			expected,
+			stringify!($bridge)
+		);
+	}};
+}
+
+macro_rules! assert_finality_calls {
+	(@submit $pipeline:ty, $runtime:expr, $pallet:expr, $call:expr, $extra_args:expr) => {{
+		type CallBuilder =
+			<$pipeline as SubstrateFinalitySyncPipeline>::SubmitFinalityProofCallBuilder;
+		let call = CallBuilder::build_submit_finality_proof_call(
+			SyncHeader::from(header()),
+			justification(),
+			true,
+			verification_context(),
+		);
+		let args = [header().encode(), justification().encode(), $extra_args.to_vec()].concat();
+		let expected = expected_call($runtime, $pallet, $call, &args);
+		assert_eq!(call.encode(), expected, stringify!($pipeline));
+	}};
+	(@report $pipeline:ty, $source_runtime:expr) => {{
+		let equivocation_proof = zeroed();
+		let encoded_equivocation_proof = Encode::encode(&equivocation_proof);
+		type CallBuilder =
+			<$pipeline as SubstrateEquivocationDetectionPipeline>::ReportEquivocationCallBuilder;
+		let key_owner_proof = Decode::decode(&mut &KEY_OWNER_PROOF[..]).unwrap();
+		let call = CallBuilder::build_report_equivocation_call(equivocation_proof, key_owner_proof);
+		let args = [encoded_equivocation_proof, KEY_OWNER_PROOF.to_vec()].concat();
+		assert_eq!(
+			call.encode(),
+			expected_call($source_runtime, "Grandpa", "report_equivocation", &args),
+			stringify!($pipeline),
+		);
+	}};
+	($pipeline:ty, $runtime:expr, $pallet:expr, $source_runtime:expr) => {
+		assert_finality_calls!(@submit $pipeline, $runtime, $pallet, "submit_finality_proof", &[]);
+		assert_finality_calls!(@report $pipeline, $source_runtime);
+	};
+	($pipeline:ty, $runtime:expr, $pallet:expr, $source_runtime:expr, ex) => {
+		let extra_args = (SET_ID, true).encode();
+		let call = "submit_finality_proof_ex";
+		assert_finality_calls!(@submit $pipeline, $runtime, $pallet, call, extra_args);
+		assert_finality_calls!(@report $pipeline, $source_runtime);
+	};
+}
+
+macro_rules! assert_parachains_call {
+	($pipeline:ty, $runtime:expr, $pallet:expr, $call:expr, $extra_args:expr) => {{
+		let at_relay_block = HeaderId(5, ParaHash::repeat_byte(1));
+		let parachains = vec![(ParaId(1002), ParaHash::repeat_byte(2))];
+		let proof = ParaHeadsProof { storage_proof: vec![vec![3]] };
+		let args = [
+			(at_relay_block.0, at_relay_block.1).encode(),
+			parachains.encode(),
+			proof.encode(),
+			$extra_args.to_vec(),
+		]
+		.concat();
+		type CallBuilder =
+			<$pipeline as SubstrateParachainsPipeline>::SubmitParachainHeadsCallBuilder;
+		let call =
+			CallBuilder::build_submit_parachain_heads_call(at_relay_block, parachains, proof, true);
+		assert_eq!(
+			call.encode(),
+			expected_call($runtime, $pallet, $call, &args),
+			stringify!($pipeline)
+		);
+	}};
+}
+
+macro_rules! assert_messages_calls {
+	(
+		$lane:ty,
+		$target_runtime:expr,
+		$target_pallet:expr,
+		$source_runtime:expr,
+		$source_pallet:expr
+	) => {{
+		let proof: (Weight, _) = zeroed();
+		let dispatch_weight = Weight::from_parts(1_000, 2_000);
+		let args = [
+			relayer().encode(),
+			Encode::encode(&proof.1),
+			10u32.encode(),
+			dispatch_weight.encode(),
+		]
+		.concat();
+		type ProofCallBuilder = <$lane as SubstrateMessageLane>::ReceiveMessagesProofCallBuilder;
+		let call = ProofCallBuilder::build_receive_messages_proof_call(
+			relayer(),
+			proof,
+			10,
+			dispatch_weight,
+			false,
+		);
+		assert_eq!(
+			call.encode(),
+			expected_call($target_runtime, $target_pallet, "receive_messages_proof", &args),
+			stringify!($lane),
+		);
+
+		let proof: (bp_messages::UnrewardedRelayersState, _) = zeroed();
+		let args = [Encode::encode(&proof.1), proof.0.encode()].concat();
+		type DeliveryProofCallBuilder =
+			<$lane as SubstrateMessageLane>::ReceiveMessagesDeliveryProofCallBuilder;
+		let call =
+			DeliveryProofCallBuilder::build_receive_messages_delivery_proof_call(proof, false);
+		assert_eq!(
+			call.encode(),
+			expected_call(
+				$source_runtime,
+				$source_pallet,
+				"receive_messages_delivery_proof",
+				&args
+			),
+			stringify!($lane),
+		);
+	}};
+}
+
+#[test]
+fn init_bridge_calls_match_runtime_metadata() {
+	assert_init_bridge_call!(
+		KusamaToBridgeHubPolkadotCliBridge,
+		"bridge-hub-polkadot",
+		"BridgeKusamaGrandpa"
+	);
+	assert_init_bridge_call!(
+		PolkadotToBridgeHubKusamaCliBridge,
+		"bridge-hub-kusama",
+		"BridgePolkadotGrandpa"
+	);
+	assert_init_bridge_call!(
+		PolkadotToPolkadotBulletinCliBridge,
+		"polkadot-bulletin",
+		"BridgePolkadotGrandpa",
+		sudo
+	);
+	assert_init_bridge_call!(
+		RococoToRococoBulletinCliBridge,
+		"polkadot-bulletin",
+		"BridgePolkadotGrandpa",
+		sudo
+	);
+	assert_init_bridge_call!(
+		RococoBulletinToBridgeHubRococoCliBridge,
+		"bridge-hub-rococo",
+		"BridgePolkadotBulletinGrandpa"
+	);
+	assert_init_bridge_call!(
+		RococoToBridgeHubWestendCliBridge,
+		"bridge-hub-westend",
+		"BridgeRococoGrandpa"
+	);
+	assert_init_bridge_call!(
+		WestendToBridgeHubRococoCliBridge,
+		"bridge-hub-rococo",
+		"BridgeWestendGrandpa"
+	);
+}
+
+#[test]
+fn finality_calls_match_runtime_metadata() {
+	assert_finality_calls!(
+		KusamaFinalityToBridgeHubPolkadot,
+		"bridge-hub-polkadot",
+		"BridgeKusamaGrandpa",
+		"kusama"
+	);
+	assert_finality_calls!(
+		PolkadotFinalityToBridgeHubKusama,
+		"bridge-hub-kusama",
+		"BridgePolkadotGrandpa",
+		"polkadot"
+	);
+	assert_finality_calls!(
+		PolkadotFinalityToPolkadotBulletin,
+		"polkadot-bulletin",
+		"BridgePolkadotGrandpa",
+		"polkadot"
+	);
+	assert_finality_calls!(
+		RococoFinalityToRococoBulletin,
+		"polkadot-bulletin",
+		"BridgePolkadotGrandpa",
+		"rococo"
+	);
+	assert_finality_calls!(
+		RococoBulletinFinalityToBridgeHubRococo,
+		"bridge-hub-rococo",
+		"BridgePolkadotBulletinGrandpa",
+		"polkadot-bulletin"
+	);
+	assert_finality_calls!(
+		RococoFinalityToBridgeHubWestend,
+		"bridge-hub-westend",
+		"BridgeRococoGrandpa",
+		"rococo",
+		ex
+	);
+	assert_finality_calls!(
+		WestendFinalityToBridgeHubRococo,
+		"bridge-hub-rococo",
+		"BridgeWestendGrandpa",
+		"westend",
+		ex
+	);
+}
+
+#[test]
+fn parachains_calls_match_runtime_metadata() {
+	assert_parachains_call!(
+		BridgeHubKusamaToBridgeHubPolkadot,
+		"bridge-hub-polkadot",
+		"BridgeKusamaParachains",
+		"submit_parachain_heads",
+		&[]
+	);
+	assert_parachains_call!(
+		BridgeHubPolkadotToBridgeHubKusama,
+		"bridge-hub-kusama",
+		"BridgePolkadotParachains",
+		"submit_parachain_heads",
+		&[]
+	);
+	assert_parachains_call!(
+		PolkadotToPolkadotBulletin,
+		"polkadot-bulletin",
+		"BridgePolkadotParachains",
+		"submit_parachain_heads",
+		&[]
+	);
+	assert_parachains_call!(
+		RococoToRococoBulletin,
+		"polkadot-bulletin",
+		"BridgePolkadotParachains",
+		"submit_parachain_heads",
+		&[]
+	);
+	assert_parachains_call!(
+		BridgeHubRococoToBridgeHubWestend,
+		"bridge-hub-westend",
+		"BridgeRococoParachains",
+		"submit_parachain_heads_ex",
+		&true.encode()
+	);
+	assert_parachains_call!(
+		BridgeHubWestendToBridgeHubRococo,
+		"bridge-hub-rococo",
+		"BridgeWestendParachains",
+		"submit_parachain_heads_ex",
+		&true.encode()
+	);
+}
+
+#[test]
+fn messages_calls_match_runtime_metadata() {
+	assert_messages_calls!(
+		BridgeHubKusamaMessagesToBridgeHubPolkadotMessageLane,
+		"bridge-hub-polkadot",
+		"BridgeKusamaMessages",
+		"bridge-hub-kusama",
+		"BridgePolkadotMessages"
+	);
+	assert_messages_calls!(
+		BridgeHubPolkadotMessagesToBridgeHubKusamaMessageLane,
+		"bridge-hub-kusama",
+		"BridgePolkadotMessages",
+		"bridge-hub-polkadot",
+		"BridgeKusamaMessages"
+	);
+	assert_messages_calls!(
+		BridgeHubRococoMessagesToRococoBulletinMessageLane,
+		"polkadot-bulletin",
+		"BridgePolkadotMessages",
+		"bridge-hub-rococo",
+		"BridgePolkadotBulletinMessages"
+	);
+	assert_messages_calls!(
+		RococoBulletinMessagesToBridgeHubRococoMessageLane,
+		"bridge-hub-rococo",
+		"BridgePolkadotBulletinMessages",
+		"polkadot-bulletin",
+		"BridgePolkadotMessages"
+	);
+	assert_messages_calls!(
+		BridgeHubRococoMessagesToBridgeHubWestendMessageLane,
+		"bridge-hub-westend",
+		"BridgeRococoMessages",
+		"bridge-hub-rococo",
+		"BridgeWestendMessages"
+	);
+	assert_messages_calls!(
+		BridgeHubWestendMessagesToBridgeHubRococoMessageLane,
+		"bridge-hub-rococo",
+		"BridgeWestendMessages",
+		"bridge-hub-westend",
+		"BridgeRococoMessages"
+	);
+}
+
+// TODO: https://github.com/paritytech/parity-bridges-common/issues/2547 - the Polkadot Bridge Hub
+// runtime doesn't have bridge pallets for the Polkadot Bulletin chain yet and the relay is using
+// the `BridgeKusama*` pallets as placeholders. The test pins this mapping, so it fails (and must
+// be updated to use `BridgePolkadotBulletin*` pallets) once the pallets are deployed.
+#[test]
+fn polkadot_bulletin_calls_use_bridge_hub_polkadot_placeholder_pallets() {
+	assert_init_bridge_call!(
+		PolkadotBulletinToBridgeHubPolkadotCliBridge,
+		"bridge-hub-polkadot",
+		"BridgeKusamaGrandpa"
+	);
+	assert_finality_calls!(
+		PolkadotBulletinFinalityToBridgeHubPolkadot,
+		"bridge-hub-polkadot",
+		"BridgeKusamaGrandpa",
+		"polkadot-bulletin"
+	);
+	assert_messages_calls!(
+		BridgeHubPolkadotMessagesToPolkadotBulletinMessageLane,
+		"polkadot-bulletin",
+		"BridgePolkadotMessages",
+		"bridge-hub-polkadot",
+		"BridgeKusamaMessages"
+	);
+	assert_messages_calls!(
+		PolkadotBulletinMessagesToBridgeHubPolkadotMessageLane,
+		"bridge-hub-polkadot",
+		"BridgeKusamaMessages",
+		"polkadot-bulletin",
+		"BridgePolkadotMessages"
+	);
+	assert!(
+		expected_call_index("bridge-hub-polkadot", "BridgePolkadotBulletinGrandpa", "initialize")
+			.is_none(),
+		"Bridge Hub Polkadot has Polkadot Bulletin bridge pallets - stop using placeholders",
+	);
+}
@@ -0,0 +1,419 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Administration of bridge pallets at the target chain.
+//!
+//! On production chains, operating modes and owners of bridge pallets are changed by governance,
+//! so the relay signer can't submit these calls. With `--encode-only`, the call is printed
+//! instead, so that it may be dispatched by governance.
+
+use crate::{
+	bridges::{
+		kusama_polkadot::{
+			kusama_headers_to_bridge_hub_polkadot::KusamaToBridgeHubPolkadotCliBridge,
+			polkadot_headers_to_bridge_hub_kusama::PolkadotToBridgeHubKusamaCliBridge,
+		},
+		polkadot_bulletin::polkadot_headers_to_polkadot_bulletin::PolkadotToPolkadotBulletinCliBridge,
+		rococo_bulletin::{
+			rococo_bulletin_headers_to_bridge_hub_rococo::RococoBulletinToBridgeHubRococoCliBridge,
+			rococo_headers_to_rococo_bulletin::RococoToRococoBulletinCliBridge,
+		},
+		rococo_westend::{
+			rococo_headers_to_bridge_hub_westend::RococoToBridgeHubWestendCliBridge,
+			westend_headers_to_bridge_hub_rococo::WestendToBridgeHubRococoCliBridge,
+		},
+	},
+	cli::{init_bridge::print_encoded_call, LOG_TARGET},
+};
+
+use relay_substrate_client::{
+	AccountIdOf, AccountKeyPairOf, CallOf, Chain, Client, TransactionEra, UnsignedTransaction,
+};
+use sp_core::Pair;
+use sp_runtime::AccountId32;
+use structopt::StructOpt;
+use strum::{EnumString, VariantNames};
+use substrate_relay_helper::cli::{
+	bridge::CliBridgeBase,
+	chain_schema::{TargetConnectionParams, TargetSigningParams},
+};
+
+/// Bridge with pallets at the target chain that may be administered by the relay.
+pub trait BridgeAdmin: CliBridgeBase {
+	/// Encode administrative call of the bridge pallet at the target chain.
+	fn encode_admin_call(
+		pallet: BridgePallet,
+		call: AdminCall,
+	) -> anyhow::Result<CallOf<Self::Target>>;
+}
+
+/// Implement `BridgeAdmin` for the bridge, using given `RuntimeCall` variants of bridge pallets.
+///
+/// If `sudo` is specified, calls are wrapped into the `Sudo::sudo` call.
+macro_rules! impl_bridge_admin {
+	(
+		$bridge:ty,
+		$client:ident,
+		grandpa: $grandpa:ident,
+		$(parachains: $parachains:ident,)?
+		messages: $messages:ident
+		$(, sudo: $sudo:ident)? $(,)?
+	) => {
+		impl BridgeAdmin for $bridge {
+			fn encode_admin_call(
+				pallet: BridgePallet,
+				call: AdminCall,
+			) -> anyhow::Result<CallOf<Self::Target>> {
+				use $client::{
+					runtime_types::{
+						bp_messages::MessagesOperatingMode, bp_runtime::BasicOperatingMode,
+						pallet_bridge_grandpa::pallet::Call as GrandpaCall,
+						pallet_bridge_messages::pallet::Call as MessagesCall,
+						pallet_bridge_parachains::pallet::Call as ParachainsCall,
+					},
+					RuntimeCall,
+				};
+
+				let basic_operating_mode = |mode| match mode {
+					OperatingMode::Normal => Ok(BasicOperatingMode::Normal),
+					OperatingMode::Halted => Ok(BasicOperatingMode::Halted),
+					OperatingMode::RejectingOutboundMessages => Err(anyhow::format_err!(
+						"Only the messages pallet may reject outbound messages"
+					)),
+				};
+				let call = match (pallet, call) {
+					(BridgePallet::Grandpa, AdminCall::SetOperatingMode(mode)) =>
+						RuntimeCall::$grandpa(GrandpaCall::set_operating_mode {
+							operating_mode: basic_operating_mode(mode)?,
+						}),
+					(BridgePallet::Grandpa, AdminCall::SetOwner(new_owner)) =>
+						RuntimeCall::$grandpa(GrandpaCall::set_owner { new_owner }),
+					$(
+						(BridgePallet::Parachains, AdminCall::SetOperatingMode(mode)) =>
+							RuntimeCall::$parachains(ParachainsCall::set_operating_mode {
+								operating_mode: basic_operating_mode(mode)?,
+							}),
+						(BridgePallet::Parachains, AdminCall::SetOwner(new_owner)) =>
+							RuntimeCall::$parachains(ParachainsCall::set_owner { new_owner }),
+					)?
+					(BridgePallet::Messages, AdminCall::SetOperatingMode(mode)) =>
+						RuntimeCall::$messages(MessagesCall::set_operating_mode {
+							operating_mode: match mode {
+								OperatingMode::RejectingOutboundMessages =>
+									MessagesOperatingMode::RejectingOutboundMessages,
+								mode => MessagesOperatingMode::Basic(basic_operating_mode(mode)?),
+							},
+						}),
+					(BridgePallet::Messages, AdminCall::SetOwner(new_owner)) =>
+						RuntimeCall::$messages(MessagesCall::set_owner { new_owner }),
+					#[allow(unreachable_patterns)]
+					(pallet, _) =>
+						return Err(anyhow::format_err!(
+							"There's no {:?} pallet of the bridge at {}",
+							pallet,
+							<Self::Target as Chain>::NAME,
+						)),
+				};
+				$(let call = RuntimeCall::$sudo($client::SudoCall::sudo { call: Box::new(call) });)?
+
+				Ok(call)
+			}
+		}
+	};
+}
+
+impl_bridge_admin!(
+	RococoToBridgeHubWestendCliBridge,
+	relay_bridge_hub_westend_client,
+	grandpa: BridgeRococoGrandpa,
+	parachains: BridgeRococoParachains,
+	messages: BridgeRococoMessages,
+);
+impl_bridge_admin!(
+	WestendToBridgeHubRococoCliBridge,
+	relay_bridge_hub_rococo_client,
+	grandpa: BridgeWestendGrandpa,
+	parachains: BridgeWestendParachains,
+	messages: BridgeWestendMessages,
+);
+impl_bridge_admin!(
+	KusamaToBridgeHubPolkadotCliBridge,
+	relay_bridge_hub_polkadot_client,
+	grandpa: BridgeKusamaGrandpa,
+	parachains: BridgeKusamaParachains,
+	messages: BridgeKusamaMessages,
+);
+impl_bridge_admin!(
+	PolkadotToBridgeHubKusamaCliBridge,
+	relay_bridge_hub_kusama_client,
+	grandpa: BridgePolkadotGrandpa,
+	parachains: BridgePolkadotParachains,
+	messages: BridgePolkadotMessages,
+);
+impl_bridge_admin!(
+	PolkadotToPolkadotBulletinCliBridge,
+	relay_polkadot_bulletin_client,
+	grandpa: BridgePolkadotGrandpa,
+	parachains: BridgePolkadotParachains,
+	messages: BridgePolkadotMessages,
+	sudo: Sudo,
+);
+impl_bridge_admin!(
+	RococoToRococoBulletinCliBridge,
+	relay_polkadot_bulletin_client,
+	grandpa: BridgePolkadotGrandpa,
+	parachains: BridgePolkadotParachains,
+	messages: BridgePolkadotMessages,
+	sudo: Sudo,
+);
+impl_bridge_admin!(
+	RococoBulletinToBridgeHubRococoCliBridge,
+	relay_bridge_hub_rococo_client,
+	grandpa: BridgePolkadotBulletinGrandpa,
+	messages: BridgePolkadotBulletinMessages,
+);
+
+/// Bridge, which pallets at the target chain are administered.
+///
+/// Pallets of the bridge with Polkadot Bulletin are not yet deployed at Polkadot Bridge Hub, so
+/// the `polkadot-bulletin-to-bridge-hub-polkadot` bridge is not supported.
+#[derive(Debug, EnumString, VariantNames)]
+#[strum(serialize_all = "kebab_case")]
+pub enum AdminBridgeName {
+	KusamaToBridgeHubPolkadot,
+	PolkadotToBridgeHubKusama,
+	PolkadotToPolkadotBulletin,
+	RococoToRococoBulletin,
+	RococoBulletinToBridgeHubRococo,
+	RococoToBridgeHubWestend,
+	WestendToBridgeHubRococo,
+}
+
+/// Bridge pallet at the target chain.
+#[derive(Clone, Copy, Debug, PartialEq, EnumString, VariantNames)]
+#[strum(serialize_all = "kebab_case")]
+pub enum BridgePallet {
+	/// Bridge GRANDPA pallet.
+	Grandpa,
+	/// Bridge parachains pallet.
+	Parachains,
+	/// Bridge messages pallet.
+	Messages,
+}
+
+/// Operating mode of the bridge pallet.
+#[derive(Clone, Copy, Debug, PartialEq, EnumString, VariantNames)]
+#[strum(serialize_all = "kebab_case")]
+pub enum OperatingMode {
+	/// All pallet operations are allowed.
+	Normal,
+	/// All pallet operations, except administrative, are rejected.
+	Halted,
+	/// Outbound messages are rejected. Only supported by the messages pallet.
+	RejectingOutboundMessages,
+}
+
+/// Administrative call of the bridge pallet.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AdminCall {
+	/// Change operating mode of the pallet.
+	SetOperatingMode(OperatingMode),
+	/// Change owner of the pallet. `None` resets the owner.
+	SetOwner(Option<AccountId32>),
+}
+
+/// Parameters of administrative commands.
+#[derive(StructOpt)]
+struct AdminCallParams {
+	/// A bridge instance, which pallet is administered.
+	#[structopt(possible_values = AdminBridgeName::VARIANTS, case_insensitive = true)]
+	bridge: AdminBridgeName,
+	/// Bridge pallet at the target chain.
+	#[structopt(long, possible_values = BridgePallet::VARIANTS, case_insensitive = true)]
+	pallet: BridgePallet,
+	#[structopt(flatten)]
+	target: TargetConnectionParams,
+	#[structopt(flatten)]
+	target_sign: TargetSigningParams,
+	/// Do not submit the transaction. Instead, print the SCALE-encoded call, its hash and
+	/// decoded form, so that it may be dispatched by governance. The signer is not required.
+	#[structopt(long)]
+	encode_only: bool,
+}
+
+impl AdminCallParams {
+	/// Name of the bridge, served by this command.
+	fn bridge_name(&self) -> String {
+		format!("{:?}", self.bridge)
+	}
+
+	/// Either submit or print the call.
+	async fn run(self, call: AdminCall) -> anyhow::Result<()> {
+		match self.bridge {
+			AdminBridgeName::KusamaToBridgeHubPolkadot =>
+				submit_admin_call::<KusamaToBridgeHubPolkadotCliBridge>(self, call).await,
+			AdminBridgeName::PolkadotToBridgeHubKusama =>
+				submit_admin_call::<PolkadotToBridgeHubKusamaCliBridge>(self, call).await,
+			AdminBridgeName::PolkadotToPolkadotBulletin =>
+				submit_admin_call::<PolkadotToPolkadotBulletinCliBridge>(self, call).await,
+			AdminBridgeName::RococoToRococoBulletin =>
+				submit_admin_call::<RococoToRococoBulletinCliBridge>(self, call).await,
+			AdminBridgeName::RococoBulletinToBridgeHubRococo =>
+				submit_admin_call::<RococoBulletinToBridgeHubRococoCliBridge>(self, call).await,
+			AdminBridgeName::RococoToBridgeHubWestend =>
+				submit_admin_call::<RococoToBridgeHubWestendCliBridge>(self, call).await,
+			AdminBridgeName::WestendToBridgeHubRococo =>
+				submit_admin_call::<WestendToBridgeHubRococoCliBridge>(self, call).await,
+		}
+	}
+}
+
+/// Change operating mode of the bridge pallet at the target chain.
+#[derive(StructOpt)]
+pub struct SetOperatingMode {
+	#[structopt(flatten)]
+	params: AdminCallParams,
+	/// New operating mode of the pallet.
+	#[structopt(possible_values = OperatingMode::VARIANTS, case_insensitive = true)]
+	mode: OperatingMode,
+}
+
+impl SetOperatingMode {
+	/// Name of the bridge, served by this command.
+	pub fn bridge_name(&self) -> String {
+		self.params.bridge_name()
+	}
+
+	/// Run the command.
+	pub async fn run(self) -> anyhow::Result<()> {
+		self.params.run(AdminCall::SetOperatingMode(self.mode)).await
+	}
+}
+
+/// Change owner of the bridge pallet at the target chain.
+#[derive(StructOpt)]
+pub struct SetOwner {
+	#[structopt(flatten)]
+	params: AdminCallParams,
+	/// SS58 address of the new owner. If not specified, the owner is reset.
+	#[structopt(long)]
+	new_owner: Option<AccountId32>,
+}
+
+impl SetOwner {
+	/// Name of the bridge, served by this command.
+	pub fn bridge_name(&self) -> String {
+		self.params.bridge_name()
+	}
+
+	/// Run the command.
+	pub async fn run(self) -> anyhow::Result<()> {
+		self.params.run(AdminCall::SetOwner(self.new_owner)).await
+	}
+}
+
+/// Either submit the administrative call to the target chain or print it.
+async fn submit_admin_call<Bridge: BridgeAdmin>(
+	params: AdminCallParams,
+	call: AdminCall,
+) -> anyhow::Result<()>
+where
+	AccountIdOf<Bridge::Target>: From<<AccountKeyPairOf<Bridge::Target> as Pair>::Public>,
+{
+	let call = Bridge::encode_admin_call(params.pallet, call)?;
+	if params.encode_only {
+		print_encoded_call::<Bridge::Target>(&call);
+		return Ok(())
+	}
+
+	let target_client = params.target.into_client::<Bridge::Target>().await?;
+	let signer = params.target_sign.to_keypair::<Bridge::Target>()?;
+	let mortality = params.target_sign.transactions_mortality()?;
+	let tx_hash = target_client
+		.submit_signed_extrinsic(&signer, move |best_block_id, nonce| {
+			Ok(UnsignedTransaction::new(call.into(), nonce)
+				.era(TransactionEra::new(best_block_id, mortality)))
+		})
+		.await?;
+
+	log::info!(
+		target: LOG_TARGET,
+		tx_hash:? = tx_hash;
+		"Submitted {:?} pallet administrative transaction to {}: {:?}",
+		params.pallet,
+		Bridge::Target::NAME,
+		tx_hash,
+	);
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::cli::init_bridge::encoded_call_summary;
+	use codec::Encode;
+
+	#[test]
+	fn encoded_admin_call_is_printed() {
+		let call = RococoToBridgeHubWestendCliBridge::encode_admin_call(
+			BridgePallet::Grandpa,
+			AdminCall::SetOperatingMode(OperatingMode::Halted),
+		)
+		.unwrap();
+		assert_eq!(call.encode(), vec![42, 3, 1]);
+		assert_eq!(
+			encoded_call_summary::<relay_bridge_hub_westend_client::BridgeHubWestend>(&call),
+			"Encoded BridgeHubWestend call: 0x2a0301\n\
+			Call hash: 0xb9b9ef9ac377926dfe4c4a48bcef8053bbde10113204971d51b825ecbb01627b\n\
+			Decoded call: BridgeRococoGrandpa(\n    \
+				set_operating_mode {\n        \
+					operating_mode: Halted,\n    \
+				},\n\
+			)",
+		);
+	}
+
+	#[test]
+	fn admin_calls_are_checked() {
+		// only messages pallet may reject outbound messages
+		assert!(RococoToBridgeHubWestendCliBridge::encode_admin_call(
+			BridgePallet::Grandpa,
+			AdminCall::SetOperatingMode(OperatingMode::RejectingOutboundMessages),
+		)
+		.is_err());
+		assert!(RococoToBridgeHubWestendCliBridge::encode_admin_call(
+			BridgePallet::Messages,
+			AdminCall::SetOperatingMode(OperatingMode::RejectingOutboundMessages),
+		)
+		.is_ok());
+
+		// there's no parachains pallet in the bridge with Rococo Bulletin
+		assert!(RococoBulletinToBridgeHubRococoCliBridge::encode_admin_call(
+			BridgePallet::Parachains,
+			AdminCall::SetOwner(None),
+		)
+		.is_err());
+
+		// calls to Bulletin chain pallets are dispatched by sudo
+		let call = RococoToRococoBulletinCliBridge::encode_admin_call(
+			BridgePallet::Messages,
+			AdminCall::SetOwner(Some(AccountId32::new([1; 32]))),
+		)
+		.unwrap();
+		assert!(matches!(call, relay_polkadot_bulletin_client::RuntimeCall::Sudo(_)));
+	}
+}
@@ -57,6 +57,11 @@ impl EquivocationsDetector for RococoToBridgeHubWestendCliBridge {}
 impl EquivocationsDetector for WestendToBridgeHubRococoCliBridge {}
 
 impl DetectEquivocations {
+	/// Name of the bridge, served by this command.
+	pub fn bridge_name(&self) -> String {
+		format!("{:?}", self.bridge)
+	}
+
 	/// Run the command.
 	pub async fn run(self) -> anyhow::Result<()> {
 		match self.bridge {
@@ -0,0 +1,736 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Diagnostics of the stuck message.
+//!
+//! The message is either waiting for delivery to the target chain, or for the delivery
+//! confirmation at the source chain. For every stage we check all conditions that may block the
+//! relay, reading the same on-chain state that the relay and the runtime are reading:
+//!
+//! - operating modes of the messages pallet and of the finality pallets, which are receiving
+//!   headers of the bridged chain;
+//! - whether the header with the message (or with the delivery) has been finalized at the receiving
+//!   chain. If the bridged chain is a parachain, we distinguish the missing relay header from the
+//!   missing parachain head by comparing the relay block of the best known parachain head with the
+//!   best finalized relay header;
+//! - dispatch weight of the message and limits of the inbound lane at the target chain;
+//! - balances of relayer accounts (if specified).
+//!
+//! Found reasons are ranked, so that the one that must be fixed first is printed first.
+
+use crate::{
+	bridges::{
+		kusama_polkadot::{
+			bridge_hub_kusama_messages_to_bridge_hub_polkadot::BridgeHubKusamaToBridgeHubPolkadotMessagesCliBridge,
+			bridge_hub_polkadot_messages_to_bridge_hub_kusama::BridgeHubPolkadotToBridgeHubKusamaMessagesCliBridge,
+			kusama_parachains_to_bridge_hub_polkadot::BridgeHubKusamaToBridgeHubPolkadot,
+			polkadot_parachains_to_bridge_hub_kusama::BridgeHubPolkadotToBridgeHubKusama,
+		},
+		polkadot_bulletin::{
+			bridge_hub_polkadot_messages_to_polkadot_bulletin::BridgeHubPolkadotToPolkadotBulletinMessagesCliBridge,
+			polkadot_bulletin_headers_to_bridge_hub_polkadot::PolkadotBulletinFinalityToBridgeHubPolkadot,
+			polkadot_bulletin_messages_to_bridge_hub_polkadot::PolkadotBulletinToBridgeHubPolkadotMessagesCliBridge,
+			polkadot_parachains_to_polkadot_bulletin::PolkadotToPolkadotBulletin,
+		},
+		rococo_bulletin::{
+			bridge_hub_rococo_messages_to_rococo_bulletin::BridgeHubRococoToRococoBulletinMessagesCliBridge,
+			rococo_bulletin_headers_to_bridge_hub_rococo::RococoBulletinFinalityToBridgeHubRococo,
+			rococo_bulletin_messages_to_bridge_hub_rococo::RococoBulletinToBridgeHubRococoMessagesCliBridge,
+			rococo_parachains_to_rococo_bulletin::RococoToRococoBulletin,
+		},
+		rococo_westend::{
+			bridge_hub_rococo_messages_to_bridge_hub_westend::BridgeHubRococoToBridgeHubWestendMessagesCliBridge,
+			bridge_hub_westend_messages_to_bridge_hub_rococo::BridgeHubWestendToBridgeHubRococoMessagesCliBridge,
+			rococo_parachains_to_bridge_hub_westend::BridgeHubRococoToBridgeHubWestend,
+			westend_parachains_to_bridge_hub_rococo::BridgeHubWestendToBridgeHubRococo,
+		},
+	},
+	cli::{
+		estimate_profit::ChainWithBridgeTransactions, relay_messages::FullBridge,
+		signer_top_up::free_balance,
+	},
+};
+
+use bp_messages::{
+	InboundLaneData, MessageNonce, MessagesOperatingMode, OutboundLaneData, OutboundMessageDetails,
+};
+use bp_parachains::{ParaInfo, ParasInfoKeyProvider};
+use bp_polkadot_core::parachains::ParaId;
+use bp_runtime::{BasicOperatingMode, OperatingMode, StorageMapKeyProvider};
+use codec::Decode;
+use frame_support::weights::Weight;
+use relay_substrate_client::{
+	AccountIdOf, Chain, ChainWithBalances, ChainWithGrandpa, ChainWithMessages, Client, HashOf,
+	HeaderIdOf, RelayChain,
+};
+use sp_core::{crypto::AccountId32, storage::StorageKey, H256};
+use sp_runtime::traits::TryConvert;
+use std::fmt;
+use structopt::StructOpt;
+use strum::VariantNames;
+use substrate_relay_helper::{
+	cli::{
+		bridge::MessagesCliBridge,
+		chain_schema::{SourceConnectionParams, TargetConnectionParams},
+		HexLaneId,
+	},
+	finality::SubstrateFinalitySyncPipeline,
+	messages::SubstrateMessageLane,
+	parachains::SubstrateParachainsPipeline,
+};
+
+/// Name of the operating mode storage value in all bridge pallets.
+const OPERATING_MODE_VALUE_NAME: &str = "PalletOperatingMode";
+
+/// Explain why the message is not delivered or not confirmed.
+#[derive(StructOpt)]
+pub struct DiagnoseLane {
+	/// A bridge instance to diagnose.
+	#[structopt(possible_values = FullBridge::VARIANTS, case_insensitive = true)]
+	bridge: FullBridge,
+	/// Hex-encoded lane identifier.
+	#[structopt(long)]
+	lane: HexLaneId,
+	/// Nonce of the stuck message.
+	#[structopt(long)]
+	nonce: MessageNonce,
+	/// Account of the relayer that is delivering messages to the target chain. If specified,
+	/// its balance is checked.
+	#[structopt(long)]
+	target_relayer: Option<AccountId32>,
+	/// Account of the relayer that is delivering confirmations to the source chain. If
+	/// specified, its balance is checked.
+	#[structopt(long)]
+	source_relayer: Option<AccountId32>,
+	/// Relayer balance is considered too low when it is below this value.
+	#[structopt(long, default_value = "1")]
+	min_relayer_balance: u128,
+	#[structopt(flatten)]
+	source: SourceConnectionParams,
+	#[structopt(flatten)]
+	target: TargetConnectionParams,
+}
+
+/// Finality of the bridged chain headers at the chain that is receiving messages or
+/// confirmations.
+#[derive(Clone, Copy, Debug)]
+pub struct BridgedFinality {
+	/// Name of the chain, whose headers are finalized by the GRANDPA pallet.
+	grandpa_chain: &'static str,
+	/// Name of the GRANDPA pallet.
+	grandpa_pallet: &'static str,
+	/// Runtime API method that returns the best finalized header of the `grandpa_chain`.
+	best_finalized_method: &'static str,
+	/// Name of the parachains pallet and the bridged parachain identifier, if the bridged chain
+	/// is a parachain.
+	parachain: Option<(&'static str, u32)>,
+}
+
+impl BridgedFinality {
+	/// Bridged chain headers are finalized by the GRANDPA pallet.
+	fn grandpa<P: SubstrateFinalitySyncPipeline>() -> Self
+	where
+		P::SourceChain: ChainWithGrandpa,
+	{
+		BridgedFinality {
+			grandpa_chain: P::SourceChain::NAME,
+			grandpa_pallet: P::SourceChain::WITH_CHAIN_GRANDPA_PALLET_NAME,
+			best_finalized_method: P::SourceChain::BEST_FINALIZED_HEADER_ID_METHOD,
+			parachain: None,
+		}
+	}
+
+	/// Bridged chain is a parachain, whose heads are tracked by the parachains pallet.
+	fn parachain<P: SubstrateParachainsPipeline>() -> Self
+	where
+		P::SourceRelayChain: ChainWithGrandpa + RelayChain,
+	{
+		BridgedFinality {
+			grandpa_chain: P::SourceRelayChain::NAME,
+			grandpa_pallet: P::SourceRelayChain::WITH_CHAIN_GRANDPA_PALLET_NAME,
+			best_finalized_method: P::SourceRelayChain::BEST_FINALIZED_HEADER_ID_METHOD,
+			parachain: Some((
+				P::SourceRelayChain::WITH_CHAIN_BRIDGE_PARACHAINS_PALLET_NAME,
+				P::SourceParachain::PARACHAIN_ID,
+			)),
+		}
+	}
+}
+
+/// Condition that blocks delivery or confirmation of the message.
+#[derive(Debug, PartialEq)]
+pub enum Blocker {
+	/// Bridge pallet is halted.
+	PalletHalted { chain: &'static str, pallet: &'static str },
+	/// Message dispatch weight exceeds the maximal extrinsic weight at the target chain, so it
+	/// may never be delivered.
+	MessageTooHeavy { dispatch_weight: Weight, max_extrinsic_weight: Weight },
+	/// The target inbound lane has reached the limit of unrewarded relayer entries. Delivery
+	/// confirmations need to be relayed first.
+	TooManyUnrewardedRelayers { count: MessageNonce, max: MessageNonce },
+	/// The target inbound lane has reached the limit of unconfirmed messages. Delivery
+	/// confirmations need to be relayed first.
+	TooManyUnconfirmedMessages { count: MessageNonce, max: MessageNonce },
+	/// Header of the GRANDPA chain is not yet finalized at the receiving chain.
+	HeaderNotFinalized { chain: &'static str, at: &'static str },
+	/// Head of the bridged parachain is not yet relayed to the receiving chain, even though
+	/// newer relay chain headers are finalized there.
+	ParachainHeadMissing {
+		parachain: u32,
+		at: &'static str,
+		at_relay_block: Option<u32>,
+		best_relay_block: u32,
+	},
+	/// Relayer balance is too low to pay for the transaction.
+	LowRelayerBalance { chain: &'static str, balance: u128, min: u128 },
+}
+
+impl Blocker {
+	/// Rank of the blocker. Blockers with lower ranks need to be resolved first.
+	fn rank(&self) -> u8 {
+		match *self {
+			Self::PalletHalted { .. } => 0,
+			Self::MessageTooHeavy { .. } => 1,
+			Self::LowRelayerBalance { .. } => 2,
+			Self::TooManyUnrewardedRelayers { .. } | Self::TooManyUnconfirmedMessages { .. } => 3,
+			Self::HeaderNotFinalized { .. } => 4,
+			Self::ParachainHeadMissing { .. } => 5,
+		}
+	}
+}
+
+impl fmt::Display for Blocker {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Self::PalletHalted { chain, pallet } =>
+				write!(f, "pallet {} at {} is halted", pallet, chain),
+			Self::MessageTooHeavy { dispatch_weight, max_extrinsic_weight } => write!(
+				f,
+				"message dispatch weight {:?} exceeds the maximal extrinsic weight {:?}",
+				dispatch_weight, max_extrinsic_weight,
+			),
+			Self::TooManyUnrewardedRelayers { count, max } => write!(
+				f,
+				"inbound lane has {} unrewarded relayer entries (max {}), delivery confirmations \
+				must be relayed first",
+				count, max,
+			),
+			Self::TooManyUnconfirmedMessages { count, max } => write!(
+				f,
+				"inbound lane has {} unconfirmed messages (max {}), delivery confirmations must \
+				be relayed first",
+				count, max,
+			),
+			Self::HeaderNotFinalized { chain, at } =>
+				write!(f, "required {} header is not yet finalized at {}", chain, at),
+			Self::ParachainHeadMissing { parachain, at, at_relay_block, best_relay_block } =>
+				write!(
+					f,
+					"head of parachain {} at {} is proven at relay block {:?}, but relay block {} \
+					is already finalized there: parachain head needs to be relayed",
+					parachain, at, at_relay_block, best_relay_block,
+				),
+			Self::LowRelayerBalance { chain, balance, min } =>
+				write!(f, "relayer balance at {} is {}, which is below {}", chain, balance, min),
+		}
+	}
+}
+
+/// Delivery state of the message.
+#[derive(Debug, PartialEq)]
+enum MessageState {
+	/// The message is not yet sent.
+	NotSent,
+	/// The message is waiting for delivery to the target chain.
+	WaitingForDelivery,
+	/// The message is delivered and is waiting for delivery confirmation at the source chain.
+	WaitingForConfirmation,
+	/// The message is delivered and confirmed.
+	Confirmed,
+}
+
+/// State of the outbound lane, which is used by diagnostics.
+#[derive(Debug, Default)]
+struct OutboundLaneState {
+	latest_received_nonce: MessageNonce,
+	latest_generated_nonce: MessageNonce,
+}
+
+/// State of the inbound lane, which is used by diagnostics.
+#[derive(Debug, Default)]
+struct InboundLaneState {
+	last_delivered_nonce: MessageNonce,
+	last_confirmed_nonce: MessageNonce,
+	unrewarded_relayers: MessageNonce,
+}
+
+impl MessageState {
+	fn new(nonce: MessageNonce, outbound: &OutboundLaneState, inbound: &InboundLaneState) -> Self {
+		if nonce > outbound.latest_generated_nonce {
+			MessageState::NotSent
+		} else if nonce <= outbound.latest_received_nonce {
+			MessageState::Confirmed
+		} else if nonce <= inbound.last_delivered_nonce {
+			MessageState::WaitingForConfirmation
+		} else {
+			MessageState::WaitingForDelivery
+		}
+	}
+}
+
+impl DiagnoseLane {
+	/// Name of the bridge.
+	pub fn bridge_name(&self) -> String {
+		format!("{:?}", self.bridge)
+	}
+
+	/// Run the command.
+	pub async fn run(self) -> anyhow::Result<()> {
+		match self.bridge {
+			FullBridge::BridgeHubRococoToBridgeHubWestend =>
+				diagnose_lane::<BridgeHubRococoToBridgeHubWestendMessagesCliBridge>(
+					self,
+					BridgedFinality::parachain::<BridgeHubRococoToBridgeHubWestend>(),
+					BridgedFinality::parachain::<BridgeHubWestendToBridgeHubRococo>(),
+				)
+				.await,
+			FullBridge::BridgeHubWestendToBridgeHubRococo =>
+				diagnose_lane::<BridgeHubWestendToBridgeHubRococoMessagesCliBridge>(
+					self,
+					BridgedFinality::parachain::<BridgeHubWestendToBridgeHubRococo>(),
+					BridgedFinality::parachain::<BridgeHubRococoToBridgeHubWestend>(),
+				)
+				.await,
+			FullBridge::BridgeHubKusamaToBridgeHubPolkadot =>
+				diagnose_lane::<BridgeHubKusamaToBridgeHubPolkadotMessagesCliBridge>(
+					self,
+					BridgedFinality::parachain::<BridgeHubKusamaToBridgeHubPolkadot>(),
+					BridgedFinality::parachain::<BridgeHubPolkadotToBridgeHubKusama>(),
+				)
+				.await,
+			FullBridge::BridgeHubPolkadotToBridgeHubKusama =>
+				diagnose_lane::<BridgeHubPolkadotToBridgeHubKusamaMessagesCliBridge>(
+					self,
+					BridgedFinality::parachain::<BridgeHubPolkadotToBridgeHubKusama>(),
+					BridgedFinality::parachain::<BridgeHubKusamaToBridgeHubPolkadot>(),
+				)
+				.await,
+			FullBridge::PolkadotBulletinToBridgeHubPolkadot =>
+				diagnose_lane::<PolkadotBulletinToBridgeHubPolkadotMessagesCliBridge>(
+					self,
+					BridgedFinality::grandpa::<PolkadotBulletinFinalityToBridgeHubPolkadot>(),
+					BridgedFinality::parachain::<PolkadotToPolkadotBulletin>(),
+				)
+				.await,
+			FullBridge::BridgeHubPolkadotToPolkadotBulletin =>
+				diagnose_lane::<BridgeHubPolkadotToPolkadotBulletinMessagesCliBridge>(
+					self,
+					BridgedFinality::parachain::<PolkadotToPolkadotBulletin>(),
+					BridgedFinality::grandpa::<PolkadotBulletinFinalityToBridgeHubPolkadot>(),
+				)
+				.await,
+			FullBridge::RococoBulletinToBridgeHubRococo =>
+				diagnose_lane::<RococoBulletinToBridgeHubRococoMessagesCliBridge>(
+					self,
+					BridgedFinality::grandpa::<RococoBulletinFinalityToBridgeHubRococo>(),
+					BridgedFinality::parachain::<RococoToRococoBulletin>(),
+				)
+				.await,
+			FullBridge::BridgeHubRococoToRococoBulletin =>
+				diagnose_lane::<BridgeHubRococoToRococoBulletinMessagesCliBridge>(
+					self,
+					BridgedFinality::parachain::<RococoToRococoBulletin>(),
+					BridgedFinality::grandpa::<RococoBulletinFinalityToBridgeHubRococo>(),
+				)
+				.await,
+		}
+	}
+}
+
+/// Lane identifier type of the messages bridge.
+type LaneIdOf<Bridge> =
+	<<Bridge as MessagesCliBridge>::MessagesLane as SubstrateMessageLane>::LaneId;
+
+/// Diagnose the message. `source_finality` is the finality of source chain headers at the target
+/// chain and `target_finality` is the finality of target chain headers at the source chain.
+async fn diagnose_lane<Bridge: MessagesCliBridge>(
+	params: DiagnoseLane,
+	source_finality: BridgedFinality,
+	target_finality: BridgedFinality,
+) -> anyhow::Result<()>
+where
+	Bridge::Source: ChainWithMessages + ChainWithBalances + ChainWithBridgeTransactions,
+	Bridge::Target: ChainWithMessages + ChainWithBalances + ChainWithBridgeTransactions,
+	AccountIdOf<Bridge::Source>: From<AccountId32>,
+	AccountIdOf<Bridge::Target>: From<AccountId32>,
+{
+	let lane: LaneIdOf<Bridge> = HexLaneId::try_convert(params.lane)
+		.map_err(|invalid_lane_id| anyhow::format_err!("Invalid laneId: {:?}!", invalid_lane_id))?;
+	let source_client = params.source.into_client::<Bridge::Source>().await?;
+	let target_client = params.target.into_client::<Bridge::Target>().await?;
+	let source_at = source_client.best_finalized_header_hash().await?;
+	let target_at = target_client.best_finalized_header_hash().await?;
+	// messages pallet at the source chain is named after the target chain and vice versa
+	let source_messages_pallet = Bridge::Target::WITH_CHAIN_MESSAGES_PALLET_NAME;
+	let target_messages_pallet = Bridge::Source::WITH_CHAIN_MESSAGES_PALLET_NAME;
+
+	let outbound_key =
+		bp_messages::storage_keys::outbound_lane_data_key(source_messages_pallet, &lane);
+	let inbound_key =
+		bp_messages::storage_keys::inbound_lane_data_key(target_messages_pallet, &lane);
+	let outbound = outbound_lane_state(&source_client, source_at, outbound_key.clone()).await?;
+	let inbound = inbound_lane_state::<_, AccountIdOf<Bridge::Source>>(
+		&target_client,
+		target_at,
+		inbound_key.clone(),
+	)
+	.await?;
+
+	let direction = format!("{} -> {}", Bridge::Source::NAME, Bridge::Target::NAME);
+	let state = MessageState::new(params.nonce, &outbound, &inbound);
+	let mut blockers = Vec::new();
+	match state {
+		MessageState::NotSent => {
+			println!(
+				"Message {} is not sent yet ({}): the latest sent message is {}",
+				params.nonce, direction, outbound.latest_generated_nonce,
+			);
+			return Ok(())
+		},
+		MessageState::Confirmed => {
+			println!("Message {} is delivered and confirmed ({})", params.nonce, direction);
+			return Ok(())
+		},
+		MessageState::WaitingForDelivery => {
+			println!("Message {} is waiting for delivery ({})", params.nonce, direction);
+
+			check_pallet_halted::<_, MessagesOperatingMode>(
+				&target_client,
+				target_at,
+				target_messages_pallet,
+				&mut blockers,
+			)
+			.await?;
+
+			// the message must be sent at the source header that is finalized at the target chain
+			let known_source_header = best_finalized_header::<_, Bridge::Source>(
+				&target_client,
+				target_at,
+				Bridge::Source::BEST_FINALIZED_HEADER_ID_METHOD,
+			)
+			.await?;
+			let is_finalized = match known_source_header {
+				Some(id) =>
+					outbound_lane_state(&source_client, id.hash(), outbound_key)
+						.await?
+						.latest_generated_nonce >=
+						params.nonce,
+				None => false,
+			};
+			if !is_finalized {
+				check_bridged_finality(&target_client, target_at, &source_finality, &mut blockers)
+					.await?;
+			}
+
+			let details: Vec<OutboundMessageDetails> = source_client
+				.state_call(
+					source_at,
+					Bridge::Target::TO_CHAIN_MESSAGE_DETAILS_METHOD.into(),
+					(lane, params.nonce, params.nonce),
+				)
+				.await?;
+			let max_extrinsic_weight = Bridge::Target::max_extrinsic_weight();
+			for details in details {
+				if details.dispatch_weight.any_gt(max_extrinsic_weight) {
+					blockers.push(Blocker::MessageTooHeavy {
+						dispatch_weight: details.dispatch_weight,
+						max_extrinsic_weight,
+					});
+				}
+			}
+
+			// limits of the inbound lane are defined by the confirmation transaction limits
+			let max_unrewarded_relayers =
+				Bridge::Source::MAX_UNREWARDED_RELAYERS_IN_CONFIRMATION_TX;
+			if inbound.unrewarded_relayers >= max_unrewarded_relayers {
+				blockers.push(Blocker::TooManyUnrewardedRelayers {
+					count: inbound.unrewarded_relayers,
+					max: max_unrewarded_relayers,
+				});
+			}
+			let unconfirmed_messages =
+				inbound.last_delivered_nonce.saturating_sub(inbound.last_confirmed_nonce);
+			let max_unconfirmed_messages =
+				Bridge::Source::MAX_UNCONFIRMED_MESSAGES_IN_CONFIRMATION_TX;
+			if unconfirmed_messages >= max_unconfirmed_messages {
+				blockers.push(Blocker::TooManyUnconfirmedMessages {
+					count: unconfirmed_messages,
+					max: max_unconfirmed_messages,
+				});
+			}
+
+			if let Some(relayer) = params.target_relayer {
+				check_relayer_balance(
+					&target_client,
+					target_at,
+					relayer.into(),
+					params.min_relayer_balance,
+					&mut blockers,
+				)
+				.await?;
+			}
+		},
+		MessageState::WaitingForConfirmation => {
+			println!(
+				"Message {} is waiting for delivery confirmation ({})",
+				params.nonce, direction
+			);
+
+			check_pallet_halted::<_, MessagesOperatingMode>(
+				&source_client,
+				source_at,
+				source_messages_pallet,
+				&mut blockers,
+			)
+			.await?;
+
+			// the message must be delivered at the target header that is finalized at the source
+			// chain
+			let known_target_header = best_finalized_header::<_, Bridge::Target>(
+				&source_client,
+				source_at,
+				Bridge::Target::BEST_FINALIZED_HEADER_ID_METHOD,
+			)
+			.await?;
+			let is_finalized = match known_target_header {
+				Some(id) =>
+					inbound_lane_state::<_, AccountIdOf<Bridge::Source>>(
+						&target_client,
+						id.hash(),
+						inbound_key,
+					)
+					.await?
+					.last_delivered_nonce >=
+						params.nonce,
+				None => false,
+			};
+			if !is_finalized {
+				check_bridged_finality(&source_client, source_at, &target_finality, &mut blockers)
+					.await?;
+			}
+
+			if let Some(relayer) = params.source_relayer {
+				check_relayer_balance(
+					&source_client,
+					source_at,
+					relayer.into(),
+					params.min_relayer_balance,
+					&mut blockers,
+				)
+				.await?;
+			}
+		},
+	}
+
+	print_blockers(blockers);
+	Ok(())
+}
+
+fn print_blockers(mut blockers: Vec<Blocker>) {
+	if blockers.is_empty() {
+		println!("No blocking conditions found: the message should be relayed soon");
+		return
+	}
+
+	blockers.sort_by_key(Blocker::rank);
+	println!("Possible reasons (the one to be resolved first goes first):");
+	for (index, blocker) in blockers.iter().enumerate() {
+		println!("  {}. {}", index + 1, blocker);
+	}
+}
+
+/// Read outbound lane data. Missing data means that the lane has no messages yet.
+async fn outbound_lane_state<C: Chain>(
+	client: &impl Client<C>,
+	at: HashOf<C>,
+	storage_key: StorageKey,
+) -> anyhow::Result<OutboundLaneState> {
+	let data = match client.raw_storage_value(at, storage_key).await? {
+		Some(data) => OutboundLaneData::decode(&mut &data.0[..])?,
+		None => return Ok(OutboundLaneState::default()),
+	};
+	Ok(OutboundLaneState {
+		latest_received_nonce: data.latest_received_nonce,
+		latest_generated_nonce: data.latest_generated_nonce,
+	})
+}
+
+/// Read inbound lane data. `RelayerId` is the type of relayer accounts at the bridged chain.
+async fn inbound_lane_state<C: Chain, RelayerId: Decode>(
+	client: &impl Client<C>,
+	at: HashOf<C>,
+	storage_key: StorageKey,
+) -> anyhow::Result<InboundLaneState> {
+	let data = match client.raw_storage_value(at, storage_key).await? {
+		Some(data) => InboundLaneData::<RelayerId>::decode(&mut &data.0[..])?,
+		None => return Ok(InboundLaneState::default()),
+	};
+	Ok(InboundLaneState {
+		last_delivered_nonce: data.last_delivered_nonce(),
+		last_confirmed_nonce: data.last_confirmed_nonce,
+		unrewarded_relayers: data.relayers.len() as MessageNonce,
+	})
+}
+
+/// Read the best finalized header of the `Bridged` chain, known to the chain `C`.
+async fn best_finalized_header<C: Chain, Bridged: Chain>(
+	client: &impl Client<C>,
+	at: HashOf<C>,
+	method: &str,
+) -> anyhow::Result<Option<HeaderIdOf<Bridged>>> {
+	Ok(client.state_call(at, method.into(), ()).await?)
+}
+
+/// Push the `PalletHalted` blocker if the bridge pallet at chain `C` is halted.
+async fn check_pallet_halted<C: Chain, Mode: Decode + OperatingMode>(
+	client: &impl Client<C>,
+	at: HashOf<C>,
+	pallet: &'static str,
+	blockers: &mut Vec<Blocker>,
+) -> anyhow::Result<()> {
+	let storage_key = StorageKey(
+		bp_runtime::storage_value_final_key(
+			pallet.as_bytes(),
+			OPERATING_MODE_VALUE_NAME.as_bytes(),
+		)
+		.to_vec(),
+	);
+	// missing value means that the pallet operates in the default (normal) mode
+	if let Some(mode) = client.raw_storage_value(at, storage_key).await? {
+		if Mode::decode(&mut &mode.0[..])?.is_halted() {
+			blockers.push(Blocker::PalletHalted { chain: C::NAME, pallet });
+		}
+	}
+	Ok(())
+}
+
+/// Find out why the required header of the bridged chain is not finalized at chain `C`.
+async fn check_bridged_finality<C: Chain>(
+	client: &impl Client<C>,
+	at: HashOf<C>,
+	finality: &BridgedFinality,
+	blockers: &mut Vec<Blocker>,
+) -> anyhow::Result<()> {
+	check_pallet_halted::<_, BasicOperatingMode>(client, at, finality.grandpa_pallet, blockers)
+		.await?;
+
+	let (parachains_pallet, parachain) = match finality.parachain {
+		Some(parachain) => parachain,
+		None => {
+			blockers
+				.push(Blocker::HeaderNotFinalized { chain: finality.grandpa_chain, at: C::NAME });
+			return Ok(())
+		},
+	};
+
+	check_pallet_halted::<_, BasicOperatingMode>(client, at, parachains_pallet, blockers).await?;
+
+	// all relay chains that we are bridging with have `u32` block numbers and `H256` hashes
+	let best_relay_block = client
+		.state_call::<_, Option<(u32, H256)>>(at, finality.best_finalized_method.into(), ())
+		.await?
+		.map(|(number, _)| number);
+	let para_info_key = ParasInfoKeyProvider::final_key(parachains_pallet, &ParaId(parachain));
+	let at_relay_block = match client.raw_storage_value(at, para_info_key).await? {
+		Some(para_info) =>
+			Some(ParaInfo::decode(&mut &para_info.0[..])?.best_head_hash.at_relay_block_number),
+		None => None,
+	};
+	blockers.push(parachain_blocker(
+		finality.grandpa_chain,
+		C::NAME,
+		parachain,
+		at_relay_block,
+		best_relay_block,
+	));
+	Ok(())
+}
+
+/// Select between missing relay header and missing parachain head.
+fn parachain_blocker(
+	relay_chain: &'static str,
+	at: &'static str,
+	parachain: u32,
+	at_relay_block: Option<u32>,
+	best_relay_block: Option<u32>,
+) -> Blocker {
+	match best_relay_block {
+		Some(best_relay_block) if at_relay_block.map_or(true, |block| block < best_relay_block) =>
+			Blocker::ParachainHeadMissing { parachain, at, at_relay_block, best_relay_block },
+		_ => Blocker::HeaderNotFinalized { chain: relay_chain, at },
+	}
+}
+
+/// Push the `LowRelayerBalance` blocker if the relayer balance at chain `C` is too low.
+async fn check_relayer_balance<C: ChainWithBalances + ChainWithBridgeTransactions>(
+	client: &impl Client<C>,
+	at: HashOf<C>,
+	relayer: AccountIdOf<C>,
+	min_balance: u128,
+	blockers: &mut Vec<Blocker>,
+) -> anyhow::Result<()> {
+	// transactions are free at chains without transaction payment
+	if !C::HAS_TRANSACTION_PAYMENT {
+		return Ok(())
+	}
+
+	let balance = free_balance(client, &relayer, at).await?;
+	if balance < min_balance {
+		blockers.push(Blocker::LowRelayerBalance { chain: C::NAME, balance, min: min_balance });
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn blockers_are_ranked() {
+		let mut blockers = vec![
+			parachain_blocker("Rococo", "BridgeHubWestend", 1013, Some(10), Some(20)),
+			Blocker::TooManyUnconfirmedMessages { count: 10, max: 10 },
+			parachain_blocker("Rococo", "BridgeHubWestend", 1013, Some(20), Some(20)),
+			Blocker::PalletHalted { chain: "BridgeHubWestend", pallet: "BridgeRococoMessages" },
+		];
+		blockers.sort_by_key(Blocker::rank);
+
+		assert_eq!(
+			blockers,
+			vec![
+				Blocker::PalletHalted { chain: "BridgeHubWestend", pallet: "BridgeRococoMessages" },
+				Blocker::TooManyUnconfirmedMessages { count: 10, max: 10 },
+				Blocker::HeaderNotFinalized { chain: "Rococo", at: "BridgeHubWestend" },
+				Blocker::ParachainHeadMissing {
+					parachain: 1013,
+					at: "BridgeHubWestend",
+					at_relay_block: Some(10),
+					best_relay_block: 20,
+				},
+			],
+		);
+	}
+}
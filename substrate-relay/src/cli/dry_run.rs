@@ -0,0 +1,348 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Dry-run mode of the relay.
+//!
+//! In the dry-run mode, the RPC proxy intercepts transaction submissions. Submitted transaction is
+//! applied to the best block state using the `BlockBuilder_apply_extrinsic` runtime API (the same
+//! way `system_dryRun` does) and its fee is estimated using the `TransactionPaymentApi`. Results
+//! are logged and the relay is told that the transaction has been finalized.
+//!
+//! The chain state is not altered, though. Relay loops are reading the state from nodes, so they
+//! only observe progress that is made by other relayers. When the relay loop sees that its
+//! "finalized" transaction has had no effect, it treats it as lost and submits (and dry-runs) the
+//! same transaction again after the stall timeout.
+
+use crate::cli::LOG_TARGET;
+
+use codec::{Decode, Encode};
+use frame_support::{dispatch::DispatchClass, weights::Weight};
+use serde_json::{json, Value};
+use sp_runtime::ApplyExtrinsicResult;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Prefix of identifiers of requests and subscriptions, generated by the proxy.
+const DRY_RUN_ID_PREFIX: &str = "dry-run-";
+
+/// What the proxy does with the relay request.
+#[derive(Debug, PartialEq)]
+pub enum Intercepted {
+	/// Forward the request to the node.
+	Forward,
+	/// Reply to the relay without contacting the node.
+	Reply(Vec<String>),
+	/// Send own requests to the node and reply to the relay when all responses are received.
+	Query(Vec<String>),
+}
+
+/// Kind of the request, sent by the proxy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Query {
+	ApplyExtrinsic,
+	QueryInfo,
+	FinalizedHead,
+}
+
+/// Transaction that is being dry-run.
+#[derive(Debug)]
+struct PendingDryRun {
+	/// Identifier of the relay request.
+	request_id: Value,
+	/// Whether the relay has subscribed to transaction status updates.
+	watch: bool,
+	/// Encoded transaction.
+	transaction: Vec<u8>,
+	/// Responses to proxy requests.
+	responses: BTreeMap<Query, Result<Value, Value>>,
+	/// Proxy requests, sent for this transaction.
+	queries: BTreeSet<Query>,
+}
+
+/// Dry-run state of the single connection.
+pub struct DryRunState {
+	/// Node endpoint.
+	upstream: String,
+	/// Next identifier of the proxy request or subscription.
+	next_id: u64,
+	/// Proxy request identifier, mapped to the dry-run identifier and request kind.
+	queries: BTreeMap<String, (u64, Query)>,
+	/// Dry-run identifier, mapped to the pending dry-run.
+	pending: BTreeMap<u64, PendingDryRun>,
+}
+
+impl DryRunState {
+	/// Create dry-run state of the connection to given node.
+	pub fn new(upstream: String) -> Self {
+		DryRunState { upstream, next_id: 0, queries: BTreeMap::new(), pending: BTreeMap::new() }
+	}
+
+	/// Process relay request.
+	pub fn on_request(&mut self, request: &str) -> Intercepted {
+		let request: Value = match serde_json::from_str(request) {
+			Ok(request) => request,
+			Err(_) => return Intercepted::Forward,
+		};
+		let request_id = request["id"].clone();
+		let watch = match request["method"].as_str() {
+			Some("author_submitExtrinsic") => false,
+			Some("author_submitAndWatchExtrinsic") => true,
+			Some("author_unwatchExtrinsic") => {
+				let is_own_subscription = request["params"][0]
+					.as_str()
+					.map(|id| id.starts_with(DRY_RUN_ID_PREFIX))
+					.unwrap_or(false);
+				if !is_own_subscription {
+					return Intercepted::Forward
+				}
+				return Intercepted::Reply(vec![response(request_id, Value::Bool(true))])
+			},
+			_ => return Intercepted::Forward,
+		};
+
+		let transaction = match request["params"][0].as_str().and_then(decode_hex) {
+			Some(transaction) => transaction,
+			None => return Intercepted::Forward,
+		};
+
+		let dry_run_id = self.next_id();
+		let mut queries = vec![
+			(
+				Query::ApplyExtrinsic,
+				"state_call",
+				json!(["BlockBuilder_apply_extrinsic", hex(&transaction)]),
+			),
+			(
+				Query::QueryInfo,
+				"state_call",
+				json!([
+					"TransactionPaymentApi_query_info",
+					hex(&[transaction.clone(), (transaction.len() as u32).encode()].concat()),
+				]),
+			),
+		];
+		if watch {
+			queries.push((Query::FinalizedHead, "chain_getFinalizedHead", json!([])));
+		}
+
+		let mut pending = PendingDryRun {
+			request_id,
+			watch,
+			transaction,
+			responses: BTreeMap::new(),
+			queries: BTreeSet::new(),
+		};
+		let requests = queries
+			.into_iter()
+			.map(|(query, method, params)| {
+				let id = format!("{}{}", DRY_RUN_ID_PREFIX, self.next_id());
+				self.queries.insert(id.clone(), (dry_run_id, query));
+				pending.queries.insert(query);
+				json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+					.to_string()
+			})
+			.collect();
+		self.pending.insert(dry_run_id, pending);
+
+		Intercepted::Query(requests)
+	}
+
+	/// Process node response. Returns messages that need to be sent to the relay.
+	pub fn on_response(&mut self, response: String) -> Vec<String> {
+		let parsed: Value = match serde_json::from_str(&response) {
+			Ok(parsed) => parsed,
+			Err(_) => return vec![response],
+		};
+		let (dry_run_id, query) = match parsed["id"].as_str().and_then(|id| self.queries.remove(id))
+		{
+			Some(query) => query,
+			None => return vec![response],
+		};
+
+		let pending = match self.pending.get_mut(&dry_run_id) {
+			Some(pending) => pending,
+			None => return Vec::new(),
+		};
+		let result = match parsed.get("error") {
+			Some(error) => Err(error.clone()),
+			None => Ok(parsed["result"].clone()),
+		};
+		pending.responses.insert(query, result);
+		if pending.responses.len() != pending.queries.len() {
+			return Vec::new()
+		}
+
+		match self.pending.remove(&dry_run_id) {
+			Some(pending) => self.complete(pending),
+			None => Vec::new(),
+		}
+	}
+
+	/// Log dry-run results and tell the relay that the transaction has succeeded.
+	fn complete(&mut self, pending: PendingDryRun) -> Vec<String> {
+		let tx_hash = hex(&sp_core::hashing::blake2_256(&pending.transaction));
+		let apply_result =
+			decode_response::<ApplyExtrinsicResult>(pending.responses.get(&Query::ApplyExtrinsic));
+		let fee = decode_response::<RuntimeDispatchInfo>(pending.responses.get(&Query::QueryInfo))
+			.map(|info| {
+				format!("{} (weight: {:?}, class: {:?})", info.partial_fee, info.weight, info.class)
+			})
+			.unwrap_or_else(|e| format!("unknown ({})", e));
+		match apply_result {
+			Ok(Ok(Ok(()))) => log::info!(
+				target: LOG_TARGET,
+				tx_hash:% = tx_hash;
+				"Dry-run of transaction {} at {} has succeeded. Estimated fee: {}",
+				tx_hash,
+				self.upstream,
+				fee,
+			),
+			Ok(Ok(Err(dispatch_error))) => log::warn!(
+				target: LOG_TARGET,
+				tx_hash:% = tx_hash;
+				"Dry-run of transaction {} at {} has failed with dispatch error: {:?}. \
+				 Estimated fee: {}",
+				tx_hash,
+				self.upstream,
+				dispatch_error,
+				fee,
+			),
+			Ok(Err(validity_error)) => log::warn!(
+				target: LOG_TARGET,
+				tx_hash:% = tx_hash;
+				"Dry-run of transaction {} at {} has failed, because it is invalid: {:?}",
+				tx_hash,
+				self.upstream,
+				validity_error,
+			),
+			Err(e) => log::warn!(
+				target: LOG_TARGET,
+				tx_hash:% = tx_hash;
+				"Failed to dry-run transaction {} at {}: {}",
+				tx_hash,
+				self.upstream,
+				e,
+			),
+		}
+
+		if !pending.watch {
+			return vec![response(pending.request_id, Value::String(tx_hash))]
+		}
+
+		// report that the transaction has been included into the best finalized block, so that
+		// the relay continues as if it has succeeded
+		let subscription = format!("{}{}", DRY_RUN_ID_PREFIX, self.next_id());
+		let finalized_head = pending
+			.responses
+			.get(&Query::FinalizedHead)
+			.and_then(|result| result.clone().ok())
+			.unwrap_or(Value::Null);
+		let mut messages = vec![
+			response(pending.request_id, Value::String(subscription.clone())),
+			notification(&subscription, json!("ready")),
+		];
+		if !finalized_head.is_null() {
+			messages.push(notification(&subscription, json!({ "inBlock": finalized_head })));
+			messages.push(notification(&subscription, json!({ "finalized": finalized_head })));
+		} else {
+			messages.push(notification(&subscription, json!("dropped")));
+		}
+		messages
+	}
+
+	fn next_id(&mut self) -> u64 {
+		self.next_id += 1;
+		self.next_id
+	}
+}
+
+/// Mirrors the `pallet_transaction_payment::RuntimeDispatchInfo<u128>`.
+#[derive(Decode)]
+struct RuntimeDispatchInfo {
+	weight: Weight,
+	class: DispatchClass,
+	partial_fee: u128,
+}
+
+fn decode_response<T: Decode>(response: Option<&Result<Value, Value>>) -> Result<T, String> {
+	match response {
+		Some(Ok(result)) => {
+			let encoded = result
+				.as_str()
+				.and_then(decode_hex)
+				.ok_or_else(|| format!("unexpected response: {}", result))?;
+			T::decode(&mut &encoded[..]).map_err(|e| format!("failed to decode response: {:?}", e))
+		},
+		Some(Err(error)) => Err(format!("node has returned error: {}", error)),
+		None => Err("no response".into()),
+	}
+}
+
+fn response(id: Value, result: Value) -> String {
+	json!({ "jsonrpc": "2.0", "id": id, "result": result }).to_string()
+}
+
+fn notification(subscription: &str, result: Value) -> String {
+	json!({
+		"jsonrpc": "2.0",
+		"method": "author_extrinsicUpdate",
+		"params": { "subscription": subscription, "result": result },
+	})
+	.to_string()
+}
+
+fn hex(data: &[u8]) -> String {
+	format!("0x{}", hex::encode(data))
+}
+
+fn decode_hex(data: &str) -> Option<Vec<u8>> {
+	hex::decode(data.trim_start_matches("0x")).ok()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn submitted_transaction_is_reported_finalized() {
+		let mut state = DryRunState::new("ws://node".into());
+		let requests = match state.on_request(
+			r#"{"jsonrpc":"2.0","id":7,"method":"author_submitAndWatchExtrinsic","params":["0x0102"]}"#,
+		) {
+			Intercepted::Query(requests) => requests,
+			intercepted => panic!("Unexpected {:?}", intercepted),
+		};
+		assert_eq!(requests.len(), 3);
+
+		let mut messages = Vec::new();
+		for (request, result) in requests.iter().zip([
+			hex(&ApplyExtrinsicResult::Ok(Ok(())).encode()),
+			"0x".into(),
+			"0x1234".into(),
+		]) {
+			let request: Value = serde_json::from_str(request).unwrap();
+			messages.extend(state.on_response(response(request["id"].clone(), result.into())));
+		}
+
+		let messages = messages
+			.iter()
+			.map(|m| serde_json::from_str(m).unwrap())
+			.collect::<Vec<Value>>();
+		assert_eq!(messages.len(), 4);
+		assert_eq!(messages[0]["id"], 7);
+		assert_eq!(messages[3]["params"]["result"], json!({ "finalized": "0x1234" }));
+		assert!(state.pending.is_empty());
+	}
+}
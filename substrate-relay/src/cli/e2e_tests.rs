@@ -0,0 +1,361 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! End-to-end tests of relay commands, running against mock nodes.
+//!
+//! Every test starts mock nodes of bridged chains, fills storage of bridge pallets and
+//! runtime APIs with the state of a freshly initialized bridge and then waits until the
+//! relay submits transaction with the data it is expected to relay.
+
+use crate::cli::{
+	relay_headers::RelayHeaders, relay_headers_and_messages::RelayHeadersAndMessagesCommand,
+	relay_messages::RelayMessages, relay_parachains::RelayParachains,
+};
+
+use bp_messages::{
+	InboundLaneData, LegacyLaneId, MessagesOperatingMode, OutboundLaneData, OutboundMessageDetails,
+};
+use bp_polkadot_core::parachains::{ParaHead, ParaId};
+use bp_runtime::BasicOperatingMode;
+use codec::Encode;
+use frame_support::weights::Weight;
+use relay_bridge_hub_rococo_client::BridgeHubRococo;
+use relay_bridge_hub_westend_client::BridgeHubWestend;
+use relay_mock_node::{Header, MockChain, MockNode};
+use relay_rococo_client::Rococo;
+use relay_substrate_client::{Chain, ChainWithGrandpa, ChainWithMessages, Parachain, RelayChain};
+use relay_utils::HeaderId;
+use relay_westend_client::Westend;
+use sp_core::crypto::AccountId32;
+use sp_runtime::traits::Header as _;
+use std::time::Duration;
+use structopt::StructOpt;
+use substrate_relay_helper::parachains::source::parachain_head_storage_key_at_source;
+
+/// Lane of the `BridgeHubRococo` <> `BridgeHubWestend` bridge, used in tests.
+const LANE: [u8; 4] = [0, 0, 0, 2];
+/// Payload of the message, sent over the test lane.
+const MESSAGE_PAYLOAD: &[u8] = b"e2e-test-message-payload";
+/// Name of the operating mode storage value in all bridge pallets.
+const OPERATING_MODE_VALUE_NAME: &str = "PalletOperatingMode";
+/// Maximal time, given to the relay to submit expected transaction.
+const SUBMIT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Creates mock chain with runtime version, used by all relay clients in tests.
+fn mock_chain(name: &str) -> MockChain {
+	let mut chain = MockChain::new(name);
+	chain.set_runtime_version(1, 1);
+	chain
+}
+
+/// Returns header of the best block of the mock chain.
+fn best_header(chain: &MockChain) -> Header {
+	chain
+		.header(chain.best_block_number())
+		.expect("best header always exists")
+		.clone()
+}
+
+/// Produces new block and finalizes it with the valid GRANDPA justification.
+fn produce_justified_block(chain: &mut MockChain) -> Header {
+	chain.produce_block();
+	let header = best_header(chain);
+	let justification = bp_test_utils::make_default_justification(&header);
+	chain.finalize(*header.number(), Some(justification.encode()));
+	header
+}
+
+/// Makes `header` the best finalized header of the `Bridged` chain, known to the mock chain.
+fn set_best_finalized<Bridged: Chain>(chain: &mut MockChain, header: &Header) {
+	let best_finalized = Some(HeaderId(*header.number(), header.hash())).encode();
+	chain.set_runtime_api(Bridged::BEST_FINALIZED_HEADER_ID_METHOD, move |_, _| {
+		Ok(best_finalized.clone())
+	});
+}
+
+/// Sets operating mode of the bridge pallet at the mock chain.
+fn set_operating_mode(chain: &mut MockChain, pallet: &str, mode: impl Encode) {
+	chain.set_storage(
+		bp_runtime::storage_value_final_key(
+			pallet.as_bytes(),
+			OPERATING_MODE_VALUE_NAME.as_bytes(),
+		)
+		.to_vec(),
+		mode.encode(),
+	);
+}
+
+/// Initializes GRANDPA pallet of the `Bridged` chain at the mock chain with `genesis` header.
+fn initialize_grandpa_pallet<Bridged: ChainWithGrandpa>(chain: &mut MockChain, genesis: &Header) {
+	let pallet = Bridged::WITH_CHAIN_GRANDPA_PALLET_NAME;
+	set_operating_mode(chain, pallet, BasicOperatingMode::Normal);
+	chain.set_storage(
+		bp_header_chain::storage_keys::current_authority_set_key(pallet).0,
+		(bp_test_utils::authority_list(), bp_test_utils::TEST_GRANDPA_SET_ID).encode(),
+	);
+	chain
+		.set_runtime_api(&format!("{}FinalityApi_free_headers_interval", Bridged::NAME), |_, _| {
+			Ok(None::<u32>.encode())
+		});
+	set_best_finalized::<Bridged>(chain, genesis);
+}
+
+/// Initializes parachains pallet of the `Relay` chain at the mock chain.
+fn initialize_parachains_pallet<Relay: RelayChain, Para: Parachain>(chain: &mut MockChain) {
+	set_operating_mode(
+		chain,
+		Relay::WITH_CHAIN_BRIDGE_PARACHAINS_PALLET_NAME,
+		BasicOperatingMode::Normal,
+	);
+	chain.set_runtime_api(Para::BEST_FINALIZED_HEADER_ID_METHOD, |_, _| {
+		Ok(None::<HeaderId<relay_mock_node::Hash, u32>>.encode())
+	});
+}
+
+/// Returns identifier of the `Para` chain.
+fn para_id<Para: Parachain>() -> ParaId {
+	ParaId(Para::PARACHAIN_ID)
+}
+
+/// Stores head of the `Para` chain in the `paras` pallet of the relay chain.
+fn set_parachain_head<Relay: RelayChain, Para: Parachain>(chain: &mut MockChain, head: &Header) {
+	chain.set_storage(
+		parachain_head_storage_key_at_source(Relay::PARAS_PALLET_NAME, para_id::<Para>()).0,
+		ParaHead(head.encode()).encode(),
+	);
+}
+
+/// Opens the test lane at the source chain and queues single message there.
+fn queue_message<Target: ChainWithMessages>(chain: &mut MockChain, bridged_genesis: &Header) {
+	let lane = LegacyLaneId(LANE);
+	let pallet = Target::WITH_CHAIN_MESSAGES_PALLET_NAME;
+	set_operating_mode(chain, pallet, MessagesOperatingMode::Basic(BasicOperatingMode::Normal));
+	chain.set_storage(
+		bp_messages::storage_keys::outbound_lane_data_key(pallet, &lane).0,
+		OutboundLaneData { latest_generated_nonce: 1, ..Default::default() }.encode(),
+	);
+	chain.set_storage(
+		bp_messages::storage_keys::message_key(pallet, &lane, 1).0,
+		MESSAGE_PAYLOAD.to_vec().encode(),
+	);
+	let details = vec![OutboundMessageDetails {
+		nonce: 1,
+		dispatch_weight: Weight::from_parts(1_000, 0),
+		size: MESSAGE_PAYLOAD.len() as u32,
+	}]
+	.encode();
+	chain.set_runtime_api(Target::TO_CHAIN_MESSAGE_DETAILS_METHOD, move |_, _| Ok(details.clone()));
+	set_best_finalized::<Target>(chain, bridged_genesis);
+}
+
+/// Opens the test lane at the target chain.
+fn open_inbound_lane<Source: ChainWithMessages>(chain: &mut MockChain) {
+	let lane = LegacyLaneId(LANE);
+	let pallet = Source::WITH_CHAIN_MESSAGES_PALLET_NAME;
+	set_operating_mode(chain, pallet, MessagesOperatingMode::Basic(BasicOperatingMode::Normal));
+	chain.set_storage(
+		bp_messages::storage_keys::inbound_lane_data_key(pallet, &lane).0,
+		InboundLaneData::<AccountId32>::default().encode(),
+	);
+}
+
+/// Returns command line of the relay command, connected to given mock nodes.
+fn command_line(command: &[&str], nodes: &[(&str, &MockNode)]) -> Vec<String> {
+	let mut args = vec!["substrate-relay".to_string()];
+	args.extend(command.iter().map(|arg| arg.to_string()));
+	for (prefix, node) in nodes {
+		args.extend([
+			format!("--{prefix}-uri"),
+			node.uri(),
+			format!("--{prefix}-version-mode"),
+			"Auto".into(),
+		]);
+	}
+	args.push("--no-prometheus".into());
+	args
+}
+
+/// Waits until transaction, containing `expected` bytes, is submitted to the mock node.
+async fn wait_for_submission(node: &MockNode, expected: &[u8]) {
+	let is_submitted = || {
+		node.chain()
+			.submitted_extrinsics()
+			.iter()
+			.any(|tx| tx.windows(expected.len()).any(|window| window == expected))
+	};
+	async_std::future::timeout(SUBMIT_TIMEOUT, async {
+		while !is_submitted() {
+			async_std::task::sleep(Duration::from_millis(100)).await;
+		}
+	})
+	.await
+	.unwrap_or_else(|_| {
+		panic!(
+			"{} has not received expected transaction in {SUBMIT_TIMEOUT:?}",
+			node.chain().name()
+		)
+	});
+}
+
+/// Starts the relay command in background.
+fn spawn_relay(relay: impl std::future::Future<Output = anyhow::Result<()>> + Send + 'static) {
+	async_std::task::spawn(async move {
+		if let Err(e) = relay.await {
+			panic!("Relay has failed: {e:?}");
+		}
+	});
+}
+
+#[async_std::test]
+async fn relay_headers_submits_justified_header() {
+	let mut rococo = mock_chain("rococo");
+	let genesis = best_header(&rococo);
+	let header = produce_justified_block(&mut rococo);
+	let rococo = MockNode::start(rococo).await.unwrap();
+
+	let mut bridge_hub_westend = mock_chain("bridge-hub-westend");
+	initialize_grandpa_pallet::<Rococo>(&mut bridge_hub_westend, &genesis);
+	let bridge_hub_westend = MockNode::start(bridge_hub_westend).await.unwrap();
+
+	let relay = RelayHeaders::from_iter(command_line(
+		&["rococo-to-bridge-hub-westend", "--target-signer", "//Alice"],
+		&[("source", &rococo), ("target", &bridge_hub_westend)],
+	));
+	spawn_relay(relay.run());
+
+	wait_for_submission(&bridge_hub_westend, &header.encode()).await;
+}
+
+#[async_std::test]
+async fn relay_parachains_submits_parachain_head() {
+	let para_head = best_header(&mock_chain("bridge-hub-rococo"));
+
+	let mut rococo = mock_chain("rococo");
+	let genesis = best_header(&rococo);
+	set_parachain_head::<Rococo, BridgeHubRococo>(&mut rococo, &para_head);
+	let relay_header = produce_justified_block(&mut rococo);
+	let rococo = MockNode::start(rococo).await.unwrap();
+
+	let mut bridge_hub_westend = mock_chain("bridge-hub-westend");
+	initialize_grandpa_pallet::<Rococo>(&mut bridge_hub_westend, &genesis);
+	initialize_parachains_pallet::<Rococo, BridgeHubRococo>(&mut bridge_hub_westend);
+	set_best_finalized::<Rococo>(&mut bridge_hub_westend, &relay_header);
+	let bridge_hub_westend = MockNode::start(bridge_hub_westend).await.unwrap();
+
+	let relay = RelayParachains::from_iter(command_line(
+		&["rococo-to-bridge-hub-westend", "--target-signer", "//Alice"],
+		&[("source", &rococo), ("target", &bridge_hub_westend)],
+	));
+	spawn_relay(relay.run());
+
+	let head_hash = ParaHead(para_head.encode()).hash();
+	wait_for_submission(
+		&bridge_hub_westend,
+		&vec![(para_id::<BridgeHubRococo>(), head_hash)].encode(),
+	)
+	.await;
+}
+
+#[async_std::test]
+async fn relay_messages_delivers_queued_message() {
+	let mut bridge_hub_westend = mock_chain("bridge-hub-westend");
+	let target_genesis = best_header(&bridge_hub_westend);
+
+	let mut bridge_hub_rococo = mock_chain("bridge-hub-rococo");
+	queue_message::<BridgeHubWestend>(&mut bridge_hub_rococo, &target_genesis);
+	bridge_hub_rococo.produce_finalized_block(None);
+	let message_header = best_header(&bridge_hub_rococo);
+	let bridge_hub_rococo = MockNode::start(bridge_hub_rococo).await.unwrap();
+
+	open_inbound_lane::<BridgeHubRococo>(&mut bridge_hub_westend);
+	set_best_finalized::<BridgeHubRococo>(&mut bridge_hub_westend, &message_header);
+	let bridge_hub_westend = MockNode::start(bridge_hub_westend).await.unwrap();
+
+	let relay = RelayMessages::from_iter(command_line(
+		&[
+			"bridge-hub-rococo-to-bridge-hub-westend",
+			"--lane",
+			&hex::encode(LANE),
+			"--source-signer",
+			"//Alice",
+			"--target-signer",
+			"//Alice",
+		],
+		&[("source", &bridge_hub_rococo), ("target", &bridge_hub_westend)],
+	));
+	spawn_relay(relay.run());
+
+	wait_for_submission(&bridge_hub_westend, MESSAGE_PAYLOAD).await;
+}
+
+#[async_std::test]
+async fn relay_headers_and_messages_delivers_queued_message() {
+	let mut bridge_hub_westend = mock_chain("bridge-hub-westend");
+	let bridge_hub_westend_genesis = best_header(&bridge_hub_westend);
+
+	let mut bridge_hub_rococo = mock_chain("bridge-hub-rococo");
+	queue_message::<BridgeHubWestend>(&mut bridge_hub_rococo, &bridge_hub_westend_genesis);
+	open_inbound_lane::<BridgeHubWestend>(&mut bridge_hub_rococo);
+	bridge_hub_rococo.produce_finalized_block(None);
+	let message_header = best_header(&bridge_hub_rococo);
+
+	let mut rococo = mock_chain("rococo");
+	let rococo_genesis = best_header(&rococo);
+	set_parachain_head::<Rococo, BridgeHubRococo>(&mut rococo, &message_header);
+	let rococo_header = produce_justified_block(&mut rococo);
+
+	let mut westend = mock_chain("westend");
+	let westend_genesis = best_header(&westend);
+	set_parachain_head::<Westend, BridgeHubWestend>(&mut westend, &bridge_hub_westend_genesis);
+	produce_justified_block(&mut westend);
+
+	open_inbound_lane::<BridgeHubRococo>(&mut bridge_hub_westend);
+	initialize_grandpa_pallet::<Rococo>(&mut bridge_hub_westend, &rococo_genesis);
+	initialize_parachains_pallet::<Rococo, BridgeHubRococo>(&mut bridge_hub_westend);
+	set_best_finalized::<Rococo>(&mut bridge_hub_westend, &rococo_header);
+	set_best_finalized::<BridgeHubRococo>(&mut bridge_hub_westend, &message_header);
+	bridge_hub_westend.produce_finalized_block(None);
+
+	initialize_grandpa_pallet::<Westend>(&mut bridge_hub_rococo, &westend_genesis);
+	initialize_parachains_pallet::<Westend, BridgeHubWestend>(&mut bridge_hub_rococo);
+	set_best_finalized::<BridgeHubWestend>(&mut bridge_hub_rococo, &bridge_hub_westend_genesis);
+
+	let rococo = MockNode::start(rococo).await.unwrap();
+	let westend = MockNode::start(westend).await.unwrap();
+	let bridge_hub_rococo = MockNode::start(bridge_hub_rococo).await.unwrap();
+	let bridge_hub_westend = MockNode::start(bridge_hub_westend).await.unwrap();
+
+	let relay = RelayHeadersAndMessagesCommand::from_iter(command_line(
+		&[
+			"bridge-hub-rococo-bridge-hub-westend",
+			"--lane",
+			&hex::encode(LANE),
+			"--bridge-hub-rococo-signer",
+			"//Alice",
+			"--bridge-hub-westend-signer",
+			"//Alice",
+		],
+		&[
+			("rococo", &rococo),
+			("westend", &westend),
+			("bridge-hub-rococo", &bridge_hub_rococo),
+			("bridge-hub-westend", &bridge_hub_westend),
+		],
+	));
+	spawn_relay(relay.run());
+
+	wait_for_submission(&bridge_hub_westend, MESSAGE_PAYLOAD).await;
+}
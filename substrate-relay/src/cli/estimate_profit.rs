@@ -0,0 +1,672 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Estimation of relaying economics.
+//!
+//! For the `Source -> Target` messages bridge, we are looking for bridge transactions in given
+//! ranges of blocks:
+//!
+//! - at the target chain: `submit_finality_proof_ex`, `submit_parachain_heads_ex` and
+//!   `receive_messages_proof`;
+//! - at the source chain: `receive_messages_delivery_proof`.
+//!
+//! Fee of every bridge call is estimated using the `TransactionPaymentCallApi` at the block where
+//! it has been included. Calls of utility batches are estimated separately. Estimated fees are
+//! compared with rewards that have been registered by the `pallet_bridge_relayers` in the same
+//! blocks: rewards for delivery at the target chain and rewards for confirmations at the source
+//! chain. Finality and parachain transactions are not bound to any lane, so their fees are only
+//! included in the direction total.
+
+use crate::{
+	bridges::{
+		kusama_polkadot::{
+			bridge_hub_kusama_messages_to_bridge_hub_polkadot::BridgeHubKusamaToBridgeHubPolkadotMessagesCliBridge,
+			bridge_hub_polkadot_messages_to_bridge_hub_kusama::BridgeHubPolkadotToBridgeHubKusamaMessagesCliBridge,
+		},
+		polkadot_bulletin::{
+			bridge_hub_polkadot_messages_to_polkadot_bulletin::BridgeHubPolkadotToPolkadotBulletinMessagesCliBridge,
+			polkadot_bulletin_messages_to_bridge_hub_polkadot::PolkadotBulletinToBridgeHubPolkadotMessagesCliBridge,
+		},
+		rococo_bulletin::{
+			bridge_hub_rococo_messages_to_rococo_bulletin::BridgeHubRococoToRococoBulletinMessagesCliBridge,
+			rococo_bulletin_messages_to_bridge_hub_rococo::RococoBulletinToBridgeHubRococoMessagesCliBridge,
+			BridgeHubRococoAsBridgeHubPolkadot,
+		},
+		rococo_westend::{
+			bridge_hub_rococo_messages_to_bridge_hub_westend::BridgeHubRococoToBridgeHubWestendMessagesCliBridge,
+			bridge_hub_westend_messages_to_bridge_hub_rococo::BridgeHubWestendToBridgeHubRococoMessagesCliBridge,
+		},
+	},
+	cli::{relay_messages::FullBridge, LOG_TARGET},
+};
+
+use bp_runtime::{Chain as ChainBase, ChainId};
+use codec::{Decode, Encode};
+use relay_bridge_hub_kusama_client::BridgeHubKusama;
+use relay_bridge_hub_polkadot_client::BridgeHubPolkadot;
+use relay_bridge_hub_rococo_client::BridgeHubRococo;
+use relay_bridge_hub_westend_client::BridgeHubWestend;
+use relay_polkadot_bulletin_client::PolkadotBulletin;
+use relay_substrate_client::{
+	BlockWithJustification, CallOf, Chain, ChainWithBalances, ChainWithTransactions, Client, HashOf,
+};
+use sp_core::{storage::StorageKey, H256};
+use std::{collections::BTreeMap, str::FromStr};
+use structopt::StructOpt;
+use strum::VariantNames;
+use substrate_relay_helper::cli::{
+	bridge::{CliBridgeBase, MessagesCliBridge},
+	chain_schema::{SourceConnectionParams, TargetConnectionParams},
+};
+
+/// Name of the runtime API method that is used to estimate call fees.
+const QUERY_CALL_FEE_DETAILS_METHOD: &str = "TransactionPaymentCallApi_query_call_fee_details";
+
+/// Estimate relaying profit of the messages bridge.
+#[derive(StructOpt)]
+pub struct EstimateProfit {
+	/// A bridge instance to estimate profit for.
+	#[structopt(possible_values = FullBridge::VARIANTS, case_insensitive = true)]
+	bridge: FullBridge,
+	/// Range of source chain blocks (`<first>..<last>`, inclusive), where delivery confirmations
+	/// are looked for.
+	#[structopt(long)]
+	source_blocks: BlockRange,
+	/// Range of target chain blocks (`<first>..<last>`, inclusive), where finality, parachain
+	/// and messages delivery transactions are looked for.
+	#[structopt(long)]
+	target_blocks: BlockRange,
+	#[structopt(flatten)]
+	source: SourceConnectionParams,
+	#[structopt(flatten)]
+	target: TargetConnectionParams,
+}
+
+/// Inclusive range of block numbers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlockRange {
+	first: u32,
+	last: u32,
+}
+
+impl FromStr for BlockRange {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (first, last) = s
+			.split_once("..")
+			.ok_or_else(|| anyhow::format_err!("Expected block range `<first>..<last>`"))?;
+		let range = BlockRange { first: first.parse()?, last: last.parse()? };
+		if range.first > range.last {
+			return Err(anyhow::format_err!("First block of the range is after the last block"))
+		}
+		Ok(range)
+	}
+}
+
+/// Type of the bridge transaction (call).
+#[derive(Clone, Debug, PartialEq)]
+pub enum TransactionType {
+	/// `submit_finality_proof_ex` call.
+	FinalityProof,
+	/// `submit_parachain_heads_ex` call.
+	ParachainHeads,
+	/// `receive_messages_proof` call at given (hex-encoded) lane.
+	MessagesProof(String),
+	/// `receive_messages_delivery_proof` call at given (hex-encoded) lane.
+	MessagesDeliveryProof(String),
+}
+
+impl TransactionType {
+	fn call_name(&self) -> &'static str {
+		match *self {
+			Self::FinalityProof => "submit_finality_proof_ex",
+			Self::ParachainHeads => "submit_parachain_heads_ex",
+			Self::MessagesProof(_) => "receive_messages_proof",
+			Self::MessagesDeliveryProof(_) => "receive_messages_delivery_proof",
+		}
+	}
+
+	fn lane(&self) -> Option<&str> {
+		match *self {
+			Self::FinalityProof | Self::ParachainHeads => None,
+			Self::MessagesProof(ref lane) | Self::MessagesDeliveryProof(ref lane) => Some(lane),
+		}
+	}
+}
+
+/// Owner of the account that pays relayer rewards. Mirrors the `bp_relayers::RewardsAccountOwner`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RewardsAccountOwner {
+	/// Reward for delivering confirmations of messages, sent by this chain.
+	ThisChain,
+	/// Reward for delivering messages, sent by the bridged chain.
+	BridgedChain,
+}
+
+/// Reward, registered by the `pallet_bridge_relayers`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegisteredReward {
+	/// Hex-encoded lane identifier.
+	pub lane: String,
+	/// Rewards account owner.
+	pub owner: RewardsAccountOwner,
+	/// Reward amount.
+	pub reward: u128,
+}
+
+/// Fee details, returned by the `TransactionPaymentCallApi`. Mirrors the
+/// `pallet_transaction_payment::FeeDetails<u128>`.
+#[derive(Decode)]
+struct FeeDetails {
+	inclusion_fee: Option<InclusionFee>,
+	_tip: u128,
+}
+
+#[derive(Decode)]
+struct InclusionFee {
+	base_fee: u128,
+	len_fee: u128,
+	adjusted_weight_fee: u128,
+}
+
+/// Chain, where relayers are submitting bridge transactions.
+pub trait ChainWithBridgeTransactions: ChainWithBalances + ChainWithTransactions {
+	/// Whether the chain charges transaction fees.
+	const HAS_TRANSACTION_PAYMENT: bool = true;
+
+	/// Decode call of the encoded transaction.
+	fn decode_call(transaction: &[u8]) -> Option<CallOf<Self>>;
+	/// Select calls that are serving the bridge with the `bridged_chain`. Calls of utility
+	/// batches are selected separately.
+	fn bridge_calls(
+		call: CallOf<Self>,
+		bridged_chain: ChainId,
+	) -> Vec<(TransactionType, CallOf<Self>)>;
+	/// Select rewards for serving the bridge with the `bridged_chain` from the encoded
+	/// `System::Events` storage value.
+	fn registered_rewards(
+		events: &[u8],
+		bridged_chain: ChainId,
+	) -> anyhow::Result<Vec<RegisteredReward>>;
+}
+
+/// Implement `ChainWithBridgeTransactions` for a bridge hub, using its generated runtime types.
+macro_rules! impl_bridge_hub_with_bridge_transactions {
+	(
+		$chain:ty,
+		$client:ident,
+		$runtime:ident,
+		relayers: [$($relayers:ident),*],
+		bridges: [
+			$($bridged:ty => {
+				grandpa: $grandpa:ident,
+				$(parachains: $parachains:ident,)?
+				messages: $messages:ident $(,)?
+			}),* $(,)?
+		] $(,)?
+	) => {
+		impl ChainWithBridgeTransactions for $chain {
+			fn decode_call(transaction: &[u8]) -> Option<CallOf<Self>> {
+				<Self as ChainWithTransactions>::SignedTransaction::decode(&mut &transaction[..])
+					.ok()
+					.map(|transaction| transaction.function)
+			}
+
+			fn bridge_calls(
+				call: CallOf<Self>,
+				bridged_chain: ChainId,
+			) -> Vec<(TransactionType, CallOf<Self>)> {
+				use $client::{
+					runtime_types::{
+						pallet_bridge_grandpa::pallet::Call as GrandpaCall,
+						pallet_bridge_messages::pallet::Call as MessagesCall,
+						pallet_bridge_parachains::pallet::Call as ParachainsCall,
+						pallet_utility::pallet::Call as UtilityCall,
+					},
+					RuntimeCall,
+				};
+
+				let transaction_type = match call {
+					RuntimeCall::Utility(UtilityCall::batch { ref calls }) |
+					RuntimeCall::Utility(UtilityCall::batch_all { ref calls }) |
+					RuntimeCall::Utility(UtilityCall::force_batch { ref calls }) =>
+						return calls
+							.iter()
+							.cloned()
+							.flat_map(|call| Self::bridge_calls(call, bridged_chain))
+							.collect(),
+					$(
+						RuntimeCall::$grandpa(GrandpaCall::submit_finality_proof_ex { .. })
+							if bridged_chain == <$bridged as ChainBase>::ID =>
+							TransactionType::FinalityProof,
+						$(
+							RuntimeCall::$parachains(ParachainsCall::submit_parachain_heads_ex { .. })
+								if bridged_chain == <$bridged as ChainBase>::ID =>
+								TransactionType::ParachainHeads,
+						)?
+						RuntimeCall::$messages(MessagesCall::receive_messages_proof { ref proof, .. })
+							if bridged_chain == <$bridged as ChainBase>::ID =>
+							TransactionType::MessagesProof(hex::encode(proof.lane.encode())),
+						RuntimeCall::$messages(MessagesCall::receive_messages_delivery_proof {
+							ref proof,
+							..
+						}) if bridged_chain == <$bridged as ChainBase>::ID =>
+							TransactionType::MessagesDeliveryProof(hex::encode(proof.lane.encode())),
+					)*
+					_ => return Vec::new(),
+				};
+				vec![(transaction_type, call)]
+			}
+
+			fn registered_rewards(
+				events: &[u8],
+				bridged_chain: ChainId,
+			) -> anyhow::Result<Vec<RegisteredReward>> {
+				use $client::runtime_types::{
+					bp_relayers::RewardsAccountOwner as RuntimeRewardsAccountOwner,
+					frame_system::EventRecord,
+					pallet_bridge_relayers::pallet::Event as RelayersEvent,
+					$runtime::RuntimeEvent,
+				};
+
+				let events = Vec::<EventRecord<RuntimeEvent, H256>>::decode(&mut &events[..])?;
+				Ok(events
+					.into_iter()
+					.filter_map(|record| match record.event {
+						$(
+							RuntimeEvent::$relayers(RelayersEvent::RewardRegistered {
+								rewards_account_params,
+								reward,
+								..
+							}) => Some((rewards_account_params, reward)),
+						)*
+						_ => None,
+					})
+					.filter(|(params, _)| params.bridged_chain_id == bridged_chain)
+					.map(|(params, reward)| RegisteredReward {
+						lane: hex::encode(params.lane_id.encode()),
+						owner: match params.owner {
+							RuntimeRewardsAccountOwner::ThisChain => RewardsAccountOwner::ThisChain,
+							RuntimeRewardsAccountOwner::BridgedChain =>
+								RewardsAccountOwner::BridgedChain,
+						},
+						reward,
+					})
+					.collect())
+			}
+		}
+	};
+}
+
+impl_bridge_hub_with_bridge_transactions!(
+	BridgeHubRococo,
+	relay_bridge_hub_rococo_client,
+	bridge_hub_rococo_runtime,
+	relayers: [BridgeRelayers, BridgeRelayersForPermissionlessLanes],
+	bridges: [
+		BridgeHubWestend => {
+			grandpa: BridgeWestendGrandpa,
+			parachains: BridgeWestendParachains,
+			messages: BridgeWestendMessages,
+		},
+		PolkadotBulletin => {
+			grandpa: BridgePolkadotBulletinGrandpa,
+			messages: BridgePolkadotBulletinMessages,
+		},
+	],
+);
+
+impl_bridge_hub_with_bridge_transactions!(
+	BridgeHubWestend,
+	relay_bridge_hub_westend_client,
+	bridge_hub_westend_runtime,
+	relayers: [BridgeRelayers],
+	bridges: [
+		BridgeHubRococo => {
+			grandpa: BridgeRococoGrandpa,
+			parachains: BridgeRococoParachains,
+			messages: BridgeRococoMessages,
+		},
+	],
+);
+
+impl_bridge_hub_with_bridge_transactions!(
+	BridgeHubKusama,
+	relay_bridge_hub_kusama_client,
+	bridge_hub_kusama_runtime,
+	relayers: [BridgeRelayers],
+	bridges: [
+		BridgeHubPolkadot => {
+			grandpa: BridgePolkadotGrandpa,
+			parachains: BridgePolkadotParachains,
+			messages: BridgePolkadotMessages,
+		},
+	],
+);
+
+// TODO: https://github.com/paritytech/parity-bridges-common/issues/2547 - add Bulletin pallets
+// when the runtime is regenerated
+impl_bridge_hub_with_bridge_transactions!(
+	BridgeHubPolkadot,
+	relay_bridge_hub_polkadot_client,
+	bridge_hub_polkadot_runtime,
+	relayers: [BridgeRelayers],
+	bridges: [
+		BridgeHubKusama => {
+			grandpa: BridgeKusamaGrandpa,
+			parachains: BridgeKusamaParachains,
+			messages: BridgeKusamaMessages,
+		},
+	],
+);
+
+impl ChainWithBridgeTransactions for BridgeHubRococoAsBridgeHubPolkadot {
+	fn decode_call(transaction: &[u8]) -> Option<CallOf<Self>> {
+		BridgeHubRococo::decode_call(transaction)
+	}
+
+	fn bridge_calls(
+		call: CallOf<Self>,
+		bridged_chain: ChainId,
+	) -> Vec<(TransactionType, CallOf<Self>)> {
+		BridgeHubRococo::bridge_calls(call, bridged_chain)
+	}
+
+	fn registered_rewards(
+		events: &[u8],
+		bridged_chain: ChainId,
+	) -> anyhow::Result<Vec<RegisteredReward>> {
+		BridgeHubRococo::registered_rewards(events, bridged_chain)
+	}
+}
+
+impl ChainWithBridgeTransactions for PolkadotBulletin {
+	// there are no fees and no relayer rewards at the Bulletin chain
+	const HAS_TRANSACTION_PAYMENT: bool = false;
+
+	fn decode_call(transaction: &[u8]) -> Option<CallOf<Self>> {
+		<Self as ChainWithTransactions>::SignedTransaction::decode(&mut &transaction[..])
+			.ok()
+			.map(|transaction| transaction.function)
+	}
+
+	fn bridge_calls(
+		call: CallOf<Self>,
+		_bridged_chain: ChainId,
+	) -> Vec<(TransactionType, CallOf<Self>)> {
+		use relay_polkadot_bulletin_client::{
+			BridgePolkadotGrandpaCall, BridgePolkadotMessagesCall, BridgePolkadotParachainsCall,
+			RuntimeCall,
+		};
+
+		// Bulletin chain is bridged with a single chain, so all bridge calls are selected
+		let transaction_type = match call {
+			RuntimeCall::BridgePolkadotGrandpa(
+				BridgePolkadotGrandpaCall::submit_finality_proof_ex { .. },
+			) => TransactionType::FinalityProof,
+			RuntimeCall::BridgePolkadotParachains(
+				BridgePolkadotParachainsCall::submit_parachain_heads_ex { .. },
+			) => TransactionType::ParachainHeads,
+			RuntimeCall::BridgePolkadotMessages(
+				BridgePolkadotMessagesCall::receive_messages_proof { ref proof, .. },
+			) => TransactionType::MessagesProof(hex::encode(proof.lane.encode())),
+			RuntimeCall::BridgePolkadotMessages(
+				BridgePolkadotMessagesCall::receive_messages_delivery_proof { ref proof, .. },
+			) => TransactionType::MessagesDeliveryProof(hex::encode(proof.lane.encode())),
+			_ => return Vec::new(),
+		};
+		vec![(transaction_type, call)]
+	}
+
+	fn registered_rewards(
+		_events: &[u8],
+		_bridged_chain: ChainId,
+	) -> anyhow::Result<Vec<RegisteredReward>> {
+		Ok(Vec::new())
+	}
+}
+
+/// Fees and rewards of relayers.
+#[derive(Debug, Default, PartialEq)]
+struct Economics {
+	/// Estimated fees, mapped by the call name.
+	fees: BTreeMap<&'static str, u128>,
+	/// Registered rewards, mapped by the rewards account owner.
+	rewards: BTreeMap<&'static str, u128>,
+}
+
+impl Economics {
+	fn add_fee(&mut self, call_name: &'static str, fee: u128) {
+		*self.fees.entry(call_name).or_default() += fee;
+	}
+
+	fn add_reward(&mut self, owner: RewardsAccountOwner, reward: u128) {
+		let reward_kind = match owner {
+			RewardsAccountOwner::BridgedChain => "delivery",
+			RewardsAccountOwner::ThisChain => "confirmation",
+		};
+		*self.rewards.entry(reward_kind).or_default() += reward;
+	}
+
+	fn profit(&self) -> i128 {
+		self.rewards.values().sum::<u128>() as i128 - self.fees.values().sum::<u128>() as i128
+	}
+
+	fn print(&self, title: &str) {
+		println!("{}:", title);
+		for (call_name, fee) in &self.fees {
+			println!("  fees for {}: {}", call_name, fee);
+		}
+		for (reward_kind, reward) in &self.rewards {
+			println!("  rewards for {}: {}", reward_kind, reward);
+		}
+		println!("  profit: {}", self.profit());
+	}
+}
+
+/// Relaying economics of the messages bridge.
+#[derive(Debug, Default, PartialEq)]
+struct Report {
+	/// Economics of every lane.
+	lanes: BTreeMap<String, Economics>,
+	/// Economics of the whole direction, including transactions that are not bound to lanes.
+	total: Economics,
+}
+
+impl Report {
+	fn add_fee(&mut self, transaction_type: &TransactionType, fee: u128) {
+		self.total.add_fee(transaction_type.call_name(), fee);
+		if let Some(lane) = transaction_type.lane() {
+			self.lanes
+				.entry(lane.into())
+				.or_default()
+				.add_fee(transaction_type.call_name(), fee);
+		}
+	}
+
+	fn add_reward(&mut self, reward: &RegisteredReward) {
+		self.total.add_reward(reward.owner, reward.reward);
+		self.lanes
+			.entry(reward.lane.clone())
+			.or_default()
+			.add_reward(reward.owner, reward.reward);
+	}
+}
+
+impl EstimateProfit {
+	/// Name of the bridge.
+	pub fn bridge_name(&self) -> String {
+		format!("{:?}", self.bridge)
+	}
+
+	/// Run the command.
+	pub async fn run(self) -> anyhow::Result<()> {
+		match self.bridge {
+			FullBridge::BridgeHubRococoToBridgeHubWestend =>
+				estimate_profit::<BridgeHubRococoToBridgeHubWestendMessagesCliBridge>(self).await,
+			FullBridge::BridgeHubWestendToBridgeHubRococo =>
+				estimate_profit::<BridgeHubWestendToBridgeHubRococoMessagesCliBridge>(self).await,
+			FullBridge::BridgeHubKusamaToBridgeHubPolkadot =>
+				estimate_profit::<BridgeHubKusamaToBridgeHubPolkadotMessagesCliBridge>(self).await,
+			FullBridge::BridgeHubPolkadotToBridgeHubKusama =>
+				estimate_profit::<BridgeHubPolkadotToBridgeHubKusamaMessagesCliBridge>(self).await,
+			FullBridge::PolkadotBulletinToBridgeHubPolkadot =>
+				estimate_profit::<PolkadotBulletinToBridgeHubPolkadotMessagesCliBridge>(self).await,
+			FullBridge::BridgeHubPolkadotToPolkadotBulletin =>
+				estimate_profit::<BridgeHubPolkadotToPolkadotBulletinMessagesCliBridge>(self).await,
+			FullBridge::RococoBulletinToBridgeHubRococo =>
+				estimate_profit::<RococoBulletinToBridgeHubRococoMessagesCliBridge>(self).await,
+			FullBridge::BridgeHubRococoToRococoBulletin =>
+				estimate_profit::<BridgeHubRococoToRococoBulletinMessagesCliBridge>(self).await,
+		}
+	}
+}
+
+async fn estimate_profit<Bridge: MessagesCliBridge>(params: EstimateProfit) -> anyhow::Result<()>
+where
+	Bridge::Source: ChainWithBridgeTransactions,
+	Bridge::Target: ChainWithBridgeTransactions,
+{
+	let source_client = params.source.into_client::<Bridge::Source>().await?;
+	let target_client = params.target.into_client::<Bridge::Target>().await?;
+
+	let mut report = Report::default();
+	scan_blocks::<Bridge::Target>(
+		&target_client,
+		params.target_blocks,
+		<Bridge::Source as ChainBase>::ID,
+		|transaction_type| !matches!(transaction_type, TransactionType::MessagesDeliveryProof(_)),
+		RewardsAccountOwner::BridgedChain,
+		&mut report,
+	)
+	.await?;
+	scan_blocks::<Bridge::Source>(
+		&source_client,
+		params.source_blocks,
+		<Bridge::Target as ChainBase>::ID,
+		|transaction_type| matches!(transaction_type, TransactionType::MessagesDeliveryProof(_)),
+		RewardsAccountOwner::ThisChain,
+		&mut report,
+	)
+	.await?;
+
+	let direction = format!("{} -> {}", Bridge::Source::NAME, Bridge::Target::NAME);
+	for (lane, economics) in &report.lanes {
+		economics.print(&format!("{}, lane {}", direction, lane));
+	}
+	report.total.print(&direction);
+
+	Ok(())
+}
+
+/// Estimate fees of selected bridge transactions and collect rewards of given owner in the range
+/// of blocks.
+async fn scan_blocks<C: ChainWithBridgeTransactions>(
+	client: &impl Client<C>,
+	range: BlockRange,
+	bridged_chain: ChainId,
+	select: impl Fn(&TransactionType) -> bool,
+	rewards_owner: RewardsAccountOwner,
+	report: &mut Report,
+) -> anyhow::Result<()> {
+	let events_key = StorageKey(bp_runtime::storage_value_final_key(b"System", b"Events").to_vec());
+	for number in range.first..=range.last {
+		let hash = client.header_hash_by_number(number.into()).await?;
+		let block = client.block_by_hash(hash).await?;
+
+		for transaction in block.extrinsics() {
+			let call = match C::decode_call(&transaction) {
+				Some(call) => call,
+				None => continue,
+			};
+			for (transaction_type, call) in C::bridge_calls(call, bridged_chain) {
+				if !select(&transaction_type) {
+					continue
+				}
+
+				let fee = estimate_call_fee(client, hash, call).await?;
+				report.add_fee(&transaction_type, fee);
+			}
+		}
+
+		let events = client.raw_storage_value(hash, events_key.clone()).await?;
+		let rewards = events
+			.map(|events| C::registered_rewards(&events.0, bridged_chain))
+			.transpose()
+			.unwrap_or_else(|e| {
+				log::warn!(
+					target: LOG_TARGET,
+					"Failed to decode events of {} block {}: {}",
+					C::NAME,
+					number,
+					e,
+				);
+				None
+			})
+			.unwrap_or_default();
+		for reward in rewards.iter().filter(|reward| reward.owner == rewards_owner) {
+			report.add_reward(reward);
+		}
+
+		log::debug!(target: LOG_TARGET, "Processed {} block {}", C::NAME, number);
+	}
+
+	Ok(())
+}
+
+/// Estimate fee of the call, as if it is submitted in a separate transaction.
+pub async fn estimate_call_fee<C: ChainWithBridgeTransactions>(
+	client: &impl Client<C>,
+	at: HashOf<C>,
+	call: CallOf<C>,
+) -> anyhow::Result<u128> {
+	if !C::HAS_TRANSACTION_PAYMENT {
+		return Ok(0)
+	}
+
+	let call_len = call.encoded_size() as u32;
+	let fee_details: FeeDetails = client
+		.state_call(at, QUERY_CALL_FEE_DETAILS_METHOD.into(), (call, call_len))
+		.await?;
+	Ok(fee_details
+		.inclusion_fee
+		.map(|fee| fee.base_fee + fee.len_fee + fee.adjusted_weight_fee)
+		.unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn report_is_split_by_lanes() {
+		let mut report = Report::default();
+		report.add_fee(&TransactionType::FinalityProof, 10);
+		report.add_fee(&TransactionType::MessagesProof("00000001".into()), 20);
+		report.add_fee(&TransactionType::MessagesDeliveryProof("00000001".into()), 5);
+		report.add_reward(&RegisteredReward {
+			lane: "00000001".into(),
+			owner: RewardsAccountOwner::BridgedChain,
+			reward: 40,
+		});
+
+		assert_eq!(report.lanes["00000001"].profit(), 15);
+		assert_eq!(report.total.profit(), 5);
+		assert_eq!("10..20".parse::<BlockRange>().unwrap(), BlockRange { first: 10, last: 20 });
+		assert!("20..10".parse::<BlockRange>().is_err());
+	}
+}
@@ -0,0 +1,491 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Generation of Grafana dashboards and Prometheus alert rules for supported bridges.
+//!
+//! Metric prefixes are computed by the same functions that are used by relay loops when they
+//! register their metrics, so the generated dashboards are always in sync with the relay.
+
+use crate::cli::{relay_headers::RelayHeadersBridge, relay_messages::FullBridge, LOG_TARGET};
+
+use serde_json::{json, Value};
+use std::{
+	path::{Path, PathBuf},
+	str::FromStr,
+};
+use structopt::StructOpt;
+use strum::VariantNames;
+use substrate_relay_helper::{cli::HexLaneId, TaggedAccount};
+
+/// Generate Grafana dashboard and Prometheus alert rules for the bridge.
+#[derive(StructOpt)]
+pub enum GenerateDashboards {
+	/// Generate dashboard and alert rules for the finality (headers) relay.
+	Headers(HeadersDashboards),
+	/// Generate dashboard and alert rules for the messages relay.
+	Messages(MessagesDashboards),
+}
+
+/// Generate dashboard and alert rules for the finality relay.
+#[derive(StructOpt)]
+pub struct HeadersDashboards {
+	/// A bridge instance to generate dashboards for.
+	#[structopt(possible_values = RelayHeadersBridge::VARIANTS, case_insensitive = true)]
+	bridge: RelayHeadersBridge,
+	#[structopt(flatten)]
+	params: DashboardParams,
+}
+
+/// Generate dashboard and alert rules for the messages relay.
+#[derive(StructOpt)]
+pub struct MessagesDashboards {
+	/// A bridge instance to generate dashboards for.
+	#[structopt(possible_values = FullBridge::VARIANTS, case_insensitive = true)]
+	bridge: FullBridge,
+	/// Hex-encoded lane identifiers that are served by the relay.
+	#[structopt(long = "lane", required = true, parse(try_from_str = parse_lane))]
+	lanes: Vec<String>,
+	#[structopt(flatten)]
+	params: DashboardParams,
+}
+
+/// Common dashboard generation parameters.
+#[derive(StructOpt)]
+pub struct DashboardParams {
+	/// Directory where the dashboard and alert rules are written to.
+	#[structopt(long, default_value = ".")]
+	output_dir: PathBuf,
+	/// Additional label matchers that are added to all queries, e.g. `domain="parity-testnet"`.
+	#[structopt(long)]
+	label_matchers: Option<String>,
+}
+
+impl GenerateDashboards {
+	/// Run the command.
+	pub async fn run(self) -> anyhow::Result<()> {
+		let (file_name, params, dashboard, alerts) = match self {
+			Self::Headers(arg) => {
+				let (source, target) = arg.bridge.chain_names();
+				let names = MetricNames::new(arg.params.label_matchers.clone());
+				let (dashboard, alerts) =
+					headers_dashboard(&names, source, target, &arg.bridge.metrics_prefix());
+				(format!("relay-{}-to-{}-headers", source, target), arg.params, dashboard, alerts)
+			},
+			Self::Messages(arg) => {
+				let (source, target) = arg.bridge.chain_names();
+				let names = MetricNames::new(arg.params.label_matchers.clone());
+				let lanes = lanes_metrics_prefixes(&arg.bridge, &arg.lanes)?;
+				let (dashboard, alerts) = messages_dashboard(&names, source, target, &lanes);
+				(format!("relay-{}-to-{}-messages", source, target), arg.params, dashboard, alerts)
+			},
+		};
+
+		let file_name = file_name.to_lowercase();
+		write_json(&params.output_dir.join(format!("{}-dashboard.json", file_name)), &dashboard)?;
+		write_json(&params.output_dir.join(format!("{}-alerts.json", file_name)), &alerts)?;
+		Ok(())
+	}
+}
+
+/// Builds names of relay metrics.
+struct MetricNames {
+	label_matchers: Option<String>,
+}
+
+impl MetricNames {
+	fn new(label_matchers: Option<String>) -> Self {
+		MetricNames { label_matchers }
+	}
+
+	/// Name of the balance metric of the relay account at the `chain`.
+	fn relay_balance(chain: &str, account: TaggedAccount<()>) -> String {
+		format!("at_{}_relay_{}_balance", chain, account.tag())
+	}
+
+	/// Metric selector with additional label matchers.
+	fn select(&self, metric: &str, matchers: &[&str]) -> String {
+		let matchers = self
+			.label_matchers
+			.iter()
+			.map(String::as_str)
+			.chain(matchers.iter().copied())
+			.collect::<Vec<_>>();
+		if matchers.is_empty() {
+			metric.into()
+		} else {
+			format!("{}{{{}}}", metric, matchers.join(","))
+		}
+	}
+
+	/// Selector of the lane state nonce of given type.
+	fn lane_nonce(&self, prefix: &str, nonce_type: &str) -> String {
+		self.select(
+			&format!("{}_lane_state_nonces", prefix),
+			&[&format!("type=\"{}\"", nonce_type)],
+		)
+	}
+}
+
+/// Pairs of lanes and prefixes of metrics of their messages relay loops.
+fn lanes_metrics_prefixes(
+	bridge: &FullBridge,
+	lanes: &[String],
+) -> anyhow::Result<Vec<(String, String)>> {
+	lanes
+		.iter()
+		.map(|lane| {
+			let hex_lane = HexLaneId::from_str(lane)
+				.map_err(|e| anyhow::format_err!("Invalid lane id {}: {:?}", lane, e))?;
+			Ok((lane.clone(), bridge.metrics_prefix(hex_lane)?))
+		})
+		.collect()
+}
+
+/// Generate dashboard and alert rules for the finality relay with given metrics prefix.
+fn headers_dashboard(
+	names: &MetricNames,
+	source: &str,
+	target: &str,
+	prefix: &str,
+) -> (Value, Value) {
+	let mut dashboard = Dashboard::new(format!("{} to {} headers relay", source, target));
+	dashboard.panel(
+		format!("Best finalized {} headers", source),
+		&[
+			(
+				names.select(&format!("{}_best_source_block_number", prefix), &[]),
+				"At source".into(),
+			),
+			(
+				names.select(&format!("{}_best_source_at_target_block_number", prefix), &[]),
+				"At target".into(),
+			),
+		],
+	);
+	dashboard.panel(
+		format!("{} headers mismatch", source),
+		&[(
+			names.select(
+				&format!("{}_is_source_and_source_at_target_using_different_forks", prefix),
+				&[],
+			),
+			"Different forks".into(),
+		)],
+	);
+	let balance = MetricNames::relay_balance(
+		target,
+		TaggedAccount::Headers { id: (), bridged_chain: source.into() },
+	);
+	dashboard.panel(
+		format!("Relay balance at {}", target),
+		&[(names.select(&balance, &[]), "Balance".into())],
+	);
+
+	let mut alerts = Alerts::new(format!("{} to {} headers relay", source, target));
+	alerts.rule(
+		format!("{}HeadersMismatch", prefix),
+		format!(
+			"{} > 0",
+			names.select(
+				&format!("{}_is_source_and_source_at_target_using_different_forks", prefix),
+				&[]
+			)
+		),
+		"1m",
+		format!("Best {} headers at {} and at {} are on different forks", source, source, target),
+	);
+	alerts.rule(
+		format!("{}SourceStalled", prefix),
+		format!(
+			"increase({}[10m]) == 0",
+			names.select(&format!("{}_best_source_block_number", prefix), &[])
+		),
+		"10m",
+		format!("Relay has not seen new finalized {} headers for 10 minutes", source),
+	);
+
+	(dashboard.into_json(), alerts.into_json())
+}
+
+/// Generate dashboard and alert rules for the messages relay. The `lanes` are pairs of lanes and
+/// prefixes of metrics of their relay loops.
+fn messages_dashboard(
+	names: &MetricNames,
+	source: &str,
+	target: &str,
+	lanes: &[(String, String)],
+) -> (Value, Value) {
+	let mut dashboard = Dashboard::new(format!("{} to {} messages relay", source, target));
+	let mut alerts = Alerts::new(format!("{} to {} messages relay", source, target));
+
+	for (lane, prefix) in lanes {
+		let generated = names.lane_nonce(prefix, "source_latest_generated");
+		let received = names.lane_nonce(prefix, "target_latest_received");
+		let confirmed = names.lane_nonce(prefix, "source_latest_confirmed");
+		let forks = names.select(
+			&format!("{}_is_source_and_source_at_target_using_different_forks", prefix),
+			&[],
+		);
+
+		dashboard.panel(
+			format!("Best finalized {} headers ({})", source, lane),
+			&[
+				(
+					names.select(&format!("{}_best_source_block_number", prefix), &[]),
+					"At source".into(),
+				),
+				(
+					names.select(&format!("{}_best_source_at_target_block_number", prefix), &[]),
+					"At target".into(),
+				),
+			],
+		);
+		dashboard.panel(
+			format!("Best finalized {} headers ({})", target, lane),
+			&[
+				(
+					names.select(&format!("{}_best_target_block_number", prefix), &[]),
+					"At target".into(),
+				),
+				(
+					names.select(&format!("{}_best_target_at_source_block_number", prefix), &[]),
+					"At source".into(),
+				),
+			],
+		);
+		dashboard.panel(
+			format!("Delivery race ({})", lane),
+			&[
+				(generated.clone(), format!("Latest message sent from {}", source)),
+				(received.clone(), format!("Latest {} message received by {}", source, target)),
+			],
+		);
+		dashboard.panel(
+			format!("Confirmations race ({})", lane),
+			&[
+				(
+					confirmed.clone(),
+					format!("Latest delivery confirmation from {} to {}", target, source),
+				),
+				(received.clone(), format!("Latest {} message received by {}", source, target)),
+			],
+		);
+		dashboard.panel(
+			format!("Headers mismatch ({})", lane),
+			&[(forks.clone(), "Different forks".into())],
+		);
+
+		alerts.rule(
+			format!("{}DeliveryLags", prefix),
+			no_progress_expr(&generated, &received),
+			"10m",
+			format!(
+				"Messages from {} to {} ({}) are either not delivered, or are delivered with lags",
+				source, target, lane
+			),
+		);
+		alerts.rule(
+			format!("{}ConfirmationLags", prefix),
+			no_progress_expr(&received, &confirmed),
+			"10m",
+			format!(
+				"Deliveries of messages from {} to {} ({}) are either not confirmed, or are \
+				 confirmed with lags",
+				source, target, lane
+			),
+		);
+		alerts.rule(
+			format!("{}HeadersMismatch", prefix),
+			format!("{} > 0", forks),
+			"1m",
+			format!("Relay of {} to {} ({}) sees headers on different forks", source, target, lane),
+		);
+	}
+
+	dashboard.panel(
+		format!("Relay balances at {} and {}", source, target),
+		&[
+			(
+				names.select(
+					&MetricNames::relay_balance(
+						source,
+						TaggedAccount::Messages { id: (), bridged_chain: target.into() },
+					),
+					&[],
+				),
+				format!("At {}", source),
+			),
+			(
+				names.select(
+					&MetricNames::relay_balance(
+						target,
+						TaggedAccount::Messages { id: (), bridged_chain: source.into() },
+					),
+					&[],
+				),
+				format!("At {}", target),
+			),
+		],
+	);
+
+	(dashboard.into_json(), alerts.into_json())
+}
+
+/// Expression that fires when the `required` nonce is ahead of the `actual` nonce, but the
+/// `actual` nonce has not increased within the last 10 minutes.
+fn no_progress_expr(required: &str, actual: &str) -> String {
+	format!("({} > on () {}) and on () increase({}[10m]) == 0", required, actual, actual)
+}
+
+/// Grafana dashboard.
+struct Dashboard {
+	title: String,
+	panels: Vec<Value>,
+}
+
+impl Dashboard {
+	fn new(title: String) -> Self {
+		Dashboard { title, panels: Vec::new() }
+	}
+
+	/// Add time series panel with given (query, legend) pairs.
+	fn panel(&mut self, title: String, queries: &[(String, String)]) {
+		let id = self.panels.len() + 1;
+		let targets = queries
+			.iter()
+			.zip('A'..)
+			.map(|((expr, legend), ref_id)| {
+				json!({
+					"datasource": { "type": "prometheus", "uid": "${datasource}" },
+					"expr": expr,
+					"legendFormat": legend,
+					"refId": ref_id.to_string(),
+				})
+			})
+			.collect::<Vec<_>>();
+		self.panels.push(json!({
+			"id": id,
+			"type": "timeseries",
+			"title": title,
+			"datasource": { "type": "prometheus", "uid": "${datasource}" },
+			"gridPos": { "h": 8, "w": 12, "x": (id - 1) % 2 * 12, "y": (id - 1) / 2 * 8 },
+			"targets": targets,
+		}));
+	}
+
+	fn into_json(self) -> Value {
+		json!({
+			"title": self.title,
+			"editable": true,
+			"refresh": "1m",
+			"schemaVersion": 37,
+			"tags": ["bridges"],
+			"time": { "from": "now-24h", "to": "now" },
+			"templating": {
+				"list": [{
+					"name": "datasource",
+					"label": "Data source",
+					"type": "datasource",
+					"query": "prometheus",
+				}],
+			},
+			"panels": self.panels,
+		})
+	}
+}
+
+/// Prometheus alert rules group.
+struct Alerts {
+	name: String,
+	rules: Vec<Value>,
+}
+
+impl Alerts {
+	fn new(name: String) -> Self {
+		Alerts { name, rules: Vec::new() }
+	}
+
+	fn rule(&mut self, alert: String, expr: String, for_: &str, summary: String) {
+		self.rules.push(json!({
+			"alert": alert,
+			"expr": expr,
+			"for": for_,
+			"labels": { "severity": "warning" },
+			"annotations": { "summary": summary },
+		}));
+	}
+
+	/// Rules file in the Prometheus format. JSON is a subset of YAML, so it may be loaded by
+	/// Prometheus or imported to Grafana as is.
+	fn into_json(self) -> Value {
+		json!({
+			"groups": [{
+				"name": self.name,
+				"interval": "1m",
+				"rules": self.rules,
+			}],
+		})
+	}
+}
+
+fn parse_lane(lane: &str) -> anyhow::Result<String> {
+	let lane = lane.trim_start_matches("0x");
+	hex::decode(lane).map_err(|e| anyhow::format_err!("Invalid lane id {}: {}", lane, e))?;
+	Ok(lane.into())
+}
+
+fn write_json(path: &Path, value: &Value) -> anyhow::Result<()> {
+	std::fs::write(path, serde_json::to_string_pretty(value)?)?;
+	log::info!(target: LOG_TARGET, "Written {}", path.display());
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn metric_names_match_relay_metrics() {
+		let names = MetricNames::new(Some("domain=\"parity-testnet\"".into()));
+		let lanes = lanes_metrics_prefixes(
+			&FullBridge::BridgeHubRococoToBridgeHubWestend,
+			&["00000002".into()],
+		)
+		.unwrap();
+		let (dashboard, alerts) =
+			messages_dashboard(&names, "BridgeHubRococo", "BridgeHubWestend", &lanes);
+
+		let dashboard = dashboard.to_string();
+		assert!(dashboard.contains(
+			"BridgeHubRococo_to_BridgeHubWestend_MessageLane_00000002_lane_state_nonces\
+			 {domain=\\\"parity-testnet\\\",type=\\\"source_latest_generated\\\"}"
+		));
+		assert!(dashboard.contains(
+			"at_BridgeHubRococo_relay_BridgeHubWestendMessages_balance{domain=\\\"parity-testnet\\\"}"
+		));
+		assert_eq!(alerts["groups"][0]["rules"].as_array().unwrap().len(), 3);
+
+		let (dashboard, _) = headers_dashboard(
+			&names,
+			"Rococo",
+			"BridgeHubWestend",
+			&RelayHeadersBridge::RococoToBridgeHubWestend.metrics_prefix(),
+		);
+		assert!(dashboard
+			.to_string()
+			.contains("Rococo_to_BridgeHubWestend_Sync_best_source_at_target_block_number"));
+	}
+}
@@ -0,0 +1,399 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Health and readiness endpoints of the relay.
+//!
+//! Relay loops are not reporting their state directly, but every loop updates its Prometheus
+//! metrics (best block numbers and lane nonces) on every iteration. So the health service
+//! periodically scrapes relay metrics and groups them by the loop that has produced them:
+//!
+//! - `/ready` returns `200` when metrics of all expected loops have been reported, meaning that all
+//!   clients are connected and every loop has finished its first iteration;
+//! - `/health` returns `200` when every loop has updated its metrics within the last `N` average
+//!   block intervals. Otherwise it returns `503` and the list of stalled loops.
+//!
+//! When health endpoints are enabled, the Prometheus listener is owned by the health service
+//! instead of relay loops. It serves metrics of the relay registry at `/metrics`, along with
+//! `/health` and `/ready` endpoints.
+
+use crate::cli::{
+	shutdown::{is_shutdown_requested, on_metrics_scraped, on_metrics_served},
+	LOG_TARGET,
+};
+
+use async_std::{
+	io::{ReadExt, WriteExt},
+	net::{TcpListener, TcpStream},
+};
+use futures::StreamExt;
+use parking_lot::Mutex;
+use prometheus_endpoint::{
+	prometheus::{Encoder, TextEncoder},
+	Registry,
+};
+use std::{
+	collections::BTreeMap,
+	sync::Arc,
+	time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use substrate_relay_helper::cli::PrometheusParams;
+
+/// Maximal time we wait for the health request.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Suffixes of metrics that are updated by relay loops on every iteration.
+const PROGRESS_METRICS_SUFFIXES: &[&str] = &[
+	"_best_source_block_number",
+	"_best_target_block_number",
+	"_best_block_numbers",
+	"_lane_state_nonces",
+	"_best_parachain_block_number_at_source",
+	"_best_parachain_block_number_at_target",
+];
+
+/// Health endpoints parameters.
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct HealthParams {
+	/// Expose `/health` and `/ready` endpoints next to `/metrics` on the Prometheus port.
+	///
+	/// Requires Prometheus metrics to be enabled.
+	#[structopt(long)]
+	pub health_endpoints: bool,
+	/// Loop is considered stalled if it has not made any progress within this number of average
+	/// block intervals.
+	#[structopt(long, default_value = "10")]
+	pub health_stall_intervals: u32,
+}
+
+impl HealthParams {
+	/// Take the Prometheus listener from relay loops, if health endpoints are enabled.
+	///
+	/// Returns parameters of the listener that must be passed to the `start` afterwards.
+	pub fn take_prometheus(
+		&self,
+		prometheus_params: &mut PrometheusParams,
+	) -> anyhow::Result<Option<PrometheusParams>> {
+		if !self.health_endpoints {
+			return Ok(None)
+		}
+		if prometheus_params.no_prometheus {
+			return Err(anyhow::format_err!("--health-endpoints requires Prometheus to be enabled"))
+		}
+
+		// relay loops are still registering their metrics, but are not exposing them
+		let listener_params = prometheus_params.clone();
+		prometheus_params.no_prometheus = true;
+		Ok(Some(listener_params))
+	}
+
+	/// Start serving metrics of the registry and health endpoints in background.
+	pub async fn start(
+		&self,
+		prometheus_params: Option<PrometheusParams>,
+		registry: Registry,
+		expected_loops: usize,
+		average_block_interval: Duration,
+	) -> anyhow::Result<()> {
+		let prometheus_params = match prometheus_params {
+			Some(prometheus_params) => prometheus_params,
+			None => return Ok(()),
+		};
+
+		let listener = TcpListener::bind((
+			prometheus_params.prometheus_host.as_str(),
+			prometheus_params.prometheus_port,
+		))
+		.await?;
+		let state = Arc::new(Mutex::new(LoopsState::new(
+			expected_loops,
+			average_block_interval * self.health_stall_intervals,
+		)));
+
+		log::info!(
+			target: LOG_TARGET,
+			"Exposing metrics and health endpoints on {}:{}",
+			prometheus_params.prometheus_host,
+			prometheus_params.prometheus_port,
+		);
+
+		async_std::task::spawn(watch_metrics(
+			registry.clone(),
+			average_block_interval,
+			state.clone(),
+		));
+		async_std::task::spawn(serve(listener, registry, Some(state)));
+		on_metrics_served();
+
+		Ok(())
+	}
+}
+
+/// Start serving metrics of the registry without health endpoints in background.
+pub async fn serve_metrics(
+	prometheus_params: PrometheusParams,
+	registry: Registry,
+) -> anyhow::Result<()> {
+	if prometheus_params.no_prometheus {
+		return Ok(())
+	}
+
+	let listener = TcpListener::bind((
+		prometheus_params.prometheus_host.as_str(),
+		prometheus_params.prometheus_port,
+	))
+	.await?;
+
+	log::info!(
+		target: LOG_TARGET,
+		"Exposing metrics on {}:{}",
+		prometheus_params.prometheus_host,
+		prometheus_params.prometheus_port,
+	);
+
+	async_std::task::spawn(serve(listener, registry, None));
+	on_metrics_served();
+
+	Ok(())
+}
+
+/// State of all relay loops, derived from their metrics.
+struct LoopsState {
+	/// Number of loops that the relay is expected to run.
+	expected_loops: usize,
+	/// Loop is stalled if it has made no progress within this interval.
+	stall_timeout: Duration,
+	/// Known loops, mapped to their last seen metrics and the time when they have changed.
+	loops: BTreeMap<String, (BTreeMap<String, String>, Instant)>,
+}
+
+impl LoopsState {
+	fn new(expected_loops: usize, stall_timeout: Duration) -> Self {
+		LoopsState { expected_loops, stall_timeout, loops: BTreeMap::new() }
+	}
+
+	/// Update loops state with the freshly scraped metrics.
+	fn observe(&mut self, metrics: &str, now: Instant) {
+		for (loop_name, samples) in progress_samples(metrics) {
+			match self.loops.get_mut(&loop_name) {
+				Some((known_samples, _)) if *known_samples == samples => (),
+				_ => {
+					self.loops.insert(loop_name, (samples, now));
+				},
+			}
+		}
+	}
+
+	/// Returns true if all expected loops have reported their metrics.
+	fn is_ready(&self) -> bool {
+		self.loops.len() >= self.expected_loops
+	}
+
+	/// Returns names of loops that have made no progress for too long.
+	fn stalled_loops(&self, now: Instant) -> Vec<&str> {
+		self.loops
+			.iter()
+			.filter(|(_, (_, updated_at))| now.duration_since(*updated_at) > self.stall_timeout)
+			.map(|(loop_name, _)| loop_name.as_str())
+			.collect()
+	}
+}
+
+/// Select samples of progress metrics and group them by the relay loop.
+fn progress_samples(metrics: &str) -> BTreeMap<String, BTreeMap<String, String>> {
+	let mut loops = BTreeMap::<String, BTreeMap<String, String>>::new();
+	for line in metrics.lines().filter(|line| !line.starts_with('#')) {
+		let (series, value) = match line.rsplit_once(' ') {
+			Some(sample) => sample,
+			None => continue,
+		};
+		let metric_name = series.split('{').next().unwrap_or(series);
+		let loop_name = PROGRESS_METRICS_SUFFIXES
+			.iter()
+			.find_map(|suffix| metric_name.strip_suffix(suffix));
+		if let Some(loop_name) = loop_name {
+			// loops of different bridges, served by the same process, may have the same name
+			let loop_name = match bridge_label(series) {
+				Some(bridge) => format!("{}/{}", bridge, loop_name),
+				None => loop_name.into(),
+			};
+			loops.entry(loop_name).or_default().insert(series.into(), value.into());
+		}
+	}
+	loops
+}
+
+/// Returns value of the `bridge` label of the sample, if it has one.
+fn bridge_label(series: &str) -> Option<&str> {
+	series
+		.split_once("bridge=\"")
+		.and_then(|(_, labels)| labels.split_once('"'))
+		.map(|(bridge, _)| bridge)
+}
+
+/// Periodically read relay metrics and update loops state.
+async fn watch_metrics(
+	registry: Registry,
+	scrape_interval: Duration,
+	state: Arc<Mutex<LoopsState>>,
+) {
+	loop {
+		match encode_metrics(&registry) {
+			Ok(metrics) => state.lock().observe(&metrics, Instant::now()),
+			Err(e) => log::debug!(target: LOG_TARGET, "Failed to read relay metrics: {}", e),
+		}
+		async_std::task::sleep(scrape_interval).await;
+	}
+}
+
+/// Encode all metrics of the registry using the Prometheus text format.
+fn encode_metrics(registry: &Registry) -> anyhow::Result<String> {
+	let mut encoded = Vec::new();
+	TextEncoder::new().encode(&registry.gather(), &mut encoded)?;
+	Ok(String::from_utf8(encoded)?)
+}
+
+/// Serve metrics and health requests. Every connection is served by its own task.
+///
+/// Health endpoints are not served if there's no loops state.
+async fn serve(listener: TcpListener, registry: Registry, state: Option<Arc<Mutex<LoopsState>>>) {
+	let mut incoming = listener.incoming();
+	while let Some(stream) = incoming.next().await {
+		let stream = match stream {
+			Ok(stream) => stream,
+			Err(e) => {
+				log::debug!(target: LOG_TARGET, "Failed to accept health connection: {}", e);
+				continue
+			},
+		};
+
+		let registry = registry.clone();
+		let state = state.clone();
+		async_std::task::spawn(async move {
+			if let Err(e) = handle_request(stream, &registry, &state).await {
+				log::debug!(target: LOG_TARGET, "Failed to serve health request: {}", e);
+			}
+		});
+	}
+}
+
+async fn handle_request(
+	mut stream: TcpStream,
+	registry: &Registry,
+	state: &Option<Arc<Mutex<LoopsState>>>,
+) -> anyhow::Result<()> {
+	let mut request = [0u8; 1024];
+	let read = async_std::io::timeout(READ_TIMEOUT, stream.read(&mut request)).await?;
+	let request = String::from_utf8_lossy(&request[..read]);
+	let path = request.split_whitespace().nth(1).unwrap_or_default();
+
+	let (status, body) = match state.as_ref().map(|state| state.lock()) {
+		Some(state) => match path {
+			"/ready" if is_shutdown_requested() =>
+				("503 Service Unavailable", "shutting down".to_string()),
+			"/ready" if state.is_ready() => ("200 OK", "ready".to_string()),
+			"/ready" => (
+				"503 Service Unavailable",
+				format!(
+					"{} of {} relay loops are running",
+					state.loops.len(),
+					state.expected_loops
+				),
+			),
+			"/health" => match state.stalled_loops(Instant::now()) {
+				stalled if stalled.is_empty() => ("200 OK", "healthy".to_string()),
+				stalled => ("503 Service Unavailable", format!("stalled: {}", stalled.join(", "))),
+			},
+			"/metrics" => metrics_response(registry)?,
+			_ => ("404 Not Found", String::new()),
+		},
+		None => match path {
+			"/metrics" => metrics_response(registry)?,
+			_ => ("404 Not Found", String::new()),
+		},
+	};
+
+	stream
+		.write_all(
+			format!(
+				"HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+				status,
+				body.len(),
+				body,
+			)
+			.as_bytes(),
+		)
+		.await?;
+	Ok(())
+}
+
+/// Respond to the metrics request.
+fn metrics_response(registry: &Registry) -> anyhow::Result<(&'static str, String)> {
+	on_metrics_scraped();
+	Ok(("200 OK", encode_metrics(registry)?))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const METRICS: &str = r#"
+# HELP Rococo_to_BridgeHubWestend_Sync_best_source_block_number Best block number at source.
+Rococo_to_BridgeHubWestend_Sync_best_source_block_number 100
+Rococo_to_BridgeHubWestend_Sync_best_target_block_number 50
+BridgeHubRococo_to_BridgeHubWestend_MessageLane_00000002_lane_state_nonces{type="source_latest_generated"} 10
+BridgeHubRococo_to_BridgeHubWestend_MessageLane_00000002_lane_state_nonces{type="target_latest_received"} 9
+at_BridgeHubRococo_relay_BridgeHubWestendMessages_balance 1000
+"#;
+
+	#[test]
+	fn loops_state_is_derived_from_metrics() {
+		let start = Instant::now();
+		let mut state = LoopsState::new(2, Duration::from_secs(60));
+		assert!(!state.is_ready());
+
+		state.observe(METRICS, start);
+		assert!(state.is_ready());
+		assert_eq!(
+			state.loops.keys().collect::<Vec<_>>(),
+			vec![
+				"BridgeHubRococo_to_BridgeHubWestend_MessageLane_00000002",
+				"Rococo_to_BridgeHubWestend_Sync",
+			],
+		);
+
+		// headers loop makes progress, but messages loop does not
+		let later = start + Duration::from_secs(61);
+		state.observe(&METRICS.replace(" 100", " 101"), later);
+		assert_eq!(
+			state.stalled_loops(later),
+			vec!["BridgeHubRococo_to_BridgeHubWestend_MessageLane_00000002"],
+		);
+
+		// same loops of different bridges are not merged
+		let labeled = r#"
+Rococo_to_BridgeHubWestend_Sync_best_source_block_number{bridge="rococo-westend"} 100
+Rococo_to_BridgeHubWestend_Sync_best_source_block_number{bridge="rococo-westend-2"} 100
+"#;
+		assert_eq!(
+			progress_samples(labeled).keys().collect::<Vec<_>>(),
+			vec![
+				"rococo-westend-2/Rococo_to_BridgeHubWestend_Sync",
+				"rococo-westend/Rococo_to_BridgeHubWestend_Sync",
+			],
+		);
+	}
+}
@@ -0,0 +1,197 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{
+	bridges::{
+		kusama_polkadot::{
+			kusama_headers_to_bridge_hub_polkadot::KusamaToBridgeHubPolkadotCliBridge,
+			polkadot_headers_to_bridge_hub_kusama::PolkadotToBridgeHubKusamaCliBridge,
+		},
+		polkadot_bulletin::{
+			polkadot_bulletin_headers_to_bridge_hub_polkadot::PolkadotBulletinToBridgeHubPolkadotCliBridge,
+			polkadot_headers_to_polkadot_bulletin::PolkadotToPolkadotBulletinCliBridge,
+		},
+		rococo_bulletin::{
+			rococo_bulletin_headers_to_bridge_hub_rococo::RococoBulletinToBridgeHubRococoCliBridge,
+			rococo_headers_to_rococo_bulletin::RococoToRococoBulletinCliBridge,
+		},
+		rococo_westend::{
+			rococo_headers_to_bridge_hub_westend::RococoToBridgeHubWestendCliBridge,
+			westend_headers_to_bridge_hub_rococo::WestendToBridgeHubRococoCliBridge,
+		},
+	},
+	cli::{
+		justification_archive::read_justification_file, relay_headers::RelayHeadersBridge,
+		LOG_TARGET,
+	},
+};
+
+use bp_header_chain::FinalityProof;
+use codec::Decode;
+use relay_substrate_client::{
+	AccountIdOf, AccountKeyPairOf, CallOf, Chain, Client, HeaderOf, TransactionEra,
+	UnsignedTransaction,
+};
+use sp_core::Pair;
+use sp_runtime::traits::Header as _;
+use std::path::PathBuf;
+use structopt::StructOpt;
+use strum::VariantNames;
+use substrate_relay_helper::{
+	cli::{
+		bridge::{CliBridgeBase, RelayToRelayHeadersCliBridge},
+		chain_schema::{SourceConnectionParams, TargetConnectionParams, TargetSigningParams},
+	},
+	finality::{SubmitFinalityProofCallBuilder, SubstrateFinalitySyncPipeline},
+	finality_base::{engine::Engine, SubstrateFinalityPipeline, SyncHeader},
+};
+
+/// Finality engine of the bridge.
+type FinalityEngine<Bridge> =
+	<<Bridge as CliBridgeBase>::Finality as SubstrateFinalityPipeline>::FinalityEngine;
+/// Finality proof of the bridge source chain.
+pub type FinalityProofOf<Bridge> =
+	<FinalityEngine<Bridge> as Engine<<Bridge as CliBridgeBase>::Source>>::FinalityProof;
+
+/// Submit finality proof of the source header from the justification file.
+///
+/// The file contains hex-encoded justification. Justifications, archived by the relay with
+/// `--justification-archive <dir>`, are stored in the `<dir>/<header-hash>.justification` files.
+#[derive(StructOpt)]
+pub struct ImportJustification {
+	/// A bridge instance to import justification for.
+	#[structopt(possible_values = RelayHeadersBridge::VARIANTS, case_insensitive = true)]
+	bridge: RelayHeadersBridge,
+	#[structopt(flatten)]
+	source: SourceConnectionParams,
+	#[structopt(flatten)]
+	target: TargetConnectionParams,
+	#[structopt(flatten)]
+	target_sign: TargetSigningParams,
+	/// Path to the file with hex-encoded justification.
+	#[structopt(long)]
+	justification_file: PathBuf,
+}
+
+impl ImportJustification {
+	/// Name of the bridge, served by this command.
+	pub fn bridge_name(&self) -> String {
+		format!("{:?}", self.bridge)
+	}
+
+	/// Run the command.
+	pub async fn run(self) -> anyhow::Result<()> {
+		match self.bridge {
+			RelayHeadersBridge::RococoToBridgeHubWestend =>
+				import_justification::<RococoToBridgeHubWestendCliBridge>(self).await,
+			RelayHeadersBridge::WestendToBridgeHubRococo =>
+				import_justification::<WestendToBridgeHubRococoCliBridge>(self).await,
+			RelayHeadersBridge::KusamaToBridgeHubPolkadot =>
+				import_justification::<KusamaToBridgeHubPolkadotCliBridge>(self).await,
+			RelayHeadersBridge::PolkadotToBridgeHubKusama =>
+				import_justification::<PolkadotToBridgeHubKusamaCliBridge>(self).await,
+			RelayHeadersBridge::PolkadotToPolkadotBulletin =>
+				import_justification::<PolkadotToPolkadotBulletinCliBridge>(self).await,
+			RelayHeadersBridge::PolkadotBulletinToBridgeHubPolkadot =>
+				import_justification::<PolkadotBulletinToBridgeHubPolkadotCliBridge>(self).await,
+			RelayHeadersBridge::RococoToRococoBulletin =>
+				import_justification::<RococoToRococoBulletinCliBridge>(self).await,
+			RelayHeadersBridge::RococoBulletinToBridgeHubRococo =>
+				import_justification::<RococoBulletinToBridgeHubRococoCliBridge>(self).await,
+		}
+	}
+}
+
+/// Verify justification from the file and submit it to the target chain.
+async fn import_justification<Bridge: RelayToRelayHeadersCliBridge>(
+	params: ImportJustification,
+) -> anyhow::Result<()>
+where
+	AccountIdOf<Bridge::Target>: From<<AccountKeyPairOf<Bridge::Target> as Pair>::Public>,
+{
+	let encoded = read_justification_file(&params.justification_file)?;
+	let proof = decode_finality_proof::<Bridge>(&encoded)
+		.map_err(|e| anyhow::format_err!("{} from {}", e, params.justification_file.display()))?;
+
+	let source_client = params.source.into_client::<Bridge::Source>().await?;
+	let target_client = params.target.into_client::<Bridge::Target>().await?;
+	let header = source_client.header_by_hash(proof.target_header_hash()).await?;
+	let header_id = (*header.number(), header.hash());
+	let call = submit_finality_proof_call::<Bridge>(&target_client, header, proof).await?;
+
+	let signer = params.target_sign.to_keypair::<Bridge::Target>()?;
+	let mortality = params.target_sign.transactions_mortality()?;
+	let tx_hash = target_client
+		.submit_signed_extrinsic(&signer, move |best_block_id, nonce| {
+			Ok(UnsignedTransaction::new(call.into(), nonce)
+				.era(TransactionEra::new(best_block_id, mortality)))
+		})
+		.await?;
+
+	log::info!(
+		target: LOG_TARGET,
+		header:% = header_id.0, tx_hash:? = tx_hash;
+		"Submitted justification of {} header {:?} to {} in transaction {:?}",
+		Bridge::Source::NAME,
+		header_id,
+		Bridge::Target::NAME,
+		tx_hash,
+	);
+
+	Ok(())
+}
+
+/// Decode finality proof of the source chain.
+pub fn decode_finality_proof<Bridge: RelayToRelayHeadersCliBridge>(
+	encoded: &[u8],
+) -> anyhow::Result<FinalityProofOf<Bridge>> {
+	FinalityProofOf::<Bridge>::decode(&mut &encoded[..]).map_err(|e| {
+		anyhow::format_err!("Failed to decode {} justification: {:?}", Bridge::Source::NAME, e)
+	})
+}
+
+/// Verify finality proof of the source header and build the call that submits it to the target
+/// chain.
+pub async fn submit_finality_proof_call<Bridge: RelayToRelayHeadersCliBridge>(
+	target_client: &impl Client<Bridge::Target>,
+	header: HeaderOf<Bridge::Source>,
+	mut proof: FinalityProofOf<Bridge>,
+) -> anyhow::Result<CallOf<Bridge::Target>> {
+	type CallBuilder<Bridge> =
+		<<Bridge as CliBridgeBase>::Finality as SubstrateFinalitySyncPipeline>::SubmitFinalityProofCallBuilder;
+
+	let header_id = (*header.number(), header.hash());
+	let context = <FinalityEngine<Bridge> as Engine<Bridge::Source>>::verify_and_optimize_proof(
+		target_client,
+		&header,
+		&mut proof,
+	)
+	.await
+	.map_err(|e| {
+		anyhow::format_err!(
+			"Justification of {} header {:?} is rejected: {}",
+			Bridge::Source::NAME,
+			header_id,
+			e,
+		)
+	})?;
+	Ok(CallBuilder::<Bridge>::build_submit_finality_proof_call(
+		SyncHeader::from(header),
+		proof,
+		false,
+		context,
+	))
+}
@@ -14,32 +14,62 @@
 // You should have received a copy of the GNU General Public License
 // along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
 
-use crate::bridges::{
-	kusama_polkadot::{
-		kusama_headers_to_bridge_hub_polkadot::KusamaToBridgeHubPolkadotCliBridge,
-		polkadot_headers_to_bridge_hub_kusama::PolkadotToBridgeHubKusamaCliBridge,
+use crate::{
+	bridges::{
+		kusama_polkadot::{
+			kusama_headers_to_bridge_hub_polkadot::KusamaToBridgeHubPolkadotCliBridge,
+			polkadot_headers_to_bridge_hub_kusama::PolkadotToBridgeHubKusamaCliBridge,
+		},
+		polkadot_bulletin::{
+			polkadot_bulletin_headers_to_bridge_hub_polkadot::PolkadotBulletinToBridgeHubPolkadotCliBridge,
+			polkadot_headers_to_polkadot_bulletin::PolkadotToPolkadotBulletinCliBridge,
+		},
+		rococo_bulletin::{
+			rococo_bulletin_headers_to_bridge_hub_rococo::RococoBulletinToBridgeHubRococoCliBridge,
+			rococo_headers_to_rococo_bulletin::RococoToRococoBulletinCliBridge,
+		},
+		rococo_westend::{
+			rococo_headers_to_bridge_hub_westend::RococoToBridgeHubWestendCliBridge,
+			westend_headers_to_bridge_hub_rococo::WestendToBridgeHubRococoCliBridge,
+		},
 	},
-	polkadot_bulletin::{
-		polkadot_bulletin_headers_to_bridge_hub_polkadot::PolkadotBulletinToBridgeHubPolkadotCliBridge,
-		polkadot_headers_to_polkadot_bulletin::PolkadotToPolkadotBulletinCliBridge,
-	},
-	rococo_bulletin::{
-		rococo_bulletin_headers_to_bridge_hub_rococo::RococoBulletinToBridgeHubRococoCliBridge,
-		rococo_headers_to_rococo_bulletin::RococoToRococoBulletinCliBridge,
-	},
-	rococo_westend::{
-		rococo_headers_to_bridge_hub_westend::RococoToBridgeHubWestendCliBridge,
-		westend_headers_to_bridge_hub_rococo::WestendToBridgeHubRococoCliBridge,
+	cli::{
+		justification_archive::GRANDPA_ENGINE_ID,
+		offline_signing::{export_unsigned_transaction, ExportUnsignedParams},
+		LOG_TARGET,
 	},
 };
-use relay_substrate_client::Chain;
+use bp_header_chain::{
+	justification::{verify_justification, GrandpaJustification, JustificationVerificationContext},
+	AuthoritySet, ConsensusLogReader, GrandpaConsensusLogReader, InitializationData,
+};
+use bp_runtime::BasicOperatingMode;
+use codec::{Decode, Encode};
+use futures::StreamExt;
+use relay_substrate_client::{
+	AccountIdOf, AccountKeyPairOf, BlockNumberOf, BlockWithJustification, CallOf, Chain,
+	ChainWithGrandpa, Client, HashOf, HeaderOf, TransactionEra, UnsignedTransaction,
+};
+use sp_core::{Pair, H256};
+use sp_runtime::{traits::Header as _, AccountId32};
+use std::str::FromStr;
 use structopt::StructOpt;
 use strum::{EnumString, VariantNames};
 use substrate_relay_helper::{
-	cli::init_bridge::{BridgeInitializer, InitBridgeParams},
+	cli::{
+		bridge::CliBridgeBase,
+		init_bridge::{BridgeInitializer, InitBridgeParams},
+	},
 	finality_base::engine::{Engine, Grandpa as GrandpaFinalityEngine},
 };
 
+/// Name of the runtime API method that returns the current GRANDPA authorities.
+const GRANDPA_AUTHORITIES_METHOD: &str = "GrandpaApi_grandpa_authorities";
+/// Name of the runtime API method that returns the current GRANDPA authorities set id.
+const GRANDPA_CURRENT_SET_ID_METHOD: &str = "GrandpaApi_current_set_id";
+/// Number of source headers that are read at once, while looking for the next mandatory header.
+const SCAN_CONCURRENCY: usize = 16;
+
 impl BridgeInitializer for RococoToBridgeHubWestendCliBridge {
 	type Engine = GrandpaFinalityEngine<Self::Source>;
 
@@ -157,6 +187,41 @@ pub struct InitBridge {
 	bridge: InitBridgeName,
 	#[structopt(flatten)]
 	params: InitBridgeParams,
+	#[structopt(flatten)]
+	export: ExportUnsignedParams,
+	/// Initialize the bridge with the finalized source header with given number or hash,
+	/// instead of the best finalized header.
+	#[structopt(long)]
+	at_block: Option<AtBlock>,
+	/// Do not submit or export the transaction. Instead, print the SCALE-encoded initialization
+	/// call, its hash and decoded form, so that it may be dispatched by governance. The signer
+	/// is not required.
+	#[structopt(long, conflicts_with = "export-unsigned")]
+	encode_only: bool,
+}
+
+/// Source header to initialize the bridge with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AtBlock {
+	/// Header with given number.
+	Number(u32),
+	/// Header with given hash.
+	Hash(H256),
+}
+
+impl FromStr for AtBlock {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		if s.starts_with("0x") {
+			return H256::from_str(s)
+				.map(AtBlock::Hash)
+				.map_err(|e| anyhow::format_err!("Invalid header hash {}: {:?}", s, e))
+		}
+		s.parse()
+			.map(AtBlock::Number)
+			.map_err(|e| anyhow::format_err!("Invalid header number {}: {}", s, e))
+	}
 }
 
 #[derive(Debug, EnumString, VariantNames)]
@@ -174,26 +239,278 @@ pub enum InitBridgeName {
 }
 
 impl InitBridge {
+	/// Name of the bridge, served by this command.
+	pub fn bridge_name(&self) -> String {
+		format!("{:?}", self.bridge)
+	}
+
 	/// Run the command.
 	pub async fn run(self) -> anyhow::Result<()> {
 		match self.bridge {
 			InitBridgeName::KusamaToBridgeHubPolkadot =>
-				KusamaToBridgeHubPolkadotCliBridge::init_bridge(self.params),
+				init_bridge::<KusamaToBridgeHubPolkadotCliBridge>(self).await,
 			InitBridgeName::PolkadotToBridgeHubKusama =>
-				PolkadotToBridgeHubKusamaCliBridge::init_bridge(self.params),
+				init_bridge::<PolkadotToBridgeHubKusamaCliBridge>(self).await,
 			InitBridgeName::PolkadotToPolkadotBulletin =>
-				PolkadotToPolkadotBulletinCliBridge::init_bridge(self.params),
+				init_bridge::<PolkadotToPolkadotBulletinCliBridge>(self).await,
 			InitBridgeName::PolkadotBulletinToBridgeHubPolkadot =>
-				PolkadotBulletinToBridgeHubPolkadotCliBridge::init_bridge(self.params),
+				init_bridge::<PolkadotBulletinToBridgeHubPolkadotCliBridge>(self).await,
 			InitBridgeName::RococoToRococoBulletin =>
-				RococoToRococoBulletinCliBridge::init_bridge(self.params),
+				init_bridge::<RococoToRococoBulletinCliBridge>(self).await,
 			InitBridgeName::RococoBulletinToBridgeHubRococo =>
-				RococoBulletinToBridgeHubRococoCliBridge::init_bridge(self.params),
+				init_bridge::<RococoBulletinToBridgeHubRococoCliBridge>(self).await,
 			InitBridgeName::RococoToBridgeHubWestend =>
-				RococoToBridgeHubWestendCliBridge::init_bridge(self.params),
+				init_bridge::<RococoToBridgeHubWestendCliBridge>(self).await,
 			InitBridgeName::WestendToBridgeHubRococo =>
-				WestendToBridgeHubRococoCliBridge::init_bridge(self.params),
+				init_bridge::<WestendToBridgeHubRococoCliBridge>(self).await,
 		}
-		.await
+	}
+}
+
+/// Either submit the bridge initialization transaction or export it for offline signing.
+async fn init_bridge<Bridge>(command: InitBridge) -> anyhow::Result<()>
+where
+	Bridge: BridgeInitializer<Engine = GrandpaFinalityEngine<<Bridge as CliBridgeBase>::Source>>,
+	Bridge::Source: ChainWithGrandpa,
+	HashOf<Bridge::Source>: From<H256>,
+	AccountIdOf<Bridge::Target>: From<<AccountKeyPairOf<Bridge::Target> as Pair>::Public>,
+	AccountIdOf<Bridge::Target>: From<AccountId32>,
+{
+	let InitBridge { params, export, at_block, encode_only, .. } = command;
+	let export_target = export.export_target()?;
+	if export_target.is_none() && at_block.is_none() && !encode_only {
+		return Bridge::init_bridge(params).await
+	}
+
+	let source_client = params.source.into_client::<Bridge::Source>().await?;
+	let init_data = match at_block {
+		Some(at_block) => prepare_initialization_data_at(&source_client, at_block).await?,
+		None =>
+			<Bridge::Engine as Engine<Bridge::Source>>::prepare_initialization_data(source_client)
+				.await
+				.map_err(|e| {
+					anyhow::format_err!("Failed to prepare initialization data: {:?}", e)
+				})?,
+	};
+	let call = Bridge::encode_init_bridge(init_data);
+	if encode_only {
+		print_encoded_call::<Bridge::Target>(&call);
+		return Ok(())
+	}
+
+	let target_client = params.target.into_client::<Bridge::Target>().await?;
+	let mortality = params.target_sign.transactions_mortality()?;
+	let (path, signer) = match export_target {
+		Some(export_target) => export_target,
+		None => {
+			let signer = params.target_sign.to_keypair::<Bridge::Target>()?;
+			let tx_hash = target_client
+				.submit_signed_extrinsic(&signer, move |best_block_id, nonce| {
+					Ok(UnsignedTransaction::new(call.into(), nonce)
+						.era(TransactionEra::new(best_block_id, mortality)))
+				})
+				.await?;
+			log::info!(
+				target: LOG_TARGET,
+				"Submitted {} bridge initialization transaction to {}: {:?}",
+				Bridge::Source::NAME,
+				Bridge::Target::NAME,
+				tx_hash,
+			);
+			return Ok(())
+		},
+	};
+
+	export_unsigned_transaction(&target_client, signer.into(), call, mortality, &path).await
+}
+
+/// Print the SCALE-encoded call, its hash and decoded form.
+pub fn print_encoded_call<C: Chain>(call: &CallOf<C>) {
+	println!("{}", encoded_call_summary::<C>(call));
+}
+
+/// Returns the SCALE-encoded call, its hash and decoded form.
+pub fn encoded_call_summary<C: Chain>(call: &CallOf<C>) -> String {
+	let encoded = call.encode();
+	format!(
+		"Encoded {} call: 0x{}\nCall hash: {:?}\nDecoded call: {:#?}",
+		C::NAME,
+		hex::encode(&encoded),
+		H256(sp_core::hashing::blake2_256(&encoded)),
+		call,
+	)
+}
+
+/// Prepare bridge initialization data for the given finalized source header.
+///
+/// The authorities set and its id are read from the state at this header. If the header enacts
+/// the authorities set change, the state already contains the new set, which is required to
+/// verify justifications of the following headers. To make sure that the set is right, it is
+/// used to verify the justification of the next mandatory header.
+async fn prepare_initialization_data_at<C: ChainWithGrandpa>(
+	client: &impl Client<C>,
+	at_block: AtBlock,
+) -> anyhow::Result<InitializationData<HeaderOf<C>>>
+where
+	HashOf<C>: From<H256>,
+{
+	let header = match at_block {
+		AtBlock::Number(number) => client.header_by_number(number.into()).await?,
+		AtBlock::Hash(hash) => client.header_by_hash(hash.into()).await?,
+	};
+	let header_id = (*header.number(), header.hash());
+
+	let best_finalized_header =
+		client.header_by_hash(client.best_finalized_header_hash().await?).await?;
+	if header_id.0 > *best_finalized_header.number() {
+		return Err(anyhow::format_err!(
+			"{} header {:?} is not finalized yet. Best finalized header: {}",
+			C::NAME,
+			header_id,
+			best_finalized_header.number(),
+		))
+	}
+	if client.header_hash_by_number(header_id.0).await? != header_id.1 {
+		return Err(anyhow::format_err!(
+			"{} header {:?} is not on the finalized chain",
+			C::NAME,
+			header_id,
+		))
+	}
+
+	let authority_set = authority_set_at(client, header_id.1).await?;
+	let init_data = InitializationData {
+		header: Box::new(header),
+		authority_list: authority_set.authorities,
+		set_id: authority_set.set_id,
+		operating_mode: BasicOperatingMode::Normal,
+	};
+
+	let to = block_number::<C>(*best_finalized_header.number())?;
+	let from = block_number::<C>(header_id.0)?.saturating_add(1);
+	match find_next_mandatory_header(client, from, to).await? {
+		Some(mandatory) => verify_authorities_set(client, &init_data, mandatory).await?,
+		None => log::warn!(
+			target: LOG_TARGET,
+			"There are no finalized mandatory {} headers after {:?}. Unable to verify \
+			authorities set {}",
+			C::NAME,
+			header_id,
+			init_data.set_id,
+		),
+	}
+
+	log::info!(
+		target: LOG_TARGET,
+		"Prepared initialization data for {} header {:?}: authorities set {} of {} authorities",
+		C::NAME,
+		header_id,
+		init_data.set_id,
+		init_data.authority_list.len(),
+	);
+
+	Ok(init_data)
+}
+
+/// Read the GRANDPA authorities set from the state at the given header.
+pub async fn authority_set_at<C: ChainWithGrandpa>(
+	client: &impl Client<C>,
+	at: HashOf<C>,
+) -> anyhow::Result<AuthoritySet> {
+	let authorities = client.state_call(at, GRANDPA_AUTHORITIES_METHOD.into(), ()).await?;
+	let set_id = client.state_call(at, GRANDPA_CURRENT_SET_ID_METHOD.into(), ()).await?;
+	Ok(AuthoritySet::new(authorities, set_id))
+}
+
+/// Returns the first mandatory header in the range.
+async fn find_next_mandatory_header<C: ChainWithGrandpa>(
+	client: &impl Client<C>,
+	from: u32,
+	to: u32,
+) -> anyhow::Result<Option<HeaderOf<C>>> {
+	let mut headers = futures::stream::iter(from..=to)
+		.map(|number| async move {
+			let header = client.header_by_number(BlockNumberOf::<C>::from(number)).await;
+			(number, header)
+		})
+		.buffered(SCAN_CONCURRENCY);
+	while let Some((number, header)) = headers.next().await {
+		let header = header.map_err(|e| {
+			anyhow::format_err!("Failed to read {} header {}: {}", C::NAME, number, e)
+		})?;
+		if GrandpaConsensusLogReader::<BlockNumberOf<C>>::schedules_authorities_change(
+			header.digest(),
+		) {
+			return Ok(Some(header))
+		}
+	}
+
+	Ok(None)
+}
+
+/// Verify that the justification of the mandatory header is signed by the authorities set from
+/// the initialization data.
+async fn verify_authorities_set<C: ChainWithGrandpa>(
+	client: &impl Client<C>,
+	init_data: &InitializationData<HeaderOf<C>>,
+	mandatory: HeaderOf<C>,
+) -> anyhow::Result<()> {
+	let mandatory_id = (*mandatory.number(), mandatory.hash());
+	let block = client.block_by_hash(mandatory_id.1).await?;
+	let justification = block.justification(GRANDPA_ENGINE_ID).ok_or_else(|| {
+		anyhow::format_err!(
+			"Justification of mandatory {} header {:?} is missing",
+			C::NAME,
+			mandatory_id,
+		)
+	})?;
+	let justification = GrandpaJustification::<HeaderOf<C>>::decode(&mut &justification[..])
+		.map_err(|e| anyhow::format_err!("Failed to decode {} justification: {:?}", C::NAME, e))?;
+
+	let context: JustificationVerificationContext =
+		AuthoritySet::new(init_data.authority_list.clone(), init_data.set_id)
+			.try_into()
+			.map_err(|e| anyhow::format_err!("Invalid authorities set: {:?}", e))?;
+	verify_justification::<HeaderOf<C>>(mandatory_id, &context, &justification).map_err(|e| {
+		anyhow::format_err!(
+			"Authorities set {} has not signed the next mandatory {} header {:?}: {:?}",
+			init_data.set_id,
+			C::NAME,
+			mandatory_id,
+			e,
+		)
+	})?;
+
+	log::info!(
+		target: LOG_TARGET,
+		"Authorities set {} has signed the next mandatory {} header {:?}",
+		init_data.set_id,
+		C::NAME,
+		mandatory_id,
+	);
+
+	Ok(())
+}
+
+/// Convert source header number to `u32`.
+fn block_number<C: Chain>(number: BlockNumberOf<C>) -> anyhow::Result<u32> {
+	number
+		.try_into()
+		.map_err(|_| anyhow::format_err!("Unexpected {} header number: {}", C::NAME, number))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn at_block_is_parsed() {
+		assert_eq!(AtBlock::from_str("42").unwrap(), AtBlock::Number(42));
+		assert_eq!(
+			AtBlock::from_str(&format!("{:?}", H256::repeat_byte(1))).unwrap(),
+			AtBlock::Hash(H256::repeat_byte(1)),
+		);
+		assert!(AtBlock::from_str("0x42").is_err());
+		assert!(AtBlock::from_str("latest").is_err());
 	}
 }
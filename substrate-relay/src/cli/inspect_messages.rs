@@ -0,0 +1,344 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Inspection of outbound messages.
+//!
+//! Payloads of outbound messages are read from the source chain messages pallet and decoded as
+//! bridged XCM messages (`bp_xcm_bridge_hub::BridgeMessage`): the universal location of the
+//! message destination, followed by the `VersionedXcm`. Dispatch weight and size of messages
+//! are read using the outbound lane runtime API - the same way the messages relay does that.
+
+use crate::{
+	bridges::{
+		kusama_polkadot::{
+			bridge_hub_kusama_messages_to_bridge_hub_polkadot::BridgeHubKusamaToBridgeHubPolkadotMessagesCliBridge,
+			bridge_hub_polkadot_messages_to_bridge_hub_kusama::BridgeHubPolkadotToBridgeHubKusamaMessagesCliBridge,
+		},
+		polkadot_bulletin::{
+			bridge_hub_polkadot_messages_to_polkadot_bulletin::BridgeHubPolkadotToPolkadotBulletinMessagesCliBridge,
+			polkadot_bulletin_messages_to_bridge_hub_polkadot::PolkadotBulletinToBridgeHubPolkadotMessagesCliBridge,
+		},
+		rococo_bulletin::{
+			bridge_hub_rococo_messages_to_rococo_bulletin::BridgeHubRococoToRococoBulletinMessagesCliBridge,
+			rococo_bulletin_messages_to_bridge_hub_rococo::RococoBulletinToBridgeHubRococoMessagesCliBridge,
+			BridgeHubRococoAsBridgeHubPolkadot,
+		},
+		rococo_westend::{
+			bridge_hub_rococo_messages_to_bridge_hub_westend::BridgeHubRococoToBridgeHubWestendMessagesCliBridge,
+			bridge_hub_westend_messages_to_bridge_hub_rococo::BridgeHubWestendToBridgeHubRococoMessagesCliBridge,
+		},
+	},
+	cli::relay_messages::FullBridge,
+};
+
+use bp_messages::{MessageNonce, OutboundMessageDetails};
+use codec::Decode;
+use relay_bridge_hub_kusama_client::BridgeHubKusama;
+use relay_bridge_hub_polkadot_client::BridgeHubPolkadot;
+use relay_bridge_hub_rococo_client::BridgeHubRococo;
+use relay_bridge_hub_westend_client::BridgeHubWestend;
+use relay_polkadot_bulletin_client::PolkadotBulletin;
+use relay_substrate_client::{ChainWithMessages, Client};
+use sp_runtime::traits::TryConvert;
+use std::{collections::BTreeMap, str::FromStr};
+use structopt::StructOpt;
+use strum::VariantNames;
+use substrate_relay_helper::{
+	cli::{bridge::MessagesCliBridge, chain_schema::SourceConnectionParams, HexLaneId},
+	messages::SubstrateMessageLane,
+};
+
+/// Inspect outbound messages at the source chain.
+#[derive(StructOpt)]
+pub struct InspectMessages {
+	/// A bridge instance to inspect messages of.
+	#[structopt(possible_values = FullBridge::VARIANTS, case_insensitive = true)]
+	bridge: FullBridge,
+	/// Hex-encoded lane identifier.
+	#[structopt(long)]
+	lane: HexLaneId,
+	/// Range of message nonces (`<first>..<last>`, inclusive).
+	#[structopt(long)]
+	nonces: NonceRange,
+	#[structopt(flatten)]
+	source: SourceConnectionParams,
+}
+
+/// Inclusive range of message nonces.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NonceRange {
+	first: MessageNonce,
+	last: MessageNonce,
+}
+
+impl FromStr for NonceRange {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (first, last) = s
+			.split_once("..")
+			.ok_or_else(|| anyhow::format_err!("Expected nonce range `<first>..<last>`"))?;
+		let range = NonceRange { first: first.parse()?, last: last.parse()? };
+		if range.first > range.last {
+			return Err(anyhow::format_err!("First nonce of the range is after the last nonce"))
+		}
+		Ok(range)
+	}
+}
+
+/// Outbound message payload, decoded as a bridged XCM message.
+#[derive(Debug, Default, PartialEq)]
+pub struct XcmMessage {
+	/// Universal location of the message destination.
+	pub destination: String,
+	/// Message origin junctions, set by `UniversalOrigin` and `DescendOrigin` instructions.
+	pub origin: Vec<String>,
+	/// Assets, placed into the holding register by the message, with their amounts.
+	pub assets: Vec<String>,
+	/// All message instructions.
+	pub instructions: Vec<String>,
+}
+
+/// Chain, which is sending XCM messages over the bridge.
+pub trait ChainWithXcmMessages: ChainWithMessages {
+	/// Decode outbound message payload.
+	fn decode_message(payload: &[u8]) -> anyhow::Result<XcmMessage>;
+}
+
+/// Describe instructions of the V3 or V4 XCM message.
+macro_rules! describe_xcm {
+	($message:ident, $xcm:expr, $version:ident, $asset_module:ident) => {
+		for instruction in $xcm.0 {
+			match instruction {
+				$version::Instruction::WithdrawAsset(ref assets) |
+				$version::Instruction::ReserveAssetDeposited(ref assets) |
+				$version::Instruction::ReceiveTeleportedAsset(ref assets) =>
+					$message.assets.extend(assets.0.iter().map(|asset| match asset.fun {
+						$version::$asset_module::Fungibility::Fungible(amount) =>
+							format!("{:?}: {}", asset.id, amount),
+						$version::$asset_module::Fungibility::NonFungible(ref instance) =>
+							format!("{:?}: {:?}", asset.id, instance),
+					})),
+				$version::Instruction::UniversalOrigin(ref junction) =>
+					$message.origin = vec![format!("{:?}", junction)],
+				$version::Instruction::DescendOrigin(ref interior) =>
+					$message.origin.push(format!("{:?}", interior)),
+				_ => (),
+			}
+			$message.instructions.push(format!("{:?}", instruction));
+		}
+	};
+}
+
+/// Implement `ChainWithXcmMessages` for a bridge hub, using its generated XCM types.
+macro_rules! impl_bridge_hub_with_xcm_messages {
+	($chain:ty, $client:ident) => {
+		impl ChainWithXcmMessages for $chain {
+			fn decode_message(payload: &[u8]) -> anyhow::Result<XcmMessage> {
+				use $client::runtime_types::{
+					staging_xcm::v4,
+					xcm::{v2, v3, VersionedXcm},
+				};
+
+				/// Mirrors the `xcm::VersionedInteriorLocation`, which is missing from the
+				/// generated runtime types.
+				#[derive(Decode)]
+				enum VersionedInteriorLocation {
+					#[codec(index = 2)]
+					V2(v2::multilocation::Junctions),
+					#[codec(index = 3)]
+					V3(v3::junctions::Junctions),
+					#[codec(index = 4)]
+					V4(v4::junctions::Junctions),
+				}
+
+				let (destination, xcm) =
+					<(VersionedInteriorLocation, VersionedXcm)>::decode(&mut &payload[..])?;
+				let destination = match destination {
+					VersionedInteriorLocation::V2(interior) => format!("V2({:?})", interior),
+					VersionedInteriorLocation::V3(interior) => format!("V3({:?})", interior),
+					VersionedInteriorLocation::V4(interior) => format!("V4({:?})", interior),
+				};
+				let mut message = XcmMessage { destination, ..Default::default() };
+				match xcm {
+					VersionedXcm::V2(xcm) => message
+						.instructions
+						.extend(xcm.0.iter().map(|instruction| format!("{:?}", instruction))),
+					VersionedXcm::V3(xcm) => describe_xcm!(message, xcm, v3, multiasset),
+					VersionedXcm::V4(xcm) => describe_xcm!(message, xcm, v4, asset),
+				}
+				Ok(message)
+			}
+		}
+	};
+}
+
+impl_bridge_hub_with_xcm_messages!(BridgeHubRococo, relay_bridge_hub_rococo_client);
+impl_bridge_hub_with_xcm_messages!(BridgeHubWestend, relay_bridge_hub_westend_client);
+impl_bridge_hub_with_xcm_messages!(BridgeHubKusama, relay_bridge_hub_kusama_client);
+impl_bridge_hub_with_xcm_messages!(BridgeHubPolkadot, relay_bridge_hub_polkadot_client);
+
+impl ChainWithXcmMessages for BridgeHubRococoAsBridgeHubPolkadot {
+	fn decode_message(payload: &[u8]) -> anyhow::Result<XcmMessage> {
+		BridgeHubRococo::decode_message(payload)
+	}
+}
+
+impl ChainWithXcmMessages for PolkadotBulletin {
+	// there are no XCM types in the Bulletin chain metadata, but the XCM encoding does not
+	// depend on the chain, so we may use types of its bridged bridge hub
+	fn decode_message(payload: &[u8]) -> anyhow::Result<XcmMessage> {
+		BridgeHubPolkadot::decode_message(payload)
+	}
+}
+
+impl InspectMessages {
+	/// Name of the bridge.
+	pub fn bridge_name(&self) -> String {
+		format!("{:?}", self.bridge)
+	}
+
+	/// Run the command.
+	pub async fn run(self) -> anyhow::Result<()> {
+		match self.bridge {
+			FullBridge::BridgeHubRococoToBridgeHubWestend =>
+				inspect_messages::<BridgeHubRococoToBridgeHubWestendMessagesCliBridge>(self).await,
+			FullBridge::BridgeHubWestendToBridgeHubRococo =>
+				inspect_messages::<BridgeHubWestendToBridgeHubRococoMessagesCliBridge>(self).await,
+			FullBridge::BridgeHubKusamaToBridgeHubPolkadot =>
+				inspect_messages::<BridgeHubKusamaToBridgeHubPolkadotMessagesCliBridge>(self).await,
+			FullBridge::BridgeHubPolkadotToBridgeHubKusama =>
+				inspect_messages::<BridgeHubPolkadotToBridgeHubKusamaMessagesCliBridge>(self).await,
+			FullBridge::PolkadotBulletinToBridgeHubPolkadot =>
+				inspect_messages::<PolkadotBulletinToBridgeHubPolkadotMessagesCliBridge>(self).await,
+			FullBridge::BridgeHubPolkadotToPolkadotBulletin =>
+				inspect_messages::<BridgeHubPolkadotToPolkadotBulletinMessagesCliBridge>(self).await,
+			FullBridge::RococoBulletinToBridgeHubRococo =>
+				inspect_messages::<RococoBulletinToBridgeHubRococoMessagesCliBridge>(self).await,
+			FullBridge::BridgeHubRococoToRococoBulletin =>
+				inspect_messages::<BridgeHubRococoToRococoBulletinMessagesCliBridge>(self).await,
+		}
+	}
+}
+
+/// Lane identifier type of the messages bridge.
+type LaneIdOf<Bridge> =
+	<<Bridge as MessagesCliBridge>::MessagesLane as SubstrateMessageLane>::LaneId;
+
+async fn inspect_messages<Bridge: MessagesCliBridge>(params: InspectMessages) -> anyhow::Result<()>
+where
+	Bridge::Source: ChainWithXcmMessages,
+	Bridge::Target: ChainWithMessages,
+{
+	let lane: LaneIdOf<Bridge> = HexLaneId::try_convert(params.lane)
+		.map_err(|invalid_lane_id| anyhow::format_err!("Invalid laneId: {:?}!", invalid_lane_id))?;
+	let client = params.source.into_client::<Bridge::Source>().await?;
+	let at = client.best_header_hash().await?;
+
+	let details: Vec<OutboundMessageDetails> = client
+		.state_call(
+			at,
+			Bridge::Target::TO_CHAIN_MESSAGE_DETAILS_METHOD.into(),
+			(lane, params.nonces.first, params.nonces.last),
+		)
+		.await?;
+	let details = details
+		.into_iter()
+		.map(|details| (details.nonce, details))
+		.collect::<BTreeMap<_, _>>();
+
+	for nonce in params.nonces.first..=params.nonces.last {
+		let storage_key = bp_messages::storage_keys::message_key(
+			Bridge::Target::WITH_CHAIN_MESSAGES_PALLET_NAME,
+			&lane,
+			nonce,
+		);
+		let payload = match client.raw_storage_value(at, storage_key).await? {
+			Some(payload) => Vec::<u8>::decode(&mut &payload.0[..])?,
+			None => {
+				println!(
+					"Message {}: missing from the outbound lane (it is either confirmed, or not \
+					sent yet)",
+					nonce,
+				);
+				continue
+			},
+		};
+
+		match details.get(&nonce) {
+			Some(details) => println!(
+				"Message {}: size {}, dispatch weight {:?}",
+				nonce, details.size, details.dispatch_weight,
+			),
+			None => println!("Message {}: size {}", nonce, payload.len()),
+		}
+		match Bridge::Source::decode_message(&payload) {
+			Ok(message) => print_message(&message),
+			Err(e) => println!("  failed to decode XCM message {}: {}", hex::encode(&payload), e),
+		}
+	}
+
+	Ok(())
+}
+
+fn print_message(message: &XcmMessage) {
+	println!("  destination: {}", message.destination);
+	println!("  origin: {}", message.origin.join(" / "));
+	for asset in &message.assets {
+		println!("  asset: {}", asset);
+	}
+	println!("  instructions:");
+	for instruction in &message.instructions {
+		println!("    {}", instruction);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use codec::Encode;
+	use relay_bridge_hub_polkadot_client::runtime_types::staging_xcm::v4::{
+		asset::{Asset, AssetId, Assets, Fungibility},
+		junction::{Junction, NetworkId},
+		junctions::Junctions,
+		location::Location,
+		Instruction, Xcm,
+	};
+
+	#[test]
+	fn bridged_xcm_message_is_decoded() {
+		let relay_chain = Location { parents: 1, interior: Junctions::Here };
+		let xcm = Xcm(vec![
+			Instruction::UniversalOrigin(Junction::GlobalConsensus(NetworkId::Kusama)),
+			Instruction::DescendOrigin(Junctions::X1([Junction::Parachain(1000)])),
+			Instruction::ReserveAssetDeposited(Assets(vec![Asset {
+				id: AssetId(relay_chain),
+				fun: Fungibility::Fungible(100),
+			}])),
+		]);
+		// `VersionedInteriorLocation::V4`, followed by `VersionedXcm::V4`
+		let payload =
+			[vec![4], Junctions::X1([Junction::Parachain(1002)]).encode(), vec![4], xcm.encode()]
+				.concat();
+
+		let message = BridgeHubPolkadot::decode_message(&payload).unwrap();
+		assert_eq!(message.destination, "V4(X1([Parachain(1002)]))");
+		assert_eq!(message.origin, vec!["GlobalConsensus(Kusama)", "X1([Parachain(1000)])"]);
+		assert_eq!(message.assets, vec!["AssetId(Location { parents: 1, interior: Here }): 100"],);
+		assert_eq!(message.instructions.len(), 3);
+		assert!(NonceRange::from_str("5..3").is_err());
+	}
+}
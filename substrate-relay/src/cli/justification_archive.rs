@@ -0,0 +1,351 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Local archive of GRANDPA justifications.
+//!
+//! Nodes are only keeping justifications of some blocks, so the justification of the mandatory
+//! header may be pruned before it is relayed. With `--justification-archive <dir>`, the RPC proxy
+//! of every source node subscribes to its GRANDPA justifications and stores justifications of
+//! mandatory headers (headers that are changing the GRANDPA authorities set) in the directory.
+//! Justifications of other headers are not archived, because the relay may always use the
+//! justification of the later header instead. Nodes of chains without GRANDPA finality
+//! (parachains) are rejecting the subscription and are not archived.
+//!
+//! When the relay reads the block (`chain_getBlock`) and the node returns it without the GRANDPA
+//! justification, the proxy adds the archived justification to the response.
+//!
+//! Every justification is stored in the `<dir>/<header-hash>.justification` file as hex-encoded
+//! SCALE. Archived files may be submitted with the `import-justification` command.
+
+use crate::cli::LOG_TARGET;
+
+use async_tungstenite::tungstenite::Message;
+use codec::Decode;
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use sp_core::H256;
+use std::{
+	collections::BTreeMap,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+/// GRANDPA consensus engine identifier.
+pub const GRANDPA_ENGINE_ID: [u8; 4] = *b"FRNK";
+/// Index of the `DigestItem::Consensus` variant.
+const CONSENSUS_DIGEST_ITEM: u8 = 4;
+/// Indices of GRANDPA `ScheduledChange` and `ForcedChange` consensus logs.
+const AUTHORITIES_CHANGE_LOGS: [u8; 2] = [1, 2];
+/// Identifier of the justifications subscription request.
+const SUBSCRIBE_REQUEST_ID: u64 = 1;
+/// Extension of justification files.
+const JUSTIFICATION_FILE_EXTENSION: &str = "justification";
+/// Delay before resubscribing to justifications after failure.
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(10);
+
+/// Directory with archived justifications.
+#[derive(Clone, Debug)]
+pub struct JustificationArchive {
+	dir: PathBuf,
+}
+
+impl JustificationArchive {
+	/// Open the archive, creating its directory if required.
+	pub fn open(dir: &Path) -> anyhow::Result<Self> {
+		std::fs::create_dir_all(dir).map_err(|e| {
+			anyhow::format_err!("Failed to create justification archive {}: {}", dir.display(), e)
+		})?;
+		Ok(JustificationArchive { dir: dir.into() })
+	}
+
+	/// Store encoded justification. Returns hash of the justified header.
+	///
+	/// The justification is written to the temporary file first, so the relay never reads
+	/// partially written justifications.
+	pub fn save(&self, justification: &[u8]) -> anyhow::Result<H256> {
+		let hash = target_header_hash(justification)?;
+		let path = self.path(&hash);
+		let temp_path = path.with_extension(format!("{}.tmp", JUSTIFICATION_FILE_EXTENSION));
+		std::fs::write(&temp_path, format!("0x{}", hex::encode(justification)))?;
+		std::fs::rename(&temp_path, &path)?;
+		Ok(hash)
+	}
+
+	/// Read encoded justification of the header, if it is archived.
+	pub fn load(&self, hash: &H256) -> anyhow::Result<Option<Vec<u8>>> {
+		let path = self.path(hash);
+		if !path.exists() {
+			return Ok(None)
+		}
+		read_justification_file(&path).map(Some)
+	}
+
+	fn path(&self, hash: &H256) -> PathBuf {
+		self.dir.join(format!("{:?}.{}", hash, JUSTIFICATION_FILE_EXTENSION))
+	}
+}
+
+/// Read hex-encoded justification from the file.
+pub fn read_justification_file(path: &Path) -> anyhow::Result<Vec<u8>> {
+	let encoded = std::fs::read_to_string(path)
+		.map_err(|e| anyhow::format_err!("Failed to read {}: {}", path.display(), e))?;
+	let encoded = encoded.trim();
+	hex::decode(encoded.strip_prefix("0x").unwrap_or(encoded))
+		.map_err(|e| anyhow::format_err!("Invalid justification in {}: {}", path.display(), e))
+}
+
+/// Returns hash of the header, justified by the encoded GRANDPA justification.
+///
+/// All chains with GRANDPA finality, that we are bridging with, are using `H256` hashes. The
+/// justification starts with the round number, followed by the hash of the commit target.
+fn target_header_hash(justification: &[u8]) -> anyhow::Result<H256> {
+	let (_round, hash) = <(u64, H256)>::decode(&mut &justification[..])
+		.map_err(|e| anyhow::format_err!("Invalid GRANDPA justification: {:?}", e))?;
+	Ok(hash)
+}
+
+/// Returns true if the header (as returned by the `chain_getHeader`) changes the GRANDPA
+/// authorities set.
+fn is_mandatory_header(header: &Value) -> bool {
+	header["digest"]["logs"].as_array().into_iter().flatten().any(|log| {
+		let log = match log.as_str().and_then(|log| hex::decode(log.trim_start_matches("0x")).ok())
+		{
+			Some(log) => log,
+			None => return false,
+		};
+		match <(u8, [u8; 4], Vec<u8>)>::decode(&mut &log[..]) {
+			Ok((CONSENSUS_DIGEST_ITEM, GRANDPA_ENGINE_ID, data)) =>
+				data.first().map_or(false, |log| AUTHORITIES_CHANGE_LOGS.contains(log)),
+			_ => false,
+		}
+	})
+}
+
+/// Subscribe to GRANDPA justifications of the node and store justifications of mandatory headers
+/// in the archive.
+pub async fn archive_justifications(upstream: String, archive: JustificationArchive) {
+	loop {
+		match subscribe_justifications(&upstream, &archive).await {
+			Ok(false) => {
+				log::info!(
+					target: LOG_TARGET,
+					"Node {} does not provide GRANDPA justifications. Archive is disabled",
+					upstream,
+				);
+				return
+			},
+			Ok(true) => log::warn!(
+				target: LOG_TARGET,
+				"Justifications subscription at {} is closed",
+				upstream,
+			),
+			Err(e) => log::warn!(
+				target: LOG_TARGET,
+				"Justifications subscription at {} has failed: {}",
+				upstream,
+				e,
+			),
+		}
+		async_std::task::sleep(RESUBSCRIBE_DELAY).await;
+	}
+}
+
+/// Archive justifications until the subscription is closed. Returns false if the node does not
+/// support GRANDPA justifications subscription.
+async fn subscribe_justifications(
+	upstream: &str,
+	archive: &JustificationArchive,
+) -> anyhow::Result<bool> {
+	let (mut node, _) = async_tungstenite::async_std::connect_async(upstream).await?;
+	let request = json!({
+		"jsonrpc": "2.0",
+		"id": SUBSCRIBE_REQUEST_ID,
+		"method": "grandpa_subscribeJustifications",
+		"params": [],
+	});
+	node.send(Message::Text(request.to_string())).await?;
+
+	// justifications, waiting for their headers, mapped by header request identifiers
+	let mut justifications = BTreeMap::<u64, Vec<u8>>::new();
+	let mut next_id = SUBSCRIBE_REQUEST_ID;
+	while let Some(message) = node.next().await {
+		let message: Value = match message? {
+			Message::Text(message) => serde_json::from_str(&message)?,
+			Message::Close(_) => break,
+			_ => continue,
+		};
+		if message["id"] == SUBSCRIBE_REQUEST_ID && message.get("error").is_some() {
+			return Ok(false)
+		}
+
+		if let Some(justification) =
+			message["id"].as_u64().and_then(|id| justifications.remove(&id))
+		{
+			if !is_mandatory_header(&message["result"]) {
+				continue
+			}
+
+			let hash = archive.save(&justification)?;
+			log::debug!(
+				target: LOG_TARGET,
+				header_hash:? = hash;
+				"Archived justification of mandatory header {:?} from {}",
+				hash,
+				upstream,
+			);
+			continue
+		}
+
+		let justification = match message["params"]["result"].as_str() {
+			Some(justification) => hex::decode(justification.trim_start_matches("0x"))?,
+			None => continue,
+		};
+		next_id += 1;
+		let request = json!({
+			"jsonrpc": "2.0",
+			"id": next_id,
+			"method": "chain_getHeader",
+			"params": [target_header_hash(&justification)?],
+		});
+		justifications.insert(next_id, justification);
+		node.send(Message::Text(request.to_string())).await?;
+	}
+
+	Ok(true)
+}
+
+/// Adds archived justifications to blocks, returned by the node without them.
+pub struct ArchiveInterceptor {
+	archive: JustificationArchive,
+	/// Identifiers of block requests, mapped to requested block hashes.
+	block_requests: BTreeMap<String, H256>,
+}
+
+impl ArchiveInterceptor {
+	pub fn new(archive: JustificationArchive) -> Self {
+		ArchiveInterceptor { archive, block_requests: BTreeMap::new() }
+	}
+
+	/// Remember block requests of the relay.
+	pub fn on_request(&mut self, request: &str) {
+		let request: Value = match serde_json::from_str(request) {
+			Ok(request) => request,
+			Err(_) => return,
+		};
+		if request["method"] != "chain_getBlock" {
+			return
+		}
+
+		// justifications of best blocks are never requested by the relay
+		let hash = request["params"][0]
+			.as_str()
+			.and_then(|hash| hex::decode(hash.trim_start_matches("0x")).ok())
+			.filter(|hash| hash.len() == 32);
+		if let Some(hash) = hash {
+			self.block_requests.insert(request["id"].to_string(), H256::from_slice(&hash));
+		}
+	}
+
+	/// Add the archived justification to the block response, if the node has returned it without
+	/// the GRANDPA justification.
+	pub fn on_response(&mut self, response: String) -> String {
+		let mut parsed: Value = match serde_json::from_str(&response) {
+			Ok(parsed) => parsed,
+			Err(_) => return response,
+		};
+		let hash = match self.block_requests.remove(&parsed["id"].to_string()) {
+			Some(hash) => hash,
+			None => return response,
+		};
+		let block = &mut parsed["result"];
+		if !block.is_object() {
+			return response
+		}
+
+		let mut justifications = block["justifications"].as_array().cloned().unwrap_or_default();
+		let grandpa_engine_id = json!(GRANDPA_ENGINE_ID);
+		if justifications.iter().any(|justification| justification[0] == grandpa_engine_id) {
+			return response
+		}
+		let justification = match self.archive.load(&hash) {
+			Ok(Some(justification)) => justification,
+			Ok(None) => return response,
+			Err(e) => {
+				log::warn!(target: LOG_TARGET, "Failed to read archived justification: {}", e);
+				return response
+			},
+		};
+
+		log::info!(
+			target: LOG_TARGET,
+			header_hash:? = hash;
+			"Using archived justification of {:?}",
+			hash,
+		);
+		justifications.push(json!([GRANDPA_ENGINE_ID, justification]));
+		block["justifications"] = Value::Array(justifications);
+		parsed.to_string()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use codec::Encode;
+
+	#[test]
+	fn archived_justification_is_added_to_block() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let archive = JustificationArchive::open(&temp_dir.path().join("archive")).unwrap();
+		let hash = H256::repeat_byte(42);
+		let justification = (7u64, hash, 100u32, Vec::<u8>::new(), Vec::<u8>::new()).encode();
+		assert_eq!(archive.save(&justification).unwrap(), hash);
+		assert_eq!(archive.load(&hash).unwrap(), Some(justification.clone()));
+		assert_eq!(archive.load(&H256::zero()).unwrap(), None);
+		assert_eq!(std::fs::read_dir(temp_dir.path().join("archive")).unwrap().count(), 1);
+
+		// only headers that are changing GRANDPA authorities are archived
+		let header = |log: (u8, [u8; 4], Vec<u8>)| json!({ "digest": { "logs": [format!("0x{}", hex::encode(log.encode()))] } });
+		assert!(is_mandatory_header(&header((4, GRANDPA_ENGINE_ID, vec![1, 0]))));
+		assert!(is_mandatory_header(&header((4, GRANDPA_ENGINE_ID, vec![2, 0]))));
+		assert!(!is_mandatory_header(&header((4, GRANDPA_ENGINE_ID, vec![3, 0]))));
+		assert!(!is_mandatory_header(&header((6, *b"BABE", vec![1, 0]))));
+
+		let mut interceptor = ArchiveInterceptor::new(archive);
+		let request = |id: u64, hash: H256| {
+			json!({ "jsonrpc": "2.0", "id": id, "method": "chain_getBlock", "params": [hash] })
+				.to_string()
+		};
+		let response = |id: u64| {
+			json!({
+				"jsonrpc": "2.0",
+				"id": id,
+				"result": { "block": { "extrinsics": [] }, "justifications": null },
+			})
+			.to_string()
+		};
+
+		// archived justification is added
+		interceptor.on_request(&request(1, hash));
+		let block: Value = serde_json::from_str(&interceptor.on_response(response(1))).unwrap();
+		assert_eq!(block["result"]["justifications"], json!([[GRANDPA_ENGINE_ID, justification]]));
+
+		// other blocks and responses are not changed
+		interceptor.on_request(&request(2, H256::zero()));
+		assert_eq!(interceptor.on_response(response(2)), response(2));
+		assert_eq!(interceptor.on_response(response(1)), response(1));
+	}
+}
@@ -0,0 +1,501 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Leader election for running several relay replicas.
+//!
+//! When several replicas of the same relay are running, only the one that holds the lease is
+//! allowed to submit transactions. Other replicas (standbys) are connected to all nodes, but
+//! are not starting their relay loops until they acquire the lease. The leader renews its lease
+//! periodically. If it fails to do that before the lease expires, it stops relaying and exits,
+//! so that one of standbys may take over.
+
+use crate::cli::{
+	shutdown::{is_shutdown_requested, on_relay_started},
+	LOG_TARGET,
+};
+
+use async_trait::async_trait;
+use futures::{select, Future, FutureExt};
+use std::{
+	fs::File,
+	os::unix::io::AsRawFd,
+	path::PathBuf,
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use structopt::StructOpt;
+
+/// Number of attempts to lock the lease file.
+const LOCK_ATTEMPTS: usize = 100;
+/// Interval between attempts to lock the lease file.
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Storage of the relay leadership lease.
+#[async_trait]
+pub trait LeaseStore: Send + Sync {
+	/// Try to acquire or renew the lease for given holder.
+	///
+	/// Returns `true` if the `holder` owns the lease for the next `timeout` after this call.
+	async fn try_acquire(&self, holder: &str, timeout: Duration) -> anyhow::Result<bool>;
+	/// Release the lease if it is owned by the given holder.
+	///
+	/// This method is called when the leader is dropped, so it may block for a short time.
+	fn release(&self, holder: &str) -> anyhow::Result<()>;
+}
+
+/// Lease, stored in the `LeaseStore`.
+#[derive(Clone, Debug, PartialEq)]
+struct Lease {
+	/// Current lease holder.
+	holder: String,
+	/// Unix timestamp (in seconds) when the lease expires.
+	expires_at: u64,
+}
+
+impl Lease {
+	/// Returns true if `holder` may take this lease at `now`.
+	fn may_be_taken_by(&self, holder: &str, now: u64) -> bool {
+		self.holder == holder || self.expires_at <= now
+	}
+}
+
+/// Lease store that is backed by a file on the (shared) storage.
+///
+/// All replicas must have access to the same file and their clocks must be roughly synchronized.
+/// Replicas are reading and updating the lease while holding an exclusive `flock` on the
+/// `<path>.lock` file, so only one replica may change the lease at a time. The lock is released
+/// by the kernel when the replica crashes, so there are no stale locks. The shared storage must
+/// support `flock` (e.g. local filesystem or NFSv4).
+pub struct FileLeaseStore {
+	path: PathBuf,
+}
+
+impl FileLeaseStore {
+	/// Create new file lease store.
+	pub fn new(path: PathBuf) -> Self {
+		FileLeaseStore { path }
+	}
+
+	fn lock_file(&self) -> anyhow::Result<File> {
+		let mut lock_path = self.path.clone().into_os_string();
+		lock_path.push(".lock");
+		Ok(std::fs::OpenOptions::new().write(true).create(true).open(lock_path)?)
+	}
+
+	fn read(&self) -> anyhow::Result<Option<Lease>> {
+		let contents = match std::fs::read_to_string(&self.path) {
+			Ok(contents) => contents,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+			Err(e) => return Err(e.into()),
+		};
+
+		let mut lines = contents.lines();
+		match (lines.next(), lines.next().map(str::parse)) {
+			(Some(holder), Some(Ok(expires_at))) =>
+				Ok(Some(Lease { holder: holder.into(), expires_at })),
+			// the file is corrupted - the lease will be overwritten by the next writer
+			_ => Ok(Some(Lease { holder: String::new(), expires_at: 0 })),
+		}
+	}
+
+	fn write(&self, lease: &Lease) -> anyhow::Result<()> {
+		// write to the temporary file first and then rename it, so that the lease is never
+		// partially written, even if the replica crashes
+		let mut temp_path = self.path.clone().into_os_string();
+		temp_path.push(format!(".{}", lease.holder));
+		std::fs::write(&temp_path, format!("{}\n{}\n", lease.holder, lease.expires_at))?;
+		std::fs::rename(&temp_path, &self.path)?;
+		Ok(())
+	}
+}
+
+#[async_trait]
+impl LeaseStore for FileLeaseStore {
+	async fn try_acquire(&self, holder: &str, timeout: Duration) -> anyhow::Result<bool> {
+		let _lock = FileLock::acquire(self.lock_file()?).await?;
+		let now = unix_time();
+		if let Some(lease) = self.read()? {
+			if !lease.may_be_taken_by(holder, now) {
+				return Ok(false)
+			}
+		}
+
+		self.write(&Lease { holder: holder.into(), expires_at: now + timeout.as_secs() })?;
+		Ok(true)
+	}
+
+	fn release(&self, holder: &str) -> anyhow::Result<()> {
+		let _lock = FileLock::acquire_blocking(self.lock_file()?)?;
+		match self.read()? {
+			Some(lease) if lease.holder == holder =>
+				self.write(&Lease { holder: holder.into(), expires_at: 0 }),
+			_ => Ok(()),
+		}
+	}
+}
+
+/// Exclusive `flock` on the lock file. The lock is released when the file is closed.
+struct FileLock {
+	_file: File,
+}
+
+impl FileLock {
+	/// Lock the file, waiting until it is unlocked by other replica.
+	async fn acquire(file: File) -> anyhow::Result<Self> {
+		for _ in 0..LOCK_ATTEMPTS {
+			if flock(&file, libc::LOCK_EX | libc::LOCK_NB)? {
+				return Ok(FileLock { _file: file })
+			}
+			async_std::task::sleep(LOCK_RETRY_INTERVAL).await;
+		}
+
+		Err(anyhow::format_err!("Lease file is locked by other replica"))
+	}
+
+	/// Lock the file, blocking current thread until it is unlocked by other replica.
+	///
+	/// The lock is only held while the lease is read and written, so the wait is short.
+	fn acquire_blocking(file: File) -> anyhow::Result<Self> {
+		flock(&file, libc::LOCK_EX)?;
+		Ok(FileLock { _file: file })
+	}
+}
+
+/// Apply `flock` operation to the file. Returns `false` if the non-blocking lock is held by
+/// other replica.
+fn flock(file: &File, operation: libc::c_int) -> std::io::Result<bool> {
+	// SAFETY: the file descriptor is valid while the `file` is alive
+	if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+		return Ok(true)
+	}
+
+	let error = std::io::Error::last_os_error();
+	match error.kind() {
+		std::io::ErrorKind::WouldBlock => Ok(false),
+		_ => Err(error),
+	}
+}
+
+/// Leader election parameters.
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct LeaderElectionParams {
+	/// Path to the lease file on the storage, shared by all relay replicas.
+	///
+	/// If specified, the relay only submits transactions while it holds the lease. Otherwise,
+	/// leader election is disabled.
+	#[structopt(long)]
+	pub leader_lease_file: Option<PathBuf>,
+	/// Unique identifier of this relay replica. Defaults to the `<hostname>-<pid>`.
+	#[structopt(long)]
+	pub leader_id: Option<String>,
+	/// Lease timeout in seconds. If leader fails to renew its lease within this timeout, one of
+	/// standby replicas takes over.
+	#[structopt(long, default_value = "30")]
+	pub leader_lease_timeout: u64,
+}
+
+impl LeaderElectionParams {
+	/// Run given relay future, but only while we are holding the leadership lease.
+	///
+	/// If leader election is disabled, the relay is started immediately.
+	pub async fn run(&self, relay: impl Future<Output = anyhow::Result<()>>) -> anyhow::Result<()> {
+		match self.leader_lease_file {
+			Some(ref path) =>
+				LeaderElection::new(
+					FileLeaseStore::new(path.clone()),
+					self.leader_id.clone().unwrap_or_else(default_leader_id),
+					Duration::from_secs(self.leader_lease_timeout),
+				)
+				.run(relay)
+				.await,
+			None => {
+				on_relay_started();
+				relay.await
+			},
+		}
+	}
+}
+
+/// Leader election over some lease store.
+pub struct LeaderElection<S> {
+	store: S,
+	id: String,
+	timeout: Duration,
+}
+
+impl<S: LeaseStore> LeaderElection<S> {
+	/// Create new leader election.
+	pub fn new(store: S, id: String, timeout: Duration) -> Self {
+		LeaderElection { store, id, timeout }
+	}
+
+	/// Wait until we become a leader and then run the relay.
+	///
+	/// Returns error if the leadership has been lost. Since relay loops may not be paused, the
+	/// process should be restarted (and join as a standby) in this case. If the exit signal is
+	/// received while we are waiting for the leadership, returns immediately.
+	///
+	/// The lease is released when the relay stops, even if this future is dropped (e.g. after
+	/// the second exit signal).
+	pub async fn run(&self, relay: impl Future<Output = anyhow::Result<()>>) -> anyhow::Result<()> {
+		log::info!(target: LOG_TARGET, "Relay {} is waiting for the leadership", self.id);
+		let acquired_at = loop {
+			let acquired_at = Instant::now();
+			if self.try_acquire().await.unwrap_or_else(|e| self.on_store_error(e)) {
+				break acquired_at
+			}
+			if is_shutdown_requested() {
+				return Ok(())
+			}
+			async_std::task::sleep(self.renew_interval()).await;
+		};
+		log::info!(target: LOG_TARGET, "Relay {} has become the leader", self.id);
+		on_relay_started();
+
+		let _lease = LeaseGuard { election: self };
+		let relay = relay.fuse();
+		let renew_lease = self.renew_lease(acquired_at + self.timeout).fuse();
+		futures::pin_mut!(relay, renew_lease);
+
+		select! {
+			result = relay => result,
+			result = renew_lease => result,
+		}
+	}
+
+	/// Renew lease while we are the leader. Returns error when the leadership is lost.
+	///
+	/// If the lease store is unavailable, we keep retrying until the lease that we are holding
+	/// expires. The leadership is lost immediately if the lease is taken by other replica.
+	async fn renew_lease(&self, mut expires_at: Instant) -> anyhow::Result<()> {
+		loop {
+			async_std::task::sleep(self.renew_interval()).await;
+			let renewed_at = Instant::now();
+			match self.try_acquire().await {
+				Ok(true) => expires_at = renewed_at + self.timeout,
+				Ok(false) =>
+					return Err(anyhow::format_err!(
+						"Relay {} has lost the leadership to other replica",
+						self.id
+					)),
+				Err(e) if renewed_at >= expires_at => {
+					self.on_store_error(e);
+					return Err(anyhow::format_err!(
+						"Relay {} has lost the leadership: lease has expired",
+						self.id
+					))
+				},
+				Err(e) => {
+					self.on_store_error(e);
+				},
+			}
+		}
+	}
+
+	async fn try_acquire(&self) -> anyhow::Result<bool> {
+		self.store.try_acquire(&self.id, self.timeout).await
+	}
+
+	fn on_store_error(&self, e: anyhow::Error) -> bool {
+		log::warn!(target: LOG_TARGET, "Relay {} has failed to access lease store: {}", self.id, e);
+		false
+	}
+
+	fn renew_interval(&self) -> Duration {
+		self.timeout / 3
+	}
+}
+
+/// Releases the lease of the leader when dropped.
+struct LeaseGuard<'a, S: LeaseStore> {
+	election: &'a LeaderElection<S>,
+}
+
+impl<'a, S: LeaseStore> Drop for LeaseGuard<'a, S> {
+	fn drop(&mut self) {
+		let id = &self.election.id;
+		if let Err(e) = self.election.store.release(id) {
+			log::warn!(target: LOG_TARGET, "Relay {} has failed to release the lease: {}", id, e);
+		}
+	}
+}
+
+fn default_leader_id() -> String {
+	let hostname = std::env::var("HOSTNAME").unwrap_or_else(|_| "relay".into());
+	format!("{}-{}", hostname, std::process::id())
+}
+
+fn unix_time() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use parking_lot::Mutex;
+	use std::sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	};
+
+	/// In-memory lease store, used as a local stand-in for the shared storage.
+	#[derive(Clone, Default)]
+	pub struct InMemoryLeaseStore {
+		lease: Arc<Mutex<Option<Lease>>>,
+	}
+
+	#[async_trait]
+	impl LeaseStore for InMemoryLeaseStore {
+		async fn try_acquire(&self, holder: &str, timeout: Duration) -> anyhow::Result<bool> {
+			let now = unix_time();
+			let mut lease = self.lease.lock();
+			match *lease {
+				Some(ref lease) if !lease.may_be_taken_by(holder, now) => Ok(false),
+				_ => {
+					*lease =
+						Some(Lease { holder: holder.into(), expires_at: now + timeout.as_secs() });
+					Ok(true)
+				},
+			}
+		}
+
+		fn release(&self, holder: &str) -> anyhow::Result<()> {
+			let mut lease = self.lease.lock();
+			if lease.as_ref().map(|lease| lease.holder == holder).unwrap_or(false) {
+				*lease = None;
+			}
+			Ok(())
+		}
+	}
+
+	const TIMEOUT: Duration = Duration::from_secs(3);
+
+	/// Lease store that fails to renew the lease for the given number of times.
+	#[derive(Clone, Default)]
+	pub struct UnavailableLeaseStore {
+		store: InMemoryLeaseStore,
+		acquired: Arc<AtomicUsize>,
+		failures: usize,
+	}
+
+	#[async_trait]
+	impl LeaseStore for UnavailableLeaseStore {
+		async fn try_acquire(&self, holder: &str, timeout: Duration) -> anyhow::Result<bool> {
+			let attempt = self.acquired.fetch_add(1, Ordering::SeqCst);
+			if attempt > 0 && attempt <= self.failures {
+				return Err(anyhow::format_err!("Lease store is unavailable"))
+			}
+			self.store.try_acquire(holder, timeout).await
+		}
+
+		fn release(&self, holder: &str) -> anyhow::Result<()> {
+			self.store.release(holder)
+		}
+	}
+
+	#[async_std::test]
+	async fn only_one_holder_may_own_the_lease() {
+		let store = InMemoryLeaseStore::default();
+		assert!(store.try_acquire("a", TIMEOUT).await.unwrap());
+		assert!(!store.try_acquire("b", TIMEOUT).await.unwrap());
+		assert!(store.try_acquire("a", TIMEOUT).await.unwrap());
+
+		store.release("a").unwrap();
+		assert!(store.try_acquire("b", TIMEOUT).await.unwrap());
+		assert!(!store.try_acquire("a", TIMEOUT).await.unwrap());
+	}
+
+	#[async_std::test]
+	async fn file_lease_may_be_taken_after_expiration() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let store = FileLeaseStore::new(temp_dir.path().join("lease"));
+		assert!(store.try_acquire("a", TIMEOUT).await.unwrap());
+		assert!(!store.try_acquire("b", TIMEOUT).await.unwrap());
+
+		store.write(&Lease { holder: "a".into(), expires_at: unix_time() - 1 }).unwrap();
+		assert!(store.try_acquire("b", TIMEOUT).await.unwrap());
+		assert!(!store.try_acquire("a", TIMEOUT).await.unwrap());
+	}
+
+	#[test]
+	fn file_lease_is_acquired_by_single_concurrent_replica() {
+		const REPLICAS: usize = 8;
+
+		let temp_dir = tempfile::tempdir().unwrap();
+		let path = temp_dir.path().join("lease");
+		for _ in 0..10 {
+			let barrier = Arc::new(std::sync::Barrier::new(REPLICAS));
+			let replicas = (0..REPLICAS)
+				.map(|replica| {
+					let store = FileLeaseStore::new(path.clone());
+					let barrier = barrier.clone();
+					std::thread::spawn(move || {
+						barrier.wait();
+						async_std::task::block_on(
+							store.try_acquire(&format!("replica-{}", replica), TIMEOUT),
+						)
+						.unwrap()
+					})
+				})
+				.collect::<Vec<_>>();
+			let acquired = replicas
+				.into_iter()
+				.map(|replica| replica.join().unwrap())
+				.filter(|acquired| *acquired)
+				.count();
+			assert_eq!(acquired, 1);
+
+			// let replicas race for the expired lease again
+			FileLeaseStore::new(path.clone())
+				.write(&Lease { holder: String::new(), expires_at: 0 })
+				.unwrap();
+		}
+	}
+
+	#[test]
+	fn standby_starts_relaying_when_leader_stops() {
+		let store = InMemoryLeaseStore::default();
+		let leader = LeaderElection::new(store.clone(), "leader".into(), TIMEOUT);
+		let standby = LeaderElection::new(store, "standby".into(), TIMEOUT);
+
+		async_std::task::block_on(async {
+			// leader relays and stops
+			leader.run(futures::future::ready(Ok(()))).await.unwrap();
+			// standby takes over immediately, because the lease has been released
+			standby.run(futures::future::ready(Ok(()))).await.unwrap();
+		});
+	}
+
+	#[test]
+	fn leader_keeps_relaying_while_lease_store_is_unavailable() {
+		// first renewal fails, but the lease has not expired yet
+		let store = UnavailableLeaseStore { failures: 1, ..Default::default() };
+		let leader = LeaderElection::new(store, "leader".into(), TIMEOUT);
+
+		async_std::task::block_on(leader.run(async {
+			async_std::task::sleep(TIMEOUT * 5 / 6).await;
+			Ok(())
+		}))
+		.unwrap();
+	}
+
+	#[test]
+	fn leader_stops_relaying_when_lease_expires() {
+		let store = UnavailableLeaseStore { failures: usize::MAX, ..Default::default() };
+		let leader = LeaderElection::new(store, "leader".into(), TIMEOUT);
+
+		async_std::task::block_on(leader.run(futures::future::pending())).unwrap_err();
+	}
+}
@@ -0,0 +1,457 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Connections to chains through the embedded light client.
+//!
+//! Instead of the node URI, the connection may point to the chain specification file, using the
+//! `light-client://` scheme (e.g. `--kusama-uri light-client://specs/kusama.json`). Such
+//! connections are redirected to the local endpoint, served by the embedded smoldot light client.
+//! Light client does not need trusted full nodes - it is connected to the chain network using
+//! boot nodes of the chain specification and verifies everything it gets from the network.
+//!
+//! The light client is meant for source relay chains (Kusama, Polkadot, Rococo, Westend), where
+//! the relay mostly reads finalized headers, justifications and storage proofs. Parachains and
+//! target chains, where the relay submits its transactions, must be reached through full nodes,
+//! so light client connections to other chains are rejected before the relay is started. All relay
+//! connections of the chain are sharing the same light client (see the `shared_connections`
+//! module).
+
+use crate::cli::{
+	rpc_proxy::{NodeConnections, DEFAULT_HOST},
+	shared_connections::{accept_connections, Event, Multiplexer},
+	LOG_TARGET,
+};
+
+use futures::{
+	channel::mpsc::{self, UnboundedReceiver},
+	FutureExt, StreamExt,
+};
+use smoldot_light::{
+	platform::DefaultPlatform, AddChainConfig, AddChainConfigJsonRpc, AddChainSuccess,
+};
+use std::{num::NonZeroU32, path::PathBuf};
+
+/// URI scheme of chain specification files.
+const LIGHT_CLIENT_SCHEME: &str = "light-client://";
+/// Maximal number of JSON-RPC requests that the light client is processing at once.
+const MAX_PENDING_REQUESTS: u32 = 128;
+/// Maximal number of subscriptions of all relay connections.
+const MAX_SUBSCRIPTIONS: u32 = 1024;
+/// Chains that may be reached through the light client.
+const LIGHT_CLIENT_CHAINS: &[&str] = &["kusama", "polkadot", "rococo", "westend"];
+
+/// Local endpoint, served by the embedded light client.
+#[derive(Debug)]
+pub struct LightClientEndpoint {
+	listener: std::net::TcpListener,
+	chain_spec_path: PathBuf,
+	chain_spec: String,
+}
+
+impl LightClientEndpoint {
+	/// Start the light client and serving relay connections in background.
+	pub fn spawn(self) {
+		let (events_tx, events_rx) = mpsc::unbounded();
+		let name = format!("Light client of {}", self.chain_spec_path.display());
+		async_std::task::spawn(async move {
+			if let Err(e) = run_light_client(self.chain_spec, events_rx).await {
+				log::error!(
+					target: LOG_TARGET,
+					"Light client of {} has failed: {}",
+					self.chain_spec_path.display(),
+					e,
+				);
+			}
+		});
+		accept_connections(self.listener, name, events_tx);
+	}
+}
+
+/// Redirect node connections with chain specifications to light client endpoints.
+///
+/// Endpoints are reused by connections with the same chain specification. New endpoints are added
+/// to the `endpoints`. Other connections are left unchanged.
+pub fn redirect_connections(
+	connections: &mut dyn NodeConnections,
+	endpoints: &mut Vec<LightClientEndpoint>,
+) -> anyhow::Result<()> {
+	let source_chain = connections.source_chain().map(String::from);
+	connections.redirect(&mut |prefix, upstream| {
+		let chain_spec_path = match upstream.strip_prefix(LIGHT_CLIENT_SCHEME) {
+			Some(chain_spec_path) => PathBuf::from(chain_spec_path),
+			None => return Ok(None),
+		};
+		ensure_light_client_chain(source_chain.as_deref(), prefix)?;
+		if let Some(endpoint) =
+			endpoints.iter().find(|endpoint| endpoint.chain_spec_path == chain_spec_path)
+		{
+			return Ok(Some(endpoint.listener.local_addr()?.port()))
+		}
+
+		let chain_spec = std::fs::read_to_string(&chain_spec_path).map_err(|e| {
+			anyhow::format_err!(
+				"Failed to read chain specification of --{}-uri from {}: {}",
+				prefix,
+				chain_spec_path.display(),
+				e,
+			)
+		})?;
+		let listener = std::net::TcpListener::bind((DEFAULT_HOST, 0))?;
+		let port = listener.local_addr()?.port();
+		endpoints.push(LightClientEndpoint { listener, chain_spec_path, chain_spec });
+		Ok(Some(port))
+	})
+}
+
+/// Ensure that the chain, connected using options with the given prefix, may be reached through
+/// the light client.
+///
+/// Generic `source` connections are checked using the source chain of the bridge (e.g. `rococo`
+/// of the `rococo-to-bridge-hub-westend`). Relay is submitting transactions to target chains, so
+/// generic `target` connections are never reached through the light client.
+fn ensure_light_client_chain(source_chain: Option<&str>, prefix: &str) -> anyhow::Result<()> {
+	let chain = match prefix {
+		"source" => source_chain,
+		"target" => None,
+		prefix => Some(prefix),
+	};
+	if chain.map_or(false, |chain| LIGHT_CLIENT_CHAINS.contains(&chain)) {
+		return Ok(())
+	}
+
+	Err(anyhow::format_err!(
+		"--{}-uri: light client only supports source relay chains: {}",
+		prefix,
+		LIGHT_CLIENT_CHAINS.join(", "),
+	))
+}
+
+/// Run the light client, serving requests of all relay connections.
+async fn run_light_client(
+	chain_spec: String,
+	mut events: UnboundedReceiver<Event>,
+) -> anyhow::Result<()> {
+	let platform = DefaultPlatform::new(
+		env!("CARGO_PKG_NAME").into(),
+		option_env!("CARGO_PKG_VERSION").unwrap_or_default().into(),
+	);
+	let mut client = smoldot_light::Client::new(platform);
+	let AddChainSuccess { chain_id, json_rpc_responses } = client
+		.add_chain(AddChainConfig {
+			user_data: (),
+			specification: &chain_spec,
+			database_content: "",
+			potential_relay_chains: std::iter::empty(),
+			json_rpc: AddChainConfigJsonRpc::Enabled {
+				max_pending_requests: NonZeroU32::new(MAX_PENDING_REQUESTS)
+					.expect("MAX_PENDING_REQUESTS is not zero; qed"),
+				max_subscriptions: MAX_SUBSCRIPTIONS,
+			},
+		})
+		.map_err(|e| anyhow::format_err!("Failed to start light client: {}", e))?;
+	let mut responses =
+		json_rpc_responses.expect("JSON-RPC is enabled in the chain configuration; qed");
+
+	let mut state = Multiplexer::default();
+	loop {
+		let requests = futures::select! {
+			event = events.next().fuse() => match event {
+				Some(event) => state.on_event(event),
+				None => return Ok(()),
+			},
+			response = responses.next().fuse() => match response {
+				Some(response) => {
+					state.on_channel_message(response);
+					Vec::new()
+				},
+				None => return Err(anyhow::format_err!("Light client has stopped")),
+			},
+		};
+		for request in requests {
+			if let Err(e) = client.json_rpc_request(request, chain_id) {
+				log::warn!(target: LOG_TARGET, "Light client has rejected request: {}", e);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::cli::rpc_proxy::CommandLine;
+	use async_tungstenite::tungstenite::Message;
+	use futures::SinkExt;
+	use serde_json::{json, Value};
+	use std::{collections::BTreeMap, time::Duration};
+
+	/// Maximal time the light client test waits for responses.
+	const RESPONSE_TIMEOUT: Duration = Duration::from_secs(60);
+	/// Storage key of the runtime code.
+	const CODE_KEY: &str = "0x3a636f6465";
+	/// Identifier and version of the `Core` runtime API.
+	const CORE_API: ([u8; 8], u32) = ([0xdf, 0x6a, 0xcb, 0x68, 0x99, 0x07, 0x60, 0x9b], 4);
+	/// Offset of the encoded runtime version in the memory of the test runtime.
+	const VERSION_OFFSET: i64 = 1024;
+	/// Prefixes of node connection options, used by tests.
+	const PREFIXES: &[&str] =
+		&["source", "target", "rococo", "bridge-hub-rococo", "rococo-bulletin"];
+
+	fn unsigned_leb128(mut value: u64) -> Vec<u8> {
+		let mut encoded = Vec::new();
+		loop {
+			let byte = (value & 0x7f) as u8;
+			value >>= 7;
+			if value == 0 {
+				encoded.push(byte);
+				return encoded
+			}
+			encoded.push(byte | 0x80);
+		}
+	}
+
+	fn signed_leb128(mut value: i64) -> Vec<u8> {
+		let mut encoded = Vec::new();
+		loop {
+			let byte = (value & 0x7f) as u8;
+			value >>= 7;
+			if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+				encoded.push(byte);
+				return encoded
+			}
+			encoded.push(byte | 0x80);
+		}
+	}
+
+	fn wasm_section(id: u8, content: Vec<u8>) -> Vec<u8> {
+		[vec![id], unsigned_leb128(content.len() as u64), content].concat()
+	}
+
+	fn wasm_name(name: &str) -> Vec<u8> {
+		[unsigned_leb128(name.len() as u64), name.as_bytes().to_vec()].concat()
+	}
+
+	/// Returns the smallest runtime that the light client is able to start with: it only exports
+	/// the `Core_version` function, returning version without consensus and finality APIs.
+	fn test_runtime() -> Vec<u8> {
+		use codec::Encode;
+
+		let version = (
+			"light-client-test".to_string(),
+			"light-client-test".to_string(),
+			1u32,
+			1u32,
+			1u32,
+			vec![CORE_API],
+			1u32,
+			1u8,
+		)
+			.encode();
+		// runtime functions are returning pointer to the result and its length, packed into `i64`
+		let version_ptr_and_len = ((version.len() as i64) << 32) | VERSION_OFFSET;
+		let core_version =
+			[vec![0x00, 0x42], signed_leb128(version_ptr_and_len), vec![0x0b]].concat();
+
+		[
+			b"\0asm".to_vec(),
+			vec![0x01, 0x00, 0x00, 0x00],
+			// type of runtime functions: `(i32, i32) -> i64`
+			wasm_section(1, vec![0x01, 0x60, 0x02, 0x7f, 0x7f, 0x01, 0x7e]),
+			wasm_section(3, vec![0x01, 0x00]),
+			wasm_section(5, vec![0x01, 0x00, 0x11]),
+			// `__heap_base` starts after the encoded runtime version
+			wasm_section(
+				6,
+				[vec![0x01, 0x7f, 0x00, 0x41], signed_leb128(65536), vec![0x0b]].concat(),
+			),
+			wasm_section(
+				7,
+				[
+					vec![0x03],
+					wasm_name("memory"),
+					vec![0x02, 0x00],
+					wasm_name("__heap_base"),
+					vec![0x03, 0x00],
+					wasm_name("Core_version"),
+					vec![0x00, 0x00],
+				]
+				.concat(),
+			),
+			wasm_section(
+				10,
+				[vec![0x01], unsigned_leb128(core_version.len() as u64), core_version].concat(),
+			),
+			wasm_section(
+				11,
+				[
+					vec![0x01, 0x00, 0x41],
+					signed_leb128(VERSION_OFFSET),
+					vec![0x0b],
+					unsigned_leb128(version.len() as u64),
+					version,
+				]
+				.concat(),
+			),
+		]
+		.concat()
+	}
+
+	/// Returns raw specification of the local chain without boot nodes.
+	fn test_chain_spec() -> Value {
+		json!({
+			"name": "Light Client Testnet",
+			"id": "light_client_testnet",
+			"chainType": "Local",
+			"bootNodes": [],
+			"protocolId": "dot",
+			"properties": null,
+			"genesis": {
+				"raw": {
+					"top": { CODE_KEY: format!("0x{}", hex::encode(test_runtime())) },
+					"childrenDefault": {},
+				},
+			},
+		})
+	}
+
+	#[test]
+	fn connections_are_redirected_to_light_client() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let chain_spec_path = temp_dir.path().join("local.json");
+		let chain_spec = test_chain_spec();
+		std::fs::write(&chain_spec_path, chain_spec.to_string()).unwrap();
+
+		let light_client_uri = format!("{}{}", LIGHT_CLIENT_SCHEME, chain_spec_path.display());
+		let args = vec![
+			"substrate-relay".to_string(),
+			"relay-headers".into(),
+			"rococo-to-bridge-hub-westend".into(),
+			"--source-uri".into(),
+			light_client_uri,
+			"--target-uri".into(),
+			"wss://bridge-hub-westend:443".into(),
+		];
+		let mut endpoints = Vec::new();
+		let mut command_line = CommandLine::new(args, PREFIXES);
+		redirect_connections(&mut command_line, &mut endpoints).unwrap();
+		let args = command_line.into_args();
+		assert_eq!(endpoints.len(), 1);
+		assert_eq!(endpoints[0].chain_spec, chain_spec.to_string());
+		assert_eq!(
+			args[3..],
+			[
+				"--source-host".to_string(),
+				DEFAULT_HOST.into(),
+				"--source-port".into(),
+				endpoints[0].listener.local_addr().unwrap().port().to_string(),
+				"--target-uri".into(),
+				"wss://bridge-hub-westend:443".into(),
+			],
+		);
+
+		// missing chain specification is reported before the relay is started
+		let missing = vec![
+			"rococo-to-bridge-hub-westend".to_string(),
+			"--source-uri".into(),
+			format!("{}missing.json", LIGHT_CLIENT_SCHEME),
+		];
+		assert!(
+			redirect_connections(&mut CommandLine::new(missing, PREFIXES), &mut endpoints).is_err()
+		);
+
+		// parachains and target chains are not reached through the light client
+		for (bridge, prefix) in [
+			("rococo-to-bridge-hub-westend", "target"),
+			("bridge-hub-rococo-to-bridge-hub-westend", "source"),
+			("bridge-hub-rococo-bridge-hub-westend", "bridge-hub-rococo"),
+			("rococo-bulletin-bridge-hub-rococo", "rococo-bulletin"),
+		] {
+			let args = vec![
+				bridge.to_string(),
+				format!("--{}-uri", prefix),
+				format!("{}{}", LIGHT_CLIENT_SCHEME, chain_spec_path.display()),
+			];
+			assert!(redirect_connections(&mut CommandLine::new(args, PREFIXES), &mut Vec::new())
+				.is_err());
+		}
+		let args = vec![
+			"bridge-hub-rococo-bridge-hub-westend".to_string(),
+			"--rococo-uri".into(),
+			format!("{}{}", LIGHT_CLIENT_SCHEME, chain_spec_path.display()),
+		];
+		assert!(
+			redirect_connections(&mut CommandLine::new(args, PREFIXES), &mut Vec::new()).is_ok()
+		);
+	}
+
+	#[async_std::test]
+	async fn light_client_serves_relay_requests() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let chain_spec_path = temp_dir.path().join("local.json");
+		std::fs::write(&chain_spec_path, test_chain_spec().to_string()).unwrap();
+		let args = vec![
+			"--rococo-uri".to_string(),
+			format!("{}{}", LIGHT_CLIENT_SCHEME, chain_spec_path.display()),
+		];
+		let mut endpoints = Vec::new();
+		redirect_connections(&mut CommandLine::new(args, PREFIXES), &mut endpoints).unwrap();
+		let port = endpoints[0].listener.local_addr().unwrap().port();
+		endpoints.into_iter().for_each(LightClientEndpoint::spawn);
+
+		let (mut connection, _) =
+			async_tungstenite::async_std::connect_async(format!("ws://{}:{}", DEFAULT_HOST, port))
+				.await
+				.unwrap();
+		// there are no boot nodes, so the light client answers using the chain specification
+		for request in [
+			json!({
+				"jsonrpc": "2.0",
+				"id": 1,
+				"method": "system_chain",
+				"params": [],
+			}),
+			json!({
+				"jsonrpc": "2.0",
+				"id": 2,
+				"method": "chain_getHeader",
+				"params": [],
+			}),
+		] {
+			connection.send(Message::Text(request.to_string())).await.unwrap();
+		}
+
+		let mut responses = BTreeMap::new();
+		async_std::future::timeout(RESPONSE_TIMEOUT, async {
+			while responses.len() < 2 {
+				match connection.next().await {
+					Some(Ok(Message::Text(message))) => {
+						let message: Value = serde_json::from_str(&message).unwrap();
+						if let Some(id) = message["id"].as_u64() {
+							responses.insert(id, message);
+						}
+					},
+					Some(Ok(_)) => (),
+					Some(Err(e)) => panic!("Light client connection has failed: {}", e),
+					None => panic!("Light client connection is closed"),
+				}
+			}
+		})
+		.await
+		.expect("light client has not answered in time");
+
+		assert_eq!(responses[&1]["result"], "Light Client Testnet", "{}", responses[&1]);
+		assert_eq!(responses[&2]["result"]["number"], "0x0", "{}", responses[&2]);
+	}
+}
@@ -0,0 +1,208 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Relay logging.
+//!
+//! Relay loops are logging human-readable messages. In the JSON mode, every message is emitted
+//! as a single JSON object.
+//!
+//! Every relay loop is running in the `async_std` task, named after its metrics prefix (e.g.
+//! `BridgeHubRococo_to_BridgeHubWestend_MessageLane_00000002`). The loop name, bridge direction
+//! and messages lane are derived from this name and are emitted as separate fields of every
+//! record of the loop. Other relay entities (nonces, header numbers and transaction hashes) are
+//! only emitted as separate fields if they are attached to the record as key-values (e.g.
+//! `log::info!(target: LOG_TARGET, header:% = number; "...")`).
+
+use log::kv::{Error as KvError, Key, Value as KvValue, VisitSource};
+use serde_json::{Map, Value};
+use std::io::Write;
+use structopt::StructOpt;
+use strum::{EnumString, VariantNames};
+
+/// Format of log messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, VariantNames)]
+#[strum(serialize_all = "kebab_case")]
+pub enum LogFormat {
+	/// Human-readable log lines.
+	Text,
+	/// One JSON object per line.
+	Json,
+}
+
+/// Logging parameters.
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct LogParams {
+	/// Format of log messages.
+	#[structopt(
+		long,
+		default_value = "text",
+		possible_values = LogFormat::VARIANTS,
+		case_insensitive = true
+	)]
+	pub log_format: LogFormat,
+}
+
+/// Initialize logger that emits one JSON object per log message.
+///
+/// The `bridge` is the name of the bridge that is served by the relay, if it is known.
+pub fn initialize_json_logger(bridge: Option<String>) {
+	let bridge_direction = bridge.as_deref().and_then(bridge_direction);
+
+	let mut builder = env_logger::Builder::new();
+	builder.filter_level(log::LevelFilter::Warn);
+	builder.filter_module("bridge", log::LevelFilter::Info);
+	builder.parse_default_env();
+	builder.format(move |buf, record| {
+		let mut fields = Map::new();
+		fields.insert("timestamp".into(), buf.timestamp_millis().to_string().into());
+		fields.insert("level".into(), record.level().as_str().into());
+		fields.insert("target".into(), record.target().into());
+		if let Some(ref bridge) = bridge {
+			fields.insert("bridge".into(), bridge.clone().into());
+		}
+
+		if let Some(ref direction) = bridge_direction {
+			fields.insert("direction".into(), direction.clone().into());
+		}
+		if let Some(task) = async_std::task::try_current() {
+			if let Some(loop_name) = task.name() {
+				insert_loop_fields(&mut fields, loop_name);
+			}
+		}
+		let _ = record.key_values().visit(&mut FieldsVisitor(&mut fields));
+		fields.insert("message".into(), record.args().to_string().into());
+
+		writeln!(buf, "{}", Value::Object(fields))
+	});
+	builder.init();
+}
+
+/// Inserts key-values of the log record into the log fields.
+struct FieldsVisitor<'a>(&'a mut Map<String, Value>);
+
+impl<'a, 'kvs> VisitSource<'kvs> for FieldsVisitor<'a> {
+	fn visit_pair(&mut self, key: Key<'kvs>, value: KvValue<'kvs>) -> Result<(), KvError> {
+		let value = match value.to_u64() {
+			Some(value) => value.into(),
+			None => value.to_string().into(),
+		};
+		self.0.insert(key.as_str().into(), value);
+		Ok(())
+	}
+}
+
+/// Inserts fields, derived from the relay loop name.
+///
+/// Loop names are metrics prefixes of relay loops: `<Source>_to_<Target>_Sync` for finality
+/// loops, `<Source>_to_<Target>_MessageLane_<lane>` for messages loops and so on. Names of tasks
+/// that are not relay loops are ignored.
+fn insert_loop_fields(fields: &mut Map<String, Value>, loop_name: &str) {
+	let parts = loop_name.split('_').collect::<Vec<_>>();
+	let to_index = match parts.iter().position(|part| *part == "to") {
+		Some(to_index) if to_index > 0 && to_index + 1 < parts.len() => to_index,
+		_ => return,
+	};
+
+	fields.insert("loop".into(), loop_name.into());
+	fields.insert(
+		"direction".into(),
+		format!("{}->{}", parts[to_index - 1], parts[to_index + 1]).into(),
+	);
+	let lane = parts[to_index + 2..]
+		.windows(2)
+		.find(|window| window[0] == "MessageLane")
+		.map(|window| window[1]);
+	if let Some(lane) = lane {
+		fields.insert("lane".into(), lane.into());
+	}
+}
+
+/// Direction of the bridge, e.g. `RococoToBridgeHubWestend` => `Rococo->BridgeHubWestend`.
+///
+/// Returns `None` for bidirectional bridges.
+fn bridge_direction(bridge: &str) -> Option<String> {
+	bridge
+		.split_once("To")
+		.map(|(source, target)| format!("{}->{}", source, target))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn key_values_are_inserted_into_fields() {
+		use log::kv::Source;
+
+		let tx_hash = format!("0x{}", "ab".repeat(32));
+		let lane = "00000002";
+		let key_values: &[(&str, KvValue)] = &[
+			("lane", KvValue::from_display(&lane)),
+			("nonces", KvValue::from_display(&"1..=10")),
+			("header", KvValue::from(200u64)),
+			("tx_hash", KvValue::from_display(&tx_hash)),
+		];
+
+		let mut fields = Map::new();
+		key_values.visit(&mut FieldsVisitor(&mut fields)).unwrap();
+		assert_eq!(
+			Value::Object(fields),
+			serde_json::json!({
+				"lane": "00000002",
+				"nonces": "1..=10",
+				"header": 200,
+				"tx_hash": tx_hash,
+			}),
+		);
+	}
+
+	#[test]
+	fn fields_are_derived_from_loop_name() {
+		let mut fields = Map::new();
+		insert_loop_fields(&mut fields, "BridgeHubRococo_to_BridgeHubWestend_MessageLane_00000002");
+		assert_eq!(
+			Value::Object(fields),
+			serde_json::json!({
+				"loop": "BridgeHubRococo_to_BridgeHubWestend_MessageLane_00000002",
+				"direction": "BridgeHubRococo->BridgeHubWestend",
+				"lane": "00000002",
+			}),
+		);
+
+		let mut fields = Map::new();
+		insert_loop_fields(&mut fields, "Rococo_to_BridgeHubWestend_Sync");
+		assert_eq!(
+			Value::Object(fields),
+			serde_json::json!({
+				"loop": "Rococo_to_BridgeHubWestend_Sync",
+				"direction": "Rococo->BridgeHubWestend",
+			}),
+		);
+
+		let mut fields = Map::new();
+		insert_loop_fields(&mut fields, "rpc_proxy");
+		assert!(fields.is_empty());
+	}
+
+	#[test]
+	fn direction_is_derived_from_bridge_name() {
+		assert_eq!(
+			bridge_direction("RococoToBridgeHubWestend"),
+			Some("Rococo->BridgeHubWestend".into())
+		);
+		assert_eq!(bridge_direction("BridgeHubRococoBridgeHubWestend"), None);
+	}
+}
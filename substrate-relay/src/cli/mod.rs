@@ -22,25 +22,118 @@ use signal_hook::consts::*;
 use signal_hook_async_std::Signals;
 use structopt::StructOpt;
 
+mod bridge_admin;
 mod chain_schema;
 mod detect_equivocations;
+mod diagnose_lane;
+mod dry_run;
+#[cfg(test)]
+mod e2e_tests;
+mod estimate_profit;
+mod generate_dashboards;
+mod health;
+mod import_justification;
 mod init_bridge;
+mod inspect_messages;
+mod justification_archive;
+mod leader_election;
+mod light_client;
+mod logger;
+mod offline_signing;
+mod relay_bridges;
 mod relay_headers;
 mod relay_headers_and_messages;
 mod relay_messages;
 mod relay_parachains;
+mod relayer_rewards;
+mod rpc_failover;
+mod rpc_proxy;
+mod rpc_recording;
+mod shared_connections;
+mod shutdown;
+mod signer_top_up;
+mod tip_policy;
+mod verify_bridge;
 
 /// The target that will be used when publishing logs related to this pallet.
 pub const LOG_TARGET: &str = "bridge";
 
 /// Parse relay CLI args.
-pub fn parse_args() -> Command {
-	Command::from_args()
+///
+/// Node connections with chain specifications are redirected to the embedded light client and
+/// connections with several endpoints are redirected to failover proxies. In the dry-run, record
+/// and replay modes and when the graceful shutdown is enabled, node connections are redirected to
+/// RPC proxies.
+pub fn parse_args() -> Cli {
+	let mut cli = Cli::from_args();
+	let is_proxy_enabled = cli.rpc_proxy.is_enabled() || cli.command.is_graceful_shutdown_enabled();
+	let mut command_line =
+		rpc_proxy::CommandLine::new(std::env::args().collect(), cli.command.connection_prefixes());
+	let connections: &mut dyn rpc_proxy::NodeConnections = match cli.command {
+		Command::RelayHeadersAndMessages(ref mut command) => command.as_mut(),
+		_ => &mut command_line,
+	};
+	let mut light_clients = Vec::new();
+	let mut failover_proxies = Vec::new();
+	let redirected = match cli.rpc_proxy.replay {
+		Some(_) => Ok(()),
+		None => light_client::redirect_connections(connections, &mut light_clients)
+			.and_then(|_| rpc_failover::redirect_connections(connections, &mut failover_proxies)),
+	};
+	let redirected = redirected.and_then(|_| match is_proxy_enabled {
+		true => rpc_proxy::redirect_connections(connections, &cli.rpc_proxy),
+		false => Ok(Vec::new()),
+	});
+	let proxies = match redirected {
+		Ok(proxies) => proxies,
+		Err(e) => structopt::clap::Error::with_description(
+			&e.to_string(),
+			structopt::clap::ErrorKind::InvalidValue,
+		)
+		.exit(),
+	};
+	// connections of the complex relay are redirected after parsing, other commands need to
+	// parse the rewritten command line
+	let mut cli = if matches!(cli.command, Command::RelayHeadersAndMessages(_)) {
+		cli
+	} else {
+		Cli::from_iter(command_line.into_args())
+	};
+	cli.rpc_proxies = proxies;
+	cli.failover_proxies = failover_proxies;
+	cli.light_clients = light_clients;
+	cli
 }
 
-/// Substrate-to-Substrate bridge utilities.
+/// Substrate-to-Substrate relay CLI.
 #[derive(StructOpt)]
 #[structopt(about = "Substrate-to-Substrate relay")]
+pub struct Cli {
+	#[structopt(flatten)]
+	log: logger::LogParams,
+	#[structopt(flatten)]
+	rpc_proxy: rpc_proxy::RpcProxyParams,
+	#[structopt(skip)]
+	rpc_proxies: Vec<rpc_proxy::RpcProxy>,
+	#[structopt(skip)]
+	failover_proxies: Vec<rpc_failover::FailoverProxy>,
+	#[structopt(skip)]
+	light_clients: Vec<light_client::LightClientEndpoint>,
+	#[structopt(subcommand)]
+	command: Command,
+}
+
+impl Cli {
+	/// Run the command and return the process exit code.
+	pub async fn run(self) -> i32 {
+		self.command
+			.run(self.log.log_format, self.rpc_proxies, self.failover_proxies, self.light_clients)
+			.await
+	}
+}
+
+/// Substrate-to-Substrate bridge utilities.
+#[derive(StructOpt)]
 pub enum Command {
 	/// Initialize on-chain bridge pallet with current header data.
 	///
@@ -53,6 +146,20 @@ pub enum Command {
 	RelayHeaders(relay_headers::RelayHeaders),
 	/// Relay single header.
 	RelayHeader(relay_headers::RelayHeader),
+	/// Relay all mandatory headers in the range of source blocks.
+	///
+	/// Headers that are already imported by the target chain are skipped, so the command may be
+	/// restarted with the same range to resume relaying.
+	RelayHeadersRange(relay_headers::RelayHeadersRange),
+	/// Submit finality proof of the source header from the justification file.
+	///
+	/// Justification may be archived by the relay (see `--justification-archive`) or supplied
+	/// by hand.
+	ImportJustification(import_justification::ImportJustification),
+	/// Compare the bridge state at the target chain with the source chain.
+	///
+	/// Does not submit any transactions. Fails if any divergence is found.
+	VerifyBridge(verify_bridge::VerifyBridge),
 	/// Relay parachain heads.
 	RelayParachains(relay_parachains::RelayParachains),
 	/// Relay single parachain head.
@@ -72,24 +179,69 @@ pub enum Command {
 	/// and two `RelayMessages` relays. Headers are only relayed when they are required by
 	/// the message relays - i.e. when there are messages or confirmations that needs to be
 	/// relayed between chains.
-	RelayHeadersAndMessages(Box<relay_headers_and_messages::RelayHeadersAndMessages>),
+	RelayHeadersAndMessages(Box<relay_headers_and_messages::RelayHeadersAndMessagesCommand>),
+	/// Start several headers and messages relays in the same process.
+	///
+	/// Bridges are listed in the configuration file. Connections to nodes of chains that are
+	/// used by several bridges are shared, and metrics are labeled with the bridge label.
+	RelayBridges(relay_bridges::RelayBridges),
 	/// Detect and report equivocations.
 	///
 	/// Parses the source chain headers that were synchronized with the target chain looking for
 	/// equivocations. If any equivocation is found, it is reported to the source chain.
 	DetectEquivocations(detect_equivocations::DetectEquivocations),
+	/// Sign transaction, exported by one-shot command with `--export-unsigned`.
+	///
+	/// Does not require network access and may be used on an air-gapped machine.
+	SignOffline(offline_signing::SignOffline),
+	/// Submit transaction, signed with `sign-offline` command.
+	SubmitSigned(offline_signing::SubmitSigned),
+	/// Change operating mode of the bridge pallet at the target chain.
+	///
+	/// With `--encode-only`, the call is printed instead, so that it may be dispatched by
+	/// governance.
+	SetOperatingMode(bridge_admin::SetOperatingMode),
+	/// Change owner of the bridge pallet at the target chain.
+	///
+	/// With `--encode-only`, the call is printed instead, so that it may be dispatched by
+	/// governance.
+	SetOwner(bridge_admin::SetOwner),
+	/// Generate Grafana dashboard and Prometheus alert rules for the bridge.
+	///
+	/// Metric names are generated the same way the relay names its metrics.
+	GenerateDashboards(generate_dashboards::GenerateDashboards),
+	/// Estimate relaying profit of the messages bridge.
+	///
+	/// Compares estimated fees of bridge transactions with rewards that have been registered
+	/// for relayers in given ranges of blocks.
+	EstimateProfit(estimate_profit::EstimateProfit),
+	/// Inspect outbound messages of the messages bridge.
+	///
+	/// Reads payloads of messages in given range of nonces and decodes them as XCM messages.
+	InspectMessages(inspect_messages::InspectMessages),
+	/// Explain why the message is not delivered or not confirmed.
+	///
+	/// Checks all conditions that may block relaying of the message and prints them, ranked by
+	/// the order in which they need to be resolved.
+	DiagnoseLane(diagnose_lane::DiagnoseLane),
 }
 
 impl Command {
 	// Initialize logger depending on the command.
-	fn init_logger(&self) {
+	fn init_logger(&self, log_format: logger::LogFormat) {
 		use relay_utils::initialize::{initialize_logger, initialize_relay};
 
+		if log_format == logger::LogFormat::Json {
+			logger::initialize_json_logger(self.bridge_name());
+			return
+		}
+
 		match self {
 			Self::InitBridge(_) |
 			Self::RelayHeaders(_) |
 			Self::RelayMessages(_) |
-			Self::RelayHeadersAndMessages(_) => {
+			Self::RelayHeadersAndMessages(_) |
+			Self::RelayBridges(_) => {
 				initialize_relay();
 			},
 			_ => {
@@ -98,6 +250,64 @@ impl Command {
 		}
 	}
 
+	// Name of the bridge, served by the command.
+	fn bridge_name(&self) -> Option<String> {
+		match self {
+			Self::InitBridge(arg) => Some(arg.bridge_name()),
+			Self::RelayHeaders(arg) => Some(arg.bridge_name()),
+			Self::RelayHeader(arg) => Some(arg.bridge_name()),
+			Self::RelayHeadersRange(arg) => Some(arg.bridge_name()),
+			Self::ImportJustification(arg) => Some(arg.bridge_name()),
+			Self::VerifyBridge(arg) => Some(arg.bridge_name()),
+			Self::RelayParachains(arg) => Some(arg.bridge_name()),
+			Self::RelayParachainHead(arg) => Some(arg.bridge_name()),
+			Self::RelayMessages(arg) => Some(arg.bridge_name()),
+			Self::RelayMessagesRange(arg) => Some(arg.bridge_name()),
+			Self::RelayMessagesDeliveryConfirmation(arg) => Some(arg.bridge_name()),
+			Self::RelayHeadersAndMessages(arg) => Some(arg.bridge_name()),
+			Self::DetectEquivocations(arg) => Some(arg.bridge_name()),
+			Self::EstimateProfit(arg) => Some(arg.bridge_name()),
+			Self::InspectMessages(arg) => Some(arg.bridge_name()),
+			Self::DiagnoseLane(arg) => Some(arg.bridge_name()),
+			Self::SetOperatingMode(arg) => Some(arg.bridge_name()),
+			Self::SetOwner(arg) => Some(arg.bridge_name()),
+			Self::RelayBridges(_) |
+			Self::SignOffline(_) |
+			Self::SubmitSigned(_) |
+			Self::GenerateDashboards(_) => None,
+		}
+	}
+
+	// Prefixes of node connection options, that are redirected by rewriting the command line.
+	//
+	// Connections of the complex relay are redirected after parsing, and bridges of the
+	// `relay-bridges` command are configured in the file.
+	fn connection_prefixes(&self) -> &'static [&'static str] {
+		const SOURCE_AND_TARGET: &[&str] = &["source", "target"];
+		match self {
+			Self::InitBridge(_) |
+			Self::RelayHeaders(_) |
+			Self::RelayHeader(_) |
+			Self::RelayHeadersRange(_) |
+			Self::ImportJustification(_) |
+			Self::VerifyBridge(_) |
+			Self::RelayParachains(_) |
+			Self::RelayParachainHead(_) |
+			Self::RelayMessages(_) |
+			Self::RelayMessagesRange(_) |
+			Self::RelayMessagesDeliveryConfirmation(_) |
+			Self::DetectEquivocations(_) |
+			Self::EstimateProfit(_) |
+			Self::DiagnoseLane(_) => SOURCE_AND_TARGET,
+			Self::InspectMessages(_) => &["source"],
+			Self::SubmitSigned(_) | Self::SetOperatingMode(_) | Self::SetOwner(_) => &["target"],
+			Self::RelayHeadersAndMessages(_) |
+			Self::RelayBridges(_) |
+			Self::SignOffline(_) |
+			Self::GenerateDashboards(_) => &[],
+		}
+	}
+
 	// Set relayer version metric value.
 	fn init_version(&self) {
 		*relay_utils::initialize::RELAYER_VERSION.lock() =
@@ -110,27 +320,58 @@ impl Command {
 			Self::InitBridge(arg) => arg.run().await?,
 			Self::RelayHeaders(arg) => arg.run().await?,
 			Self::RelayHeader(arg) => arg.run().await?,
+			Self::RelayHeadersRange(arg) => arg.run().await?,
+			Self::ImportJustification(arg) => arg.run().await?,
+			Self::VerifyBridge(arg) => arg.run().await?,
 			Self::RelayParachains(arg) => arg.run().await?,
 			Self::RelayParachainHead(arg) => arg.run().await?,
 			Self::RelayMessages(arg) => arg.run().await?,
 			Self::RelayMessagesRange(arg) => arg.run().await?,
 			Self::RelayMessagesDeliveryConfirmation(arg) => arg.run().await?,
 			Self::RelayHeadersAndMessages(arg) => arg.run().await?,
+			Self::RelayBridges(arg) => arg.run().await?,
 			Self::DetectEquivocations(arg) => arg.run().await?,
+			Self::SignOffline(arg) => arg.run().await?,
+			Self::SubmitSigned(arg) => arg.run().await?,
+			Self::SetOperatingMode(arg) => arg.run().await?,
+			Self::SetOwner(arg) => arg.run().await?,
+			Self::GenerateDashboards(arg) => arg.run().await?,
+			Self::EstimateProfit(arg) => arg.run().await?,
+			Self::InspectMessages(arg) => arg.run().await?,
+			Self::DiagnoseLane(arg) => arg.run().await?,
 		}
 		Ok(())
 	}
 
-	/// Run the command.
-	pub async fn run(self) {
-		self.init_logger();
+	// Returns true if the command stops gracefully after the exit signal.
+	fn is_graceful_shutdown_enabled(&self) -> bool {
+		match self {
+			Self::RelayHeadersAndMessages(arg) => arg.is_graceful_shutdown_enabled(),
+			Self::RelayBridges(arg) => arg.is_graceful_shutdown_enabled(),
+			_ => false,
+		}
+	}
+
+	/// Run the command and return the process exit code.
+	async fn run(
+		self,
+		log_format: logger::LogFormat,
+		rpc_proxies: Vec<rpc_proxy::RpcProxy>,
+		failover_proxies: Vec<rpc_failover::FailoverProxy>,
+		light_clients: Vec<light_client::LightClientEndpoint>,
+	) -> i32 {
+		self.init_logger(log_format);
 		self.init_version();
+		light_clients.into_iter().for_each(light_client::LightClientEndpoint::spawn);
+		failover_proxies.into_iter().for_each(rpc_failover::FailoverProxy::spawn);
+		rpc_proxies.into_iter().for_each(rpc_proxy::RpcProxy::spawn);
 
+		let is_graceful_shutdown_enabled = self.is_graceful_shutdown_enabled();
 		let exit_signals = match Signals::new([SIGINT, SIGTERM]) {
 			Ok(signals) => signals,
 			Err(e) => {
 				log::error!(target: LOG_TARGET, "Could not register exit signals: {}", e);
-				return
+				return 0
 			},
 		};
 		let run = self.do_run().fuse();
@@ -140,10 +381,32 @@ impl Command {
 			signal = exit_signals.next().fuse() => {
 				log::info!(target: LOG_TARGET, "Received exit signal {:?}", signal);
 			},
-			result = run => {
+			result = run.as_mut() => {
+				// failed commands exit with zero code, only the graceful shutdown uses other exit codes
 				if let Err(e) = result {
 					log::error!(target: LOG_TARGET, "substrate-relay: {}", e);
 				}
+				return 0
+			},
+		}
+
+		if !is_graceful_shutdown_enabled {
+			return 0
+		}
+
+		// let the relay finish pending work. The second signal stops it immediately
+		shutdown::request_shutdown();
+		select! {
+			signal = exit_signals.next().fuse() => {
+				log::info!(target: LOG_TARGET, "Received second exit signal {:?}", signal);
+				shutdown::FORCED_SHUTDOWN_EXIT_CODE
+			},
+			result = run => match result {
+				Ok(()) => shutdown::graceful_shutdown_exit_code(),
+				Err(e) => {
+					log::error!(target: LOG_TARGET, "substrate-relay: {}", e);
+					shutdown::FORCED_SHUTDOWN_EXIT_CODE
+				},
 			},
 		}
 	}
@@ -0,0 +1,386 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Support for signing relay transactions on an offline (air-gapped) machine.
+//!
+//! The workflow consists of three steps:
+//!
+//! 1) one-shot command (`init-bridge`, `relay-header`, `relay-parachain-head`,
+//!    `relay-messages-range` or `relay-messages-delivery-confirmation`) is started with
+//!    `--export-unsigned <file>` on a networked host. Instead of submitting the transaction, it
+//!    writes the call and everything that is required to sign it (era, nonce, genesis hash and
+//!    runtime version) to the file;
+//! 2) the `sign-offline` command reads this file on the air-gapped host and writes the signed
+//!    transaction to another file;
+//! 3) the `submit-signed` command broadcasts the signed transaction from a networked host.
+
+use crate::cli::LOG_TARGET;
+
+use bp_runtime::HeaderIdProvider;
+use codec::{Decode, Encode};
+use relay_substrate_client::{
+	AccountIdOf, AccountKeyPairOf, CallOf, Chain, ChainWithRuntimeVersion, ChainWithTransactions,
+	Client, EncodedOrDecodedCall, HashOf, NonceOf, SignParam, TransactionEra, UnsignedTransaction,
+};
+use serde::{Deserialize, Serialize};
+use sp_core::{crypto::Ss58Codec, Bytes, Pair};
+use sp_runtime::AccountId32;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+use substrate_relay_helper::cli::chain_schema::{TargetConnectionParams, TargetSigningParams};
+
+/// Name of the runtime API method that returns account nonce.
+const ACCOUNT_NONCE_METHOD: &str = "AccountNonceApi_account_nonce";
+
+/// Parameters, shared by all one-shot commands that support offline signing.
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct ExportUnsignedParams {
+	/// Instead of submitting the transaction, write it to the given file for offline signing.
+	#[structopt(long, requires = "signer-account")]
+	pub export_unsigned: Option<PathBuf>,
+	/// SS58 address of the account that will sign the exported transaction.
+	///
+	/// Only the address is required on a networked host - it is used to read the nonce.
+	#[structopt(long)]
+	pub signer_account: Option<String>,
+}
+
+impl ExportUnsignedParams {
+	/// Returns export file path and signer account if export has been requested.
+	pub fn export_target(&self) -> anyhow::Result<Option<(PathBuf, AccountId32)>> {
+		match (&self.export_unsigned, &self.signer_account) {
+			(Some(path), Some(account)) => Ok(Some((
+				path.clone(),
+				AccountId32::from_ss58check(account).map_err(|e| {
+					anyhow::format_err!("Invalid signer account {}: {:?}", account, e)
+				})?,
+			))),
+			(Some(_), None) =>
+				Err(anyhow::format_err!("--signer-account is required by --export-unsigned")),
+			(None, _) => Ok(None),
+		}
+	}
+}
+
+/// Unsigned transaction with all data that is required to sign it offline.
+///
+/// All chain-specific values are stored as hex-encoded SCALE.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct UnsignedTransactionFile {
+	/// Name of the chain (`Chain::NAME`) this transaction is for.
+	pub chain: String,
+	/// Account that is expected to sign the transaction.
+	pub signer: String,
+	/// Encoded call.
+	pub call: String,
+	/// Transaction nonce.
+	pub nonce: String,
+	/// Transaction tip.
+	pub tip: String,
+	/// Number of the block the transaction era starts at.
+	pub era_block_number: String,
+	/// Hash of the block the transaction era starts at.
+	pub era_block_hash: String,
+	/// Transaction mortality period. `None` if the transaction is immortal.
+	pub mortality: Option<u32>,
+	/// Chain genesis hash.
+	pub genesis_hash: String,
+	/// Runtime spec version.
+	pub spec_version: u32,
+	/// Runtime transaction version.
+	pub transaction_version: u32,
+}
+
+/// Transaction, signed offline.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SignedTransactionFile {
+	/// Name of the chain (`Chain::NAME`) this transaction is for.
+	pub chain: String,
+	/// Encoded signed transaction.
+	pub transaction: String,
+}
+
+/// Sign transaction, previously exported with `--export-unsigned`.
+#[derive(StructOpt)]
+pub struct SignOffline {
+	/// Path to the file with unsigned transaction.
+	#[structopt(long)]
+	input: PathBuf,
+	/// Path to the file where signed transaction will be written.
+	#[structopt(long)]
+	output: PathBuf,
+	#[structopt(flatten)]
+	target_sign: TargetSigningParams,
+}
+
+/// Submit transaction, previously signed with `sign-offline`.
+#[derive(StructOpt)]
+pub struct SubmitSigned {
+	/// Path to the file with signed transaction.
+	#[structopt(long)]
+	input: PathBuf,
+	#[structopt(flatten)]
+	target: TargetConnectionParams,
+}
+
+/// Call given generic function, using chain that matches the `Chain::NAME`.
+macro_rules! select_chain {
+	($chain_name:expr, $generic_fn:ident, $($arg:expr),*) => {
+		match $chain_name {
+			name if name == relay_bridge_hub_kusama_client::BridgeHubKusama::NAME =>
+				$generic_fn::<relay_bridge_hub_kusama_client::BridgeHubKusama>($($arg),*).await,
+			name if name == relay_bridge_hub_polkadot_client::BridgeHubPolkadot::NAME =>
+				$generic_fn::<relay_bridge_hub_polkadot_client::BridgeHubPolkadot>($($arg),*).await,
+			name if name == relay_bridge_hub_rococo_client::BridgeHubRococo::NAME =>
+				$generic_fn::<relay_bridge_hub_rococo_client::BridgeHubRococo>($($arg),*).await,
+			name if name == relay_bridge_hub_westend_client::BridgeHubWestend::NAME =>
+				$generic_fn::<relay_bridge_hub_westend_client::BridgeHubWestend>($($arg),*).await,
+			name if name == relay_polkadot_bulletin_client::PolkadotBulletin::NAME =>
+				$generic_fn::<relay_polkadot_bulletin_client::PolkadotBulletin>($($arg),*).await,
+			name => Err(anyhow::format_err!("Offline signing is not supported for chain {}", name)),
+		}
+	};
+}
+
+impl SignOffline {
+	/// Run the command.
+	pub async fn run(self) -> anyhow::Result<()> {
+		let unsigned: UnsignedTransactionFile = read_json(&self.input)?;
+		let chain = unsigned.chain.clone();
+		select_chain!(chain.as_str(), sign_offline, unsigned, &self.target_sign, &self.output)
+	}
+}
+
+impl SubmitSigned {
+	/// Run the command.
+	pub async fn run(self) -> anyhow::Result<()> {
+		let signed: SignedTransactionFile = read_json(&self.input)?;
+		let chain = signed.chain.clone();
+		select_chain!(chain.as_str(), submit_signed, signed, self.target)
+	}
+}
+
+/// Read everything that is required to sign the transaction offline from the target chain and
+/// write it to the file.
+pub async fn export_unsigned_transaction<C: ChainWithTransactions>(
+	client: &impl Client<C>,
+	signer: AccountIdOf<C>,
+	call: CallOf<C>,
+	mortality: Option<u32>,
+	path: &Path,
+) -> anyhow::Result<()> {
+	// the nonce is read at the block the transaction era starts at, so the transaction is valid
+	// at this block
+	let era_block_id = client.best_header().await?.id();
+	let runtime_version = client.simple_runtime_version().await?;
+	let nonce = account_nonce(client, &signer, era_block_id.1).await?;
+
+	let unsigned = UnsignedTransactionFile {
+		chain: C::NAME.into(),
+		signer: hex::encode(signer.encode()),
+		call: hex::encode(call.encode()),
+		nonce: hex::encode(nonce.encode()),
+		tip: hex::encode(C::Balance::zero().encode()),
+		era_block_number: hex::encode(era_block_id.0.encode()),
+		era_block_hash: hex::encode(era_block_id.1.encode()),
+		mortality,
+		genesis_hash: hex::encode(client.genesis_hash().encode()),
+		spec_version: runtime_version.spec_version,
+		transaction_version: runtime_version.transaction_version,
+	};
+	write_json(path, &unsigned)?;
+
+	log::info!(
+		target: LOG_TARGET,
+		nonce:? = nonce;
+		"Exported unsigned {} transaction with nonce {:?} to {}: {:?}",
+		C::NAME,
+		nonce,
+		path.display(),
+		call,
+	);
+
+	Ok(())
+}
+
+/// Read transaction nonce of given account at given block.
+///
+/// The nonce is read using the runtime API, so it works for chains without balances too.
+pub async fn account_nonce<C: Chain>(
+	client: &impl Client<C>,
+	account: &AccountIdOf<C>,
+	at: HashOf<C>,
+) -> anyhow::Result<NonceOf<C>> {
+	Ok(client.state_call(at, ACCOUNT_NONCE_METHOD.into(), account.clone()).await?)
+}
+
+async fn sign_offline<C: ChainWithTransactions>(
+	unsigned: UnsignedTransactionFile,
+	target_sign: &TargetSigningParams,
+	output: &Path,
+) -> anyhow::Result<()>
+where
+	AccountIdOf<C>: From<<AccountKeyPairOf<C> as Pair>::Public>,
+{
+	let signer = target_sign.to_keypair::<C>()?;
+	let signer_account: AccountIdOf<C> = signer.public().into();
+	let expected_signer: AccountIdOf<C> = decode_hex("signer", &unsigned.signer)?;
+	if signer_account != expected_signer {
+		return Err(anyhow::format_err!(
+			"Transaction has been exported for {:?}, but signer is {:?}",
+			expected_signer,
+			signer_account,
+		))
+	}
+
+	let call: CallOf<C> = decode_hex("call", &unsigned.call)?;
+	let era = TransactionEra::new(
+		relay_utils::HeaderId(
+			decode_hex("era_block_number", &unsigned.era_block_number)?,
+			decode_hex("era_block_hash", &unsigned.era_block_hash)?,
+		),
+		unsigned.mortality,
+	);
+	let transaction = C::sign_transaction(
+		SignParam {
+			spec_version: unsigned.spec_version,
+			transaction_version: unsigned.transaction_version,
+			genesis_hash: decode_hex("genesis_hash", &unsigned.genesis_hash)?,
+			signer,
+		},
+		UnsignedTransaction::new(
+			EncodedOrDecodedCall::Decoded(call),
+			decode_hex("nonce", &unsigned.nonce)?,
+		)
+		.era(era)
+		.tip(decode_hex("tip", &unsigned.tip)?),
+	)?;
+
+	write_json(
+		output,
+		&SignedTransactionFile {
+			chain: unsigned.chain,
+			transaction: hex::encode(transaction.encode()),
+		},
+	)?;
+
+	log::info!(
+		target: LOG_TARGET,
+		"Signed {} transaction has been written to {}",
+		C::NAME,
+		output.display(),
+	);
+
+	Ok(())
+}
+
+async fn submit_signed<C: ChainWithRuntimeVersion>(
+	signed: SignedTransactionFile,
+	target: TargetConnectionParams,
+) -> anyhow::Result<()> {
+	let transaction = hex::decode(&signed.transaction)
+		.map_err(|e| anyhow::format_err!("Invalid signed transaction: {:?}", e))?;
+	let client = target.into_client::<C>().await?;
+	let tx_hash = client.submit_unsigned_extrinsic(Bytes(transaction)).await?;
+
+	log::info!(
+		target: LOG_TARGET,
+		tx_hash:? = tx_hash;
+		"Submitted signed {} transaction {:?}",
+		C::NAME,
+		tx_hash,
+	);
+
+	Ok(())
+}
+
+fn decode_hex<T: Decode>(field: &str, value: &str) -> anyhow::Result<T> {
+	let encoded = hex::decode(value)
+		.map_err(|e| anyhow::format_err!("Invalid hex in field {}: {:?}", field, e))?;
+	T::decode(&mut &encoded[..])
+		.map_err(|e| anyhow::format_err!("Failed to decode field {}: {:?}", field, e))
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> anyhow::Result<T> {
+	let contents = std::fs::read(path)
+		.map_err(|e| anyhow::format_err!("Failed to read {}: {:?}", path.display(), e))?;
+	serde_json::from_slice(&contents)
+		.map_err(|e| anyhow::format_err!("Failed to parse {}: {:?}", path.display(), e))
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> anyhow::Result<()> {
+	let contents = serde_json::to_vec_pretty(value)?;
+	std::fs::write(path, contents)
+		.map_err(|e| anyhow::format_err!("Failed to write {}: {:?}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use relay_bridge_hub_rococo_client::BridgeHubRococo;
+	use sp_keyring::Sr25519Keyring as AccountKeyring;
+
+	#[test]
+	fn offline_signed_transaction_matches_online_signed() {
+		let signer = AccountKeyring::Alice.pair();
+		let call: CallOf<BridgeHubRococo> = relay_bridge_hub_rococo_client::RuntimeCall::System(
+			relay_bridge_hub_rococo_client::runtime_types::frame_system::pallet::Call::remark {
+				remark: vec![42],
+			},
+		);
+		let genesis_hash = [1u8; 32].into();
+		let era_block = relay_utils::HeaderId(100, [2u8; 32].into());
+
+		let unsigned = UnsignedTransactionFile {
+			chain: BridgeHubRococo::NAME.into(),
+			signer: hex::encode(AccountKeyring::Alice.to_account_id().encode()),
+			call: hex::encode(call.encode()),
+			nonce: hex::encode(7u32.encode()),
+			tip: hex::encode(0u128.encode()),
+			era_block_number: hex::encode(era_block.0.encode()),
+			era_block_hash: hex::encode(era_block.1.encode()),
+			mortality: Some(64),
+			genesis_hash: hex::encode(genesis_hash.encode()),
+			spec_version: 1,
+			transaction_version: 2,
+		};
+		let expected = BridgeHubRococo::sign_transaction(
+			SignParam { spec_version: 1, transaction_version: 2, genesis_hash, signer },
+			UnsignedTransaction::new(EncodedOrDecodedCall::Decoded(call), 7)
+				.era(TransactionEra::new(era_block, Some(64))),
+		)
+		.unwrap();
+
+		let temp_dir = tempfile::tempdir().unwrap();
+		let output = temp_dir.path().join("signed.json");
+		async_std::task::block_on(sign_offline::<BridgeHubRococo>(
+			unsigned,
+			&TargetSigningParams {
+				target_signer: Some("//Alice".into()),
+				target_signer_password: None,
+				target_signer_file: None,
+				target_signer_password_file: None,
+				target_transactions_mortality: None,
+			},
+			&output,
+		))
+		.unwrap();
+
+		let signed: SignedTransactionFile = read_json(&output).unwrap();
+		assert_eq!(signed.chain, BridgeHubRococo::NAME);
+		assert_eq!(signed.transaction, hex::encode(expected.encode()));
+	}
+}
@@ -0,0 +1,401 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Several complex relays, served by the same process.
+//!
+//! Bridges are listed in the JSON configuration file:
+//!
+//! ```json
+//! {
+//!   "bridges": [
+//!     {
+//!       "label": "rococo-westend",
+//!       "args": ["bridge-hub-rococo-bridge-hub-westend", "..."]
+//!     },
+//!     {
+//!       "label": "rococo-bulletin",
+//!       "args": ["rococo-bulletin-bridge-hub-rococo", "..."]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! where `args` are arguments of the `relay-headers-and-messages` command. All bridges are using
+//! the same client for connections to the same node, so transactions of the signer, shared by
+//! several bridges, are submitted one by one. Metrics of all bridges are exposed at the single
+//! Prometheus endpoint of the command, labeled with the `bridge` label. The RPC proxy options
+//! (`--dry-run`, `--record-rpc`, ...) do not apply to connections from the configuration file.
+
+use crate::cli::{
+	health::{self, HealthParams},
+	light_client::{self, LightClientEndpoint},
+	relay_headers_and_messages::RelayHeadersAndMessagesCommand,
+	rpc_failover::{self, FailoverProxy},
+	shutdown::ShutdownParams,
+	LOG_TARGET,
+};
+
+use futures::{lock::Mutex, Future};
+use prometheus_endpoint::{
+	prometheus::{
+		core::{Collector, Desc},
+		proto::MetricFamily,
+	},
+	Registry,
+};
+use relay_substrate_client::ChainWithRuntimeVersion;
+use relay_utils::metrics::MetricsParams;
+use serde::Deserialize;
+use std::{
+	any::{Any, TypeId},
+	collections::{BTreeMap, BTreeSet, HashMap},
+	fmt::Debug,
+	path::PathBuf,
+	sync::Arc,
+	time::Duration,
+};
+use structopt::StructOpt;
+use substrate_relay_helper::cli::{DefaultClient, PrometheusParams};
+
+/// Name of the label, added to metrics of every bridge.
+const BRIDGE_LABEL: &str = "bridge";
+
+/// Run several complex relays in the same process.
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct RelayBridges {
+	/// Path to the JSON file with the list of bridges.
+	#[structopt(long)]
+	config: PathBuf,
+	#[structopt(flatten)]
+	prometheus_params: PrometheusParams,
+	#[structopt(flatten)]
+	health: HealthParams,
+	#[structopt(flatten)]
+	shutdown: ShutdownParams,
+}
+
+/// Bridges configuration file.
+#[derive(Debug, Deserialize)]
+struct BridgesConfig {
+	bridges: Vec<BridgeConfig>,
+}
+
+/// Single bridge of the configuration file.
+#[derive(Debug, Deserialize)]
+struct BridgeConfig {
+	/// Unique label of the bridge, used in logs, errors and as the `bridge` label of its metrics.
+	label: String,
+	/// Arguments of the `relay-headers-and-messages` command.
+	args: Vec<String>,
+}
+
+impl RelayBridges {
+	/// Returns true if relays wait for pending transactions after the exit signal.
+	pub fn is_graceful_shutdown_enabled(&self) -> bool {
+		self.shutdown.shutdown_timeout != 0
+	}
+
+	/// Run the command.
+	pub async fn run(mut self) -> anyhow::Result<()> {
+		let config = std::fs::read_to_string(&self.config)
+			.map_err(|e| anyhow::format_err!("Failed to read {}: {}", self.config.display(), e))?;
+		let prepared = prepare_bridges(&config, &self.shutdown)?;
+
+		let registry = Registry::new();
+		let (expected_loops, average_block_interval) = prepared.bridges.iter().fold(
+			(0, Duration::ZERO),
+			|(expected_loops, average_block_interval), (_, bridge)| {
+				let (bridge_loops, bridge_block_interval) = bridge.expected_loops();
+				(
+					expected_loops + bridge_loops,
+					std::cmp::max(average_block_interval, bridge_block_interval),
+				)
+			},
+		);
+		match self.health.take_prometheus(&mut self.prometheus_params)? {
+			Some(prometheus_params) =>
+				self.health
+					.start(
+						Some(prometheus_params),
+						registry.clone(),
+						expected_loops,
+						average_block_interval,
+					)
+					.await?,
+			None => health::serve_metrics(self.prometheus_params, registry.clone()).await?,
+		}
+
+		prepared.light_clients.into_iter().for_each(LightClientEndpoint::spawn);
+		prepared.failover_proxies.into_iter().for_each(FailoverProxy::spawn);
+		let clients = SharedClients::default();
+		futures::future::try_join_all(prepared.bridges.into_iter().map(|(label, bridge)| {
+			let shared = SharedBridge {
+				label: label.clone(),
+				clients: clients.clone(),
+				registry: registry.clone(),
+			};
+			async move {
+				log::info!(target: LOG_TARGET, "Starting bridge {}: {}", label, bridge.bridge_name());
+				bridge
+					.run_shared(shared)
+					.await
+					.map_err(|e| anyhow::format_err!("{}: {}", label, e))
+			}
+		}))
+		.await?;
+		Ok(())
+	}
+}
+
+/// Node clients, shared by all bridges of the process.
+///
+/// Clients are mapped by the chain type and connection parameters.
+#[derive(Clone, Default)]
+struct SharedClients(Arc<Mutex<BTreeMap<(TypeId, String), Box<dyn Any + Send>>>>);
+
+/// Resources of the process, shared by the bridge with other bridges.
+pub struct SharedBridge {
+	label: String,
+	clients: SharedClients,
+	registry: Registry,
+}
+
+impl SharedBridge {
+	/// Returns client, connected to the chain node. The client is created when the first bridge
+	/// asks for it and is reused by all other bridges with the same connection parameters.
+	pub async fn client<C, P, F>(
+		&self,
+		params: P,
+		connect: impl FnOnce(P) -> F,
+	) -> anyhow::Result<DefaultClient<C>>
+	where
+		C: ChainWithRuntimeVersion,
+		P: Debug,
+		F: Future<Output = anyhow::Result<DefaultClient<C>>>,
+	{
+		let key = (TypeId::of::<C>(), format!("{:?}", params));
+		let mut clients = self.clients.0.lock().await;
+		if let Some(client) =
+			clients.get(&key).and_then(|client| client.downcast_ref::<DefaultClient<C>>())
+		{
+			return Ok(Clone::clone(client))
+		}
+
+		let client = connect(params).await?;
+		clients.insert(key, Box::new(client.clone()));
+		Ok(client)
+	}
+
+	/// Returns metrics parameters of the bridge.
+	///
+	/// Metrics of the bridge are labeled with its label and exposed by the process registry.
+	/// Relay loops are not exposing them on their own.
+	pub fn metrics_params(&self) -> anyhow::Result<MetricsParams> {
+		let const_labels = HashMap::from([(BRIDGE_LABEL.to_string(), self.label.clone())]);
+		let registry = Registry::new_custom(None, Some(const_labels.clone()))?;
+		self.registry.register(Box::new(BridgeMetrics {
+			desc: Desc::new(
+				"substrate_relay_bridge".into(),
+				"Metrics of the bridge".into(),
+				Vec::new(),
+				const_labels,
+			)?,
+			registry: registry.clone(),
+		}))?;
+		Ok(MetricsParams { address: None, registry })
+	}
+}
+
+/// Metrics of the single bridge, collected by the process registry.
+struct BridgeMetrics {
+	/// Descriptor, identifying the bridge in the process registry.
+	desc: Desc,
+	/// Registry of the bridge.
+	registry: Registry,
+}
+
+impl Collector for BridgeMetrics {
+	fn desc(&self) -> Vec<&Desc> {
+		vec![&self.desc]
+	}
+
+	fn collect(&self) -> Vec<MetricFamily> {
+		self.registry.gather()
+	}
+}
+
+/// Bridges with their labels and local endpoints that need to be started.
+struct PreparedBridges {
+	bridges: Vec<(String, RelayHeadersAndMessagesCommand)>,
+	failover_proxies: Vec<FailoverProxy>,
+	light_clients: Vec<LightClientEndpoint>,
+}
+
+/// Parse the configuration file.
+///
+/// Connections with chain specifications and with several endpoints are redirected to light
+/// clients and failover proxies.
+fn prepare_bridges(config: &str, shutdown: &ShutdownParams) -> anyhow::Result<PreparedBridges> {
+	let config: BridgesConfig = serde_json::from_str(config)
+		.map_err(|e| anyhow::format_err!("Invalid bridges configuration: {}", e))?;
+	if config.bridges.is_empty() {
+		return Err(anyhow::format_err!("No bridges in the configuration"))
+	}
+
+	let mut labels = BTreeSet::new();
+	if let Some(bridge) = config.bridges.iter().find(|bridge| !labels.insert(&bridge.label)) {
+		return Err(anyhow::format_err!("Duplicate bridge label: {}", bridge.label))
+	}
+
+	let mut light_clients = Vec::new();
+	let mut failover_proxies = Vec::new();
+	let bridges = config
+		.bridges
+		.into_iter()
+		.map(|BridgeConfig { label, args }| {
+			let mut bridge = RelayHeadersAndMessagesCommand::from_iter_safe(
+				std::iter::once("relay-headers-and-messages".to_string()).chain(args),
+			)
+			.map_err(|e| anyhow::format_err!("{}: {}", label, e))?;
+			light_client::redirect_connections(&mut bridge, &mut light_clients)
+				.and_then(|_| {
+					rpc_failover::redirect_connections(&mut bridge, &mut failover_proxies)
+				})
+				.map_err(|e| anyhow::format_err!("{}: {}", label, e))?;
+			Ok((label, bridge.with_shared_shutdown(shutdown)))
+		})
+		.collect::<anyhow::Result<Vec<_>>>()?;
+
+	Ok(PreparedBridges { bridges, failover_proxies, light_clients })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::cli::rpc_proxy::{NodeConnections, DEFAULT_HOST};
+
+	#[test]
+	fn bridges_are_prepared() {
+		let config = r#"{
+			"bridges": [
+				{
+					"label": "rococo-westend",
+					"args": [
+						"bridge-hub-rococo-bridge-hub-westend",
+						"--bridge-hub-rococo-host", "bridge-hub-rococo-collator1",
+						"--bridge-hub-rococo-port", "9944",
+						"--bridge-hub-rococo-signer", "//Alice",
+						"--bridge-hub-rococo-transactions-mortality", "64",
+						"--rococo-host", "rococo-collator1",
+						"--rococo-port", "9944",
+						"--bridge-hub-westend-host", "bridge-hub-westend-collator1",
+						"--bridge-hub-westend-port", "9944",
+						"--bridge-hub-westend-signer", "//Alice",
+						"--bridge-hub-westend-transactions-mortality", "64",
+						"--westend-host", "westend-collator1",
+						"--westend-port", "9944",
+						"--lane", "00000002"
+					]
+				},
+				{
+					"label": "rococo-bulletin",
+					"args": [
+						"rococo-bulletin-bridge-hub-rococo",
+						"--rococo-bulletin-host", "rococo-bulletin-collator1",
+						"--rococo-bulletin-port", "9944",
+						"--rococo-bulletin-signer", "//Bob",
+						"--bridge-hub-rococo-host", "bridge-hub-rococo-collator1",
+						"--bridge-hub-rococo-port", "9944",
+						"--bridge-hub-rococo-signer", "//Bob",
+						"--rococo-host", "rococo-collator1",
+						"--rococo-port", "9944",
+						"--lane", "00000000"
+					]
+				}
+			]
+		}"#;
+
+		let shutdown = ShutdownParams { shutdown_timeout: 60 };
+		let PreparedBridges { bridges, .. } = prepare_bridges(config, &shutdown).unwrap();
+		assert_eq!(
+			bridges.iter().map(|(label, _)| label.as_str()).collect::<Vec<_>>(),
+			vec!["rococo-westend", "rococo-bulletin"],
+		);
+		assert!(bridges.iter().all(|(_, bridge)| bridge.is_graceful_shutdown_enabled()));
+
+		let duplicate = config.replace("rococo-bulletin\"", "rococo-westend\"");
+		assert!(prepare_bridges(&duplicate, &shutdown).is_err());
+
+		// parsed connections with several endpoints are sharing the same failover proxy
+		let failover = config.replace(
+			"\"--rococo-host\", \"rococo-collator1\"",
+			"\"--rococo-uri\", \"ws://rococo-collator1:9944,ws://rococo-collator2:9944\"",
+		);
+		let PreparedBridges { mut bridges, failover_proxies, .. } =
+			prepare_bridges(&failover, &shutdown).unwrap();
+		assert_eq!(failover_proxies.len(), 1);
+		let mut rococo_uris = Vec::new();
+		for (_, bridge) in &mut bridges {
+			bridge
+				.redirect(&mut |prefix, upstream| {
+					if prefix == "rococo" {
+						rococo_uris.push(upstream);
+					}
+					Ok(None)
+				})
+				.unwrap();
+		}
+		assert_eq!(rococo_uris.len(), 2);
+		assert_eq!(rococo_uris[0], rococo_uris[1]);
+		assert!(rococo_uris[0].starts_with(&format!("ws://{}:", DEFAULT_HOST)));
+	}
+
+	#[test]
+	fn bridge_metrics_are_labeled() {
+		let registry = Registry::new();
+		let clients = SharedClients::default();
+		for label in ["rococo-westend", "rococo-bulletin"] {
+			let shared = SharedBridge {
+				label: label.into(),
+				clients: clients.clone(),
+				registry: registry.clone(),
+			};
+			let metrics_params = shared.metrics_params().unwrap();
+			let counter =
+				prometheus_endpoint::prometheus::Counter::new("relay_loop_iterations", "help")
+					.unwrap();
+			metrics_params.registry.register(Box::new(counter)).unwrap();
+		}
+
+		let families = registry.gather();
+		assert_eq!(families.len(), 1);
+		let labels = families[0]
+			.get_metric()
+			.iter()
+			.flat_map(|metric| metric.get_label())
+			.map(|label| (label.get_name().to_string(), label.get_value().to_string()))
+			.collect::<BTreeSet<_>>();
+		assert_eq!(
+			labels,
+			[
+				(BRIDGE_LABEL.to_string(), "rococo-bulletin".to_string()),
+				(BRIDGE_LABEL.to_string(), "rococo-westend".to_string()),
+			]
+			.into_iter()
+			.collect(),
+		);
+	}
+}
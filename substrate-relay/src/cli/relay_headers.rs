@@ -14,32 +14,60 @@
 // You should have received a copy of the GNU General Public License
 // along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
 
+use futures::StreamExt;
 use structopt::StructOpt;
 use strum::{EnumString, VariantNames};
 
-use crate::bridges::{
-	kusama_polkadot::{
-		kusama_headers_to_bridge_hub_polkadot::KusamaToBridgeHubPolkadotCliBridge,
-		polkadot_headers_to_bridge_hub_kusama::PolkadotToBridgeHubKusamaCliBridge,
+use crate::{
+	bridges::{
+		kusama_polkadot::{
+			kusama_headers_to_bridge_hub_polkadot::KusamaToBridgeHubPolkadotCliBridge,
+			polkadot_headers_to_bridge_hub_kusama::PolkadotToBridgeHubKusamaCliBridge,
+		},
+		polkadot_bulletin::{
+			polkadot_bulletin_headers_to_bridge_hub_polkadot::PolkadotBulletinToBridgeHubPolkadotCliBridge,
+			polkadot_headers_to_polkadot_bulletin::PolkadotToPolkadotBulletinCliBridge,
+		},
+		rococo_bulletin::{
+			rococo_bulletin_headers_to_bridge_hub_rococo::RococoBulletinToBridgeHubRococoCliBridge,
+			rococo_headers_to_rococo_bulletin::RococoToRococoBulletinCliBridge,
+		},
+		rococo_westend::{
+			rococo_headers_to_bridge_hub_westend::RococoToBridgeHubWestendCliBridge,
+			westend_headers_to_bridge_hub_rococo::WestendToBridgeHubRococoCliBridge,
+		},
 	},
-	polkadot_bulletin::{
-		polkadot_bulletin_headers_to_bridge_hub_polkadot::PolkadotBulletinToBridgeHubPolkadotCliBridge,
-		polkadot_headers_to_polkadot_bulletin::PolkadotToPolkadotBulletinCliBridge,
-	},
-	rococo_bulletin::{
-		rococo_bulletin_headers_to_bridge_hub_rococo::RococoBulletinToBridgeHubRococoCliBridge,
-		rococo_headers_to_rococo_bulletin::RococoToRococoBulletinCliBridge,
-	},
-	rococo_westend::{
-		rococo_headers_to_bridge_hub_westend::RococoToBridgeHubWestendCliBridge,
-		westend_headers_to_bridge_hub_rococo::WestendToBridgeHubRococoCliBridge,
+	cli::{
+		import_justification::{decode_finality_proof, submit_finality_proof_call},
+		offline_signing::{export_unsigned_transaction, ExportUnsignedParams},
+		LOG_TARGET,
 	},
 };
 
-use substrate_relay_helper::cli::relay_headers::{
-	HeadersRelayer, RelayHeaderParams, RelayHeadersParams,
+use bp_header_chain::ConsensusLogReader;
+use relay_substrate_client::{
+	AccountIdOf, BlockNumberOf, BlockWithJustification, Chain, Client, HeaderIdOf,
+};
+use sp_runtime::{
+	traits::{Header as _, UniqueSaturatedInto},
+	AccountId32,
+};
+use substrate_relay_helper::{
+	cli::{
+		bridge::{CliBridgeBase, RelayToRelayHeadersCliBridge},
+		chain_schema::{SourceConnectionParams, TargetConnectionParams, TargetSigningParams},
+		relay_headers::{HeadersRelayer, RelayHeaderParams, RelayHeadersParams},
+	},
+	finality::{relay_single_header, FinalitySyncPipelineAdapter},
+	finality_base::{engine::Engine, SubstrateFinalityPipeline},
+	TransactionParams,
 };
 
+/// Number of source headers that are read at once, while looking for mandatory headers.
+const SCAN_CONCURRENCY: usize = 16;
+/// Scan progress is reported every time this number of source headers is read.
+const SCAN_PROGRESS_STEP: u32 = 10_000;
+
 /// Start headers relayer process.
 #[derive(StructOpt)]
 pub struct RelayHeaders {
@@ -58,6 +86,31 @@ pub struct RelayHeader {
 	bridge: RelayHeadersBridge,
 	#[structopt(flatten)]
 	params: RelayHeaderParams,
+	#[structopt(flatten)]
+	export: ExportUnsignedParams,
+}
+
+/// Relay all mandatory headers in the range of source blocks.
+#[derive(StructOpt)]
+pub struct RelayHeadersRange {
+	/// A bridge instance to relay headers for.
+	#[structopt(possible_values = RelayHeadersBridge::VARIANTS, case_insensitive = true)]
+	bridge: RelayHeadersBridge,
+	#[structopt(flatten)]
+	source: SourceConnectionParams,
+	#[structopt(flatten)]
+	target: TargetConnectionParams,
+	#[structopt(flatten)]
+	target_sign: TargetSigningParams,
+	/// Number of the first source header of the range.
+	#[structopt(long)]
+	from: u32,
+	/// Number of the last source header of the range.
+	#[structopt(long)]
+	to: u32,
+	/// Also relay the last header of the range, if it is not mandatory.
+	#[structopt(long)]
+	relay_last: bool,
 }
 
 #[derive(Debug, EnumString, VariantNames)]
@@ -74,6 +127,57 @@ pub enum RelayHeadersBridge {
 	RococoBulletinToBridgeHubRococo,
 }
 
+impl RelayHeadersBridge {
+	/// Names of the source and target chains of the bridge.
+	pub fn chain_names(&self) -> (&'static str, &'static str) {
+		match self {
+			Self::RococoToBridgeHubWestend => chain_names::<RococoToBridgeHubWestendCliBridge>(),
+			Self::WestendToBridgeHubRococo => chain_names::<WestendToBridgeHubRococoCliBridge>(),
+			Self::KusamaToBridgeHubPolkadot => chain_names::<KusamaToBridgeHubPolkadotCliBridge>(),
+			Self::PolkadotToBridgeHubKusama => chain_names::<PolkadotToBridgeHubKusamaCliBridge>(),
+			Self::PolkadotToPolkadotBulletin =>
+				chain_names::<PolkadotToPolkadotBulletinCliBridge>(),
+			Self::PolkadotBulletinToBridgeHubPolkadot =>
+				chain_names::<PolkadotBulletinToBridgeHubPolkadotCliBridge>(),
+			Self::RococoToRococoBulletin => chain_names::<RococoToRococoBulletinCliBridge>(),
+			Self::RococoBulletinToBridgeHubRococo =>
+				chain_names::<RococoBulletinToBridgeHubRococoCliBridge>(),
+		}
+	}
+
+	/// Prefix of metrics of the finality relay loop of the bridge.
+	pub fn metrics_prefix(&self) -> String {
+		match self {
+			Self::RococoToBridgeHubWestend =>
+				headers_metrics_prefix::<RococoToBridgeHubWestendCliBridge>(),
+			Self::WestendToBridgeHubRococo =>
+				headers_metrics_prefix::<WestendToBridgeHubRococoCliBridge>(),
+			Self::KusamaToBridgeHubPolkadot =>
+				headers_metrics_prefix::<KusamaToBridgeHubPolkadotCliBridge>(),
+			Self::PolkadotToBridgeHubKusama =>
+				headers_metrics_prefix::<PolkadotToBridgeHubKusamaCliBridge>(),
+			Self::PolkadotToPolkadotBulletin =>
+				headers_metrics_prefix::<PolkadotToPolkadotBulletinCliBridge>(),
+			Self::PolkadotBulletinToBridgeHubPolkadot =>
+				headers_metrics_prefix::<PolkadotBulletinToBridgeHubPolkadotCliBridge>(),
+			Self::RococoToRococoBulletin =>
+				headers_metrics_prefix::<RococoToRococoBulletinCliBridge>(),
+			Self::RococoBulletinToBridgeHubRococo =>
+				headers_metrics_prefix::<RococoBulletinToBridgeHubRococoCliBridge>(),
+		}
+	}
+}
+
+/// Names of the source and target chains of the bridge.
+pub fn chain_names<Bridge: CliBridgeBase>() -> (&'static str, &'static str) {
+	(Bridge::Source::NAME, Bridge::Target::NAME)
+}
+
+/// Prefix of metrics of the finality relay loop of the bridge.
+pub fn headers_metrics_prefix<Bridge: RelayToRelayHeadersCliBridge>() -> String {
+	finality_relay::metrics_prefix::<FinalitySyncPipelineAdapter<Bridge::Finality>>()
+}
+
 impl HeadersRelayer for RococoToBridgeHubWestendCliBridge {}
 impl HeadersRelayer for WestendToBridgeHubRococoCliBridge {}
 impl HeadersRelayer for KusamaToBridgeHubPolkadotCliBridge {}
@@ -84,31 +188,40 @@ impl HeadersRelayer for RococoToRococoBulletinCliBridge {}
 impl HeadersRelayer for RococoBulletinToBridgeHubRococoCliBridge {}
 
 impl RelayHeaders {
+	/// Name of the bridge, served by this command.
+	pub fn bridge_name(&self) -> String {
+		format!("{:?}", self.bridge)
+	}
+
 	/// Run the command.
 	pub async fn run(self) -> anyhow::Result<()> {
 		match self.bridge {
 			RelayHeadersBridge::RococoToBridgeHubWestend =>
-				RococoToBridgeHubWestendCliBridge::relay_headers(self.params),
+				relay_header::<RococoToBridgeHubWestendCliBridge>(self).await,
 			RelayHeadersBridge::WestendToBridgeHubRococo =>
-				WestendToBridgeHubRococoCliBridge::relay_headers(self.params),
+				relay_header::<WestendToBridgeHubRococoCliBridge>(self).await,
 			RelayHeadersBridge::KusamaToBridgeHubPolkadot =>
-				KusamaToBridgeHubPolkadotCliBridge::relay_headers(self.params),
+				relay_header::<KusamaToBridgeHubPolkadotCliBridge>(self).await,
 			RelayHeadersBridge::PolkadotToBridgeHubKusama =>
-				PolkadotToBridgeHubKusamaCliBridge::relay_headers(self.params),
+				relay_header::<PolkadotToBridgeHubKusamaCliBridge>(self).await,
 			RelayHeadersBridge::PolkadotToPolkadotBulletin =>
-				PolkadotToPolkadotBulletinCliBridge::relay_headers(self.params),
+				relay_header::<PolkadotToPolkadotBulletinCliBridge>(self).await,
 			RelayHeadersBridge::PolkadotBulletinToBridgeHubPolkadot =>
-				PolkadotBulletinToBridgeHubPolkadotCliBridge::relay_headers(self.params),
+				relay_header::<PolkadotBulletinToBridgeHubPolkadotCliBridge>(self).await,
 			RelayHeadersBridge::RococoToRococoBulletin =>
-				RococoToRococoBulletinCliBridge::relay_headers(self.params),
+				relay_header::<RococoToRococoBulletinCliBridge>(self).await,
 			RelayHeadersBridge::RococoBulletinToBridgeHubRococo =>
-				RococoBulletinToBridgeHubRococoCliBridge::relay_headers(self.params),
+				relay_header::<RococoBulletinToBridgeHubRococoCliBridge>(self).await,
 		}
-		.await
 	}
 }
 
 impl RelayHeader {
+	/// Name of the bridge, served by this command.
+	pub fn bridge_name(&self) -> String {
+		format!("{:?}", self.bridge)
+	}
+
 	/// Run the command.
 	pub async fn run(self) -> anyhow::Result<()> {
 		match self.bridge {
@@ -132,3 +245,243 @@ impl RelayHeader {
 		.await
 	}
 }
+
+impl RelayHeadersRange {
+	/// Name of the bridge, served by this command.
+	pub fn bridge_name(&self) -> String {
+		format!("{:?}", self.bridge)
+	}
+
+	/// Run the command.
+	pub async fn run(self) -> anyhow::Result<()> {
+		if self.from > self.to {
+			return Err(anyhow::format_err!("Empty range of headers: {}..={}", self.from, self.to))
+		}
+
+		match self.bridge {
+			RelayHeadersBridge::RococoToBridgeHubWestend =>
+				relay_headers_range::<RococoToBridgeHubWestendCliBridge>(self).await,
+			RelayHeadersBridge::WestendToBridgeHubRococo =>
+				relay_headers_range::<WestendToBridgeHubRococoCliBridge>(self).await,
+			RelayHeadersBridge::KusamaToBridgeHubPolkadot =>
+				relay_headers_range::<KusamaToBridgeHubPolkadotCliBridge>(self).await,
+			RelayHeadersBridge::PolkadotToBridgeHubKusama =>
+				relay_headers_range::<PolkadotToBridgeHubKusamaCliBridge>(self).await,
+			RelayHeadersBridge::PolkadotToPolkadotBulletin =>
+				relay_headers_range::<PolkadotToPolkadotBulletinCliBridge>(self).await,
+			RelayHeadersBridge::PolkadotBulletinToBridgeHubPolkadot =>
+				relay_headers_range::<PolkadotBulletinToBridgeHubPolkadotCliBridge>(self).await,
+			RelayHeadersBridge::RococoToRococoBulletin =>
+				relay_headers_range::<RococoToRococoBulletinCliBridge>(self).await,
+			RelayHeadersBridge::RococoBulletinToBridgeHubRococo =>
+				relay_headers_range::<RococoBulletinToBridgeHubRococoCliBridge>(self).await,
+		}
+	}
+}
+
+/// Either relay single header or export the transaction for offline signing.
+async fn relay_header<Bridge: HeadersRelayer + RelayToRelayHeadersCliBridge>(
+	command: RelayHeader,
+) -> anyhow::Result<()>
+where
+	AccountIdOf<Bridge::Target>: From<AccountId32>,
+{
+	let RelayHeader { params, export, .. } = command;
+	let (path, signer) = match export.export_target()? {
+		Some(export_target) => export_target,
+		None => return Bridge::relay_header(params).await,
+	};
+
+	let source_client = params.source.into_client::<Bridge::Source>().await?;
+	let target_client = params.target.into_client::<Bridge::Target>().await?;
+	let header = source_client.header_by_number(params.number.unique_saturated_into()).await?;
+	let engine_id = <<Bridge::Finality as SubstrateFinalityPipeline>::FinalityEngine as Engine<
+		Bridge::Source,
+	>>::ID;
+	let block = source_client.block_by_hash(header.hash()).await?;
+	let justification = block.justification(engine_id).ok_or_else(|| {
+		anyhow::format_err!(
+			"{} header {} has no persistent justification",
+			Bridge::Source::NAME,
+			params.number,
+		)
+	})?;
+	let proof = decode_finality_proof::<Bridge>(justification)?;
+	let call = submit_finality_proof_call::<Bridge>(&target_client, header, proof).await?;
+
+	let mortality = params.target_sign.transactions_mortality()?;
+	export_unsigned_transaction(&target_client, signer.into(), call, mortality, &path).await
+}
+
+/// Relay all mandatory headers of the range that are not yet imported by the target chain.
+///
+/// Headers, that have been imported by the target chain, are not scanned again, so the command
+/// may be restarted with the same range after failure.
+async fn relay_headers_range<Bridge: RelayToRelayHeadersCliBridge>(
+	params: RelayHeadersRange,
+) -> anyhow::Result<()> {
+	let source_client = params.source.into_client::<Bridge::Source>().await?;
+	let target_client = params.target.into_client::<Bridge::Target>().await?;
+	let transaction_params = TransactionParams {
+		signer: params.target_sign.to_keypair::<Bridge::Target>()?,
+		mortality: params.target_sign.transactions_mortality()?,
+	};
+
+	let best_finalized = best_finalized_number::<Bridge>(&target_client).await?;
+	let from = match best_finalized {
+		Some(best_finalized) => std::cmp::max(params.from, best_finalized.saturating_add(1)),
+		None => params.from,
+	};
+	log::info!(
+		target: LOG_TARGET,
+		"Looking for mandatory {} headers in range {}..={}. Best {} header at {}: {:?}",
+		Bridge::Source::NAME,
+		from,
+		params.to,
+		Bridge::Source::NAME,
+		Bridge::Target::NAME,
+		best_finalized,
+	);
+
+	let mandatory = find_mandatory_headers::<Bridge>(&source_client, from, params.to).await?;
+	let headers =
+		headers_to_relay(mandatory, params.relay_last.then_some(params.to), best_finalized);
+	log::info!(
+		target: LOG_TARGET,
+		"Relaying {} {} headers to {}: {:?}",
+		headers.len(),
+		Bridge::Source::NAME,
+		Bridge::Target::NAME,
+		headers,
+	);
+
+	for (index, number) in headers.iter().enumerate() {
+		relay_single_header::<Bridge::Finality>(
+			source_client.clone(),
+			target_client.clone(),
+			transaction_params.clone(),
+			(*number).into(),
+		)
+		.await
+		.map_err(|e| {
+			anyhow::format_err!(
+				"Failed to relay {} header {}: {}. Restart the command to resume",
+				Bridge::Source::NAME,
+				number,
+				e,
+			)
+		})?;
+		log::info!(
+			target: LOG_TARGET,
+			header:% = number;
+			"Relayed {} header {} ({} of {})",
+			Bridge::Source::NAME,
+			number,
+			index + 1,
+			headers.len(),
+		);
+	}
+
+	Ok(())
+}
+
+/// Read number of the best source header, imported by the target chain.
+async fn best_finalized_number<Bridge: CliBridgeBase>(
+	target_client: &impl Client<Bridge::Target>,
+) -> anyhow::Result<Option<u32>> {
+	let at = target_client.best_header_hash().await?;
+	let best_finalized: Option<HeaderIdOf<Bridge::Source>> = target_client
+		.state_call(at, Bridge::Source::BEST_FINALIZED_HEADER_ID_METHOD.into(), ())
+		.await?;
+	best_finalized
+		.map(|id| {
+			id.number()
+				.try_into()
+				.map_err(|_| anyhow::format_err!("Unexpected header number: {}", id.number()))
+		})
+		.transpose()
+}
+
+/// Read all source headers in the range and return numbers of mandatory headers.
+async fn find_mandatory_headers<Bridge: RelayToRelayHeadersCliBridge>(
+	source_client: &impl Client<Bridge::Source>,
+	from: u32,
+	to: u32,
+) -> anyhow::Result<Vec<u32>> {
+	let mut mandatory = Vec::new();
+	let mut headers = futures::stream::iter(from..=to)
+		.map(|number| async move {
+			let header = source_client
+				.header_by_number(BlockNumberOf::<Bridge::Source>::from(number))
+				.await;
+			(number, header)
+		})
+		.buffered(SCAN_CONCURRENCY);
+	while let Some((number, header)) = headers.next().await {
+		let header = header.map_err(|e| {
+			anyhow::format_err!("Failed to read {} header {}: {}", Bridge::Source::NAME, number, e)
+		})?;
+		let is_mandatory =
+			<<Bridge::Finality as SubstrateFinalityPipeline>::FinalityEngine as Engine<
+				Bridge::Source,
+			>>::ConsensusLogReader::schedules_authorities_change(header.digest());
+		if is_mandatory {
+			log::info!(
+				target: LOG_TARGET,
+				header:% = number;
+				"Found mandatory {} header {}",
+				Bridge::Source::NAME,
+				number,
+			);
+			mandatory.push(number);
+		}
+		if (number - from + 1) % SCAN_PROGRESS_STEP == 0 {
+			log::info!(
+				target: LOG_TARGET,
+				"Scanned {} of {} {} headers",
+				number - from + 1,
+				to - from + 1,
+				Bridge::Source::NAME,
+			);
+		}
+	}
+
+	Ok(mandatory)
+}
+
+/// Select headers that need to be relayed: mandatory headers that are not yet imported by the
+/// target chain and the optional last header of the range.
+fn headers_to_relay(
+	mandatory: Vec<u32>,
+	last: Option<u32>,
+	best_finalized: Option<u32>,
+) -> Vec<u32> {
+	let is_imported = |number: u32| best_finalized.is_some_and(|best| number <= best);
+	let mut headers =
+		mandatory.into_iter().filter(|number| !is_imported(*number)).collect::<Vec<_>>();
+	if let Some(last) = last {
+		// all mandatory headers are in the range, so the last header may only be the last of them
+		if !is_imported(last) && !headers.contains(&last) {
+			headers.push(last);
+		}
+	}
+	headers
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn imported_headers_are_not_relayed() {
+		// nothing is imported
+		assert_eq!(headers_to_relay(vec![10, 20], None, None), vec![10, 20]);
+		assert_eq!(headers_to_relay(vec![10, 20], Some(30), None), vec![10, 20, 30]);
+		// the last header of the range is mandatory
+		assert_eq!(headers_to_relay(vec![10, 20, 30], Some(30), Some(5)), vec![10, 20, 30]);
+		// resume after the first header has been relayed
+		assert_eq!(headers_to_relay(vec![10, 20], Some(30), Some(10)), vec![20, 30]);
+		// everything is imported
+		assert_eq!(headers_to_relay(vec![10, 20], Some(30), Some(30)), Vec::<u32>::new());
+	}
+}
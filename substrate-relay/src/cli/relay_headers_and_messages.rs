@@ -24,31 +24,54 @@
 //! 3) declare a new struct for the added bridge and implement the `Full2WayBridge` trait for it.
 
 use async_trait::async_trait;
+use futures::Future;
 use structopt::StructOpt;
 
-use crate::bridges::{
-	kusama_polkadot::{
-		kusama_parachains_to_bridge_hub_polkadot::BridgeHubKusamaToBridgeHubPolkadotCliBridge,
-		polkadot_parachains_to_bridge_hub_kusama::BridgeHubPolkadotToBridgeHubKusamaCliBridge,
-	},
-	polkadot_bulletin::{
-		polkadot_bulletin_headers_to_bridge_hub_polkadot::PolkadotBulletinToBridgeHubPolkadotCliBridge,
-		polkadot_parachains_to_polkadot_bulletin::PolkadotToPolkadotBulletinCliBridge,
-	},
-	rococo_bulletin::{
-		rococo_bulletin_headers_to_bridge_hub_rococo::RococoBulletinToBridgeHubRococoCliBridge,
-		rococo_parachains_to_rococo_bulletin::RococoToRococoBulletinCliBridge,
-		BridgeHubRococoAsBridgeHubPolkadot,
+use crate::{
+	bridges::{
+		kusama_polkadot::{
+			kusama_parachains_to_bridge_hub_polkadot::BridgeHubKusamaToBridgeHubPolkadotCliBridge,
+			polkadot_parachains_to_bridge_hub_kusama::BridgeHubPolkadotToBridgeHubKusamaCliBridge,
+		},
+		polkadot_bulletin::{
+			messages_limits::{messages_limits_loop, run_until_limits_change},
+			polkadot_bulletin_headers_to_bridge_hub_polkadot::PolkadotBulletinToBridgeHubPolkadotCliBridge,
+			polkadot_parachains_to_polkadot_bulletin::PolkadotToPolkadotBulletinCliBridge,
+		},
+		rococo_bulletin::{
+			rococo_bulletin_headers_to_bridge_hub_rococo::RococoBulletinToBridgeHubRococoCliBridge,
+			rococo_parachains_to_rococo_bulletin::RococoToRococoBulletinCliBridge,
+			BridgeHubRococoAsBridgeHubPolkadot,
+		},
+		rococo_westend::{
+			rococo_parachains_to_bridge_hub_westend::BridgeHubRococoToBridgeHubWestendCliBridge,
+			westend_parachains_to_bridge_hub_rococo::BridgeHubWestendToBridgeHubRococoCliBridge,
+		},
 	},
-	rococo_westend::{
-		rococo_parachains_to_bridge_hub_westend::BridgeHubRococoToBridgeHubWestendCliBridge,
-		westend_parachains_to_bridge_hub_rococo::BridgeHubWestendToBridgeHubRococoCliBridge,
+	cli::{
+		health::HealthParams,
+		leader_election::LeaderElectionParams,
+		relay_bridges::SharedBridge,
+		relayer_rewards::{ChainWithRelayerRewards, RelayerRewardsParams},
+		rpc_proxy::{
+			redirect_parsed_connections, NodeConnectionParams, NodeConnections, DEFAULT_HOST,
+		},
+		shutdown::{PendingTransactionsWatcher, ShutdownParams},
+		signer_top_up::{run_with_top_up, ChainWithTopUp, SignerTopUpParams},
+		tip_policy::{tips_loop, ChainWithTips, TipPolicyParams, Tips},
+		LOG_TARGET,
 	},
 };
+use codec::Encode;
+use relay_polkadot_bulletin_client::PolkadotBulletin;
 use relay_substrate_client::{
-	AccountKeyPairOf, ChainRuntimeVersion, ChainWithRuntimeVersion, ChainWithTransactions,
-	Parachain, SimpleRuntimeVersion,
+	AccountIdOf, AccountKeyPairOf, Chain, ChainRuntimeVersion, ChainWithBalances,
+	ChainWithMessages, ChainWithRuntimeVersion, ChainWithTransactions, Parachain,
+	SimpleRuntimeVersion,
 };
+use sp_core::Pair;
+use sp_runtime::traits::TryConvert;
+use std::time::Duration;
 use substrate_relay_helper::{
 	cli::{
 		bridge::{
@@ -58,12 +81,15 @@ use substrate_relay_helper::{
 		chain_schema::*,
 		relay_headers_and_messages::{
 			parachain_to_parachain::ParachainToParachainBridge, relay_to_parachain::*,
-			BridgeEndCommonParams, Full2WayBridge, Full2WayBridgeCommonParams,
+			BridgeEndCommonParams, Full2WayBridge, Full2WayBridgeBase, Full2WayBridgeCommonParams,
 			HeadersAndMessagesSharedParams,
 		},
+		HexLaneId, PrometheusParams,
 	},
 	declare_chain_cli_schema, declare_parachain_to_parachain_bridge_schema,
-	declare_relay_to_parachain_bridge_schema, TransactionParams,
+	declare_relay_to_parachain_bridge_schema,
+	messages::SubstrateMessageLane,
+	TransactionParams,
 };
 
 // All supported chains.
@@ -119,6 +145,261 @@ declare_parachain_to_parachain_bridge_schema!(BridgeHubKusama, Kusama, BridgeHub
 declare_relay_to_parachain_bridge_schema!(PolkadotBulletin, BridgeHubPolkadot, Polkadot);
 declare_relay_to_parachain_bridge_schema!(RococoBulletin, BridgeHubRococo, Rococo);
 
+/// Implement `NodeConnectionParams` for the connection parameters of the chain.
+macro_rules! impl_node_connection_params {
+	($params:ident { $uri:ident, $host:ident, $port:ident, $path:ident, $secure:ident $(,)? }) => {
+		impl NodeConnectionParams for $params {
+			fn upstream(&self) -> String {
+				match self.$uri {
+					Some(ref uri) => uri.clone(),
+					None => format!(
+						"{}://{}:{}{}",
+						if self.$secure { "wss" } else { "ws" },
+						self.$host,
+						self.$port,
+						self.$path.as_deref().unwrap_or(""),
+					),
+				}
+			}
+
+			fn redirect(&mut self, port: u16) {
+				self.$uri = None;
+				self.$host = DEFAULT_HOST.into();
+				self.$port = port;
+				self.$path = None;
+				self.$secure = false;
+			}
+		}
+	};
+}
+
+impl_node_connection_params!(RococoConnectionParams {
+	rococo_uri,
+	rococo_host,
+	rococo_port,
+	rococo_path,
+	rococo_secure,
+});
+impl_node_connection_params!(WestendConnectionParams {
+	westend_uri,
+	westend_host,
+	westend_port,
+	westend_path,
+	westend_secure,
+});
+impl_node_connection_params!(KusamaConnectionParams {
+	kusama_uri,
+	kusama_host,
+	kusama_port,
+	kusama_path,
+	kusama_secure,
+});
+impl_node_connection_params!(PolkadotConnectionParams {
+	polkadot_uri,
+	polkadot_host,
+	polkadot_port,
+	polkadot_path,
+	polkadot_secure,
+});
+impl_node_connection_params!(BridgeHubRococoConnectionParams {
+	bridge_hub_rococo_uri,
+	bridge_hub_rococo_host,
+	bridge_hub_rococo_port,
+	bridge_hub_rococo_path,
+	bridge_hub_rococo_secure,
+});
+impl_node_connection_params!(BridgeHubWestendConnectionParams {
+	bridge_hub_westend_uri,
+	bridge_hub_westend_host,
+	bridge_hub_westend_port,
+	bridge_hub_westend_path,
+	bridge_hub_westend_secure,
+});
+impl_node_connection_params!(BridgeHubKusamaConnectionParams {
+	bridge_hub_kusama_uri,
+	bridge_hub_kusama_host,
+	bridge_hub_kusama_port,
+	bridge_hub_kusama_path,
+	bridge_hub_kusama_secure,
+});
+impl_node_connection_params!(BridgeHubPolkadotConnectionParams {
+	bridge_hub_polkadot_uri,
+	bridge_hub_polkadot_host,
+	bridge_hub_polkadot_port,
+	bridge_hub_polkadot_path,
+	bridge_hub_polkadot_secure,
+});
+impl_node_connection_params!(PolkadotBulletinConnectionParams {
+	polkadot_bulletin_uri,
+	polkadot_bulletin_host,
+	polkadot_bulletin_port,
+	polkadot_bulletin_path,
+	polkadot_bulletin_secure,
+});
+impl_node_connection_params!(RococoBulletinConnectionParams {
+	rococo_bulletin_uri,
+	rococo_bulletin_host,
+	rococo_bulletin_port,
+	rococo_bulletin_path,
+	rococo_bulletin_secure,
+});
+
+/// Implement construction of the bridge that is using clients, shared with other bridges of the
+/// same process (see the `relay_bridges` module).
+macro_rules! impl_into_shared_bridge {
+	(parachain_to_parachain, $params:ident) => {
+		impl $params {
+			async fn into_shared_bridge<Left, LeftRelay, Right, RightRelay, L2R, R2L>(
+				self,
+				shared_bridge: &SharedBridge,
+			) -> anyhow::Result<ParachainToParachainBridge<L2R, R2L>>
+			where
+				Left: ChainWithTransactions + ChainWithRuntimeVersion + Parachain,
+				LeftRelay: ChainWithRuntimeVersion,
+				Right: ChainWithTransactions + ChainWithRuntimeVersion + Parachain,
+				RightRelay: ChainWithRuntimeVersion,
+				L2R: CliBridgeBase<Source = Left, Target = Right>
+					+ MessagesCliBridge
+					+ ParachainToRelayHeadersCliBridge<SourceRelay = LeftRelay>,
+				R2L: CliBridgeBase<Source = Right, Target = Left>
+					+ MessagesCliBridge
+					+ ParachainToRelayHeadersCliBridge<SourceRelay = RightRelay>,
+			{
+				Ok(ParachainToParachainBridge {
+					common: shared_common_params::<L2R>(
+						shared_bridge,
+						self.shared,
+						BridgeEndCommonParams {
+							client: shared_bridge
+								.client(self.left, |params| params.into_client::<Left>())
+								.await?,
+							tx_params: self.left_sign.transaction_params::<Left>()?,
+							accounts: vec![],
+						},
+						BridgeEndCommonParams {
+							client: shared_bridge
+								.client(self.right, |params| params.into_client::<Right>())
+								.await?,
+							tx_params: self.right_sign.transaction_params::<Right>()?,
+							accounts: vec![],
+						},
+					)?,
+					left_relay: shared_bridge
+						.client(self.left_relay, |params| params.into_client::<LeftRelay>())
+						.await?,
+					right_relay: shared_bridge
+						.client(self.right_relay, |params| params.into_client::<RightRelay>())
+						.await?,
+				})
+			}
+		}
+	};
+	(relay_to_parachain, $params:ident) => {
+		impl $params {
+			async fn into_shared_bridge<Left, Right, RightRelay, L2R, R2L>(
+				self,
+				shared_bridge: &SharedBridge,
+			) -> anyhow::Result<RelayToParachainBridge<L2R, R2L>>
+			where
+				Left: ChainWithTransactions + ChainWithRuntimeVersion,
+				Right: ChainWithTransactions + ChainWithRuntimeVersion + Parachain,
+				RightRelay: ChainWithRuntimeVersion,
+				L2R: CliBridgeBase<Source = Left, Target = Right>
+					+ MessagesCliBridge
+					+ RelayToRelayHeadersCliBridge,
+				R2L: CliBridgeBase<Source = Right, Target = Left>
+					+ MessagesCliBridge
+					+ ParachainToRelayHeadersCliBridge<SourceRelay = RightRelay>,
+			{
+				Ok(RelayToParachainBridge {
+					common: shared_common_params::<L2R>(
+						shared_bridge,
+						self.shared,
+						BridgeEndCommonParams {
+							client: shared_bridge
+								.client(self.left, |params| params.into_client::<Left>())
+								.await?,
+							tx_params: self.left_sign.transaction_params::<Left>()?,
+							accounts: vec![],
+						},
+						BridgeEndCommonParams {
+							client: shared_bridge
+								.client(self.right, |params| params.into_client::<Right>())
+								.await?,
+							tx_params: self.right_sign.transaction_params::<Right>()?,
+							accounts: vec![],
+						},
+					)?,
+					right_relay: shared_bridge
+						.client(self.right_relay, |params| params.into_client::<RightRelay>())
+						.await?,
+				})
+			}
+		}
+	};
+}
+
+impl_into_shared_bridge!(parachain_to_parachain, BridgeHubRococoBridgeHubWestendHeadersAndMessages);
+impl_into_shared_bridge!(
+	parachain_to_parachain,
+	BridgeHubKusamaBridgeHubPolkadotHeadersAndMessages
+);
+impl_into_shared_bridge!(relay_to_parachain, PolkadotBulletinBridgeHubPolkadotHeadersAndMessages);
+impl_into_shared_bridge!(relay_to_parachain, RococoBulletinBridgeHubRococoHeadersAndMessages);
+
+/// Implement redirection of both complex relay signers connections to proxies, applying tip
+/// policies (see the `tip_policy` module).
+macro_rules! impl_tips_proxies {
+	($params:ident) => {
+		impl $params {
+			fn start_tips_proxies<Left, Right>(
+				&mut self,
+				tip_policy: &TipPolicyParams,
+			) -> anyhow::Result<(Option<Tips<Left>>, Option<Tips<Right>>)>
+			where
+				Left: ChainWithTips,
+				Right: ChainWithTips,
+				AccountIdOf<Left>: From<<AccountKeyPairOf<Left> as Pair>::Public>,
+				AccountIdOf<Right>: From<<AccountKeyPairOf<Right> as Pair>::Public>,
+			{
+				Ok((
+					tip_policy.left_tips::<Left>(
+						&mut self.left,
+						self.left_sign.transaction_params::<Left>()?,
+					)?,
+					tip_policy.right_tips::<Right>(
+						&mut self.right,
+						self.right_sign.transaction_params::<Right>()?,
+					)?,
+				))
+			}
+		}
+	};
+}
+
+impl_tips_proxies!(BridgeHubRococoBridgeHubWestendHeadersAndMessages);
+impl_tips_proxies!(BridgeHubKusamaBridgeHubPolkadotHeadersAndMessages);
+impl_tips_proxies!(PolkadotBulletinBridgeHubPolkadotHeadersAndMessages);
+impl_tips_proxies!(RococoBulletinBridgeHubRococoHeadersAndMessages);
+
+/// Parameters, shared by all bridge types, of the bridge that is served together with other
+/// bridges of the same process. Metrics of the bridge are exposed by the process registry.
+fn shared_common_params<L2R>(
+	shared_bridge: &SharedBridge,
+	shared: HeadersAndMessagesSharedParams,
+	left: BridgeEndCommonParams<L2R::Source>,
+	right: BridgeEndCommonParams<L2R::Target>,
+) -> anyhow::Result<Full2WayBridgeCommonParams<L2R::Source, L2R::Target>>
+where
+	L2R: MessagesCliBridge,
+	L2R::Source: ChainWithTransactions + ChainWithRuntimeVersion,
+	L2R::Target: ChainWithTransactions + ChainWithRuntimeVersion,
+{
+	let mut common = Full2WayBridgeCommonParams::new::<L2R>(shared, left, right)?;
+	common.metrics_params = shared_bridge.metrics_params()?;
+	Ok(common)
+}
+
 /// BridgeHubRococo <> BridgeHubWestend complex relay.
 pub struct BridgeHubRococoBridgeHubWestendFull2WayBridge {
 	base: <Self as Full2WayBridge>::Base,
@@ -236,30 +517,490 @@ pub enum RelayHeadersAndMessages {
 	BridgeHubRococoBridgeHubWestend(BridgeHubRococoBridgeHubWestendHeadersAndMessages),
 }
 
-impl RelayHeadersAndMessages {
+/// Complex headers+messages relay command.
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct RelayHeadersAndMessagesCommand {
+	#[structopt(flatten)]
+	leader_election: LeaderElectionParams,
+	#[structopt(flatten)]
+	health: HealthParams,
+	#[structopt(flatten)]
+	shutdown: ShutdownParams,
+	#[structopt(flatten)]
+	relayer_rewards: RelayerRewardsParams,
+	#[structopt(flatten)]
+	signer_top_up: SignerTopUpParams,
+	#[structopt(flatten)]
+	tip_policy: TipPolicyParams,
+	#[structopt(subcommand)]
+	bridge: RelayHeadersAndMessages,
+}
+
+impl RelayHeadersAndMessagesCommand {
+	/// Returns true if the relay waits for pending transactions after the exit signal.
+	pub fn is_graceful_shutdown_enabled(&self) -> bool {
+		self.shutdown.shutdown_timeout != 0
+	}
+
+	/// Name of the bridge, served by this command.
+	pub fn bridge_name(&self) -> String {
+		match self.bridge {
+			RelayHeadersAndMessages::BridgeHubKusamaBridgeHubPolkadot(_) =>
+				"BridgeHubKusamaBridgeHubPolkadot",
+			RelayHeadersAndMessages::PolkadotBulletinBridgeHubPolkadot(_) =>
+				"PolkadotBulletinBridgeHubPolkadot",
+			RelayHeadersAndMessages::RococoBulletinBridgeHubRococo(_) =>
+				"RococoBulletinBridgeHubRococo",
+			RelayHeadersAndMessages::BridgeHubRococoBridgeHubWestend(_) =>
+				"BridgeHubRococoBridgeHubWestend",
+		}
+		.into()
+	}
+
+	/// Serve the bridge together with other bridges of the same process.
+	///
+	/// All bridges of the process share the same shutdown parameters.
+	pub fn with_shared_shutdown(mut self, shutdown: &ShutdownParams) -> Self {
+		self.shutdown.shutdown_timeout = shutdown.shutdown_timeout;
+		self
+	}
+
+	/// Number of relay loops, started by the command, and the maximal average block interval of
+	/// bridged chains.
+	pub fn expected_loops(&self) -> (usize, Duration) {
+		match self.bridge {
+			RelayHeadersAndMessages::BridgeHubKusamaBridgeHubPolkadot(ref params) =>
+				expected_loops::<BridgeHubKusamaBridgeHubPolkadotFull2WayBridge>(
+					params.shared.lane.len(),
+				),
+			RelayHeadersAndMessages::PolkadotBulletinBridgeHubPolkadot(ref params) =>
+				expected_loops::<PolkadotBulletinBridgeHubPolkadotFull2WayBridge>(
+					params.shared.lane.len(),
+				),
+			RelayHeadersAndMessages::RococoBulletinBridgeHubRococo(ref params) =>
+				expected_loops::<RococoBulletinBridgeHubRococoFull2WayBridge>(
+					params.shared.lane.len(),
+				),
+			RelayHeadersAndMessages::BridgeHubRococoBridgeHubWestend(ref params) =>
+				expected_loops::<BridgeHubRococoBridgeHubWestendFull2WayBridge>(
+					params.shared.lane.len(),
+				),
+		}
+	}
+
 	/// Run the command.
 	pub async fn run(self) -> anyhow::Result<()> {
+		self.bridge
+			.run(
+				&self.leader_election,
+				&self.health,
+				&self.shutdown,
+				&self.relayer_rewards,
+				&self.signer_top_up,
+				&self.tip_policy,
+				None,
+			)
+			.await
+	}
+
+	/// Run the command, using clients and metrics registry, shared with other bridges of the
+	/// process.
+	pub async fn run_shared(self, shared_bridge: SharedBridge) -> anyhow::Result<()> {
+		if self.health.health_endpoints {
+			return Err(anyhow::format_err!(
+				"Health endpoints of all bridges are exposed by the relay-bridges command"
+			))
+		}
+
+		self.bridge
+			.run(
+				&self.leader_election,
+				&self.health,
+				&self.shutdown,
+				&self.relayer_rewards,
+				&self.signer_top_up,
+				&self.tip_policy,
+				Some(shared_bridge),
+			)
+			.await
+	}
+}
+
+/// Watch transactions of both complex relay signers.
+fn pending_transactions_watcher<Bridge: Full2WayBridge>(
+	bridge: &Bridge,
+) -> PendingTransactionsWatcher
+where
+	Bridge::Left: ChainWithBalances,
+	Bridge::Right: ChainWithBalances,
+	AccountIdOf<Bridge::Left>: From<<AccountKeyPairOf<Bridge::Left> as Pair>::Public>,
+	AccountIdOf<Bridge::Right>: From<<AccountKeyPairOf<Bridge::Right> as Pair>::Public>,
+{
+	let common = bridge.base().common();
+	PendingTransactionsWatcher::default()
+		.watch(common.left.client.clone(), common.left.tx_params.signer.public().into())
+		.watch(common.right.client.clone(), common.right.tx_params.signer.public().into())
+}
+
+/// Lane identifier type of the messages bridge.
+type LaneIdOf<Bridge> =
+	<<Bridge as MessagesCliBridge>::MessagesLane as SubstrateMessageLane>::LaneId;
+
+/// Start reading rewards of both complex relay signers.
+fn spawn_relayer_rewards_watchers<Bridge: Full2WayBridge>(
+	relayer_rewards: &RelayerRewardsParams,
+	bridge: &Bridge,
+) -> anyhow::Result<()>
+where
+	Bridge::Left: ChainWithRelayerRewards,
+	Bridge::Right: ChainWithRelayerRewards,
+	LaneIdOf<Bridge::L2R>: Send + Sync + 'static,
+	AccountIdOf<Bridge::Left>: From<<AccountKeyPairOf<Bridge::Left> as Pair>::Public>,
+	AccountIdOf<Bridge::Right>: From<<AccountKeyPairOf<Bridge::Right> as Pair>::Public>,
+{
+	let common = bridge.base().common();
+	let lanes = common
+		.shared
+		.lane
+		.iter()
+		.cloned()
+		.map(|lane| {
+			HexLaneId::try_convert(lane).map_err(|invalid_lane_id| {
+				anyhow::format_err!("Invalid laneId: {:?}!", invalid_lane_id)
+			})
+		})
+		.collect::<anyhow::Result<Vec<LaneIdOf<Bridge::L2R>>>>()?;
+
+	relayer_rewards.spawn_watcher::<Bridge::Left, Bridge::Right, _>(
+		common.left.client.clone(),
+		common.left.tx_params.signer.public().into(),
+		lanes.clone(),
+		&common.metrics_params.registry,
+	)?;
+	relayer_rewards.spawn_watcher::<Bridge::Right, Bridge::Left, _>(
+		common.right.client.clone(),
+		common.right.tx_params.signer.public().into(),
+		lanes,
+		&common.metrics_params.registry,
+	)
+}
+
+/// Prepare top-up loops of both complex relay signers.
+fn signer_top_up_loops<Bridge: Full2WayBridge>(
+	signer_top_up: &SignerTopUpParams,
+	bridge: &Bridge,
+) -> anyhow::Result<impl Future<Output = ()>>
+where
+	Bridge::Left: ChainWithTopUp,
+	Bridge::Right: ChainWithTopUp,
+	AccountIdOf<Bridge::Left>: From<<AccountKeyPairOf<Bridge::Left> as Pair>::Public>,
+	AccountIdOf<Bridge::Right>: From<<AccountKeyPairOf<Bridge::Right> as Pair>::Public>,
+{
+	let common = bridge.base().common();
+	let left = signer_top_up.left_top_up::<Bridge::Left>(
+		common.left.client.clone(),
+		common.left.tx_params.signer.public().into(),
+		&common.metrics_params.registry,
+	)?;
+	let right = signer_top_up.right_top_up::<Bridge::Right>(
+		common.right.client.clone(),
+		common.right.tx_params.signer.public().into(),
+		&common.metrics_params.registry,
+	)?;
+	Ok(async move {
+		futures::future::join(left, right).await;
+	})
+}
+
+/// Tips states of both complex relay signers.
+type TipsOf<Bridge> = (
+	Option<Tips<<Bridge as Full2WayBridge>::Left>>,
+	Option<Tips<<Bridge as Full2WayBridge>::Right>>,
+);
+
+/// Prepare loops, refreshing signing parameters of tip proxies and tracking transactions of both
+/// complex relay signers.
+fn tips_loops<Bridge: Full2WayBridge>(
+	tips: TipsOf<Bridge>,
+	bridge: &Bridge,
+) -> anyhow::Result<impl Future<Output = ()>>
+where
+	Bridge::Left: ChainWithTips,
+	Bridge::Right: ChainWithTips,
+	AccountIdOf<Bridge::Left>: From<<AccountKeyPairOf<Bridge::Left> as Pair>::Public>,
+	AccountIdOf<Bridge::Right>: From<<AccountKeyPairOf<Bridge::Right> as Pair>::Public>,
+{
+	let common = bridge.base().common();
+	let left = tips_loop::<Bridge::Left>(
+		common.left.client.clone(),
+		tips.0,
+		&common.metrics_params.registry,
+	)?;
+	let right = tips_loop::<Bridge::Right>(
+		common.right.client.clone(),
+		tips.1,
+		&common.metrics_params.registry,
+	)?;
+	Ok(async move {
+		futures::future::join(left, right).await;
+	})
+}
+
+/// Estimate limits of messages delivery transactions at the Bulletin chain and prepare loop,
+/// re-estimating them after runtime upgrades. The loop stops when limits change.
+///
+/// If estimation fails, the relay is using safe underestimations.
+async fn bulletin_messages_limits_loop<Bridge>(bridge: &Bridge) -> impl Future<Output = ()>
+where
+	Bridge: Full2WayBridge<Left = PolkadotBulletin>,
+	<Bridge::R2L as MessagesCliBridge>::MessagesLane:
+		SubstrateMessageLane<TargetChain = PolkadotBulletin>,
+	HexLaneId: TryConvert<HexLaneId, LaneIdOf<Bridge::R2L>>,
+{
+	let common = bridge.base().common();
+	let lane = match common.shared.lane.first().cloned().map(HexLaneId::try_convert) {
+		Some(Ok(lane)) => lane,
+		_ => return futures::future::Either::Left(futures::future::pending()),
+	};
+
+	futures::future::Either::Right(
+		messages_limits_loop::<<Bridge::R2L as MessagesCliBridge>::MessagesLane>(
+			common.left.client.clone(),
+			common.left.tx_params.signer.clone(),
+			lane,
+		)
+		.await,
+	)
+}
+
+/// Base of the complex relay that runs known number of headers and parachains relay loops.
+pub trait Full2WayBridgeLoops {
+	/// Number of headers and parachains relay loops, started by the complex relay.
+	const HEADERS_LOOPS: usize;
+}
+
+impl<L2R, R2L> Full2WayBridgeLoops for ParachainToParachainBridge<L2R, R2L>
+where
+	L2R: MessagesCliBridge + ParachainToRelayHeadersCliBridge,
+	R2L: MessagesCliBridge + ParachainToRelayHeadersCliBridge,
+	<L2R as CliBridgeBase>::Source: Parachain,
+	<R2L as CliBridgeBase>::Source: Parachain,
+{
+	// on-demand relay headers and parachains relays in both directions
+	const HEADERS_LOOPS: usize = 4;
+}
+
+impl<L2R, R2L> Full2WayBridgeLoops for RelayToParachainBridge<L2R, R2L>
+where
+	L2R: MessagesCliBridge + RelayToRelayHeadersCliBridge,
+	R2L: MessagesCliBridge + ParachainToRelayHeadersCliBridge,
+	<R2L as CliBridgeBase>::Source: Parachain,
+{
+	// on-demand headers relay from the left chain, on-demand relay headers and parachains relays
+	// from the right chain
+	const HEADERS_LOOPS: usize = 3;
+}
+
+/// Number of relay loops of the complex relay and the maximal average block interval of bridged
+/// chains.
+///
+/// Apart from headers and parachains relay loops, the relay runs two loops for every lane.
+fn expected_loops<Bridge: Full2WayBridge>(lanes: usize) -> (usize, Duration)
+where
+	Bridge::Base: Full2WayBridgeLoops,
+{
+	(
+		Bridge::Base::HEADERS_LOOPS + 2 * lanes,
+		std::cmp::max(Bridge::Left::AVERAGE_BLOCK_INTERVAL, Bridge::Right::AVERAGE_BLOCK_INTERVAL),
+	)
+}
+
+/// Start health service for the complex relay.
+async fn start_health_service<Bridge: Full2WayBridge>(
+	health: &HealthParams,
+	prometheus_params: Option<PrometheusParams>,
+	bridge: &Bridge,
+) -> anyhow::Result<()>
+where
+	Bridge::Base: Full2WayBridgeLoops,
+{
+	let common = bridge.base().common();
+	let (expected_loops, average_block_interval) =
+		expected_loops::<Bridge>(common.shared.lane.len());
+	health
+		.start(
+			prometheus_params,
+			common.metrics_params.registry.clone(),
+			expected_loops,
+			average_block_interval,
+		)
+		.await
+}
+
+/// Parameters of the complex relay, shared by all bridges.
+struct FullBridgeParams<'a> {
+	leader_election: &'a LeaderElectionParams,
+	health: &'a HealthParams,
+	shutdown: &'a ShutdownParams,
+	relayer_rewards: &'a RelayerRewardsParams,
+	signer_top_up: &'a SignerTopUpParams,
+}
+
+/// Run the complex relay, together with loops, serving its signers.
+///
+/// The relay is stopped when the `limits_loop`, re-estimating limits of messages delivery
+/// transactions, stops (see `run_until_limits_change`).
+async fn run_full_bridge<Bridge: Full2WayBridge>(
+	bridge: Bridge,
+	prometheus_params: Option<PrometheusParams>,
+	tips: TipsOf<Bridge>,
+	limits_loop: impl Future<Output = ()>,
+	params: FullBridgeParams<'_>,
+) -> anyhow::Result<()>
+where
+	Bridge::Base: Full2WayBridgeLoops,
+	Bridge::Left: ChainWithRelayerRewards + ChainWithTopUp + ChainWithTips,
+	Bridge::Right: ChainWithRelayerRewards + ChainWithTopUp + ChainWithTips,
+	LaneIdOf<Bridge::L2R>: Send + Sync + 'static,
+	AccountIdOf<Bridge::Left>: From<<AccountKeyPairOf<Bridge::Left> as Pair>::Public>,
+	AccountIdOf<Bridge::Right>: From<<AccountKeyPairOf<Bridge::Right> as Pair>::Public>,
+{
+	start_health_service(params.health, prometheus_params, &bridge).await?;
+	spawn_relayer_rewards_watchers(params.relayer_rewards, &bridge)?;
+	let top_up = signer_top_up_loops(params.signer_top_up, &bridge)?;
+	let tips = tips_loops(tips, &bridge)?;
+	let watcher = pending_transactions_watcher(&bridge);
+	params
+		.leader_election
+		.run(params.shutdown.run(
+			run_with_top_up(
+				run_until_limits_change(bridge.run(), limits_loop),
+				futures::future::join(top_up, tips),
+			),
+			watcher,
+		))
+		.await
+}
+
+impl RelayHeadersAndMessages {
+	/// Node connections of the bridge with prefixes of their options.
+	fn connections(&mut self) -> Vec<(&'static str, &mut dyn NodeConnectionParams)> {
+		match self {
+			RelayHeadersAndMessages::BridgeHubKusamaBridgeHubPolkadot(params) => vec![
+				("bridge-hub-kusama", &mut params.left),
+				("kusama", &mut params.left_relay),
+				("bridge-hub-polkadot", &mut params.right),
+				("polkadot", &mut params.right_relay),
+			],
+			RelayHeadersAndMessages::PolkadotBulletinBridgeHubPolkadot(params) => vec![
+				("polkadot-bulletin", &mut params.left),
+				("bridge-hub-polkadot", &mut params.right),
+				("polkadot", &mut params.right_relay),
+			],
+			RelayHeadersAndMessages::RococoBulletinBridgeHubRococo(params) => vec![
+				("rococo-bulletin", &mut params.left),
+				("bridge-hub-rococo", &mut params.right),
+				("rococo", &mut params.right_relay),
+			],
+			RelayHeadersAndMessages::BridgeHubRococoBridgeHubWestend(params) => vec![
+				("bridge-hub-rococo", &mut params.left),
+				("rococo", &mut params.left_relay),
+				("bridge-hub-westend", &mut params.right),
+				("westend", &mut params.right_relay),
+			],
+		}
+	}
+
+	/// Run the command.
+	///
+	/// Connections to all nodes are established before the leader election, so standby
+	/// replicas are ready to start relaying as soon as they acquire the lease. If the bridge is
+	/// served together with other bridges, clients are shared with them.
+	#[allow(clippy::too_many_arguments)]
+	pub async fn run(
+		self,
+		leader_election: &LeaderElectionParams,
+		health: &HealthParams,
+		shutdown: &ShutdownParams,
+		relayer_rewards: &RelayerRewardsParams,
+		signer_top_up: &SignerTopUpParams,
+		tip_policy: &TipPolicyParams,
+		shared_bridge: Option<SharedBridge>,
+	) -> anyhow::Result<()> {
+		let full_bridge_params =
+			FullBridgeParams { leader_election, health, shutdown, relayer_rewards, signer_top_up };
 		match self {
-			RelayHeadersAndMessages::BridgeHubRococoBridgeHubWestend(params) =>
-				BridgeHubRococoBridgeHubWestendFull2WayBridge::new(params.into_bridge().await?)?
-					.run()
-					.await,
-			RelayHeadersAndMessages::BridgeHubKusamaBridgeHubPolkadot(params) =>
-				BridgeHubKusamaBridgeHubPolkadotFull2WayBridge::new(params.into_bridge().await?)?
-					.run()
-					.await,
-			RelayHeadersAndMessages::PolkadotBulletinBridgeHubPolkadot(params) =>
-				PolkadotBulletinBridgeHubPolkadotFull2WayBridge::new(params.into_bridge().await?)?
-					.run()
-					.await,
-			RelayHeadersAndMessages::RococoBulletinBridgeHubRococo(params) =>
-				RococoBulletinBridgeHubRococoFull2WayBridge::new(params.into_bridge().await?)?
-					.run()
-					.await,
+			RelayHeadersAndMessages::BridgeHubRococoBridgeHubWestend(mut params) => {
+				let prometheus_params =
+					health.take_prometheus(&mut params.shared.prometheus_params)?;
+				let tips = params.start_tips_proxies(tip_policy)?;
+				let base = match shared_bridge {
+					Some(ref shared_bridge) => params.into_shared_bridge(shared_bridge).await?,
+					None => params.into_bridge().await?,
+				};
+				let bridge = BridgeHubRococoBridgeHubWestendFull2WayBridge::new(base)?;
+				let limits_loop = futures::future::pending();
+				run_full_bridge(bridge, prometheus_params, tips, limits_loop, full_bridge_params)
+					.await
+			},
+			RelayHeadersAndMessages::BridgeHubKusamaBridgeHubPolkadot(mut params) => {
+				let prometheus_params =
+					health.take_prometheus(&mut params.shared.prometheus_params)?;
+				let tips = params.start_tips_proxies(tip_policy)?;
+				let base = match shared_bridge {
+					Some(ref shared_bridge) => params.into_shared_bridge(shared_bridge).await?,
+					None => params.into_bridge().await?,
+				};
+				let bridge = BridgeHubKusamaBridgeHubPolkadotFull2WayBridge::new(base)?;
+				let limits_loop = futures::future::pending();
+				run_full_bridge(bridge, prometheus_params, tips, limits_loop, full_bridge_params)
+					.await
+			},
+			RelayHeadersAndMessages::PolkadotBulletinBridgeHubPolkadot(mut params) => {
+				let prometheus_params =
+					health.take_prometheus(&mut params.shared.prometheus_params)?;
+				let tips = params.start_tips_proxies(tip_policy)?;
+				let base = match shared_bridge {
+					Some(ref shared_bridge) => params.into_shared_bridge(shared_bridge).await?,
+					None => params.into_bridge().await?,
+				};
+				let bridge = PolkadotBulletinBridgeHubPolkadotFull2WayBridge::new(base)?;
+				let limits_loop = bulletin_messages_limits_loop(&bridge).await;
+				run_full_bridge(bridge, prometheus_params, tips, limits_loop, full_bridge_params)
+					.await
+			},
+			RelayHeadersAndMessages::RococoBulletinBridgeHubRococo(mut params) => {
+				let prometheus_params =
+					health.take_prometheus(&mut params.shared.prometheus_params)?;
+				let tips = params.start_tips_proxies(tip_policy)?;
+				let base = match shared_bridge {
+					Some(ref shared_bridge) => params.into_shared_bridge(shared_bridge).await?,
+					None => params.into_bridge().await?,
+				};
+				let bridge = RococoBulletinBridgeHubRococoFull2WayBridge::new(base)?;
+				let limits_loop = bulletin_messages_limits_loop(&bridge).await;
+				run_full_bridge(bridge, prometheus_params, tips, limits_loop, full_bridge_params)
+					.await
+			},
 		}
 	}
 }
 
+/// Connections of the complex relay are declared by its own parameters, so they are redirected
+/// after parsing.
+impl NodeConnections for RelayHeadersAndMessagesCommand {
+	fn source_chain(&self) -> Option<&str> {
+		None
+	}
+
+	fn redirect(
+		&mut self,
+		redirect: &mut dyn FnMut(&str, String) -> anyhow::Result<Option<u16>>,
+	) -> anyhow::Result<()> {
+		redirect_parsed_connections(self.bridge.connections(), redirect)
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
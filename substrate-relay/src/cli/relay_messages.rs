@@ -17,27 +17,57 @@
 use structopt::StructOpt;
 use strum::{EnumString, VariantNames};
 
-use crate::bridges::{
-	kusama_polkadot::{
-		bridge_hub_kusama_messages_to_bridge_hub_polkadot::BridgeHubKusamaToBridgeHubPolkadotMessagesCliBridge,
-		bridge_hub_polkadot_messages_to_bridge_hub_kusama::BridgeHubPolkadotToBridgeHubKusamaMessagesCliBridge,
+use crate::{
+	bridges::{
+		kusama_polkadot::{
+			bridge_hub_kusama_messages_to_bridge_hub_polkadot::BridgeHubKusamaToBridgeHubPolkadotMessagesCliBridge,
+			bridge_hub_polkadot_messages_to_bridge_hub_kusama::BridgeHubPolkadotToBridgeHubKusamaMessagesCliBridge,
+		},
+		polkadot_bulletin::{
+			bridge_hub_polkadot_messages_to_polkadot_bulletin::BridgeHubPolkadotToPolkadotBulletinMessagesCliBridge,
+			polkadot_bulletin_messages_to_bridge_hub_polkadot::PolkadotBulletinToBridgeHubPolkadotMessagesCliBridge,
+		},
+		rococo_bulletin::{
+			bridge_hub_rococo_messages_to_rococo_bulletin::BridgeHubRococoToRococoBulletinMessagesCliBridge,
+			rococo_bulletin_messages_to_bridge_hub_rococo::RococoBulletinToBridgeHubRococoMessagesCliBridge,
+		},
+		rococo_westend::{
+			bridge_hub_rococo_messages_to_bridge_hub_westend::BridgeHubRococoToBridgeHubWestendMessagesCliBridge,
+			bridge_hub_westend_messages_to_bridge_hub_rococo::BridgeHubWestendToBridgeHubRococoMessagesCliBridge,
+		},
 	},
-	polkadot_bulletin::{
-		bridge_hub_polkadot_messages_to_polkadot_bulletin::BridgeHubPolkadotToPolkadotBulletinMessagesCliBridge,
-		polkadot_bulletin_messages_to_bridge_hub_polkadot::PolkadotBulletinToBridgeHubPolkadotMessagesCliBridge,
+	cli::{
+		offline_signing::{export_unsigned_transaction, ExportUnsignedParams},
+		relay_headers::chain_names,
 	},
-	rococo_bulletin::{
-		bridge_hub_rococo_messages_to_rococo_bulletin::BridgeHubRococoToRococoBulletinMessagesCliBridge,
-		rococo_bulletin_messages_to_bridge_hub_rococo::RococoBulletinToBridgeHubRococoMessagesCliBridge,
+};
+
+use bp_runtime::HeaderIdProvider;
+use frame_support::weights::Weight;
+use messages_relay::message_lane_loop::{
+	metrics_prefix, MessageProofParameters, SourceClient, TargetClient,
+};
+use relay_substrate_client::{AccountIdOf, AccountKeyPairOf, Client};
+use sp_core::Pair;
+use sp_runtime::{
+	traits::{TryConvert, UniqueSaturatedInto},
+	AccountId32,
+};
+use substrate_relay_helper::{
+	cli::{
+		bridge::MessagesCliBridge,
+		relay_messages::{
+			MessagesRelayer, RelayMessagesDeliveryConfirmationParams, RelayMessagesParams,
+			RelayMessagesRangeParams,
+		},
+		HexLaneId,
 	},
-	rococo_westend::{
-		bridge_hub_rococo_messages_to_bridge_hub_westend::BridgeHubRococoToBridgeHubWestendMessagesCliBridge,
-		bridge_hub_westend_messages_to_bridge_hub_rococo::BridgeHubWestendToBridgeHubRococoMessagesCliBridge,
+	messages::{
+		source::SubstrateMessagesSource, target::SubstrateMessagesTarget, MessageLaneAdapter,
+		ReceiveMessagesDeliveryProofCallBuilder, ReceiveMessagesProofCallBuilder,
+		SubstrateMessageLane,
 	},
-};
-use substrate_relay_helper::cli::relay_messages::{
-	MessagesRelayer, RelayMessagesDeliveryConfirmationParams, RelayMessagesParams,
-	RelayMessagesRangeParams,
+	TransactionParams,
 };
 
 #[derive(Debug, PartialEq, Eq, EnumString, VariantNames)]
@@ -72,6 +102,8 @@ pub struct RelayMessagesRange {
 	bridge: FullBridge,
 	#[structopt(flatten)]
 	params: RelayMessagesRangeParams,
+	#[structopt(flatten)]
+	export: ExportUnsignedParams,
 }
 
 /// Relay messages delivery confirmation.
@@ -82,6 +114,56 @@ pub struct RelayMessagesDeliveryConfirmation {
 	bridge: FullBridge,
 	#[structopt(flatten)]
 	params: RelayMessagesDeliveryConfirmationParams,
+	#[structopt(flatten)]
+	export: ExportUnsignedParams,
+}
+
+impl FullBridge {
+	/// Names of the source and target chains of the bridge.
+	pub fn chain_names(&self) -> (&'static str, &'static str) {
+		match self {
+			Self::BridgeHubRococoToBridgeHubWestend =>
+				chain_names::<BridgeHubRococoToBridgeHubWestendMessagesCliBridge>(),
+			Self::BridgeHubWestendToBridgeHubRococo =>
+				chain_names::<BridgeHubWestendToBridgeHubRococoMessagesCliBridge>(),
+			Self::BridgeHubKusamaToBridgeHubPolkadot =>
+				chain_names::<BridgeHubKusamaToBridgeHubPolkadotMessagesCliBridge>(),
+			Self::BridgeHubPolkadotToBridgeHubKusama =>
+				chain_names::<BridgeHubPolkadotToBridgeHubKusamaMessagesCliBridge>(),
+			Self::PolkadotBulletinToBridgeHubPolkadot =>
+				chain_names::<PolkadotBulletinToBridgeHubPolkadotMessagesCliBridge>(),
+			Self::BridgeHubPolkadotToPolkadotBulletin =>
+				chain_names::<BridgeHubPolkadotToPolkadotBulletinMessagesCliBridge>(),
+			Self::RococoBulletinToBridgeHubRococo =>
+				chain_names::<RococoBulletinToBridgeHubRococoMessagesCliBridge>(),
+			Self::BridgeHubRococoToRococoBulletin =>
+				chain_names::<BridgeHubRococoToRococoBulletinMessagesCliBridge>(),
+		}
+	}
+
+	/// Prefix of metrics of the messages relay loop, serving given lane of the bridge.
+	pub fn metrics_prefix(&self, lane: HexLaneId) -> anyhow::Result<String> {
+		match self {
+			Self::BridgeHubRococoToBridgeHubWestend =>
+				messages_metrics_prefix::<BridgeHubRococoToBridgeHubWestendMessagesCliBridge>(lane),
+			Self::BridgeHubWestendToBridgeHubRococo =>
+				messages_metrics_prefix::<BridgeHubWestendToBridgeHubRococoMessagesCliBridge>(lane),
+			Self::BridgeHubKusamaToBridgeHubPolkadot =>
+				messages_metrics_prefix::<BridgeHubKusamaToBridgeHubPolkadotMessagesCliBridge>(lane),
+			Self::BridgeHubPolkadotToBridgeHubKusama =>
+				messages_metrics_prefix::<BridgeHubPolkadotToBridgeHubKusamaMessagesCliBridge>(lane),
+			Self::PolkadotBulletinToBridgeHubPolkadot => messages_metrics_prefix::<
+				PolkadotBulletinToBridgeHubPolkadotMessagesCliBridge,
+			>(lane),
+			Self::BridgeHubPolkadotToPolkadotBulletin => messages_metrics_prefix::<
+				BridgeHubPolkadotToPolkadotBulletinMessagesCliBridge,
+			>(lane),
+			Self::RococoBulletinToBridgeHubRococo =>
+				messages_metrics_prefix::<RococoBulletinToBridgeHubRococoMessagesCliBridge>(lane),
+			Self::BridgeHubRococoToRococoBulletin =>
+				messages_metrics_prefix::<BridgeHubRococoToRococoBulletinMessagesCliBridge>(lane),
+		}
+	}
 }
 
 impl MessagesRelayer for BridgeHubRococoToBridgeHubWestendMessagesCliBridge {}
@@ -94,6 +176,11 @@ impl MessagesRelayer for RococoBulletinToBridgeHubRococoMessagesCliBridge {}
 impl MessagesRelayer for BridgeHubRococoToRococoBulletinMessagesCliBridge {}
 
 impl RelayMessages {
+	/// Name of the bridge, served by this command.
+	pub fn bridge_name(&self) -> String {
+		format!("{:?}", self.bridge)
+	}
+
 	/// Run the command.
 	pub async fn run(self) -> anyhow::Result<()> {
 		match self.bridge {
@@ -119,75 +206,212 @@ impl RelayMessages {
 }
 
 impl RelayMessagesRange {
+	/// Name of the bridge, served by this command.
+	pub fn bridge_name(&self) -> String {
+		format!("{:?}", self.bridge)
+	}
+
 	/// Run the command.
 	pub async fn run(self) -> anyhow::Result<()> {
 		match self.bridge {
 			FullBridge::BridgeHubRococoToBridgeHubWestend =>
-				BridgeHubRococoToBridgeHubWestendMessagesCliBridge::relay_messages_range(
-					self.params,
-				),
+				relay_messages_range::<BridgeHubRococoToBridgeHubWestendMessagesCliBridge>(self)
+					.await,
 			FullBridge::BridgeHubWestendToBridgeHubRococo =>
-				BridgeHubWestendToBridgeHubRococoMessagesCliBridge::relay_messages_range(
-					self.params,
-				),
+				relay_messages_range::<BridgeHubWestendToBridgeHubRococoMessagesCliBridge>(self)
+					.await,
 			FullBridge::BridgeHubKusamaToBridgeHubPolkadot =>
-				BridgeHubKusamaToBridgeHubPolkadotMessagesCliBridge::relay_messages_range(
-					self.params,
-				),
+				relay_messages_range::<BridgeHubKusamaToBridgeHubPolkadotMessagesCliBridge>(self)
+					.await,
 			FullBridge::BridgeHubPolkadotToBridgeHubKusama =>
-				BridgeHubPolkadotToBridgeHubKusamaMessagesCliBridge::relay_messages_range(
-					self.params,
-				),
+				relay_messages_range::<BridgeHubPolkadotToBridgeHubKusamaMessagesCliBridge>(self)
+					.await,
 			FullBridge::PolkadotBulletinToBridgeHubPolkadot =>
-				PolkadotBulletinToBridgeHubPolkadotMessagesCliBridge::relay_messages_range(
-					self.params,
-				),
+				relay_messages_range::<PolkadotBulletinToBridgeHubPolkadotMessagesCliBridge>(self)
+					.await,
 			FullBridge::BridgeHubPolkadotToPolkadotBulletin =>
-				BridgeHubPolkadotToPolkadotBulletinMessagesCliBridge::relay_messages_range(
-					self.params,
-				),
+				relay_messages_range::<BridgeHubPolkadotToPolkadotBulletinMessagesCliBridge>(self)
+					.await,
 			FullBridge::RococoBulletinToBridgeHubRococo =>
-				RococoBulletinToBridgeHubRococoMessagesCliBridge::relay_messages_range(self.params),
+				relay_messages_range::<RococoBulletinToBridgeHubRococoMessagesCliBridge>(self).await,
 			FullBridge::BridgeHubRococoToRococoBulletin =>
-				BridgeHubRococoToRococoBulletinMessagesCliBridge::relay_messages_range(self.params),
+				relay_messages_range::<BridgeHubRococoToRococoBulletinMessagesCliBridge>(self).await,
 		}
-		.await
 	}
 }
 
 impl RelayMessagesDeliveryConfirmation {
+	/// Name of the bridge, served by this command.
+	pub fn bridge_name(&self) -> String {
+		format!("{:?}", self.bridge)
+	}
+
 	/// Run the command.
 	pub async fn run(self) -> anyhow::Result<()> {
 		match self.bridge {
 			FullBridge::BridgeHubRococoToBridgeHubWestend =>
-				BridgeHubRococoToBridgeHubWestendMessagesCliBridge::relay_messages_delivery_confirmation(
-					self.params,
-				),
+				relay_messages_delivery_confirmation::<
+					BridgeHubRococoToBridgeHubWestendMessagesCliBridge,
+				>(self)
+				.await,
 			FullBridge::BridgeHubWestendToBridgeHubRococo =>
-				BridgeHubWestendToBridgeHubRococoMessagesCliBridge::relay_messages_delivery_confirmation(
-					self.params,
-				),
+				relay_messages_delivery_confirmation::<
+					BridgeHubWestendToBridgeHubRococoMessagesCliBridge,
+				>(self)
+				.await,
 			FullBridge::BridgeHubKusamaToBridgeHubPolkadot =>
-				BridgeHubKusamaToBridgeHubPolkadotMessagesCliBridge::relay_messages_delivery_confirmation(
-					self.params,
-				),
+				relay_messages_delivery_confirmation::<
+					BridgeHubKusamaToBridgeHubPolkadotMessagesCliBridge,
+				>(self)
+				.await,
 			FullBridge::BridgeHubPolkadotToBridgeHubKusama =>
-				BridgeHubPolkadotToBridgeHubKusamaMessagesCliBridge::relay_messages_delivery_confirmation(
-					self.params,
-				),
+				relay_messages_delivery_confirmation::<
+					BridgeHubPolkadotToBridgeHubKusamaMessagesCliBridge,
+				>(self)
+				.await,
 			FullBridge::PolkadotBulletinToBridgeHubPolkadot =>
-				PolkadotBulletinToBridgeHubPolkadotMessagesCliBridge::relay_messages_delivery_confirmation(
-					self.params,
-				),
+				relay_messages_delivery_confirmation::<
+					PolkadotBulletinToBridgeHubPolkadotMessagesCliBridge,
+				>(self)
+				.await,
 			FullBridge::BridgeHubPolkadotToPolkadotBulletin =>
-				BridgeHubPolkadotToPolkadotBulletinMessagesCliBridge::relay_messages_delivery_confirmation(
-					self.params,
-				),
+				relay_messages_delivery_confirmation::<
+					BridgeHubPolkadotToPolkadotBulletinMessagesCliBridge,
+				>(self)
+				.await,
 			FullBridge::RococoBulletinToBridgeHubRococo =>
-				RococoBulletinToBridgeHubRococoMessagesCliBridge::relay_messages_delivery_confirmation(self.params),
+				relay_messages_delivery_confirmation::<
+					RococoBulletinToBridgeHubRococoMessagesCliBridge,
+				>(self)
+				.await,
 			FullBridge::BridgeHubRococoToRococoBulletin =>
-				BridgeHubRococoToRococoBulletinMessagesCliBridge::relay_messages_delivery_confirmation(self.params),
+				relay_messages_delivery_confirmation::<
+					BridgeHubRococoToRococoBulletinMessagesCliBridge,
+				>(self)
+				.await,
 		}
-		.await
 	}
 }
+
+/// Lane identifier type of the messages bridge.
+type LaneIdOf<Bridge> =
+	<<Bridge as MessagesCliBridge>::MessagesLane as SubstrateMessageLane>::LaneId;
+
+/// Prefix of metrics of the messages relay loop, serving given lane of the bridge.
+pub fn messages_metrics_prefix<Bridge: MessagesRelayer>(lane: HexLaneId) -> anyhow::Result<String> {
+	let lane: LaneIdOf<Bridge> = HexLaneId::try_convert(lane)
+		.map_err(|invalid_lane_id| anyhow::format_err!("Invalid laneId: {:?}!", invalid_lane_id))?;
+	Ok(metrics_prefix::<MessageLaneAdapter<Bridge::MessagesLane>>(&lane))
+}
+
+/// Either relay range of messages or export the transaction for offline signing.
+async fn relay_messages_range<Bridge: MessagesRelayer>(
+	command: RelayMessagesRange,
+) -> anyhow::Result<()>
+where
+	AccountIdOf<Bridge::Source>: From<<AccountKeyPairOf<Bridge::Source> as Pair>::Public>,
+	AccountIdOf<Bridge::Target>: From<AccountId32>,
+{
+	type CallBuilder<Bridge> = <<Bridge as MessagesCliBridge>::MessagesLane as SubstrateMessageLane>::ReceiveMessagesProofCallBuilder;
+
+	let RelayMessagesRange { params, export, .. } = command;
+	let (path, signer) = match export.export_target()? {
+		Some(export_target) => export_target,
+		None => return Bridge::relay_messages_range(params).await,
+	};
+
+	let lane: LaneIdOf<Bridge> = HexLaneId::try_convert(params.lane)
+		.map_err(|invalid_lane_id| anyhow::format_err!("Invalid laneId: {:?}!", invalid_lane_id))?;
+	let source_client = params.source.into_client::<Bridge::Source>().await?;
+	let target_client = params.target.into_client::<Bridge::Target>().await?;
+	let source_sign = params.source_sign.to_keypair::<Bridge::Source>()?;
+	let relayer_id_at_source: AccountIdOf<Bridge::Source> = source_sign.public().into();
+	let at_source_block = source_client
+		.header_by_number(params.at_source_block.unique_saturated_into())
+		.await?
+		.id();
+	let source = SubstrateMessagesSource::<Bridge::MessagesLane, _, _>::new(
+		source_client,
+		target_client.clone(),
+		lane,
+		TransactionParams {
+			signer: source_sign,
+			mortality: params.source_sign.transactions_mortality()?,
+		},
+		None,
+	);
+
+	let range = params.messages_start..=params.messages_end;
+	let dispatch_weight = source
+		.generated_message_details(at_source_block, range.clone())
+		.await
+		.map_err(|e| anyhow::format_err!("Failed to read messages details: {:?}", e))?
+		.values()
+		.fold(Weight::zero(), |acc, details| acc.saturating_add(details.dispatch_weight));
+	let (_, range, proof) = source
+		.prove_messages(
+			at_source_block,
+			range,
+			MessageProofParameters {
+				outbound_state_proof_required: params.outbound_state_proof_required,
+				dispatch_weight,
+			},
+		)
+		.await
+		.map_err(|e| anyhow::format_err!("Failed to prove messages: {:?}", e))?;
+	let messages_count = (range.end() - range.start() + 1) as u32;
+	let call = CallBuilder::<Bridge>::build_receive_messages_proof_call(
+		relayer_id_at_source,
+		proof,
+		messages_count,
+		dispatch_weight,
+		false,
+	);
+
+	let mortality = params.target_sign.transactions_mortality()?;
+	export_unsigned_transaction(&target_client, signer.into(), call, mortality, &path).await
+}
+
+/// Either relay messages delivery confirmation or export the transaction for offline signing.
+async fn relay_messages_delivery_confirmation<Bridge: MessagesRelayer>(
+	command: RelayMessagesDeliveryConfirmation,
+) -> anyhow::Result<()>
+where
+	AccountIdOf<Bridge::Source>: From<AccountId32>,
+{
+	type CallBuilder<Bridge> = <<Bridge as MessagesCliBridge>::MessagesLane as SubstrateMessageLane>::ReceiveMessagesDeliveryProofCallBuilder;
+
+	let RelayMessagesDeliveryConfirmation { params, export, .. } = command;
+	let (path, signer) = match export.export_target()? {
+		Some(export_target) => export_target,
+		None => return Bridge::relay_messages_delivery_confirmation(params).await,
+	};
+
+	let lane: LaneIdOf<Bridge> = HexLaneId::try_convert(params.lane)
+		.map_err(|invalid_lane_id| anyhow::format_err!("Invalid laneId: {:?}!", invalid_lane_id))?;
+	let source_client = params.source.into_client::<Bridge::Source>().await?;
+	let target_client = params.target.into_client::<Bridge::Target>().await?;
+	let at_target_block = target_client
+		.header_by_number(params.at_target_block.unique_saturated_into())
+		.await?
+		.id();
+	// the confirmation transaction is signed by the exported account
+	let signer: AccountIdOf<Bridge::Source> = signer.into();
+	let target = SubstrateMessagesTarget::<Bridge::MessagesLane, _, _>::new(
+		target_client,
+		source_client.clone(),
+		lane,
+		signer.clone(),
+		None,
+		None,
+	);
+
+	let (_, proof) = target
+		.prove_messages_receiving(at_target_block)
+		.await
+		.map_err(|e| anyhow::format_err!("Failed to prove messages receiving: {:?}", e))?;
+	let call = CallBuilder::<Bridge>::build_receive_messages_delivery_proof_call(proof, false);
+
+	let mortality = params.source_sign.transactions_mortality()?;
+	export_unsigned_transaction(&source_client, signer, call, mortality, &path).await
+}
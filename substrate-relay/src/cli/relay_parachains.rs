@@ -14,22 +14,38 @@
 // You should have received a copy of the GNU General Public License
 // along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
 
-use crate::bridges::{
-	kusama_polkadot::{
-		kusama_parachains_to_bridge_hub_polkadot::BridgeHubKusamaToBridgeHubPolkadotCliBridge,
-		polkadot_parachains_to_bridge_hub_kusama::BridgeHubPolkadotToBridgeHubKusamaCliBridge,
-	},
-	polkadot_bulletin::polkadot_parachains_to_polkadot_bulletin::PolkadotToPolkadotBulletinCliBridge,
-	rococo_bulletin::rococo_parachains_to_rococo_bulletin::RococoToRococoBulletinCliBridge,
-	rococo_westend::{
-		rococo_parachains_to_bridge_hub_westend::BridgeHubRococoToBridgeHubWestendCliBridge,
-		westend_parachains_to_bridge_hub_rococo::BridgeHubWestendToBridgeHubRococoCliBridge,
+use crate::{
+	bridges::{
+		kusama_polkadot::{
+			kusama_parachains_to_bridge_hub_polkadot::BridgeHubKusamaToBridgeHubPolkadotCliBridge,
+			polkadot_parachains_to_bridge_hub_kusama::BridgeHubPolkadotToBridgeHubKusamaCliBridge,
+		},
+		polkadot_bulletin::polkadot_parachains_to_polkadot_bulletin::PolkadotToPolkadotBulletinCliBridge,
+		rococo_bulletin::rococo_parachains_to_rococo_bulletin::RococoToRococoBulletinCliBridge,
+		rococo_westend::{
+			rococo_parachains_to_bridge_hub_westend::BridgeHubRococoToBridgeHubWestendCliBridge,
+			westend_parachains_to_bridge_hub_rococo::BridgeHubWestendToBridgeHubRococoCliBridge,
+		},
 	},
+	cli::offline_signing::{export_unsigned_transaction, ExportUnsignedParams},
 };
+
+use bp_polkadot_core::parachains::{ParaHash, ParaHeadsProof, ParaId};
+use bp_runtime::HeaderIdProvider;
+use codec::Decode;
+use relay_substrate_client::{AccountIdOf, Chain, Client, Parachain, RelayChain};
+use sp_runtime::AccountId32;
 use structopt::StructOpt;
 use strum::{EnumString, VariantNames};
-use substrate_relay_helper::cli::relay_parachains::{
-	ParachainsRelayer, RelayParachainHeadParams, RelayParachainsParams,
+use substrate_relay_helper::{
+	cli::{
+		bridge::ParachainToRelayHeadersCliBridge,
+		relay_parachains::{ParachainsRelayer, RelayParachainHeadParams, RelayParachainsParams},
+	},
+	parachains::{
+		source::parachain_head_storage_key_at_source, SubmitParachainHeadsCallBuilder,
+		SubstrateParachainsPipeline,
+	},
 };
 
 /// Start parachain heads relayer process.
@@ -50,6 +66,8 @@ pub struct RelayParachainHead {
 	bridge: RelayParachainsBridge,
 	#[structopt(flatten)]
 	params: RelayParachainHeadParams,
+	#[structopt(flatten)]
+	export: ExportUnsignedParams,
 }
 
 /// Parachain heads relay bridge.
@@ -72,6 +90,11 @@ impl ParachainsRelayer for PolkadotToPolkadotBulletinCliBridge {}
 impl ParachainsRelayer for RococoToRococoBulletinCliBridge {}
 
 impl RelayParachains {
+	/// Name of the bridge, served by this command.
+	pub fn bridge_name(&self) -> String {
+		format!("{:?}", self.bridge)
+	}
+
 	/// Run the command.
 	pub async fn run(self) -> anyhow::Result<()> {
 		match self.bridge {
@@ -93,22 +116,77 @@ impl RelayParachains {
 }
 
 impl RelayParachainHead {
+	/// Name of the bridge, served by this command.
+	pub fn bridge_name(&self) -> String {
+		format!("{:?}", self.bridge)
+	}
+
 	/// Run the command.
 	pub async fn run(self) -> anyhow::Result<()> {
 		match self.bridge {
 			RelayParachainsBridge::RococoToBridgeHubWestend =>
-				BridgeHubRococoToBridgeHubWestendCliBridge::relay_parachain_head(self.params),
+				relay_parachain_head::<BridgeHubRococoToBridgeHubWestendCliBridge>(self).await,
 			RelayParachainsBridge::WestendToBridgeHubRococo =>
-				BridgeHubWestendToBridgeHubRococoCliBridge::relay_parachain_head(self.params),
+				relay_parachain_head::<BridgeHubWestendToBridgeHubRococoCliBridge>(self).await,
 			RelayParachainsBridge::KusamaToBridgeHubPolkadot =>
-				BridgeHubKusamaToBridgeHubPolkadotCliBridge::relay_parachain_head(self.params),
+				relay_parachain_head::<BridgeHubKusamaToBridgeHubPolkadotCliBridge>(self).await,
 			RelayParachainsBridge::PolkadotToBridgeHubKusama =>
-				BridgeHubPolkadotToBridgeHubKusamaCliBridge::relay_parachain_head(self.params),
+				relay_parachain_head::<BridgeHubPolkadotToBridgeHubKusamaCliBridge>(self).await,
 			RelayParachainsBridge::PolkadotToPolkadotBulletin =>
-				PolkadotToPolkadotBulletinCliBridge::relay_parachain_head(self.params),
+				relay_parachain_head::<PolkadotToPolkadotBulletinCliBridge>(self).await,
 			RelayParachainsBridge::RococoToRococoBulletin =>
-				RococoToRococoBulletinCliBridge::relay_parachain_head(self.params),
+				relay_parachain_head::<RococoToRococoBulletinCliBridge>(self).await,
 		}
-		.await
 	}
 }
+
+/// Either relay single parachain head or export the transaction for offline signing.
+async fn relay_parachain_head<Bridge: ParachainsRelayer>(
+	command: RelayParachainHead,
+) -> anyhow::Result<()>
+where
+	AccountIdOf<Bridge::Target>: From<AccountId32>,
+{
+	type Pipeline<Bridge> = <Bridge as ParachainToRelayHeadersCliBridge>::ParachainFinality;
+	type CallBuilder<Bridge> =
+		<Pipeline<Bridge> as SubstrateParachainsPipeline>::SubmitParachainHeadsCallBuilder;
+	type SourceParachain<Bridge> =
+		<Pipeline<Bridge> as SubstrateParachainsPipeline>::SourceParachain;
+
+	let RelayParachainHead { params, export, .. } = command;
+	let (path, signer) = match export.export_target()? {
+		Some(export_target) => export_target,
+		None => return Bridge::relay_parachain_head(params).await,
+	};
+
+	let source_client = params.source.into_client::<Bridge::SourceRelay>().await?;
+	let target_client = params.target.into_client::<Bridge::Target>().await?;
+	let at_relay_block = source_client.header_by_number(params.at_relay_block.into()).await?.id();
+	let parachain = ParaId(SourceParachain::<Bridge>::PARACHAIN_ID);
+	let storage_key =
+		parachain_head_storage_key_at_source(Bridge::SourceRelay::PARAS_PALLET_NAME, parachain);
+	let head = source_client
+		.raw_storage_value(at_relay_block.hash(), storage_key.clone())
+		.await?
+		.ok_or_else(|| {
+			anyhow::format_err!(
+				"Head of {} is missing at {} block {:?}",
+				SourceParachain::<Bridge>::NAME,
+				Bridge::SourceRelay::NAME,
+				at_relay_block,
+			)
+		})?;
+	let head_hash =
+		ParaHash::from(sp_core::hashing::blake2_256(&Vec::<u8>::decode(&mut &head.0[..])?));
+	let storage_proof =
+		source_client.prove_storage(at_relay_block.hash(), vec![storage_key]).await?;
+	let call = CallBuilder::<Bridge>::build_submit_parachain_heads_call(
+		at_relay_block,
+		vec![(parachain, head_hash)],
+		ParaHeadsProof { storage_proof: storage_proof.into_iter_nodes().collect() },
+		false,
+	);
+
+	let mortality = params.target_sign.transactions_mortality()?;
+	export_unsigned_transaction(&target_client, signer.into(), call, mortality, &path).await
+}
@@ -0,0 +1,250 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Metrics of rewards, accrued by relay signers in the `pallet_bridge_relayers`.
+//!
+//! Metrics are registered in the relay metrics registry, next to relay balance metrics.
+
+use crate::{bridges::rococo_bulletin::BridgeHubRococoAsBridgeHubPolkadot, cli::LOG_TARGET};
+
+use bp_messages::HashedLaneId;
+use codec::{Decode, Encode};
+use frame_support::Blake2_128Concat;
+use prometheus_endpoint::{register, GaugeVec, Opts, PrometheusError, Registry, F64};
+use relay_bridge_hub_kusama_client::BridgeHubKusama;
+use relay_bridge_hub_polkadot_client::BridgeHubPolkadot;
+use relay_bridge_hub_rococo_client::{
+	BridgeHubRococo, WITH_BRIDGE_HUB_ROCOCO_PERMISSIONLESS_LANES_RELAYERS_PALLET_NAME,
+};
+use relay_bridge_hub_westend_client::BridgeHubWestend;
+use relay_polkadot_bulletin_client::PolkadotBulletin;
+use relay_substrate_client::{AccountIdOf, Chain, ChainWithMessages, Client};
+use std::{any::TypeId, time::Duration};
+use structopt::StructOpt;
+
+/// Interval between relayer rewards updates.
+const REWARDS_UPDATE_INTERVAL: Duration = Duration::from_secs(60);
+/// Name of the relayer rewards map in the `pallet_bridge_relayers`.
+const RELAYER_REWARDS_MAP_NAME: &str = "RelayerRewards";
+
+/// Owner of the account that pays relayer rewards. Mirrors the `bp_relayers::RewardsAccountOwner`.
+#[derive(Clone, Copy, Debug, Encode)]
+enum RewardsAccountOwner {
+	/// Rewards are paid for messages that are sent by this chain.
+	ThisChain,
+	/// Rewards are paid for messages that are sent by the bridged chain.
+	BridgedChain,
+}
+
+/// Relayer rewards metrics parameters.
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct RelayerRewardsParams {
+	/// Expose rewards, accrued by relay signers at both sides of the bridge, as relay metrics.
+	#[structopt(long)]
+	pub relayer_rewards_metrics: bool,
+}
+
+/// Chain with relayers pallets, where relayer rewards are registered.
+pub trait ChainWithRelayerRewards: ChainWithMessages {
+	/// Name of the relayers pallet instance that is used by permissionless lanes (lanes with
+	/// `HashedLaneId` identifiers), if it differs from the `WITH_CHAIN_RELAYERS_PALLET_NAME`.
+	const PERMISSIONLESS_LANES_RELAYERS_PALLET_NAME: Option<&'static str> = None;
+}
+
+impl ChainWithRelayerRewards for BridgeHubRococo {
+	const PERMISSIONLESS_LANES_RELAYERS_PALLET_NAME: Option<&'static str> =
+		Some(WITH_BRIDGE_HUB_ROCOCO_PERMISSIONLESS_LANES_RELAYERS_PALLET_NAME);
+}
+
+impl ChainWithRelayerRewards for BridgeHubRococoAsBridgeHubPolkadot {
+	const PERMISSIONLESS_LANES_RELAYERS_PALLET_NAME: Option<&'static str> =
+		BridgeHubRococo::PERMISSIONLESS_LANES_RELAYERS_PALLET_NAME;
+}
+
+impl ChainWithRelayerRewards for BridgeHubWestend {}
+impl ChainWithRelayerRewards for BridgeHubKusama {}
+impl ChainWithRelayerRewards for BridgeHubPolkadot {}
+impl ChainWithRelayerRewards for PolkadotBulletin {}
+
+impl RelayerRewardsParams {
+	/// Periodically read rewards of the `relayer` account at chain `C`, accrued for serving
+	/// given lanes with the `Bridged` chain.
+	pub fn spawn_watcher<C: ChainWithRelayerRewards, Bridged: Chain, LaneId>(
+		&self,
+		client: impl Client<C>,
+		relayer: AccountIdOf<C>,
+		lanes: Vec<LaneId>,
+		registry: &Registry,
+	) -> anyhow::Result<()>
+	where
+		LaneId: Encode + Send + Sync + 'static,
+	{
+		if !self.relayer_rewards_metrics {
+			return Ok(())
+		}
+
+		let pallet = match relayers_pallet::<C, LaneId>() {
+			Some(pallet) => pallet,
+			None => {
+				log::info!(
+					target: LOG_TARGET,
+					"There are no relayer rewards at {} for lanes with {} chain",
+					C::NAME,
+					Bridged::NAME,
+				);
+				return Ok(())
+			},
+		};
+		let metric = rewards_metric::<C>(registry)?;
+		async_std::task::spawn(async move {
+			loop {
+				if let Err(e) =
+					update_rewards::<C, Bridged, _>(&client, &relayer, pallet, &lanes, &metric)
+						.await
+				{
+					log::warn!(
+						target: LOG_TARGET,
+						"Failed to read relayer rewards at {}: {}",
+						C::NAME,
+						e,
+					);
+				}
+				async_std::task::sleep(REWARDS_UPDATE_INTERVAL).await;
+			}
+		});
+		Ok(())
+	}
+}
+
+/// Name of the relayers pallet instance at chain `C`, which registers rewards for lanes with
+/// `LaneId` identifiers.
+fn relayers_pallet<C: ChainWithRelayerRewards, LaneId: 'static>() -> Option<&'static str> {
+	if TypeId::of::<LaneId>() == TypeId::of::<HashedLaneId>() {
+		return C::PERMISSIONLESS_LANES_RELAYERS_PALLET_NAME.or(C::WITH_CHAIN_RELAYERS_PALLET_NAME)
+	}
+	C::WITH_CHAIN_RELAYERS_PALLET_NAME
+}
+
+/// Register the relayer rewards metric of chain `C`.
+fn rewards_metric<C: Chain>(registry: &Registry) -> Result<GaugeVec<F64>, PrometheusError> {
+	register(
+		GaugeVec::new(
+			Opts::new(
+				format!("at_{}_relay_rewards", C::NAME),
+				"Rewards, accrued by the relay account.",
+			),
+			&["relayers_pallet", "lane", "bridged_chain", "owner"],
+		)?,
+		registry,
+	)
+}
+
+async fn update_rewards<C: ChainWithMessages, Bridged: Chain, LaneId: Encode>(
+	client: &impl Client<C>,
+	relayer: &AccountIdOf<C>,
+	pallet: &str,
+	lanes: &[LaneId],
+	metric: &GaugeVec<F64>,
+) -> anyhow::Result<()> {
+	let at = client.best_finalized_header_hash().await?;
+	for lane in lanes {
+		let encoded_lane = lane.encode();
+		for owner in [RewardsAccountOwner::ThisChain, RewardsAccountOwner::BridgedChain] {
+			let storage_key =
+				relayer_rewards_key(pallet, &relayer.encode(), owner, Bridged::ID, &encoded_lane);
+			let reward = match client.raw_storage_value(at, storage_key).await? {
+				Some(reward) => u128::decode(&mut &reward.0[..])?,
+				None => 0,
+			};
+			metric
+				.with_label_values(&[
+					pallet,
+					&hex::encode(&encoded_lane),
+					Bridged::NAME,
+					&format!("{:?}", owner),
+				])
+				.set(reward as f64);
+		}
+	}
+	Ok(())
+}
+
+/// Storage key of the relayer reward in the `RelayerRewards` double map.
+///
+/// The first key is hashed with `Blake2_128Concat` and the second key (`RewardsAccountParams`)
+/// is stored as is.
+fn relayer_rewards_key(
+	pallet: &str,
+	encoded_relayer: &[u8],
+	owner: RewardsAccountOwner,
+	bridged_chain_id: bp_runtime::ChainId,
+	encoded_lane: &[u8],
+) -> sp_core::storage::StorageKey {
+	let mut storage_key = bp_runtime::storage_map_final_key::<Blake2_128Concat>(
+		pallet,
+		RELAYER_REWARDS_MAP_NAME,
+		encoded_relayer,
+	);
+	// fields of `RewardsAccountParams` are encoded in order: owner, bridged chain id, lane id
+	storage_key.0.extend(owner.encode());
+	storage_key.0.extend(bridged_chain_id);
+	storage_key.0.extend(encoded_lane);
+	storage_key
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bp_messages::LegacyLaneId;
+
+	#[test]
+	fn relayer_rewards_key_is_built() {
+		let relayer = [1u8; 32];
+		let lane = LegacyLaneId([0, 0, 0, 2]);
+		let key = relayer_rewards_key(
+			"BridgeRelayers",
+			&relayer.encode(),
+			RewardsAccountOwner::BridgedChain,
+			*b"bhwd",
+			&lane.encode(),
+		);
+
+		let mut expected = sp_core::twox_128(b"BridgeRelayers").to_vec();
+		expected.extend(sp_core::twox_128(b"RelayerRewards"));
+		expected.extend(sp_core::blake2_128(&relayer.encode()));
+		expected.extend(relayer.encode());
+		expected.extend([1u8]);
+		expected.extend(b"bhwd");
+		expected.extend([0, 0, 0, 2]);
+		assert_eq!(key.0, expected);
+	}
+
+	#[test]
+	fn relayers_pallet_is_selected_by_lane_type() {
+		assert_eq!(
+			relayers_pallet::<BridgeHubRococo, LegacyLaneId>(),
+			BridgeHubRococo::WITH_CHAIN_RELAYERS_PALLET_NAME,
+		);
+		assert_eq!(
+			relayers_pallet::<BridgeHubRococo, HashedLaneId>(),
+			Some(WITH_BRIDGE_HUB_ROCOCO_PERMISSIONLESS_LANES_RELAYERS_PALLET_NAME),
+		);
+		assert_eq!(
+			relayers_pallet::<BridgeHubWestend, HashedLaneId>(),
+			BridgeHubWestend::WITH_CHAIN_RELAYERS_PALLET_NAME,
+		);
+	}
+}
@@ -0,0 +1,794 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Failover between several RPC endpoints of the same chain.
+//!
+//! The node URI option accepts comma-separated list of endpoints (e.g.
+//! `--kusama-uri wss://first.example:443,wss://second.example:443`). Such connections are
+//! redirected to the local failover proxy, which periodically checks health of all endpoints
+//! (peers count, major sync status and best block lag) and forwards relay connections to the
+//! first healthy endpoint of the list.
+//!
+//! When the active endpoint disconnects or becomes unhealthy, the proxy connects to the next
+//! healthy endpoint without closing relay connections. Requests without responses are resent,
+//! subscriptions are reopened under their original identifiers. Transactions are not resubmitted
+//! to the new endpoint. Instead, the proxy watches finalized blocks of the new endpoint and
+//! notifies the relay when the block with the transaction of the same hash is finalized. If the
+//! transaction never appears there, the relay deals with it as with any other stalled
+//! transaction.
+
+use crate::cli::{
+	rpc_proxy::{NodeConnections, DEFAULT_HOST},
+	LOG_TARGET,
+};
+
+use async_std::net::{TcpListener, TcpStream};
+use async_tungstenite::tungstenite::Message;
+use futures::{FutureExt, SinkExt, StreamExt};
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use sp_core::H256;
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+/// Interval between endpoints health checks.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+/// Endpoint that does not respond to the health check in this time is unhealthy.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Endpoint that is behind the best endpoint by more than this number of blocks is unhealthy.
+const MAX_BEST_BLOCK_LAG: u64 = 4;
+/// Interval between active endpoint checks of relay connections.
+const ACTIVE_ENDPOINT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Delay before reconnecting, if all endpoints are failing.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Prefix of identifiers of requests, sent by the proxy.
+const FAILOVER_ID_PREFIX: &str = "failover-";
+/// Method that submits transaction and subscribes to its status updates.
+const SUBMIT_AND_WATCH_METHOD: &str = "author_submitAndWatchExtrinsic";
+/// Number of finalized blocks before the switch, checked for watched transactions at the new
+/// endpoint. Transactions may have been finalized while the proxy was switching endpoints.
+const MAX_RESCANNED_BLOCKS: u64 = 64;
+
+/// Local failover proxy of several node endpoints.
+#[derive(Debug)]
+pub struct FailoverProxy {
+	listener: std::net::TcpListener,
+	endpoints: Arc<Mutex<Endpoints>>,
+}
+
+impl FailoverProxy {
+	/// Start health checks and serving relay connections in background.
+	pub fn spawn(self) {
+		async_std::task::spawn(check_health_loop(self.endpoints.clone()));
+		async_std::task::spawn(async move {
+			let listener = TcpListener::from(self.listener);
+			let uris = self.endpoints.lock().uris.join(",");
+			log::info!(
+				target: LOG_TARGET,
+				"Failover proxy of {} is listening on {:?}",
+				uris,
+				listener.local_addr(),
+			);
+
+			let mut incoming = listener.incoming();
+			while let Some(stream) = incoming.next().await {
+				let endpoints = self.endpoints.clone();
+				let uris = uris.clone();
+				async_std::task::spawn(async move {
+					let result = match stream {
+						Ok(stream) => serve_connection(stream, endpoints).await,
+						Err(e) => Err(e.into()),
+					};
+					if let Err(e) = result {
+						log::warn!(
+							target: LOG_TARGET,
+							"Failover proxy connection to {} has failed: {}",
+							uris,
+							e,
+						);
+					}
+				});
+			}
+		});
+	}
+}
+
+/// Redirect node connections with several endpoints to failover proxies.
+///
+/// Proxies are reused by connections with the same list of endpoints. New proxies are added to
+/// the `proxies`. Other connections are left unchanged.
+pub fn redirect_connections(
+	connections: &mut dyn NodeConnections,
+	proxies: &mut Vec<FailoverProxy>,
+) -> anyhow::Result<()> {
+	connections.redirect(&mut |prefix, upstream| {
+		let uris = upstream
+			.split(',')
+			.map(|uri| uri.trim().to_string())
+			.filter(|uri| !uri.is_empty())
+			.collect::<Vec<_>>();
+		if uris.len() < 2 {
+			return Ok(None)
+		}
+		if let Some(proxy) = proxies.iter().find(|proxy| proxy.endpoints.lock().uris == uris) {
+			return Ok(Some(proxy.listener.local_addr()?.port()))
+		}
+
+		let listener = std::net::TcpListener::bind((DEFAULT_HOST, 0))?;
+		let port = listener.local_addr()?.port();
+		log::debug!(target: LOG_TARGET, "Endpoints of --{}-uri: {:?}", prefix, uris);
+		proxies.push(FailoverProxy {
+			listener,
+			endpoints: Arc::new(Mutex::new(Endpoints::new(uris))),
+		});
+		Ok(Some(port))
+	})
+}
+
+/// Health of the node endpoint.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct NodeHealth {
+	peers: u64,
+	is_syncing: bool,
+	should_have_peers: bool,
+	best_number: u64,
+}
+
+/// All endpoints of the node.
+#[derive(Debug)]
+struct Endpoints {
+	uris: Vec<String>,
+	/// Results of the last health check. `None` if endpoint has failed.
+	health: Vec<Option<NodeHealth>>,
+	/// Index of the endpoint that relay connections are forwarded to.
+	active: usize,
+}
+
+impl Endpoints {
+	fn new(uris: Vec<String>) -> Self {
+		// until the first health check, endpoints are used in the order of appearance
+		let health = uris
+			.iter()
+			.map(|_| {
+				Some(NodeHealth {
+					peers: 0,
+					is_syncing: false,
+					should_have_peers: false,
+					best_number: 0,
+				})
+			})
+			.collect();
+		Endpoints { uris, health, active: 0 }
+	}
+
+	/// Returns the reason why the endpoint can't be used, or `None` if it is healthy.
+	fn unhealthy_reason(&self, index: usize) -> Option<String> {
+		let best_number =
+			self.health.iter().flatten().map(|health| health.best_number).max().unwrap_or(0);
+		match self.health[index] {
+			None => Some("endpoint is not responding".into()),
+			Some(health) if health.is_syncing => Some("node is syncing".into()),
+			Some(health) if health.should_have_peers && health.peers == 0 =>
+				Some("node has no peers".into()),
+			Some(health) if health.best_number + MAX_BEST_BLOCK_LAG < best_number =>
+				Some(format!("best block {} is behind {}", health.best_number, best_number)),
+			Some(_) => None,
+		}
+	}
+
+	/// Update health of the endpoint and select the active endpoint.
+	fn set_health(&mut self, index: usize, health: Option<NodeHealth>) {
+		self.health[index] = health;
+		self.select_active();
+	}
+
+	/// Switch to the first healthy endpoint if the active endpoint is unhealthy.
+	fn select_active(&mut self) {
+		let reason = match self.unhealthy_reason(self.active) {
+			Some(reason) => reason,
+			None => return,
+		};
+		let healthy = (0..self.uris.len()).find(|index| self.unhealthy_reason(*index).is_none());
+		if let Some(healthy) = healthy {
+			log::warn!(
+				target: LOG_TARGET,
+				"Switching from {} to {}: {}",
+				self.uris[self.active],
+				self.uris[healthy],
+				reason,
+			);
+			self.active = healthy;
+		}
+	}
+
+	/// Returns index and URI of the active endpoint.
+	fn active(&self) -> (usize, String) {
+		(self.active, self.uris[self.active].clone())
+	}
+}
+
+/// Periodically check health of all endpoints.
+async fn check_health_loop(endpoints: Arc<Mutex<Endpoints>>) {
+	let uris = endpoints.lock().uris.clone();
+	loop {
+		for (index, uri) in uris.iter().enumerate() {
+			let health = async_std::future::timeout(HEALTH_CHECK_TIMEOUT, check_health(uri))
+				.await
+				.map_err(anyhow::Error::from)
+				.and_then(|health| health);
+			let health = match health {
+				Ok(health) => Some(health),
+				Err(e) => {
+					log::debug!(target: LOG_TARGET, "Health check of {} has failed: {}", uri, e);
+					None
+				},
+			};
+			endpoints.lock().set_health(index, health);
+		}
+
+		async_std::task::sleep(HEALTH_CHECK_INTERVAL).await;
+	}
+}
+
+/// Check health of the single endpoint.
+async fn check_health(uri: &str) -> anyhow::Result<NodeHealth> {
+	let (mut node, _) = async_tungstenite::async_std::connect_async(uri).await?;
+	for (id, method) in [(1, "system_health"), (2, "chain_getHeader")] {
+		let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": [] });
+		node.send(Message::Text(request.to_string())).await?;
+	}
+
+	let (mut system_health, mut header) = (None, None);
+	while system_health.is_none() || header.is_none() {
+		let response: Value = match node.next().await {
+			Some(Ok(Message::Text(response))) => serde_json::from_str(&response)?,
+			Some(Ok(_)) => continue,
+			Some(Err(e)) => return Err(e.into()),
+			None => return Err(anyhow::format_err!("Connection is closed by the node")),
+		};
+		match response["id"].as_u64() {
+			Some(1) => system_health = Some(response["result"].clone()),
+			Some(2) => header = Some(response["result"].clone()),
+			_ => (),
+		}
+	}
+	let _ = node.close(None).await;
+
+	let (system_health, header) = (system_health.unwrap_or_default(), header.unwrap_or_default());
+	let best_number = header["number"]
+		.as_str()
+		.and_then(|number| u64::from_str_radix(number.trim_start_matches("0x"), 16).ok())
+		.ok_or_else(|| anyhow::format_err!("Unexpected best header: {}", header))?;
+	Ok(NodeHealth {
+		peers: system_health["peers"]
+			.as_u64()
+			.ok_or_else(|| anyhow::format_err!("Unexpected system health: {}", system_health))?,
+		is_syncing: system_health["isSyncing"].as_bool().unwrap_or_default(),
+		should_have_peers: system_health["shouldHavePeers"].as_bool().unwrap_or(true),
+		best_number,
+	})
+}
+
+/// Serve single relay connection, switching between node endpoints.
+async fn serve_connection(
+	stream: TcpStream,
+	endpoints: Arc<Mutex<Endpoints>>,
+) -> anyhow::Result<()> {
+	let relay = async_tungstenite::accept_async(stream).await?;
+	let (mut relay_tx, mut relay_rx) = relay.split();
+	let mut state = ConnectionState::default();
+	let mut active_checks = async_std::stream::interval(ACTIVE_ENDPOINT_CHECK_INTERVAL);
+	loop {
+		let (active, uri) = endpoints.lock().active();
+		let node = match async_tungstenite::async_std::connect_async(uri.as_str()).await {
+			Ok((node, _)) => node,
+			Err(e) => {
+				log::warn!(target: LOG_TARGET, "Failed to connect to {}: {}", uri, e);
+				endpoints.lock().set_health(active, None);
+				async_std::task::sleep(RECONNECT_DELAY).await;
+				continue
+			},
+		};
+
+		let (mut node_tx, mut node_rx) = node.split();
+		let (to_node, to_relay) = state.on_reconnect();
+		for message in to_relay {
+			relay_tx.send(Message::Text(message)).await?;
+		}
+		for message in to_node {
+			node_tx.send(Message::Text(message)).await?;
+		}
+
+		loop {
+			futures::select! {
+				message = relay_rx.next().fuse() => match message {
+					Some(Ok(Message::Text(request))) =>
+						node_tx.send(Message::Text(state.on_request(request))).await?,
+					Some(Ok(Message::Close(_))) | None => return Ok(()),
+					Some(Ok(_)) => (),
+					Some(Err(e)) => return Err(e.into()),
+				},
+				message = node_rx.next().fuse() => match message {
+					Some(Ok(Message::Text(message))) => {
+						let (to_node, to_relay) = state.on_node_message(message);
+						for message in to_relay {
+							relay_tx.send(Message::Text(message)).await?;
+						}
+						for message in to_node {
+							node_tx.send(Message::Text(message)).await?;
+						}
+					},
+					Some(Ok(Message::Close(_))) | None => {
+						log::warn!(target: LOG_TARGET, "Connection is closed by {}", uri);
+						endpoints.lock().set_health(active, None);
+						break
+					},
+					Some(Ok(_)) => (),
+					Some(Err(e)) => {
+						log::warn!(target: LOG_TARGET, "Connection to {} has failed: {}", uri, e);
+						endpoints.lock().set_health(active, None);
+						break
+					},
+				},
+				_ = active_checks.next().fuse() => if endpoints.lock().active != active {
+					break
+				},
+			}
+		}
+	}
+}
+
+/// Subscription, opened by the relay.
+struct Subscription {
+	/// Request that has opened the subscription.
+	request: Value,
+	/// Identifier of the subscription at the current node.
+	node_id: Value,
+}
+
+/// Request of the proxy, watching transactions at the new node.
+enum WatchRequest {
+	/// Subscription to finalized headers.
+	FinalizedHeads,
+	/// Hashes of finalized blocks.
+	BlockHashes,
+	/// Finalized block with the given hash.
+	Block(Value),
+}
+
+/// State of the relay connection that needs to be restored after switching endpoints.
+#[derive(Default)]
+struct ConnectionState {
+	next_id: u64,
+	/// Requests without responses, mapped by their encoded identifiers.
+	pending: BTreeMap<String, Value>,
+	/// Subscriptions, mapped by their encoded identifiers, known to the relay.
+	subscriptions: BTreeMap<String, Subscription>,
+	/// Subscriptions that are reopened at the new node, mapped by request identifiers.
+	reopening: BTreeMap<String, String>,
+	/// Transactions, submitted to previous nodes, mapped by their hashes to identifiers of
+	/// their subscriptions, known to the relay.
+	watched: BTreeMap<H256, Value>,
+	/// Requests, watching transactions, mapped by their encoded identifiers.
+	watch_requests: BTreeMap<String, WatchRequest>,
+	/// Identifier of the finalized headers subscription at the current node.
+	finalized_heads: Option<Value>,
+	/// Number of the last finalized block, checked for watched transactions.
+	last_checked_block: Option<u64>,
+}
+
+impl ConnectionState {
+	/// Remember the relay request. Returns the request that needs to be sent to the node.
+	fn on_request(&mut self, request: String) -> String {
+		let mut parsed = match serde_json::from_str::<Value>(&request) {
+			Ok(parsed) if parsed.is_object() => parsed,
+			_ => return request,
+		};
+
+		let method = parsed["method"].as_str().unwrap_or_default();
+		if method.contains("_unsubscribe") || method == "author_unwatchExtrinsic" {
+			// reopened subscription has different identifier at the node
+			if let Some(subscription) = self.subscriptions.remove(&parsed["params"][0].to_string())
+			{
+				parsed["params"][0] = subscription.node_id;
+			}
+			self.watched
+				.retain(|_, subscription_id| *subscription_id != parsed["params"][0]);
+		}
+		let request = parsed.to_string();
+		self.pending.insert(parsed["id"].to_string(), parsed);
+		request
+	}
+
+	/// Process the node message. Returns messages that need to be sent to the node and to the
+	/// relay.
+	fn on_node_message(&mut self, message: String) -> (Vec<String>, Vec<String>) {
+		let mut parsed = match serde_json::from_str::<Value>(&message) {
+			Ok(parsed) if parsed.is_object() => parsed,
+			_ => return (Vec::new(), vec![message]),
+		};
+
+		let id = parsed["id"].to_string();
+		if let Some(request) = self.watch_requests.remove(&id) {
+			return self.on_watch_response(request, &parsed)
+		}
+
+		if let Some(subscription_id) = self.reopening.remove(&id) {
+			match parsed.get("result").filter(|result| !result.is_null()) {
+				Some(node_id) => {
+					if let Some(subscription) = self.subscriptions.get_mut(&subscription_id) {
+						subscription.node_id = node_id.clone();
+					}
+				},
+				None => log::warn!(
+					target: LOG_TARGET,
+					"Failed to reopen subscription {}: {}",
+					subscription_id,
+					message,
+				),
+			}
+			return (Vec::new(), Vec::new())
+		}
+
+		if let Some(request) = self.pending.remove(&id) {
+			let method = request["method"].as_str().unwrap_or_default();
+			let is_subscription =
+				method.contains("_subscribe") || method == SUBMIT_AND_WATCH_METHOD;
+			if let Some(node_id) = parsed.get("result").filter(|_| is_subscription) {
+				self.subscriptions.insert(
+					node_id.to_string(),
+					Subscription { request, node_id: node_id.clone() },
+				);
+			}
+			return (Vec::new(), vec![message])
+		}
+
+		let node_id = parsed["params"]["subscription"].clone();
+		if node_id.is_null() {
+			// response to the request of the proxy
+			return (Vec::new(), Vec::new())
+		}
+		if self.finalized_heads.as_ref() == Some(&node_id) {
+			return (self.on_finalized_head(&parsed), Vec::new())
+		}
+
+		// notification of the reopened subscription is sent under the original identifier
+		let subscription_id = match self
+			.subscriptions
+			.iter()
+			.find(|(_, subscription)| subscription.node_id == node_id)
+		{
+			Some((subscription_id, _)) => subscription_id.clone(),
+			None => return (Vec::new(), Vec::new()),
+		};
+		if parsed["method"] == "author_extrinsicUpdate" && is_final_transaction_status(&parsed) {
+			self.subscriptions.remove(&subscription_id);
+		}
+		if subscription_id != node_id.to_string() {
+			match serde_json::from_str(&subscription_id) {
+				Ok(subscription_id) => parsed["params"]["subscription"] = subscription_id,
+				Err(_) => return (Vec::new(), Vec::new()),
+			}
+			return (Vec::new(), vec![parsed.to_string()])
+		}
+		(Vec::new(), vec![message])
+	}
+
+	/// Prepare connection to the new node. Returns messages that need to be sent to the node and
+	/// to the relay.
+	fn on_reconnect(&mut self) -> (Vec<String>, Vec<String>) {
+		let mut to_node = Vec::new();
+		let mut to_relay = Vec::new();
+		self.reopening.clear();
+		self.watch_requests.clear();
+		self.finalized_heads = None;
+		self.last_checked_block = None;
+
+		// transactions that may have been submitted to the previous node are not resubmitted
+		let pending = std::mem::take(&mut self.pending);
+		for (id, request) in pending {
+			if request["method"] != SUBMIT_AND_WATCH_METHOD {
+				to_node.push(request.to_string());
+				self.pending.insert(id, request);
+				continue
+			}
+
+			let subscription_id = Value::String(self.next_request_id());
+			to_relay.push(
+				json!({ "jsonrpc": "2.0", "id": request["id"], "result": subscription_id })
+					.to_string(),
+			);
+			self.watch_transaction(&request, subscription_id);
+		}
+
+		let subscriptions = std::mem::take(&mut self.subscriptions);
+		for (subscription_id, mut subscription) in subscriptions {
+			if subscription.request["method"] == SUBMIT_AND_WATCH_METHOD {
+				if let Ok(subscription_id) = serde_json::from_str(&subscription_id) {
+					self.watch_transaction(&subscription.request, subscription_id);
+				}
+				continue
+			}
+
+			let id = Value::String(self.next_request_id());
+			let mut request = subscription.request.clone();
+			request["id"] = id.clone();
+			to_node.push(request.to_string());
+			self.reopening.insert(id.to_string(), subscription_id.clone());
+			subscription.node_id = Value::Null;
+			self.subscriptions.insert(subscription_id, subscription);
+		}
+
+		if !self.watched.is_empty() {
+			to_node.push(self.watch_request(
+				"chain_subscribeFinalizedHeads",
+				json!([]),
+				WatchRequest::FinalizedHeads,
+			));
+		}
+
+		(to_node, to_relay)
+	}
+
+	/// Start watching transaction of the `author_submitAndWatchExtrinsic` request by its hash.
+	fn watch_transaction(&mut self, request: &Value, subscription_id: Value) {
+		match request["params"][0].as_str().and_then(transaction_hash) {
+			Some(hash) => {
+				self.watched.insert(hash, subscription_id);
+			},
+			None => log::warn!(
+				target: LOG_TARGET,
+				"Failed to watch transaction of the unsupported request: {}",
+				request,
+			),
+		}
+	}
+
+	/// Process response to the request, watching transactions. Returns messages that need to be
+	/// sent to the node and to the relay.
+	fn on_watch_response(
+		&mut self,
+		request: WatchRequest,
+		response: &Value,
+	) -> (Vec<String>, Vec<String>) {
+		let result = match response.get("result").filter(|result| !result.is_null()) {
+			Some(result) => result,
+			None => {
+				log::warn!(
+					target: LOG_TARGET,
+					"Failed to watch transactions at the new node: {}",
+					response,
+				);
+				return (Vec::new(), Vec::new())
+			},
+		};
+
+		match request {
+			WatchRequest::FinalizedHeads => {
+				self.finalized_heads = Some(result.clone());
+				(Vec::new(), Vec::new())
+			},
+			WatchRequest::BlockHashes => {
+				let hashes = result.as_array().cloned().unwrap_or_default();
+				let to_node = hashes
+					.into_iter()
+					.filter(|hash| !hash.is_null())
+					.map(|hash| {
+						self.watch_request(
+							"chain_getBlock",
+							json!([hash.clone()]),
+							WatchRequest::Block(hash),
+						)
+					})
+					.collect();
+				(to_node, Vec::new())
+			},
+			WatchRequest::Block(block_hash) => {
+				let extrinsics =
+					result["block"]["extrinsics"].as_array().cloned().unwrap_or_default();
+				let mut to_relay = Vec::new();
+				for (index, extrinsic) in extrinsics.iter().enumerate() {
+					let subscription_id = match extrinsic
+						.as_str()
+						.and_then(transaction_hash)
+						.and_then(|hash| self.watched.remove(&hash))
+					{
+						Some(subscription_id) => subscription_id,
+						None => continue,
+					};
+					to_relay.push(
+						json!({
+							"jsonrpc": "2.0",
+							"method": "author_extrinsicUpdate",
+							"params": {
+								"subscription": subscription_id,
+								"result": { "finalized": [block_hash, index] },
+							},
+						})
+						.to_string(),
+					);
+				}
+
+				let mut to_node = Vec::new();
+				if self.watched.is_empty() {
+					if let Some(finalized_heads) = self.finalized_heads.take() {
+						to_node.push(
+							json!({
+								"jsonrpc": "2.0",
+								"id": self.next_request_id(),
+								"method": "chain_unsubscribeFinalizedHeads",
+								"params": [finalized_heads],
+							})
+							.to_string(),
+						);
+					}
+				}
+				(to_node, to_relay)
+			},
+		}
+	}
+
+	/// Check new finalized blocks for watched transactions. Returns requests that need to be
+	/// sent to the node.
+	fn on_finalized_head(&mut self, notification: &Value) -> Vec<String> {
+		let number = match notification["params"]["result"]["number"]
+			.as_str()
+			.and_then(|number| u64::from_str_radix(number.trim_start_matches("0x"), 16).ok())
+		{
+			Some(number) => number,
+			None => return Vec::new(),
+		};
+
+		let first = match self.last_checked_block {
+			Some(last_checked_block) => last_checked_block + 1,
+			None => number.saturating_sub(MAX_RESCANNED_BLOCKS),
+		};
+		if first > number {
+			return Vec::new()
+		}
+		self.last_checked_block = Some(number);
+		vec![self.watch_request(
+			"chain_getBlockHash",
+			json!([(first..=number).collect::<Vec<_>>()]),
+			WatchRequest::BlockHashes,
+		)]
+	}
+
+	/// Prepare request, watching transactions.
+	fn watch_request(&mut self, method: &str, params: Value, request: WatchRequest) -> String {
+		let id = Value::String(self.next_request_id());
+		self.watch_requests.insert(id.to_string(), request);
+		json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }).to_string()
+	}
+
+	fn next_request_id(&mut self) -> String {
+		self.next_id += 1;
+		format!("{}{}", FAILOVER_ID_PREFIX, self.next_id)
+	}
+}
+
+/// Returns hash of the hex-encoded transaction.
+fn transaction_hash(transaction: &str) -> Option<H256> {
+	let transaction = hex::decode(transaction.trim_start_matches("0x")).ok()?;
+	Some(H256(sp_core::hashing::blake2_256(&transaction)))
+}
+
+/// Returns true if the notification contains final status of the watched transaction.
+fn is_final_transaction_status(notification: &Value) -> bool {
+	let status = &notification["params"]["result"];
+	matches!(status.as_str(), Some("dropped") | Some("invalid")) ||
+		["finalized", "usurped", "finalityTimeout"]
+			.iter()
+			.any(|s| status.get(s).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn relay_connection_survives_failover() {
+		// endpoint that is behind others is not used
+		let mut endpoints = Endpoints::new(vec!["ws://first".into(), "ws://second".into()]);
+		let health = |best_number| NodeHealth {
+			peers: 8,
+			is_syncing: false,
+			should_have_peers: true,
+			best_number,
+		};
+		endpoints.set_health(1, Some(health(100)));
+		endpoints.set_health(0, Some(health(90)));
+		assert_eq!(endpoints.active(), (1, "ws://second".into()));
+		endpoints.set_health(0, Some(health(100)));
+		endpoints.set_health(1, None);
+		assert_eq!(endpoints.active(), (0, "ws://first".into()));
+
+		// relay subscribes to headers, submits two transactions and asks for the nonce
+		let mut state = ConnectionState::default();
+		state.on_request(
+			r#"{"jsonrpc":"2.0","id":1,"method":"chain_subscribeNewHeads","params":[]}"#.into(),
+		);
+		state.on_request(r#"{"jsonrpc":"2.0","id":2,"method":"author_submitAndWatchExtrinsic","params":["0x01"]}"#.into());
+		state.on_node_message(r#"{"jsonrpc":"2.0","id":1,"result":"heads"}"#.into());
+		state.on_node_message(r#"{"jsonrpc":"2.0","id":2,"result":"tx"}"#.into());
+		state.on_request(
+			r#"{"jsonrpc":"2.0","id":3,"method":"system_accountNextIndex","params":["alice"]}"#
+				.into(),
+		);
+		state.on_request(r#"{"jsonrpc":"2.0","id":4,"method":"author_submitAndWatchExtrinsic","params":["0x02"]}"#.into());
+
+		// after switching endpoints, the pending request is resent and headers subscription is
+		// reopened. Transactions are not resubmitted, but watched at finalized blocks
+		let (to_node, to_relay) = state.on_reconnect();
+		let to_node = to_node
+			.iter()
+			.map(|request| serde_json::from_str::<Value>(request).unwrap())
+			.collect::<Vec<_>>();
+		assert_eq!(
+			to_node.iter().map(|request| request["method"].clone()).collect::<Vec<_>>(),
+			vec![
+				json!("system_accountNextIndex"),
+				json!("chain_subscribeNewHeads"),
+				json!("chain_subscribeFinalizedHeads"),
+			],
+		);
+		assert_eq!(to_relay.len(), 1);
+		let submitted: Value = serde_json::from_str(&to_relay[0]).unwrap();
+		assert_eq!(submitted["id"], json!(4));
+		assert_eq!(state.watched.len(), 2);
+
+		// notifications of the reopened subscription are sent with the original identifier
+		let response = json!({ "jsonrpc": "2.0", "id": to_node[1]["id"], "result": "new-heads" });
+		assert_eq!(state.on_node_message(response.to_string()), (vec![], vec![]));
+		let notification = json!({
+			"jsonrpc": "2.0",
+			"method": "chain_newHead",
+			"params": { "subscription": "new-heads", "result": { "number": "0x1" } },
+		});
+		let (_, to_relay) = state.on_node_message(notification.to_string());
+		let notification: Value = serde_json::from_str(&to_relay[0]).unwrap();
+		assert_eq!(notification["params"]["subscription"], json!("heads"));
+
+		// previous finalized blocks are checked for watched transactions
+		let response = json!({ "jsonrpc": "2.0", "id": to_node[2]["id"], "result": "finalized" });
+		state.on_node_message(response.to_string());
+		let notification = json!({
+			"jsonrpc": "2.0",
+			"method": "chain_finalizedHead",
+			"params": { "subscription": "finalized", "result": { "number": "0x41" } },
+		});
+		let (to_node, _) = state.on_node_message(notification.to_string());
+		let request: Value = serde_json::from_str(&to_node[0]).unwrap();
+		assert_eq!(request["method"], json!("chain_getBlockHash"));
+		assert_eq!(request["params"][0].as_array().unwrap().len(), 65);
+		let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": ["0xaa"] });
+		let (to_node, _) = state.on_node_message(response.to_string());
+		let request: Value = serde_json::from_str(&to_node[0]).unwrap();
+		assert_eq!(request["method"], json!("chain_getBlock"));
+
+		// relay is notified when the block with its transaction is finalized
+		let response = json!({
+			"jsonrpc": "2.0",
+			"id": request["id"],
+			"result": { "block": { "header": {}, "extrinsics": ["0x00", "0x01"] } },
+		});
+		let (to_node, to_relay) = state.on_node_message(response.to_string());
+		assert!(to_node.is_empty());
+		let notification: Value = serde_json::from_str(&to_relay[0]).unwrap();
+		assert_eq!(notification["params"]["subscription"], json!("tx"));
+		assert_eq!(notification["params"]["result"], json!({ "finalized": ["0xaa", 1] }));
+		assert_eq!(state.watched.len(), 1);
+	}
+}
@@ -0,0 +1,783 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Local proxies of node RPC endpoints.
+//!
+//! Relay loops are using their own RPC clients, so to alter or capture the RPC traffic, all node
+//! connections of the command are redirected to local proxies. Proxies are used to:
+//!
+//! - dry-run transactions instead of submitting them (`--dry-run`);
+//! - record all messages, exchanged by the relay and nodes (`--record`);
+//! - replay recorded messages without connecting to nodes (`--replay`);
+//! - archive GRANDPA justifications of mandatory headers and serve them when nodes have pruned them
+//!   (`--justification-archive`);
+//! - reject transactions that are submitted after the exit signal (`--shutdown-timeout`);
+//! - re-sign relay transactions with tips (`--left-tip-policy` and `--right-tip-policy` of the
+//!   complex relay).
+
+use crate::cli::{
+	dry_run::{DryRunState, Intercepted},
+	justification_archive::{archive_justifications, ArchiveInterceptor, JustificationArchive},
+	rpc_recording::{Recorder, Replayer, Sender},
+	shutdown::reject_submission,
+	LOG_TARGET,
+};
+
+use async_std::net::{TcpListener, TcpStream};
+use async_tungstenite::tungstenite::Message;
+use futures::{FutureExt, SinkExt, StreamExt};
+use serde_json::Value;
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+use structopt::StructOpt;
+
+/// Suffixes of node connection options (`--<prefix>-<suffix>`).
+const CONNECTION_OPTIONS: &[&str] = &["uri", "host", "port", "path", "secure"];
+/// Default node host, used by the relay.
+pub const DEFAULT_HOST: &str = "127.0.0.1";
+/// Default node port, used by the relay.
+const DEFAULT_PORT: &str = "9944";
+
+/// RPC proxy parameters.
+#[derive(Clone, Debug, PartialEq, StructOpt)]
+pub struct RpcProxyParams {
+	/// Do not submit any transactions. Instead, every transaction is dry-run at the best block
+	/// of its chain and is reported to the relay as finalized.
+	///
+	/// The chain state is not changed by dry-run transactions, so the relay only observes the
+	/// progress made by other relayers and periodically dry-runs the same transactions again.
+	/// Connections of bridges from the `relay-bridges` configuration file are not dry-run.
+	#[structopt(long, global = true)]
+	pub dry_run: bool,
+	/// Record all RPC requests, responses and subscription notifications, exchanged by the relay
+	/// and nodes, to the given directory.
+	#[structopt(long, global = true)]
+	pub record: Option<PathBuf>,
+	/// Replay RPC messages, recorded with `--record`, instead of connecting to nodes.
+	///
+	/// Node connection options must be the same as in the recorded run. Connections of every
+	/// node are replayed in the same order they have been opened in the recorded run.
+	#[structopt(
+		long,
+		global = true,
+		conflicts_with_all = &["record", "dry-run", "justification-archive"]
+	)]
+	pub replay: Option<PathBuf>,
+	/// Archive GRANDPA justifications of mandatory headers of all source nodes to the given
+	/// directory. Archived justifications are served to the relay when nodes have pruned them.
+	#[structopt(long, global = true)]
+	pub justification_archive: Option<PathBuf>,
+}
+
+impl RpcProxyParams {
+	/// Returns true if node connections need to be proxied.
+	pub fn is_enabled(&self) -> bool {
+		self.dry_run ||
+			self.record.is_some() ||
+			self.replay.is_some() ||
+			self.justification_archive.is_some()
+	}
+}
+
+/// Hook of the proxy, rewriting transactions that are submitted by the relay.
+pub trait SubmitHook: Send + Sync {
+	/// Returns transaction that must be submitted instead of the given encoded transaction or
+	/// `None` if the transaction must be submitted as is.
+	fn rewrite_transaction(&self, transaction: &[u8]) -> anyhow::Result<Option<Vec<u8>>>;
+}
+
+impl std::fmt::Debug for dyn SubmitHook {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "SubmitHook")
+	}
+}
+
+/// Parsed parameters of the node connection.
+pub trait NodeConnectionParams {
+	/// Returns node URI.
+	fn upstream(&self) -> String;
+	/// Make connection point to the local port.
+	fn redirect(&mut self, port: u16);
+}
+
+/// Node connections of the command.
+pub trait NodeConnections {
+	/// Returns source chain of the bridge, if its node is connected using generic `source`
+	/// connection options.
+	fn source_chain(&self) -> Option<&str>;
+	/// Make node connections point to local ports, returned by the `redirect` for the options
+	/// prefix and the node URI. If `redirect` returns `None`, the connection is left as is.
+	fn redirect(
+		&mut self,
+		redirect: &mut dyn FnMut(&str, String) -> anyhow::Result<Option<u16>>,
+	) -> anyhow::Result<()>;
+}
+
+/// Redirect parsed node connections, declared with given option prefixes.
+pub fn redirect_parsed_connections(
+	connections: Vec<(&str, &mut dyn NodeConnectionParams)>,
+	redirect: &mut dyn FnMut(&str, String) -> anyhow::Result<Option<u16>>,
+) -> anyhow::Result<()> {
+	for (prefix, connection) in connections {
+		if let Some(port) = redirect(prefix, connection.upstream())? {
+			connection.redirect(port);
+		}
+	}
+	Ok(())
+}
+
+/// Command line of the command with node connections that may only be redirected by rewriting
+/// connection options.
+///
+/// Connections of many commands are declared by upstream parameters with private fields (e.g.
+/// `RelayHeadersParams`), so they can't be altered after parsing. Instead, connection options
+/// with declared prefixes are rewritten and the command line is parsed again.
+#[derive(Debug)]
+pub struct CommandLine {
+	args: Vec<String>,
+	prefixes: &'static [&'static str],
+}
+
+impl CommandLine {
+	/// Create command line with node connection options, starting with given prefixes.
+	pub fn new(args: Vec<String>, prefixes: &'static [&'static str]) -> Self {
+		CommandLine { args, prefixes }
+	}
+
+	/// Returns rewritten command line.
+	pub fn into_args(self) -> Vec<String> {
+		self.args
+	}
+}
+
+impl NodeConnections for CommandLine {
+	fn source_chain(&self) -> Option<&str> {
+		self.args
+			.iter()
+			.filter(|arg| !arg.starts_with('-'))
+			.find_map(|arg| arg.split_once("-to-"))
+			.map(|(source, _)| source)
+	}
+
+	fn redirect(
+		&mut self,
+		redirect: &mut dyn FnMut(&str, String) -> anyhow::Result<Option<u16>>,
+	) -> anyhow::Result<()> {
+		let (rewritten, redirected) = rewrite_connections(&self.args, self.prefixes, redirect)?;
+		// keep the command line unchanged if nothing is redirected
+		if redirected {
+			self.args = rewritten;
+		}
+		Ok(())
+	}
+}
+
+/// Proxied node endpoint.
+#[derive(Debug)]
+struct ProxyEndpoint {
+	/// Prefix of endpoint options in the command line.
+	prefix: String,
+	/// Node URI.
+	upstream: String,
+	/// Proxy parameters.
+	params: RpcProxyParams,
+	/// Archive of GRANDPA justifications.
+	archive: Option<JustificationArchive>,
+	/// Hook, rewriting submitted transactions.
+	submit_hook: Option<Arc<dyn SubmitHook>>,
+}
+
+/// Local proxy of the node RPC endpoint.
+#[derive(Debug)]
+pub struct RpcProxy {
+	listener: std::net::TcpListener,
+	endpoint: Arc<ProxyEndpoint>,
+}
+
+impl RpcProxy {
+	/// Start serving proxy connections in background.
+	pub fn spawn(self) {
+		if let Some(ref archive) = self.endpoint.archive {
+			async_std::task::spawn(archive_justifications(
+				self.endpoint.upstream.clone(),
+				archive.clone(),
+			));
+		}
+		async_std::task::spawn(async move {
+			let listener = TcpListener::from(self.listener);
+			log::info!(
+				target: LOG_TARGET,
+				"RPC proxy of {} is listening on {:?}",
+				self.endpoint.upstream,
+				listener.local_addr(),
+			);
+
+			let mut incoming = listener.incoming();
+			let mut connection = 0;
+			while let Some(stream) = incoming.next().await {
+				connection += 1;
+				let endpoint = self.endpoint.clone();
+				async_std::task::spawn(async move {
+					let result = match stream {
+						Ok(stream) => serve_connection(stream, &endpoint, connection).await,
+						Err(e) => Err(e.into()),
+					};
+					if let Err(e) = result {
+						log::warn!(
+							target: LOG_TARGET,
+							"RPC proxy connection to {} has failed: {}",
+							endpoint.upstream,
+							e,
+						);
+					}
+				});
+			}
+		});
+	}
+}
+
+/// Node endpoint, mentioned in the command line.
+#[derive(Debug, Default)]
+struct Endpoint {
+	/// Position in the rewritten command line, where connection options are inserted.
+	position: usize,
+	/// Index of the endpoint in the order of appearance.
+	index: usize,
+	uri: Option<String>,
+	host: Option<String>,
+	port: Option<String>,
+	path: Option<String>,
+	secure: bool,
+}
+
+impl Endpoint {
+	fn new(position: usize, index: usize) -> Self {
+		Endpoint { position, index, ..Default::default() }
+	}
+
+	fn upstream(&self) -> String {
+		if let Some(ref uri) = self.uri {
+			return uri.clone()
+		}
+
+		format!(
+			"{}://{}:{}{}",
+			if self.secure { "wss" } else { "ws" },
+			self.host.as_deref().unwrap_or(DEFAULT_HOST),
+			self.port.as_deref().unwrap_or(DEFAULT_PORT),
+			self.path.as_deref().unwrap_or(""),
+		)
+	}
+}
+
+/// Redirect all node connections of the command to RPC proxies.
+///
+/// Returns proxies that need to be started.
+pub fn redirect_connections(
+	connections: &mut dyn NodeConnections,
+	params: &RpcProxyParams,
+) -> anyhow::Result<Vec<RpcProxy>> {
+	let archive = params
+		.justification_archive
+		.as_deref()
+		.map(JustificationArchive::open)
+		.transpose()?;
+	let mut proxies = Vec::new();
+	connections.redirect(&mut |prefix, upstream| {
+		let listener = std::net::TcpListener::bind((DEFAULT_HOST, 0))?;
+		let port = listener.local_addr()?.port();
+		// the relay never reads justifications of target chains
+		let archive = archive.clone().filter(|_| prefix != "target");
+		proxies.push(RpcProxy {
+			listener,
+			endpoint: Arc::new(ProxyEndpoint {
+				prefix: prefix.into(),
+				upstream,
+				params: params.clone(),
+				archive,
+				submit_hook: None,
+			}),
+		});
+		Ok(Some(port))
+	})?;
+
+	Ok(proxies)
+}
+
+/// Start the proxy of the node endpoint, rewriting transactions that are submitted by the relay
+/// with the `hook`. Returns local port of the proxy.
+pub fn spawn_submit_proxy(
+	prefix: String,
+	upstream: String,
+	hook: Arc<dyn SubmitHook>,
+) -> anyhow::Result<u16> {
+	let listener = std::net::TcpListener::bind((DEFAULT_HOST, 0))?;
+	let port = listener.local_addr()?.port();
+	RpcProxy {
+		listener,
+		endpoint: Arc::new(ProxyEndpoint {
+			prefix,
+			upstream,
+			params: RpcProxyParams {
+				dry_run: false,
+				record: None,
+				replay: None,
+				justification_archive: None,
+			},
+			archive: None,
+			submit_hook: Some(hook),
+		}),
+	}
+	.spawn();
+	Ok(port)
+}
+
+/// Rewrite all node connections, mentioned in the command line with given prefixes, so that they
+/// point to the local port, returned by the `redirect` for the options prefix and the node URI.
+/// If `redirect` returns `None`, the connection is left pointing to the node URI.
+///
+/// Returns rewritten command line and `true` if any connection has been redirected.
+fn rewrite_connections(
+	args: &[String],
+	prefixes: &[&str],
+	redirect: &mut dyn FnMut(&str, String) -> anyhow::Result<Option<u16>>,
+) -> anyhow::Result<(Vec<String>, bool)> {
+	let mut rewritten = Vec::with_capacity(args.len());
+	let mut endpoints: BTreeMap<String, Endpoint> = BTreeMap::new();
+	let mut args = args.iter().cloned();
+	while let Some(arg) = args.next() {
+		let (name, inline_value) = match arg.strip_prefix("--") {
+			Some(option) => match option.split_once('=') {
+				Some((name, value)) => (name.to_string(), Some(value.to_string())),
+				None => (option.to_string(), None),
+			},
+			None => {
+				rewritten.push(arg);
+				continue
+			},
+		};
+
+		// transactions are signed by all chains that have signers, so we need to proxy their
+		// connections, even if they are using default endpoints. Signers without connections
+		// (e.g. `--left-funding-signer` or `--rococo-headers-to-bridge-hub-westend-signer`) are
+		// using connections of other chains
+		if let Some(prefix) =
+			name.strip_suffix("-signer").or_else(|| name.strip_suffix("-signer-file"))
+		{
+			if prefixes.contains(&prefix) {
+				let new_endpoint = Endpoint::new(rewritten.len(), endpoints.len());
+				endpoints.entry(prefix.into()).or_insert(new_endpoint);
+			}
+			rewritten.push(arg);
+			continue
+		}
+
+		let connection_option = CONNECTION_OPTIONS.iter().find_map(|suffix| {
+			name.strip_suffix(suffix)
+				.and_then(|prefix| prefix.strip_suffix('-'))
+				.filter(|prefix| prefixes.contains(prefix))
+				.map(|prefix| (prefix.to_string(), *suffix))
+		});
+		let (prefix, suffix) = match connection_option {
+			Some(connection_option) => connection_option,
+			None => {
+				rewritten.push(arg);
+				continue
+			},
+		};
+
+		let new_endpoint = Endpoint::new(rewritten.len(), endpoints.len());
+		let endpoint = endpoints.entry(prefix).or_insert(new_endpoint);
+		if suffix == "secure" {
+			endpoint.secure = true;
+			continue
+		}
+		let value = match inline_value.or_else(|| args.next()) {
+			Some(value) => value,
+			None => return Err(anyhow::format_err!("Missing value of --{}", name)),
+		};
+		match suffix {
+			"uri" => endpoint.uri = Some(value),
+			"host" => endpoint.host = Some(value),
+			"port" => endpoint.port = Some(value),
+			_ => endpoint.path = Some(value),
+		}
+	}
+
+	// insert options starting from the end, so that positions of other endpoints are valid
+	let mut endpoints = endpoints.into_iter().collect::<Vec<_>>();
+	endpoints.sort_by_key(|(_, endpoint)| std::cmp::Reverse((endpoint.position, endpoint.index)));
+	let mut redirected = false;
+	for (prefix, endpoint) in endpoints {
+		let upstream = endpoint.upstream();
+		let options = match redirect(&prefix, upstream.clone())? {
+			Some(port) => {
+				redirected = true;
+				vec![
+					format!("--{}-host", prefix),
+					DEFAULT_HOST.into(),
+					format!("--{}-port", prefix),
+					port.to_string(),
+				]
+			},
+			None => vec![format!("--{}-uri", prefix), upstream],
+		};
+		rewritten.splice(endpoint.position..endpoint.position, options);
+	}
+
+	Ok((rewritten, redirected))
+}
+
+/// Serve single relay connection.
+async fn serve_connection(
+	stream: TcpStream,
+	endpoint: &ProxyEndpoint,
+	connection: usize,
+) -> anyhow::Result<()> {
+	let relay = async_tungstenite::accept_async(stream).await?;
+	let (mut relay_tx, mut relay_rx) = relay.split();
+	let file_name = format!("{}-{}.jsonl", endpoint.prefix, connection);
+
+	if let Some(ref dir) = endpoint.params.replay {
+		let mut replayer = Replayer::open(&dir.join(file_name))?;
+		while let Some(message) = relay_rx.next().await {
+			match message? {
+				Message::Text(request) =>
+					for response in replayer.on_request(&request) {
+						relay_tx.send(Message::Text(response)).await?;
+					},
+				Message::Close(_) => break,
+				_ => (),
+			}
+		}
+		return Ok(())
+	}
+
+	let mut recorder = endpoint
+		.params
+		.record
+		.as_ref()
+		.map(|dir| Recorder::create(&dir.join(file_name)))
+		.transpose()?;
+	let mut dry_run = endpoint.params.dry_run.then(|| DryRunState::new(endpoint.upstream.clone()));
+	let mut archive = endpoint.archive.clone().map(ArchiveInterceptor::new);
+	let (node, _) = async_tungstenite::async_std::connect_async(endpoint.upstream.as_str()).await?;
+	let (mut node_tx, mut node_rx) = node.split();
+	loop {
+		futures::select! {
+			message = relay_rx.next().fuse() => match message {
+				Some(Ok(Message::Text(request))) => {
+					let request = match endpoint.submit_hook {
+						Some(ref hook) => rewrite_submission(request, hook.as_ref()),
+						None => request,
+					};
+					if let Some(ref mut recorder) = recorder {
+						recorder.record(Sender::Relay, &request)?;
+					}
+					if let Some(ref mut archive) = archive {
+						archive.on_request(&request);
+					}
+					let intercepted = match (reject_submission(&request), dry_run.as_mut()) {
+						(Some(rejection), _) => Intercepted::Reply(vec![rejection]),
+						(None, Some(dry_run)) => dry_run.on_request(&request),
+						(None, None) => Intercepted::Forward,
+					};
+					match intercepted {
+						Intercepted::Forward => node_tx.send(Message::Text(request)).await?,
+						Intercepted::Reply(responses) =>
+							for response in responses {
+								if let Some(ref mut recorder) = recorder {
+									recorder.record(Sender::Node, &response)?;
+								}
+								relay_tx.send(Message::Text(response)).await?;
+							},
+						Intercepted::Query(requests) =>
+							for request in requests {
+								node_tx.send(Message::Text(request)).await?;
+							},
+					}
+				},
+				Some(Ok(Message::Close(_))) | None => return Ok(()),
+				Some(Ok(message)) => node_tx.send(message).await?,
+				Some(Err(e)) => return Err(e.into()),
+			},
+			message = node_rx.next().fuse() => match message {
+				Some(Ok(Message::Text(response))) => {
+					let response = match archive {
+						Some(ref mut archive) => archive.on_response(response),
+						None => response,
+					};
+					let responses = match dry_run {
+						Some(ref mut dry_run) => dry_run.on_response(response),
+						None => vec![response],
+					};
+					for response in responses {
+						if let Some(ref mut recorder) = recorder {
+							recorder.record(Sender::Node, &response)?;
+						}
+						relay_tx.send(Message::Text(response)).await?;
+					}
+				},
+				Some(Ok(Message::Close(_))) | None => return Ok(()),
+				Some(Ok(message)) => relay_tx.send(message).await?,
+				Some(Err(e)) => return Err(e.into()),
+			},
+		}
+	}
+}
+
+/// Rewrite transaction of the submission request with the `hook`. If the hook fails, the original
+/// transaction is submitted.
+fn rewrite_submission(request: String, hook: &dyn SubmitHook) -> String {
+	let mut parsed: Value = match serde_json::from_str(&request) {
+		Ok(parsed) => parsed,
+		Err(_) => return request,
+	};
+	match parsed["method"].as_str() {
+		Some("author_submitExtrinsic") | Some("author_submitAndWatchExtrinsic") => (),
+		_ => return request,
+	}
+	let transaction = match parsed["params"][0]
+		.as_str()
+		.and_then(|transaction| hex::decode(transaction.trim_start_matches("0x")).ok())
+	{
+		Some(transaction) => transaction,
+		None => return request,
+	};
+
+	match hook.rewrite_transaction(&transaction) {
+		Ok(Some(transaction)) => {
+			parsed["params"][0] = Value::String(format!("0x{}", hex::encode(transaction)));
+			parsed.to_string()
+		},
+		Ok(None) => request,
+		Err(e) => {
+			log::warn!(target: LOG_TARGET, "Failed to rewrite submitted transaction: {}", e);
+			request
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn connections_are_redirected_to_proxies() {
+		let args = [
+			"substrate-relay",
+			"relay-headers",
+			"rococo-to-bridge-hub-westend",
+			"--source-host=rococo-alice",
+			"--source-port",
+			"9945",
+			"--target-uri",
+			"wss://bridge-hub-westend:443",
+			"--target-signer",
+			"//Alice",
+			"--prometheus-port",
+			"9616",
+		]
+		.iter()
+		.map(|arg| arg.to_string())
+		.collect();
+
+		let params = RpcProxyParams {
+			dry_run: true,
+			record: None,
+			replay: None,
+			justification_archive: None,
+		};
+		let mut command_line = CommandLine::new(args, &["source", "target"]);
+		let proxies = redirect_connections(&mut command_line, &params).unwrap();
+		let args = command_line.into_args();
+		let proxy_ports = proxies
+			.iter()
+			.map(|proxy| proxy.listener.local_addr().unwrap().port())
+			.collect::<Vec<_>>();
+		assert_eq!(
+			args,
+			vec![
+				"substrate-relay".to_string(),
+				"relay-headers".into(),
+				"rococo-to-bridge-hub-westend".into(),
+				"--source-host".into(),
+				DEFAULT_HOST.into(),
+				"--source-port".into(),
+				proxy_ports[1].to_string(),
+				"--target-host".into(),
+				DEFAULT_HOST.into(),
+				"--target-port".into(),
+				proxy_ports[0].to_string(),
+				"--target-signer".into(),
+				"//Alice".into(),
+				"--prometheus-port".into(),
+				"9616".into(),
+			],
+		);
+		assert_eq!(proxies[0].endpoint.upstream, "wss://bridge-hub-westend:443");
+		assert_eq!(proxies[1].endpoint.upstream, "ws://rococo-alice:9945");
+	}
+
+	#[test]
+	fn only_declared_connections_are_rewritten() {
+		let args = [
+			"relay-messages",
+			"bridge-hub-rococo-to-bridge-hub-westend",
+			"--target-signer",
+			"//Alice",
+			"--left-funding-signer",
+			"//Bob",
+			"--prometheus-host",
+			"0.0.0.0",
+		]
+		.iter()
+		.map(|arg| arg.to_string())
+		.collect::<Vec<_>>();
+
+		let mut prefixes = Vec::new();
+		let mut command_line = CommandLine::new(args.clone(), &["source", "target"]);
+		command_line
+			.redirect(&mut |prefix, upstream| {
+				prefixes.push((prefix.to_string(), upstream));
+				Ok(Some(9999))
+			})
+			.unwrap();
+		assert_eq!(prefixes, vec![("target".to_string(), "ws://127.0.0.1:9944".to_string())]);
+		assert_eq!(
+			command_line.into_args(),
+			[
+				&args[..2],
+				&[
+					"--target-host".into(),
+					DEFAULT_HOST.into(),
+					"--target-port".into(),
+					"9999".into()
+				],
+				&args[2..]
+			]
+			.concat(),
+		);
+	}
+
+	#[test]
+	fn command_line_is_unchanged_if_nothing_is_redirected() {
+		let args = ["relay-headers", "rococo-to-bridge-hub-westend", "--source-port", "9945"]
+			.iter()
+			.map(|arg| arg.to_string())
+			.collect::<Vec<_>>();
+
+		let mut command_line = CommandLine::new(args.clone(), &["source", "target"]);
+		assert_eq!(command_line.source_chain(), Some("rococo"));
+		command_line.redirect(&mut |_, _| Ok(None)).unwrap();
+		assert_eq!(command_line.into_args(), args);
+	}
+
+	#[test]
+	fn submitted_transactions_are_rewritten_by_hook() {
+		struct ReverseHook;
+
+		impl SubmitHook for ReverseHook {
+			fn rewrite_transaction(&self, transaction: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+				match transaction {
+					[] => Err(anyhow::format_err!("empty transaction")),
+					[_] => Ok(None),
+					_ => Ok(Some(transaction.iter().rev().cloned().collect())),
+				}
+			}
+		}
+
+		let request = |method: &str, transaction: &str| {
+			serde_json::json!({
+				"jsonrpc": "2.0",
+				"id": 1,
+				"method": method,
+				"params": [transaction],
+			})
+			.to_string()
+		};
+		let rewrite = |request| rewrite_submission(request, &ReverseHook);
+		assert_eq!(
+			rewrite(request("author_submitExtrinsic", "0x0102")),
+			request("author_submitExtrinsic", "0x0201"),
+		);
+		assert_eq!(
+			rewrite(request("author_submitAndWatchExtrinsic", "0x0102")),
+			request("author_submitAndWatchExtrinsic", "0x0201"),
+		);
+		for unchanged in [
+			request("author_submitExtrinsic", "0x01"),
+			request("author_submitExtrinsic", "0x"),
+			request("author_pendingExtrinsics", "0x0102"),
+		] {
+			assert_eq!(rewrite(unchanged.clone()), unchanged);
+		}
+	}
+
+	#[async_std::test]
+	async fn dry_run_proxy_does_not_submit_transactions() {
+		use codec::Encode;
+		use serde_json::{json, Value};
+		use sp_runtime::traits::Header as _;
+
+		let mut chain = relay_mock_node::MockChain::new("mock");
+		chain.set_runtime_api("BlockBuilder_apply_extrinsic", |_, _| {
+			Ok(sp_runtime::ApplyExtrinsicResult::Ok(Ok(())).encode())
+		});
+		let node = relay_mock_node::MockNode::start(chain).await.unwrap();
+
+		let args = ["substrate-relay", "relay-headers", "--source-uri", &node.uri()]
+			.iter()
+			.map(|arg| arg.to_string())
+			.collect();
+		let params = RpcProxyParams {
+			dry_run: true,
+			record: None,
+			replay: None,
+			justification_archive: None,
+		};
+		let proxies =
+			redirect_connections(&mut CommandLine::new(args, &["source", "target"]), &params)
+				.unwrap();
+		let proxy_port = proxies[0].listener.local_addr().unwrap().port();
+		proxies.into_iter().for_each(RpcProxy::spawn);
+
+		let (mut relay, _) = async_tungstenite::async_std::connect_async(format!(
+			"ws://{}:{}",
+			DEFAULT_HOST, proxy_port
+		))
+		.await
+		.unwrap();
+		let request = json!({
+			"jsonrpc": "2.0",
+			"id": 1,
+			"method": "author_submitAndWatchExtrinsic",
+			"params": ["0x0102"],
+		});
+		relay.send(Message::Text(request.to_string())).await.unwrap();
+
+		let mut statuses = Vec::new();
+		while statuses.len() != 4 {
+			match relay.next().await {
+				Some(Ok(Message::Text(message))) =>
+					statuses.push(serde_json::from_str::<Value>(&message).unwrap()),
+				message => panic!("Unexpected message: {:?}", message),
+			}
+		}
+		let genesis_hash = node.chain().header(0).unwrap().hash();
+		assert_eq!(statuses[3]["params"]["result"], json!({ "finalized": genesis_hash }));
+		assert!(node.chain().submitted_extrinsics().is_empty());
+	}
+}
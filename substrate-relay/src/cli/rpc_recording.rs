@@ -0,0 +1,345 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Recording and replaying of RPC messages, exchanged by the relay and nodes.
+//!
+//! Every proxied connection is recorded to its own file, where every line is a JSON-encoded
+//! message. When replaying, relay requests are matched with recorded requests by method and
+//! parameters, so the relay may use different request identifiers. Submitted transactions are
+//! matched by everything except their signatures, because signatures of the same transaction
+//! differ from run to run. Responses are released when the matching request is received and
+//! subscription notifications are released when all requests that have preceded them in the
+//! recording are received.
+
+use codec::{Compact, Decode, Encode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sp_core::crypto::AccountId32;
+use sp_runtime::{MultiAddress, MultiSignature};
+use std::{
+	collections::HashMap,
+	fs::File,
+	io::{BufRead, BufReader, BufWriter, Write},
+	path::Path,
+};
+
+/// Methods that are submitting transactions.
+const SUBMIT_METHODS: &[&str] = &["author_submitExtrinsic", "author_submitAndWatchExtrinsic"];
+
+/// Sender of the recorded message.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sender {
+	/// Message has been sent by the relay.
+	Relay,
+	/// Message has been sent by the node (or by the proxy on behalf of the node).
+	Node,
+}
+
+/// Single recorded message.
+#[derive(Debug, Deserialize, Serialize)]
+struct RecordedMessage {
+	sender: Sender,
+	/// The message itself. Messages that are not valid JSON are stored as strings.
+	message: Value,
+}
+
+/// Writes messages of single connection to the file.
+pub struct Recorder {
+	file: BufWriter<File>,
+}
+
+impl Recorder {
+	/// Create recording file.
+	pub fn create(path: &Path) -> anyhow::Result<Self> {
+		if let Some(dir) = path.parent() {
+			std::fs::create_dir_all(dir)?;
+		}
+		Ok(Recorder { file: BufWriter::new(File::create(path)?) })
+	}
+
+	/// Record single message.
+	pub fn record(&mut self, sender: Sender, message: &str) -> anyhow::Result<()> {
+		let message =
+			serde_json::from_str(message).unwrap_or_else(|_| Value::String(message.into()));
+		serde_json::to_writer(&mut self.file, &RecordedMessage { sender, message })?;
+		self.file.write_all(b"\n")?;
+		// flush every message, so that the recording is complete even if the relay is killed
+		self.file.flush()?;
+		Ok(())
+	}
+}
+
+/// Replays messages of single connection.
+pub struct Replayer {
+	messages: Vec<RecordedMessage>,
+	/// Whether the message at the same index has been consumed (relay requests) or
+	/// sent to the relay (node messages).
+	done: Vec<bool>,
+	/// Recorded request identifier, mapped to the identifier of the relay request.
+	request_ids: HashMap<String, Value>,
+	/// Recorded request identifier, mapped to the index of its recorded response.
+	responses: HashMap<String, usize>,
+}
+
+impl Replayer {
+	/// Open recording of the connection.
+	pub fn open(path: &Path) -> anyhow::Result<Self> {
+		let file = File::open(path).map_err(|e| {
+			anyhow::format_err!("Connection has not been recorded to {}: {}", path.display(), e)
+		})?;
+		let messages = BufReader::new(file)
+			.lines()
+			.map(|line| Ok(serde_json::from_str::<RecordedMessage>(&line?)?))
+			.collect::<anyhow::Result<Vec<_>>>()?;
+		let responses = messages
+			.iter()
+			.enumerate()
+			.filter(|(_, recorded)| recorded.sender == Sender::Node)
+			.filter(|(_, recorded)| recorded.message.get("method").is_none())
+			.filter_map(|(index, recorded)| Some((id_key(recorded.message.get("id")?), index)))
+			.collect();
+		Ok(Replayer {
+			done: vec![false; messages.len()],
+			messages,
+			request_ids: HashMap::new(),
+			responses,
+		})
+	}
+
+	/// Process relay request. Returns messages that need to be sent to the relay.
+	pub fn on_request(&mut self, request: &str) -> Vec<String> {
+		let request: Value = match serde_json::from_str(request) {
+			Ok(request) => request,
+			Err(_) => return Vec::new(),
+		};
+		let request_id = request["id"].clone();
+		let is_same_request = |recorded: &RecordedMessage| {
+			recorded.sender == Sender::Relay && is_same_request(&recorded.message, &request)
+		};
+
+		// the first unconsumed matching request
+		let position = self
+			.messages
+			.iter()
+			.zip(&self.done)
+			.position(|(recorded, done)| !done && is_same_request(recorded));
+		if let Some(position) = position {
+			self.done[position] = true;
+			if let Some(recorded_id) = self.messages[position].message.get("id") {
+				self.request_ids.insert(id_key(Some(recorded_id)), request_id);
+			}
+			return self.release()
+		}
+
+		// the relay may repeat requests more times than in the recording (e.g. because of
+		// different timings), so we re-serve the response to the last consumed matching request
+		let response = self
+			.messages
+			.iter()
+			.zip(&self.done)
+			.rev()
+			.filter(|(recorded, done)| **done && is_same_request(recorded))
+			.find_map(|(recorded, _)| self.responses.get(&id_key(recorded.message.get("id"))))
+			.map(|index| with_id(&self.messages[*index].message, request_id.clone()));
+
+		vec![response.unwrap_or_else(|| {
+			json!({
+				"jsonrpc": "2.0",
+				"id": request_id,
+				"error": { "code": -32000, "message": "request is missing from the recording" },
+			})
+			.to_string()
+		})]
+	}
+
+	/// Release node messages that may be sent to the relay.
+	fn release(&mut self) -> Vec<String> {
+		let mut released = Vec::new();
+		let mut all_requests_consumed = true;
+		for (recorded, done) in self.messages.iter().zip(self.done.iter_mut()) {
+			if recorded.sender == Sender::Relay {
+				all_requests_consumed &= *done;
+				continue
+			}
+			if *done {
+				continue
+			}
+
+			let is_notification = recorded.message.get("method").is_some();
+			let message = if is_notification {
+				all_requests_consumed.then(|| recorded.message.to_string())
+			} else {
+				self.request_ids
+					.get(&id_key(recorded.message.get("id")))
+					.map(|request_id| with_id(&recorded.message, request_id.clone()))
+			};
+			if let Some(message) = message {
+				*done = true;
+				released.push(message);
+			}
+		}
+		released
+	}
+}
+
+/// Returns true if the relay request matches the recorded request.
+fn is_same_request(recorded: &Value, request: &Value) -> bool {
+	if recorded["method"] != request["method"] {
+		return false
+	}
+
+	let is_submission = request["method"]
+		.as_str()
+		.map(|method| SUBMIT_METHODS.contains(&method))
+		.unwrap_or(false);
+	let unsigned_transaction = |request: &Value| {
+		request["params"][0]
+			.as_str()
+			.and_then(|transaction| hex::decode(transaction.trim_start_matches("0x")).ok())
+			.and_then(|transaction| without_signature(&transaction))
+	};
+	if is_submission {
+		if let (Some(recorded), Some(request)) =
+			(unsigned_transaction(recorded), unsigned_transaction(request))
+		{
+			return recorded == request
+		}
+	}
+	recorded["params"] == request["params"]
+}
+
+/// Returns the encoded transaction without its signature.
+///
+/// All bridged chains are using `MultiAddress` and `MultiSignature` in their transactions, so the
+/// signature is located right after the signer address. The rest of the transaction (signed
+/// extensions, including nonce, and the call) is left intact.
+fn without_signature(transaction: &[u8]) -> Option<Vec<u8>> {
+	let input = &mut &transaction[..];
+	let _length = Compact::<u32>::decode(input).ok()?;
+	let version = u8::decode(input).ok()?;
+	// unsigned transactions have no signature
+	if version & 0b1000_0000 == 0 {
+		return Some(transaction.to_vec())
+	}
+
+	let address = MultiAddress::<AccountId32, u32>::decode(input).ok()?;
+	let _signature = MultiSignature::decode(input).ok()?;
+	Some((version, address, *input).encode())
+}
+
+/// Returns key of the request identifier.
+fn id_key(id: Option<&Value>) -> String {
+	id.map(|id| id.to_string()).unwrap_or_default()
+}
+
+/// Returns recorded message with replaced identifier.
+fn with_id(message: &Value, id: Value) -> String {
+	let mut message = message.clone();
+	message["id"] = id;
+	message.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn recorded_messages_are_replayed() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("bridge-hub-rococo-1.jsonl");
+		let mut recorder = Recorder::create(&path).unwrap();
+		for (sender, message) in [
+			(
+				Sender::Relay,
+				r#"{"jsonrpc":"2.0","id":1,"method":"chain_subscribeFinalizedHeads","params":[]}"#,
+			),
+			(Sender::Node, r#"{"jsonrpc":"2.0","id":1,"result":"sub"}"#),
+			(
+				Sender::Node,
+				r#"{"jsonrpc":"2.0","method":"chain_finalizedHead","params":{"subscription":"sub","result":1}}"#,
+			),
+			(Sender::Relay, r#"{"jsonrpc":"2.0","id":2,"method":"system_health","params":[]}"#),
+			(
+				Sender::Node,
+				r#"{"jsonrpc":"2.0","method":"chain_finalizedHead","params":{"subscription":"sub","result":2}}"#,
+			),
+			(Sender::Node, r#"{"jsonrpc":"2.0","id":2,"result":"healthy"}"#),
+		] {
+			recorder.record(sender, message).unwrap();
+		}
+		drop(recorder);
+
+		let mut replayer = Replayer::open(&path).unwrap();
+		let messages = replayer.on_request(
+			r#"{"jsonrpc":"2.0","id":10,"method":"chain_subscribeFinalizedHeads","params":[]}"#,
+		);
+		assert_eq!(messages.len(), 2);
+		assert!(messages[0].contains(r#""id":10"#));
+
+		let messages = replayer
+			.on_request(r#"{"jsonrpc":"2.0","id":11,"method":"system_health","params":[]}"#);
+		assert_eq!(messages.len(), 2);
+		assert!(messages[1].contains(r#""id":11"#));
+
+		let messages = replayer
+			.on_request(r#"{"jsonrpc":"2.0","id":12,"method":"system_health","params":[]}"#);
+		assert_eq!(messages.len(), 1);
+		assert!(messages[0].contains(r#""id":12"#) && messages[0].contains("healthy"));
+
+		let messages =
+			replayer.on_request(r#"{"jsonrpc":"2.0","id":13,"method":"system_name","params":[]}"#);
+		assert!(messages[0].contains("request is missing from the recording"));
+	}
+
+	#[test]
+	fn submitted_transaction_is_matched_without_signature() {
+		let transaction = |signature: u8, nonce: u32| {
+			let mut transaction = vec![0b1000_0100];
+			transaction.extend(MultiAddress::<AccountId32, u32>::Id([1u8; 32].into()).encode());
+			transaction.extend(
+				MultiSignature::Sr25519(sp_core::sr25519::Signature::from_raw([signature; 64]))
+					.encode(),
+			);
+			// immortal era, nonce, tip and call
+			transaction.extend((0u8, Compact(nonce), Compact(0u128), [4u8, 0]).encode());
+			json!({
+				"jsonrpc": "2.0",
+				"id": 1,
+				"method": "author_submitExtrinsic",
+				"params": [format!("0x{}", hex::encode(transaction.encode()))],
+			})
+			.to_string()
+		};
+
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("bridge-hub-rococo-1.jsonl");
+		let mut recorder = Recorder::create(&path).unwrap();
+		recorder.record(Sender::Relay, &transaction(1, 5)).unwrap();
+		recorder
+			.record(Sender::Node, r#"{"jsonrpc":"2.0","id":1,"result":"0x1234"}"#)
+			.unwrap();
+		drop(recorder);
+
+		let mut replayer = Replayer::open(&path).unwrap();
+		let messages = replayer.on_request(&transaction(2, 6));
+		assert!(messages[0].contains("request is missing from the recording"));
+
+		let messages = replayer.on_request(&transaction(2, 5));
+		assert_eq!(messages.len(), 1);
+		assert!(messages[0].contains(r#""result":"0x1234""#));
+	}
+}
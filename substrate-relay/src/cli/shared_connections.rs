@@ -0,0 +1,364 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Relay connections, sharing the single JSON-RPC channel.
+//!
+//! Some endpoints (e.g. the embedded light client) are serving all relay connections over the
+//! single JSON-RPC channel. Requests of relay connections are forwarded to the channel with
+//! process-unique identifiers. Responses and subscription notifications are routed back to the
+//! connection that has sent the request (or opened the subscription).
+
+use crate::cli::LOG_TARGET;
+
+use async_std::net::{TcpListener, TcpStream};
+use async_tungstenite::tungstenite::Message;
+use futures::{
+	channel::mpsc::{self, UnboundedSender},
+	FutureExt, SinkExt, StreamExt,
+};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+/// Prefix of identifiers of requests, forwarded to the channel.
+const SHARED_ID_PREFIX: &str = "shared-";
+/// Method that submits transaction and subscribes to its status updates.
+const SUBMIT_AND_WATCH_METHOD: &str = "author_submitAndWatchExtrinsic";
+
+/// Start accepting relay connections in background. Events of all connections are sent to the
+/// `events`.
+pub fn accept_connections(
+	listener: std::net::TcpListener,
+	name: String,
+	events: UnboundedSender<Event>,
+) {
+	async_std::task::spawn(async move {
+		let listener = TcpListener::from(listener);
+		log::info!(target: LOG_TARGET, "{} is listening on {:?}", name, listener.local_addr());
+
+		let mut incoming = listener.incoming();
+		let mut connection = 0;
+		while let Some(stream) = incoming.next().await {
+			connection += 1;
+			let events = events.clone();
+			let name = name.clone();
+			async_std::task::spawn(async move {
+				let result = match stream {
+					Ok(stream) => serve_connection(stream, connection, events).await,
+					Err(e) => Err(e.into()),
+				};
+				if let Err(e) = result {
+					log::warn!(target: LOG_TARGET, "Relay connection to {} has failed: {}", name, e);
+				}
+			});
+		}
+	});
+}
+
+/// Event of the relay connection.
+pub enum Event {
+	/// Connection is opened. Messages to the relay are sent using the given sender.
+	Opened(usize, UnboundedSender<String>),
+	/// Relay has sent the request.
+	Request(usize, String),
+	/// Connection is closed.
+	Closed(usize),
+}
+
+/// Serve single relay connection.
+async fn serve_connection(
+	stream: TcpStream,
+	connection: usize,
+	events: UnboundedSender<Event>,
+) -> anyhow::Result<()> {
+	let relay = async_tungstenite::accept_async(stream).await?;
+	let (mut relay_tx, mut relay_rx) = relay.split();
+	let (messages_tx, mut messages_rx) = mpsc::unbounded();
+	events.unbounded_send(Event::Opened(connection, messages_tx))?;
+
+	let result = async {
+		loop {
+			futures::select! {
+				message = relay_rx.next().fuse() => match message {
+					Some(Ok(Message::Text(request))) =>
+						events.unbounded_send(Event::Request(connection, request))?,
+					Some(Ok(Message::Close(_))) | None => return Ok(()),
+					Some(Ok(_)) => (),
+					Some(Err(e)) => return Err(e.into()),
+				},
+				message = messages_rx.next().fuse() => match message {
+					Some(message) => relay_tx.send(Message::Text(message)).await?,
+					// the channel is lost, relay client will reconnect
+					None => return Ok(()),
+				},
+			}
+		}
+	}
+	.await;
+
+	let _ = events.unbounded_send(Event::Closed(connection));
+	result
+}
+
+/// Request, forwarded to the channel.
+struct ForwardedRequest {
+	/// Relay connection that has sent the request. `None` if the response needs to be ignored.
+	connection: Option<usize>,
+	/// Original identifier of the request.
+	id: Value,
+	method: String,
+}
+
+/// Subscription, opened by the relay connection.
+struct Subscription {
+	connection: usize,
+	/// Subscription identifier, returned by the channel.
+	id: Value,
+	/// Method that needs to be called to close the subscription.
+	unsubscribe_method: String,
+}
+
+/// State of the shared JSON-RPC channel.
+#[derive(Default)]
+pub struct Multiplexer {
+	next_id: u64,
+	/// Opened relay connections.
+	connections: BTreeMap<usize, UnboundedSender<String>>,
+	/// Requests, forwarded to the channel, mapped by their shared identifiers.
+	requests: BTreeMap<String, ForwardedRequest>,
+	/// Opened subscriptions, mapped by their encoded identifiers.
+	subscriptions: BTreeMap<String, Subscription>,
+}
+
+impl Multiplexer {
+	/// Handle relay connection event. Returns requests that need to be sent to the channel.
+	pub fn on_event(&mut self, event: Event) -> Vec<String> {
+		match event {
+			Event::Opened(connection, sender) => {
+				self.connections.insert(connection, sender);
+				Vec::new()
+			},
+			Event::Request(connection, request) => self.on_request(connection, request),
+			Event::Closed(connection) => self.close(connection),
+		}
+	}
+
+	/// Handle relay request. Returns requests that need to be sent to the channel.
+	fn on_request(&mut self, connection: usize, request: String) -> Vec<String> {
+		if !self.connections.contains_key(&connection) {
+			return Vec::new()
+		}
+
+		match serde_json::from_str::<Value>(&request) {
+			Ok(request) if request.is_object() => vec![self.forward(connection, request)],
+			_ => {
+				log::warn!(
+					target: LOG_TARGET,
+					"Ignoring unsupported relay request to shared connection: {}",
+					request,
+				);
+				Vec::new()
+			},
+		}
+	}
+
+	/// Handle message, received from the channel.
+	pub fn on_channel_message(&mut self, message: String) {
+		let mut message = match serde_json::from_str::<Value>(&message) {
+			Ok(message) if message.is_object() => message,
+			_ => {
+				log::warn!(target: LOG_TARGET, "Ignoring unsupported channel message: {}", message);
+				return
+			},
+		};
+
+		// response to the forwarded request
+		if let Some(request) = message["id"].as_str().and_then(|id| self.requests.remove(id)) {
+			let connection = match request.connection {
+				Some(connection) => connection,
+				None => return,
+			};
+			if let Some(unsubscribe_method) = unsubscribe_method(&request.method) {
+				if let Some(id) = message.get("result").filter(|id| !id.is_null()).cloned() {
+					self.subscriptions.insert(
+						id.to_string(),
+						Subscription { connection, id, unsubscribe_method },
+					);
+				}
+			}
+			message["id"] = request.id;
+			self.send(connection, message.to_string());
+			return
+		}
+
+		// subscription notification
+		let subscription_id = message["params"]["subscription"].to_string();
+		let connection = match self.subscriptions.get(&subscription_id) {
+			Some(subscription) => subscription.connection,
+			None => return,
+		};
+		if is_final_transaction_status(&message) {
+			self.subscriptions.remove(&subscription_id);
+		}
+		self.send(connection, message.to_string());
+	}
+
+	/// Forget the closed relay connection. Returns requests that need to be sent to the channel.
+	fn close(&mut self, connection: usize) -> Vec<String> {
+		self.connections.remove(&connection);
+		for request in self.requests.values_mut() {
+			if request.connection == Some(connection) {
+				request.connection = None;
+			}
+		}
+
+		let subscriptions = self
+			.subscriptions
+			.iter()
+			.filter(|(_, subscription)| subscription.connection == connection)
+			.map(|(id, _)| id.clone())
+			.collect::<Vec<_>>();
+		let mut to_channel = Vec::new();
+		for subscription in subscriptions {
+			if let Some(subscription) = self.subscriptions.remove(&subscription) {
+				let id = self.next_request_id();
+				to_channel.push(
+					json!({
+						"jsonrpc": "2.0",
+						"id": id,
+						"method": subscription.unsubscribe_method,
+						"params": [subscription.id],
+					})
+					.to_string(),
+				);
+				self.requests.insert(
+					id,
+					ForwardedRequest {
+						connection: None,
+						id: Value::Null,
+						method: subscription.unsubscribe_method,
+					},
+				);
+			}
+		}
+		to_channel
+	}
+
+	fn forward(&mut self, connection: usize, mut request: Value) -> String {
+		let method = request["method"].as_str().unwrap_or_default().to_string();
+		if method.contains("_unsubscribe") || method == "author_unwatchExtrinsic" {
+			self.subscriptions.remove(&request["params"][0].to_string());
+		}
+
+		let id = self.next_request_id();
+		let original_id = std::mem::replace(&mut request["id"], Value::String(id.clone()));
+		self.requests
+			.insert(id, ForwardedRequest { connection: Some(connection), id: original_id, method });
+		request.to_string()
+	}
+
+	fn send(&self, connection: usize, message: String) {
+		if let Some(sender) = self.connections.get(&connection) {
+			let _ = sender.unbounded_send(message);
+		}
+	}
+
+	fn next_request_id(&mut self) -> String {
+		self.next_id += 1;
+		format!("{}{}", SHARED_ID_PREFIX, self.next_id)
+	}
+}
+
+/// Returns method that closes subscription, opened by the given method, or `None` if method
+/// does not open subscriptions.
+fn unsubscribe_method(method: &str) -> Option<String> {
+	if method == SUBMIT_AND_WATCH_METHOD {
+		return Some("author_unwatchExtrinsic".into())
+	}
+	method
+		.contains("_subscribe")
+		.then(|| method.replacen("_subscribe", "_unsubscribe", 1))
+}
+
+/// Returns true if the notification contains final status of the watched transaction.
+fn is_final_transaction_status(notification: &Value) -> bool {
+	if notification["method"] != "author_extrinsicUpdate" {
+		return false
+	}
+
+	let status = &notification["params"]["result"];
+	matches!(status.as_str(), Some("dropped") | Some("invalid")) ||
+		["finalized", "usurped", "finalityTimeout"]
+			.iter()
+			.any(|s| status.get(s).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn request(id: u64, method: &str, params: Value) -> String {
+		json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }).to_string()
+	}
+
+	fn response(request: &str, result: Value) -> String {
+		let request: Value = serde_json::from_str(request).unwrap();
+		json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }).to_string()
+	}
+
+	fn next_message(receiver: &mut mpsc::UnboundedReceiver<String>) -> Value {
+		serde_json::from_str(&receiver.try_next().unwrap().unwrap()).unwrap()
+	}
+
+	#[test]
+	fn messages_are_routed_to_relay_connections() {
+		let mut state = Multiplexer::default();
+		let (sender1, mut receiver1) = mpsc::unbounded();
+		let (sender2, mut receiver2) = mpsc::unbounded();
+		state.on_event(Event::Opened(1, sender1));
+		state.on_event(Event::Opened(2, sender2));
+
+		// both connections are using the same request identifier
+		let request1 = state.on_event(Event::Request(1, request(1, "chain_getHeader", json!([]))));
+		let request2 = state
+			.on_event(Event::Request(2, request(1, "grandpa_subscribeJustifications", json!([]))));
+		assert_eq!((request1.len(), request2.len()), (1, 1));
+		assert_ne!(request1, request2);
+
+		// responses are returned with original identifiers
+		state.on_channel_message(response(&request2[0], json!("sub")));
+		state.on_channel_message(response(&request1[0], json!("header")));
+		let message = next_message(&mut receiver1);
+		assert_eq!((message["id"].clone(), message["result"].clone()), (json!(1), json!("header")));
+		let message = next_message(&mut receiver2);
+		assert_eq!((message["id"].clone(), message["result"].clone()), (json!(1), json!("sub")));
+
+		// subscription notifications are routed to the subscribed connection
+		let notification = json!({
+			"jsonrpc": "2.0",
+			"method": "grandpa_justifications",
+			"params": { "subscription": "sub", "result": "0x00" },
+		});
+		state.on_channel_message(notification.to_string());
+		assert_eq!(next_message(&mut receiver2)["params"]["subscription"], json!("sub"));
+		assert!(receiver1.try_next().is_err());
+
+		// subscriptions of the closed connection are closed
+		let unsubscribe = state.on_event(Event::Closed(2));
+		assert_eq!(unsubscribe.len(), 1);
+		assert!(unsubscribe[0].contains("grandpa_unsubscribeJustifications"));
+		assert!(state.subscriptions.is_empty());
+	}
+}
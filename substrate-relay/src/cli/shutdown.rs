@@ -0,0 +1,328 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Graceful shutdown of the relay.
+//!
+//! When the exit signal is received, the relay that supports graceful shutdown stops its relay
+//! loops, so no new transactions are submitted. On-demand headers and parachains relays are
+//! running in background tasks that we can't stop, so node connections of such relay are going
+//! through RPC proxies, which are rejecting all transactions after the exit signal. Then the relay
+//! waits until all transactions of its signers are included into finalized blocks, or until the
+//! shutdown deadline is reached. Finally, it waits until Prometheus collects final values of
+//! relay metrics.
+
+use crate::cli::{offline_signing::account_nonce, LOG_TARGET};
+
+use codec::{Compact, Decode};
+use futures::{future::BoxFuture, select, Future, FutureExt};
+use relay_substrate_client::{AccountIdOf, ChainWithBalances, Client};
+use serde_json::{json, Value};
+use sp_runtime::MultiAddress;
+use std::{
+	sync::atomic::{AtomicBool, AtomicU64, Ordering},
+	time::{Duration, Instant},
+};
+use structopt::StructOpt;
+
+/// Exit code of the relay that has been stopped by signal after graceful shutdown.
+pub const GRACEFUL_SHUTDOWN_EXIT_CODE: i32 = 3;
+/// Exit code of the relay that has been stopped by signal, but some of its transactions may be
+/// still pending.
+pub const FORCED_SHUTDOWN_EXIT_CODE: i32 = 4;
+/// Exit code of the standby relay replica that has been stopped by signal while waiting for the
+/// leadership.
+pub const STANDBY_SHUTDOWN_EXIT_CODE: i32 = 5;
+
+/// Interval between pending transactions checks.
+const PENDING_TRANSACTIONS_CHECK_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Interval between metrics scrape checks.
+const METRICS_SCRAPE_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Set when the exit signal is received.
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+/// Set when the relay has started its relay loops.
+static RELAY_STARTED: AtomicBool = AtomicBool::new(false);
+/// Set when relay metrics are served by the health service.
+static METRICS_SERVED: AtomicBool = AtomicBool::new(false);
+/// Number of times relay metrics have been scraped.
+static METRICS_SCRAPES: AtomicU64 = AtomicU64::new(0);
+
+/// Remember that the exit signal has been received.
+pub fn request_shutdown() {
+	SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Returns true if the exit signal has been received.
+pub fn is_shutdown_requested() -> bool {
+	SHUTDOWN_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Remember that the relay has started its relay loops (e.g. it has become the leader).
+pub fn on_relay_started() {
+	RELAY_STARTED.store(true, Ordering::SeqCst);
+}
+
+/// Remember that relay metrics are served by the health service.
+pub fn on_metrics_served() {
+	METRICS_SERVED.store(true, Ordering::SeqCst);
+}
+
+/// Remember that relay metrics have been scraped.
+pub fn on_metrics_scraped() {
+	METRICS_SCRAPES.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Exit code of the relay that has been stopped gracefully.
+pub fn graceful_shutdown_exit_code() -> i32 {
+	if RELAY_STARTED.load(Ordering::SeqCst) {
+		GRACEFUL_SHUTDOWN_EXIT_CODE
+	} else {
+		STANDBY_SHUTDOWN_EXIT_CODE
+	}
+}
+
+/// Returns error response to the transaction submission request, if the exit signal has been
+/// received. Otherwise, returns `None`.
+pub fn reject_submission(request: &str) -> Option<String> {
+	if !is_shutdown_requested() {
+		return None
+	}
+
+	let request: Value = serde_json::from_str(request).ok()?;
+	match request["method"].as_str() {
+		Some("author_submitExtrinsic") | Some("author_submitAndWatchExtrinsic") => Some(
+			json!({
+				"jsonrpc": "2.0",
+				"id": request["id"],
+				"error": { "code": -32000, "message": "The relay is shutting down" },
+			})
+			.to_string(),
+		),
+		_ => None,
+	}
+}
+
+/// Resolves when the exit signal is received.
+async fn shutdown_requested() {
+	while !is_shutdown_requested() {
+		async_std::task::sleep(Duration::from_millis(100)).await;
+	}
+}
+
+/// Wait until relay metrics are scraped once more, or until the `timeout` is reached.
+async fn flush_metrics(timeout: Duration) {
+	if !METRICS_SERVED.load(Ordering::SeqCst) {
+		return
+	}
+
+	let started_at = Instant::now();
+	let scrapes = METRICS_SCRAPES.load(Ordering::SeqCst);
+	while METRICS_SCRAPES.load(Ordering::SeqCst) == scrapes {
+		if started_at.elapsed() >= timeout {
+			log::info!(target: LOG_TARGET, "Final relay metrics have not been scraped");
+			return
+		}
+		async_std::task::sleep(METRICS_SCRAPE_CHECK_INTERVAL).await;
+	}
+}
+
+/// Graceful shutdown parameters.
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct ShutdownParams {
+	/// After the exit signal, wait up to this number of seconds for pending transactions to be
+	/// finalized. If zero, the relay is stopped immediately.
+	#[structopt(long, default_value = "0")]
+	pub shutdown_timeout: u64,
+}
+
+/// Checks whether there are pending transactions of the given signer.
+type PendingTransactionsCheck = Box<dyn Fn() -> BoxFuture<'static, anyhow::Result<bool>> + Send>;
+
+/// Watches transactions of all relay signers.
+#[derive(Default)]
+pub struct PendingTransactionsWatcher {
+	checks: Vec<(String, PendingTransactionsCheck)>,
+}
+
+impl PendingTransactionsWatcher {
+	/// Watch transactions of given signer at given chain.
+	pub fn watch<C: ChainWithBalances>(
+		mut self,
+		client: impl Client<C>,
+		signer: AccountIdOf<C>,
+	) -> Self {
+		self.checks.push((
+			format!("{} signer {:?}", C::NAME, signer),
+			Box::new(move || {
+				let client = client.clone();
+				let signer = signer.clone();
+				async move { has_pending_transactions(&client, &signer).await }.boxed()
+			}),
+		));
+		self
+	}
+
+	/// Wait until all watched transactions are finalized.
+	async fn wait(&self) {
+		loop {
+			let mut pending = Vec::new();
+			for (name, check) in &self.checks {
+				match check().await {
+					Ok(false) => (),
+					Ok(true) => pending.push(name.as_str()),
+					Err(e) => {
+						log::warn!(
+							target: LOG_TARGET,
+							"Failed to check pending transactions of {}: {}",
+							name,
+							e,
+						);
+						pending.push(name.as_str());
+					},
+				}
+			}
+
+			if pending.is_empty() {
+				return
+			}
+
+			log::info!(
+				target: LOG_TARGET,
+				"Waiting for pending transactions of: {}",
+				pending.join(", "),
+			);
+			async_std::task::sleep(PENDING_TRANSACTIONS_CHECK_INTERVAL).await;
+		}
+	}
+}
+
+/// Returns true if there are transactions of the `signer` that are either in the pool, or are
+/// included into best, but not yet finalized blocks.
+async fn has_pending_transactions<C: ChainWithBalances>(
+	client: &impl Client<C>,
+	signer: &AccountIdOf<C>,
+) -> anyhow::Result<bool> {
+	let pending_in_pool = client
+		.pending_extrinsics()
+		.await?
+		.iter()
+		.any(|tx| is_signed_by::<AccountIdOf<C>>(&tx.0, signer));
+	if pending_in_pool {
+		return Ok(true)
+	}
+
+	let best_hash = client.best_header_hash().await?;
+	let best_finalized_hash = client.best_finalized_header_hash().await?;
+	Ok(account_nonce(client, signer, best_hash).await? !=
+		account_nonce(client, signer, best_finalized_hash).await?)
+}
+
+/// Returns true if the encoded extrinsic is signed by the `signer`.
+///
+/// All our chains are using `UncheckedExtrinsic` with `MultiAddress` signer address, so we only
+/// decode the extrinsic prefix: length, version byte with the "signed" bit and the address.
+fn is_signed_by<AccountId: Decode + PartialEq>(extrinsic: &[u8], signer: &AccountId) -> bool {
+	const SIGNED_EXTRINSIC_BIT: u8 = 0b1000_0000;
+
+	let mut input = extrinsic;
+	match (Compact::<u32>::decode(&mut input), u8::decode(&mut input)) {
+		(Ok(_length), Ok(version)) if version & SIGNED_EXTRINSIC_BIT != 0 => (),
+		_ => return false,
+	}
+
+	match MultiAddress::<AccountId, ()>::decode(&mut input) {
+		Ok(MultiAddress::Id(address)) => address == *signer,
+		_ => false,
+	}
+}
+
+impl ShutdownParams {
+	/// Run the relay until the exit signal is received. Then stop relay loops and wait for
+	/// pending transactions.
+	///
+	/// Returns error if the relay has failed or the shutdown deadline has been reached.
+	pub async fn run(
+		&self,
+		relay: impl Future<Output = anyhow::Result<()>>,
+		watcher: PendingTransactionsWatcher,
+	) -> anyhow::Result<()> {
+		if self.shutdown_timeout == 0 {
+			return relay.await
+		}
+
+		let relay = relay.fuse();
+		let shutdown_requested = shutdown_requested().fuse();
+		futures::pin_mut!(relay, shutdown_requested);
+
+		select! {
+			result = relay => return result,
+			_ = shutdown_requested => (),
+		}
+
+		// relay loops are stopped at this point
+		log::info!(
+			target: LOG_TARGET,
+			"Relay loops are stopped. Waiting up to {}s for pending transactions",
+			self.shutdown_timeout,
+		);
+
+		let started_at = Instant::now();
+		let timeout = Duration::from_secs(self.shutdown_timeout);
+		if async_std::future::timeout(timeout, watcher.wait()).await.is_err() {
+			return Err(anyhow::format_err!(
+				"Shutdown deadline has been reached while waiting for pending transactions",
+			))
+		}
+
+		log::info!(
+			target: LOG_TARGET,
+			"All pending transactions are finalized in {}s",
+			started_at.elapsed().as_secs(),
+		);
+		flush_metrics(timeout.saturating_sub(started_at.elapsed())).await;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use codec::Encode;
+	use sp_runtime::AccountId32;
+
+	fn signed_extrinsic(signer: &AccountId32, call: &[u8]) -> Vec<u8> {
+		let mut extrinsic = vec![0x84];
+		MultiAddress::<AccountId32, ()>::Id(signer.clone()).encode_to(&mut extrinsic);
+		extrinsic.extend_from_slice(&[0; 65]);
+		extrinsic.extend_from_slice(call);
+		extrinsic.encode()
+	}
+
+	#[test]
+	fn is_signed_by_checks_extrinsic_signer() {
+		let relayer = AccountId32::new([1; 32]);
+		let other_relayer = AccountId32::new([2; 32]);
+
+		assert!(is_signed_by(&signed_extrinsic(&relayer, &[]), &relayer));
+		// other relayer's transaction that mentions our relayer (e.g. as `relayer_id`)
+		assert!(!is_signed_by(&signed_extrinsic(&other_relayer, &relayer.encode()), &relayer));
+		// unsigned transaction
+		let mut unsigned = vec![0x04];
+		unsigned.extend_from_slice(&relayer.encode());
+		assert!(!is_signed_by(&unsigned.encode(), &relayer));
+	}
+}
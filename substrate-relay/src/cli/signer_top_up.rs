@@ -0,0 +1,580 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Automatic top-up of relay signers from funding accounts.
+//!
+//! When the free balance of the relay signer drops below the configured threshold, the funding
+//! account transfers the missing amount (up to the target balance) to the signer using the
+//! `Balances::transfer_keep_alive` call. The total amount, transferred within 24 hours, never
+//! exceeds the configured daily cap. Transfers are persisted in the `--top-up-state-file`, so the
+//! cap is respected across relay restarts and by all replicas that share the file.
+//!
+//! Top-up loops are started together with relay loops, so only the leader replica is topping up
+//! signers.
+
+use crate::{bridges::rococo_bulletin::BridgeHubRococoAsBridgeHubPolkadot, cli::LOG_TARGET};
+
+use futures::{future::Either, Future};
+use prometheus_endpoint::{register, Counter, Gauge, PrometheusError, Registry, F64, U64};
+use relay_bridge_hub_kusama_client::BridgeHubKusama;
+use relay_bridge_hub_polkadot_client::BridgeHubPolkadot;
+use relay_bridge_hub_rococo_client::BridgeHubRococo;
+use relay_bridge_hub_westend_client::BridgeHubWestend;
+use relay_polkadot_bulletin_client::PolkadotBulletin;
+use relay_substrate_client::{
+	AccountIdOf, AccountKeyPairOf, BalanceOf, CallOf, Chain, ChainWithBalances,
+	ChainWithTransactions, Client, HashOf, NonceOf, TransactionEra, UnsignedTransaction,
+};
+use sp_core::Pair;
+use sp_runtime::traits::UniqueSaturatedInto;
+use std::{
+	collections::VecDeque,
+	path::{Path, PathBuf},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use structopt::StructOpt;
+use substrate_relay_helper::{declare_chain_cli_schema, TransactionParams};
+
+/// Interval between signer balance checks.
+const TOP_UP_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Minimal interval between two top-ups of the same signer. It gives the submitted transfer
+/// enough time to be finalized before the balance is checked again.
+const TOP_UP_COOLDOWN: Duration = Duration::from_secs(600);
+/// Interval, where the daily cap applies.
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Funding accounts of the left and right chains of the complex relay.
+declare_chain_cli_schema!(LeftFunding, left_funding);
+declare_chain_cli_schema!(RightFunding, right_funding);
+
+/// Relay signers top-up parameters.
+///
+/// The left and right chains are the chains in the order they are mentioned in the bridge name
+/// (e.g. `BridgeHubRococo` is the left chain of the `bridge-hub-rococo-bridge-hub-westend`).
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct SignerTopUpParams {
+	#[structopt(flatten)]
+	left_funding_sign: LeftFundingSigningParams,
+	/// The left chain relay signer is topped up when its free balance drops below this value.
+	#[structopt(long)]
+	left_top_up_threshold: Option<u128>,
+	/// Free balance of the left chain relay signer after the top-up.
+	#[structopt(long)]
+	left_top_up_target: Option<u128>,
+	/// Maximal amount that may be transferred to the left chain relay signer within 24 hours.
+	#[structopt(long)]
+	left_top_up_daily_cap: Option<u128>,
+	#[structopt(flatten)]
+	right_funding_sign: RightFundingSigningParams,
+	/// The right chain relay signer is topped up when its free balance drops below this value.
+	#[structopt(long)]
+	right_top_up_threshold: Option<u128>,
+	/// Free balance of the right chain relay signer after the top-up.
+	#[structopt(long)]
+	right_top_up_target: Option<u128>,
+	/// Maximal amount that may be transferred to the right chain relay signer within 24 hours.
+	#[structopt(long)]
+	right_top_up_daily_cap: Option<u128>,
+	/// Path to the file, where top-up transfers of the last 24 hours are stored. Transfers to the
+	/// left and right chain signers are stored in the `<path>.left` and `<path>.right` files.
+	///
+	/// Required if any of funding signers is specified. If several relay replicas are running,
+	/// the file should be on the storage, shared by all replicas (e.g. next to the lease file).
+	#[structopt(long)]
+	top_up_state_file: Option<PathBuf>,
+}
+
+/// Chain, where relay signers may be topped up.
+pub trait ChainWithTopUp: ChainWithBalances + ChainWithTransactions {
+	/// Returns the `Balances::transfer_keep_alive` call or `None` if the chain has no balances.
+	fn transfer_keep_alive_call(dest: AccountIdOf<Self>, value: u128) -> Option<CallOf<Self>>;
+}
+
+impl ChainWithTopUp for BridgeHubRococo {
+	fn transfer_keep_alive_call(dest: AccountIdOf<Self>, value: u128) -> Option<CallOf<Self>> {
+		Some(relay_bridge_hub_rococo_client::transfer_keep_alive_call(dest, value))
+	}
+}
+
+impl ChainWithTopUp for BridgeHubWestend {
+	fn transfer_keep_alive_call(dest: AccountIdOf<Self>, value: u128) -> Option<CallOf<Self>> {
+		Some(relay_bridge_hub_westend_client::transfer_keep_alive_call(dest, value))
+	}
+}
+
+impl ChainWithTopUp for BridgeHubKusama {
+	fn transfer_keep_alive_call(dest: AccountIdOf<Self>, value: u128) -> Option<CallOf<Self>> {
+		Some(relay_bridge_hub_kusama_client::transfer_keep_alive_call(dest, value))
+	}
+}
+
+impl ChainWithTopUp for BridgeHubPolkadot {
+	fn transfer_keep_alive_call(dest: AccountIdOf<Self>, value: u128) -> Option<CallOf<Self>> {
+		Some(relay_bridge_hub_polkadot_client::transfer_keep_alive_call(dest, value))
+	}
+}
+
+impl ChainWithTopUp for BridgeHubRococoAsBridgeHubPolkadot {
+	fn transfer_keep_alive_call(dest: AccountIdOf<Self>, value: u128) -> Option<CallOf<Self>> {
+		BridgeHubRococo::transfer_keep_alive_call(dest, value)
+	}
+}
+
+impl ChainWithTopUp for PolkadotBulletin {
+	fn transfer_keep_alive_call(_dest: AccountIdOf<Self>, _value: u128) -> Option<CallOf<Self>> {
+		None
+	}
+}
+
+/// Top-up configuration of the single signer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct TopUpConfig {
+	threshold: u128,
+	target: u128,
+	daily_cap: u128,
+}
+
+impl TopUpConfig {
+	/// Build configuration from CLI options of given side (`left` or `right`).
+	fn new(
+		side: &str,
+		has_funding_signer: bool,
+		threshold: Option<u128>,
+		target: Option<u128>,
+		daily_cap: Option<u128>,
+	) -> anyhow::Result<Option<Self>> {
+		let (threshold, target, daily_cap) =
+			match (has_funding_signer, threshold, target, daily_cap) {
+				(false, None, None, None) => return Ok(None),
+				(true, Some(threshold), Some(target), Some(daily_cap)) =>
+					(threshold, target, daily_cap),
+				_ =>
+					return Err(anyhow::format_err!(
+						"All --{}-funding-signer and --{}-top-up-* options must be used together",
+						side,
+						side,
+					)),
+			};
+		if target <= threshold {
+			return Err(anyhow::format_err!(
+				"--{}-top-up-target must be larger than --{}-top-up-threshold",
+				side,
+				side,
+			))
+		}
+
+		Ok(Some(TopUpConfig { threshold, target, daily_cap }))
+	}
+}
+
+/// Top-up state of the single signer.
+struct TopUpState {
+	config: TopUpConfig,
+	/// Unix time (in seconds) and amount of transfers within the last 24 hours.
+	transfers: VecDeque<(u64, u128)>,
+}
+
+impl TopUpState {
+	fn new(config: TopUpConfig) -> Self {
+		TopUpState { config, transfers: VecDeque::new() }
+	}
+
+	/// Read transfers from the state file. Missing file means that there were no transfers.
+	fn load(config: TopUpConfig, path: &Path) -> anyhow::Result<Self> {
+		let contents = match std::fs::read_to_string(path) {
+			Ok(contents) => contents,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::new(config)),
+			Err(e) => return Err(e.into()),
+		};
+
+		let transfers = contents
+			.lines()
+			.map(|line| {
+				let mut fields = line.split_whitespace();
+				match (fields.next().map(str::parse), fields.next().map(str::parse)) {
+					(Some(Ok(at)), Some(Ok(amount))) => Ok((at, amount)),
+					_ => Err(anyhow::format_err!(
+						"Invalid top-up state file {}: {}",
+						path.display(),
+						line,
+					)),
+				}
+			})
+			.collect::<anyhow::Result<_>>()?;
+		Ok(TopUpState { config, transfers })
+	}
+
+	/// Write transfers to the state file.
+	fn save(&self, path: &Path) -> anyhow::Result<()> {
+		let contents = self
+			.transfers
+			.iter()
+			.map(|(at, amount)| format!("{} {}\n", at, amount))
+			.collect::<String>();
+		// write to the temporary file first and then rename it, so that transfers are never
+		// partially written, even if the relay crashes
+		let mut temp_path = path.to_path_buf().into_os_string();
+		temp_path.push(".tmp");
+		std::fs::write(&temp_path, contents)?;
+		std::fs::rename(&temp_path, path)?;
+		Ok(())
+	}
+
+	/// Returns amount that needs to be transferred to the signer with given free balance.
+	fn required_amount(&self, balance: u128, now: u64) -> u128 {
+		if balance >= self.config.threshold {
+			return 0
+		}
+
+		let is_cooling_down = self
+			.transfers
+			.back()
+			.map(|(at, _)| now.saturating_sub(*at) < TOP_UP_COOLDOWN.as_secs())
+			.unwrap_or(false);
+		if is_cooling_down {
+			return 0
+		}
+
+		self.config.target - balance
+	}
+
+	/// Returns amount that may still be transferred without exceeding the daily cap.
+	fn daily_allowance(&mut self, now: u64) -> u128 {
+		while let Some((at, _)) = self.transfers.front() {
+			if now.saturating_sub(*at) < DAY.as_secs() {
+				break
+			}
+			self.transfers.pop_front();
+		}
+
+		let transferred = self.transfers.iter().map(|(_, amount)| amount).sum::<u128>();
+		self.config.daily_cap.saturating_sub(transferred)
+	}
+
+	/// Remember transfer that is about to be submitted.
+	fn record(&mut self, amount: u128, now: u64) {
+		self.transfers.push_back((now, amount));
+	}
+}
+
+impl SignerTopUpParams {
+	/// Prepare top-up loop of the left chain relay signer.
+	pub fn left_top_up<C: ChainWithTopUp>(
+		&self,
+		client: impl Client<C>,
+		signer: AccountIdOf<C>,
+		registry: &Registry,
+	) -> anyhow::Result<impl Future<Output = ()>>
+	where
+		AccountIdOf<C>: From<<AccountKeyPairOf<C> as Pair>::Public>,
+	{
+		let config = TopUpConfig::new(
+			"left",
+			self.left_funding_sign.left_funding_signer.is_some() ||
+				self.left_funding_sign.left_funding_signer_file.is_some(),
+			self.left_top_up_threshold,
+			self.left_top_up_target,
+			self.left_top_up_daily_cap,
+		)?;
+		let funding = match config {
+			Some(config) => Some((
+				self.left_funding_sign.transaction_params::<C>()?,
+				config,
+				self.state_file("left")?,
+			)),
+			None => None,
+		};
+		top_up_loop(client, signer, funding, registry)
+	}
+
+	/// Prepare top-up loop of the right chain relay signer.
+	pub fn right_top_up<C: ChainWithTopUp>(
+		&self,
+		client: impl Client<C>,
+		signer: AccountIdOf<C>,
+		registry: &Registry,
+	) -> anyhow::Result<impl Future<Output = ()>>
+	where
+		AccountIdOf<C>: From<<AccountKeyPairOf<C> as Pair>::Public>,
+	{
+		let config = TopUpConfig::new(
+			"right",
+			self.right_funding_sign.right_funding_signer.is_some() ||
+				self.right_funding_sign.right_funding_signer_file.is_some(),
+			self.right_top_up_threshold,
+			self.right_top_up_target,
+			self.right_top_up_daily_cap,
+		)?;
+		let funding = match config {
+			Some(config) => Some((
+				self.right_funding_sign.transaction_params::<C>()?,
+				config,
+				self.state_file("right")?,
+			)),
+			None => None,
+		};
+		top_up_loop(client, signer, funding, registry)
+	}
+
+	/// Path to the top-up state file of given side (`left` or `right`).
+	fn state_file(&self, side: &str) -> anyhow::Result<PathBuf> {
+		let mut path = self
+			.top_up_state_file
+			.clone()
+			.ok_or_else(|| {
+				anyhow::format_err!("--top-up-state-file is required by signer top-ups")
+			})?
+			.into_os_string();
+		path.push(format!(".{}", side));
+		Ok(path.into())
+	}
+}
+
+/// Metrics of relay signer top-ups at chain `C`.
+struct TopUpMetrics {
+	/// Number of relay signer top-ups.
+	top_ups: Counter<U64>,
+	/// Total amount, transferred to the relay signer.
+	amount: Counter<F64>,
+	/// Amount that may still be transferred to the relay signer within 24 hours.
+	daily_allowance: Gauge<F64>,
+}
+
+impl TopUpMetrics {
+	/// Create and register top-up metrics of chain `C`.
+	fn new<C: Chain>(registry: &Registry) -> Result<Self, PrometheusError> {
+		Ok(TopUpMetrics {
+			top_ups: register(
+				Counter::new(
+					format!("at_{}_relay_signer_top_ups", C::NAME),
+					"Number of relay signer top-ups.",
+				)?,
+				registry,
+			)?,
+			amount: register(
+				Counter::new(
+					format!("at_{}_relay_signer_top_up_amount", C::NAME),
+					"Total amount, transferred to the relay signer.",
+				)?,
+				registry,
+			)?,
+			daily_allowance: register(
+				Gauge::new(
+					format!("at_{}_relay_signer_top_up_daily_allowance", C::NAME),
+					"Amount that may still be transferred to the relay signer within 24 hours.",
+				)?,
+				registry,
+			)?,
+		})
+	}
+}
+
+/// Run the relay and top-up loops until the relay stops.
+pub async fn run_with_top_up(
+	relay: impl Future<Output = anyhow::Result<()>>,
+	top_up: impl Future<Output = ()>,
+) -> anyhow::Result<()> {
+	futures::pin_mut!(relay, top_up);
+	match futures::future::select(relay, top_up).await {
+		Either::Left((result, _)) => result,
+		// top-up loops never stop, unless they are disabled
+		Either::Right((_, relay)) => relay.await,
+	}
+}
+
+fn top_up_loop<C: ChainWithTopUp>(
+	client: impl Client<C>,
+	signer: AccountIdOf<C>,
+	funding: Option<(TransactionParams<AccountKeyPairOf<C>>, TopUpConfig, PathBuf)>,
+	registry: &Registry,
+) -> anyhow::Result<impl Future<Output = ()>>
+where
+	AccountIdOf<C>: From<<AccountKeyPairOf<C> as Pair>::Public>,
+{
+	let funding = match funding {
+		Some((funding, config, state_file)) => {
+			if C::transfer_keep_alive_call(signer.clone(), 0).is_none() {
+				return Err(anyhow::format_err!("Signer top-up is not supported at {}", C::NAME))
+			}
+			Some((funding, config, state_file, TopUpMetrics::new::<C>(registry)?))
+		},
+		None => None,
+	};
+
+	Ok(async move {
+		let (funding, config, state_file, metrics) = match funding {
+			Some(funding) => funding,
+			None => return,
+		};
+
+		loop {
+			if let Err(e) =
+				top_up_if_required(&client, &signer, &funding, config, &state_file, &metrics).await
+			{
+				log::warn!(
+					target: LOG_TARGET,
+					"Failed to top up relay signer at {}: {}",
+					C::NAME,
+					e,
+				);
+			}
+			async_std::task::sleep(TOP_UP_CHECK_INTERVAL).await;
+		}
+	})
+}
+
+async fn top_up_if_required<C: ChainWithTopUp>(
+	client: &impl Client<C>,
+	signer: &AccountIdOf<C>,
+	funding: &TransactionParams<AccountKeyPairOf<C>>,
+	config: TopUpConfig,
+	state_file: &Path,
+	metrics: &TopUpMetrics,
+) -> anyhow::Result<()>
+where
+	AccountIdOf<C>: From<<AccountKeyPairOf<C> as Pair>::Public>,
+{
+	// transfers are read on every check, because they may have been made by the previous leader
+	let mut state = TopUpState::load(config, state_file)?;
+	let now = unix_time();
+	let at = client.best_finalized_header_hash().await?;
+	let balance = free_balance::<C>(client, signer, at).await?;
+	let required_amount = state.required_amount(balance, now);
+	let daily_allowance = state.daily_allowance(now);
+	let amount = std::cmp::min(required_amount, daily_allowance);
+	if amount < required_amount {
+		log::warn!(
+			target: LOG_TARGET,
+			"Daily top-up cap of relay signer at {} is reached. Balance: {}, transferring: {}",
+			C::NAME,
+			balance,
+			amount,
+		);
+	}
+	if amount == 0 {
+		return Ok(())
+	}
+
+	let call = C::transfer_keep_alive_call(signer.clone(), amount)
+		.ok_or_else(|| anyhow::format_err!("Signer top-up is not supported at {}", C::NAME))?;
+
+	// the amount is reserved before the transfer is submitted, so the daily cap is never exceeded
+	// if we (or the next leader) restart. If submission fails, the amount stays reserved - we
+	// can't know whether the transaction has reached the pool
+	state.record(amount, now);
+	state.save(state_file).map_err(|e| {
+		anyhow::format_err!(
+			"Failed to save top-up state of relay signer at {} to {}: {}",
+			C::NAME,
+			state_file.display(),
+			e,
+		)
+	})?;
+
+	let mortality = funding.mortality;
+	let tx_hash = client
+		.submit_signed_extrinsic(&funding.signer, move |best_block_id, nonce| {
+			Ok(UnsignedTransaction::new(call.into(), nonce)
+				.era(TransactionEra::new(best_block_id, mortality)))
+		})
+		.await?;
+
+	log::info!(
+		target: LOG_TARGET,
+		"Topped up relay signer at {} with {} (balance: {}) in transaction {:?}",
+		C::NAME,
+		amount,
+		balance,
+		tx_hash,
+	);
+
+	metrics.top_ups.inc();
+	metrics.amount.inc_by(amount as f64);
+	metrics.daily_allowance.set(state.daily_allowance(now) as f64);
+
+	Ok(())
+}
+
+/// Read free balance of the account from the `frame_system::AccountInfo` structure.
+pub async fn free_balance<C: ChainWithBalances>(
+	client: &impl Client<C>,
+	account: &AccountIdOf<C>,
+	at: HashOf<C>,
+) -> anyhow::Result<u128> {
+	let account_info = client
+		.storage_value::<frame_system::AccountInfo<
+			NonceOf<C>,
+			pallet_balances::AccountData<BalanceOf<C>>,
+		>>(at, C::account_info_storage_key(account))
+		.await?;
+	Ok(account_info
+		.map(|account_info| account_info.data.free.unique_saturated_into())
+		.unwrap_or(0))
+}
+
+fn unix_time() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn top_up_amount_is_limited_by_daily_cap() {
+		let config = TopUpConfig { threshold: 100, target: 1_000, daily_cap: 1_500 };
+		let mut state = TopUpState::new(config);
+		let now = 1_000_000;
+
+		// balance is above threshold
+		assert_eq!(state.required_amount(100, now), 0);
+
+		// first top-up
+		assert_eq!(state.required_amount(50, now), 950);
+		assert_eq!(state.daily_allowance(now), 1_500);
+		state.record(950, now);
+
+		// no top-ups until the previous transfer is finalized
+		assert_eq!(state.required_amount(0, now + TOP_UP_COOLDOWN.as_secs() / 2), 0);
+
+		// second top-up is limited by the daily cap
+		let later = now + TOP_UP_COOLDOWN.as_secs();
+		assert_eq!(state.required_amount(0, later), 1_000);
+		assert_eq!(state.daily_allowance(later), 550);
+
+		// the cap is reset after 24 hours
+		assert_eq!(state.daily_allowance(now + DAY.as_secs()), 1_500);
+		assert!(TopUpConfig::new("left", true, Some(100), None, Some(1)).is_err());
+		assert!(TopUpConfig::new("left", true, Some(100), Some(100), Some(1)).is_err());
+		assert_eq!(TopUpConfig::new("left", false, None, None, None).unwrap(), None);
+	}
+
+	#[test]
+	fn top_up_state_survives_restart() {
+		let config = TopUpConfig { threshold: 100, target: 1_000, daily_cap: 1_500 };
+		let path = std::env::temp_dir().join(format!("top-up-state-{}", std::process::id()));
+		let mut state = TopUpState::load(config, &path).unwrap();
+		assert_eq!(state.daily_allowance(1_000_000), 1_500);
+
+		state.record(950, 1_000_000);
+		state.save(&path).unwrap();
+
+		let mut state = TopUpState::load(config, &path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!(state.required_amount(0, 1_000_000), 0);
+		assert_eq!(state.daily_allowance(1_000_000), 550);
+	}
+}
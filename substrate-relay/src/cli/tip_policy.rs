@@ -0,0 +1,700 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Tips of the complex relay transactions.
+//!
+//! Relay loops are signing their transactions with the zero tip. If the tip policy is set, the
+//! connection of the relay to the node is redirected to the local RPC proxy (see the `rpc_proxy`
+//! module), which re-signs every transaction of the relay signer with the tip, selected by the
+//! policy, before it is submitted to the node. The loop, started together with relay loops,
+//! refreshes parameters that the proxy needs to sign transactions and is tracking transactions,
+//! signed under the `fee-percent` and `escalating` policies:
+//!
+//! - fees of transactions are estimated, so that next transactions with the same call get the
+//!   configured percent of the fee as a tip;
+//!
+//! - transactions, which are not included within the configured number of blocks, are replaced with
+//!   the same transactions (at the same nonce) with the increased tip. Relay loops see that their
+//!   transaction is replaced and restart, but the replacement stays in the pool.
+
+use crate::{
+	bridges::rococo_bulletin::BridgeHubRococoAsBridgeHubPolkadot,
+	cli::{
+		estimate_profit::{estimate_call_fee, ChainWithBridgeTransactions},
+		offline_signing::account_nonce,
+		rpc_proxy::{spawn_submit_proxy, NodeConnectionParams, SubmitHook},
+		LOG_TARGET,
+	},
+};
+
+use bp_polkadot_core::SuffixedCommonSignedExtensionExt;
+use bp_runtime::HeaderIdProvider;
+use codec::{Decode, Encode};
+use futures::Future;
+use prometheus_endpoint::{register, Counter, PrometheusError, Registry, U64};
+use relay_bridge_hub_kusama_client::BridgeHubKusama;
+use relay_bridge_hub_polkadot_client::BridgeHubPolkadot;
+use relay_bridge_hub_rococo_client::BridgeHubRococo;
+use relay_bridge_hub_westend_client::BridgeHubWestend;
+use relay_polkadot_bulletin_client::PolkadotBulletin;
+use relay_substrate_client::{
+	AccountIdOf, AccountKeyPairOf, ChainWithTransactions, Client, Error as SubstrateError, HashOf,
+	HeaderIdOf, SignParam, TransactionEra, UnsignedTransaction,
+};
+use relay_utils::MaybeConnectionError;
+use sp_core::{Bytes, Pair};
+use sp_runtime::{
+	traits::{Header as _, UniqueSaturatedFrom, UniqueSaturatedInto, Zero},
+	MultiAddress,
+};
+use std::{
+	collections::BTreeMap,
+	str::FromStr,
+	sync::{Arc, Mutex, MutexGuard},
+};
+use structopt::StructOpt;
+use substrate_relay_helper::TransactionParams;
+
+/// Maximal number of remembered transactions of the single chain. Transactions are only
+/// remembered if the relay is tracking them, so the limit is never reached under normal
+/// circumstances.
+const MAX_PENDING_TRANSACTIONS: usize = 1024;
+
+/// Tip policies of the complex relay transactions.
+///
+/// Policy is one of `fixed:<tip>`, `fee-percent:<percent>` or
+/// `escalating:<initial>:<step>:<blocks>:<cap>`. With the escalating policy, the transaction that
+/// is not included within `<blocks>` blocks is replaced with the same transaction with the tip,
+/// increased by `<step>`, until the tip reaches the `<cap>`.
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct TipPolicyParams {
+	/// Tip policy of relay transactions at the left chain.
+	#[structopt(long)]
+	left_tip_policy: Option<TipPolicy>,
+	/// Tip policy of relay transactions at the right chain.
+	#[structopt(long)]
+	right_tip_policy: Option<TipPolicy>,
+}
+
+impl TipPolicyParams {
+	/// If the left chain tip policy is set, redirect the left chain connection to the proxy that
+	/// applies the policy to transactions of the relay signer.
+	///
+	/// Must be called before the relay connects to the node.
+	pub fn left_tips<C: ChainWithTips>(
+		&self,
+		connection: &mut impl NodeConnectionParams,
+		tx_params: TransactionParams<AccountKeyPairOf<C>>,
+	) -> anyhow::Result<Option<Tips<C>>>
+	where
+		AccountIdOf<C>: From<<AccountKeyPairOf<C> as Pair>::Public>,
+	{
+		start_tips_proxy("left", self.left_tip_policy, connection, tx_params)
+	}
+
+	/// If the right chain tip policy is set, redirect the right chain connection to the proxy that
+	/// applies the policy to transactions of the relay signer.
+	///
+	/// Must be called before the relay connects to the node.
+	pub fn right_tips<C: ChainWithTips>(
+		&self,
+		connection: &mut impl NodeConnectionParams,
+		tx_params: TransactionParams<AccountKeyPairOf<C>>,
+	) -> anyhow::Result<Option<Tips<C>>>
+	where
+		AccountIdOf<C>: From<<AccountKeyPairOf<C> as Pair>::Public>,
+	{
+		start_tips_proxy("right", self.right_tip_policy, connection, tx_params)
+	}
+}
+
+/// Tip policy of relay transactions at some chain.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TipPolicy {
+	/// Every transaction has the same tip.
+	Fixed(u128),
+	/// The tip is the given percent of the estimated fee of the same call. Fees are estimated
+	/// for submitted transactions, so the first transaction with the new call has zero tip.
+	FeePercent(u32),
+	/// Transactions are signed with the `initial` tip. If transaction is not included within
+	/// `blocks` blocks, it is replaced with the same transaction (at the same nonce) with the tip,
+	/// increased by `step`. The tip never exceeds the `cap`.
+	Escalating {
+		/// Tip of the new transaction.
+		initial: u128,
+		/// Tip increment.
+		step: u128,
+		/// Number of blocks to wait before replacing the transaction.
+		blocks: u32,
+		/// Maximal tip.
+		cap: u128,
+	},
+}
+
+impl TipPolicy {
+	/// Returns tip of the new transaction, given the estimated fee of its call.
+	pub fn initial_tip(&self, estimated_fee: Option<u128>) -> u128 {
+		match *self {
+			TipPolicy::Fixed(tip) => tip,
+			TipPolicy::FeePercent(percent) =>
+				estimated_fee.unwrap_or(0).saturating_mul(percent.into()) / 100,
+			TipPolicy::Escalating { initial, cap, .. } => initial.min(cap),
+		}
+	}
+
+	/// Returns tip of the transaction that replaces the transaction with given `tip`, which is
+	/// not included for `pending_blocks` blocks. Returns `None` if transaction must not be
+	/// replaced.
+	pub fn escalated_tip(&self, tip: u128, pending_blocks: u32) -> Option<u128> {
+		match *self {
+			TipPolicy::Escalating { step, blocks, cap, .. }
+				if pending_blocks >= blocks && tip < cap =>
+				Some(tip.saturating_add(step).min(cap)),
+			_ => None,
+		}
+	}
+
+	/// Returns true if signed transactions need to be tracked.
+	fn tracks_transactions(&self) -> bool {
+		!matches!(self, TipPolicy::Fixed(_))
+	}
+}
+
+impl FromStr for TipPolicy {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		fn parse<T: FromStr>(value: &str, s: &str) -> Result<T, String> {
+			value
+				.parse()
+				.map_err(|_| format!("Invalid number {:?} in tip policy {:?}", value, s))
+		}
+
+		let parts = s.split(':').collect::<Vec<_>>();
+		match parts.as_slice() {
+			["fixed", tip] => Ok(TipPolicy::Fixed(parse(tip, s)?)),
+			["fee-percent", percent] => Ok(TipPolicy::FeePercent(parse(percent, s)?)),
+			["escalating", initial, step, blocks, cap] => Ok(TipPolicy::Escalating {
+				initial: parse(initial, s)?,
+				step: parse(step, s)?,
+				blocks: parse(blocks, s)?,
+				cap: parse(cap, s)?,
+			}),
+			_ => Err(format!(
+				"Invalid tip policy {:?}. Expected `fixed:<tip>`, `fee-percent:<percent>` or \
+				`escalating:<initial>:<step>:<blocks>:<cap>`",
+				s,
+			)),
+		}
+	}
+}
+
+/// Chain, where relay transactions may have tips.
+pub trait ChainWithTips: ChainWithBridgeTransactions {
+	/// Returns signer and unsigned version of the signed transaction or `None` if the chain has
+	/// no tips.
+	fn parse_transaction(
+		transaction: Self::SignedTransaction,
+	) -> Option<(AccountIdOf<Self>, UnsignedTransaction<Self>)>;
+}
+
+/// Implement `ChainWithTips` for a bridge hub with the common signed extension.
+macro_rules! impl_bridge_hub_with_tips {
+	($chain:ty) => {
+		impl ChainWithTips for $chain {
+			fn parse_transaction(
+				transaction: Self::SignedTransaction,
+			) -> Option<(AccountIdOf<Self>, UnsignedTransaction<Self>)> {
+				let (address, _, extra) = transaction.signature?;
+				let signer = match address {
+					MultiAddress::Id(signer) => signer,
+					_ => return None,
+				};
+				Some((
+					signer,
+					UnsignedTransaction::new(transaction.function, extra.nonce()).tip(extra.tip()),
+				))
+			}
+		}
+	};
+}
+
+impl_bridge_hub_with_tips!(BridgeHubRococo);
+impl_bridge_hub_with_tips!(BridgeHubWestend);
+impl_bridge_hub_with_tips!(BridgeHubKusama);
+impl_bridge_hub_with_tips!(BridgeHubPolkadot);
+
+impl ChainWithTips for BridgeHubRococoAsBridgeHubPolkadot {
+	fn parse_transaction(
+		transaction: Self::SignedTransaction,
+	) -> Option<(AccountIdOf<Self>, UnsignedTransaction<Self>)> {
+		BridgeHubRococo::parse_transaction(transaction)
+			.map(|(signer, unsigned)| (signer, unsigned.switch_chain()))
+	}
+}
+
+impl ChainWithTips for PolkadotBulletin {
+	fn parse_transaction(
+		_transaction: Self::SignedTransaction,
+	) -> Option<(AccountIdOf<Self>, UnsignedTransaction<Self>)> {
+		None
+	}
+}
+
+/// Relay transaction, signed under the tracking tip policy.
+pub struct PendingTransaction<C: ChainWithTransactions> {
+	/// Parameters, used to sign the transaction.
+	pub param: SignParam<C>,
+	/// Unsigned transaction, including the selected tip.
+	pub unsigned: UnsignedTransaction<C>,
+}
+
+/// Parameters of the chain `C`, required to sign relay transactions.
+struct SigningContext<C: ChainWithTransactions> {
+	spec_version: u32,
+	transaction_version: u32,
+	genesis_hash: HashOf<C>,
+	/// Best block of the chain, where eras of transactions start.
+	best_block_id: HeaderIdOf<C>,
+}
+
+/// Tips state of relay transactions at the single chain.
+struct ChainTips<C: ChainWithTransactions> {
+	policy: TipPolicy,
+	/// Signing parameters, refreshed by the tips loop.
+	context: Option<SigningContext<C>>,
+	/// Estimated fees of calls, keyed by pallet and call indices.
+	fee_estimates: BTreeMap<Vec<u8>, u128>,
+	/// Remembered transactions of the chain.
+	pending: Vec<PendingTransaction<C>>,
+}
+
+/// Tips state of relay transactions at the chain `C`, shared by the RPC proxy that applies the
+/// policy and the loop that is tracking transactions.
+pub struct Tips<C: ChainWithTransactions>(Arc<Mutex<ChainTips<C>>>);
+
+impl<C: ChainWithTransactions> Clone for Tips<C> {
+	fn clone(&self) -> Self {
+		Tips(self.0.clone())
+	}
+}
+
+impl<C: ChainWithTransactions> Tips<C> {
+	/// Create tips state with given policy.
+	pub fn new(policy: TipPolicy) -> Self {
+		Tips(Arc::new(Mutex::new(ChainTips {
+			policy,
+			context: None,
+			fee_estimates: BTreeMap::new(),
+			pending: Vec::new(),
+		})))
+	}
+
+	/// Returns the tip policy.
+	pub fn policy(&self) -> TipPolicy {
+		self.state().policy
+	}
+
+	/// Remember the estimated fee of the encoded call.
+	pub fn set_fee_estimate(&self, encoded_call: &[u8], fee: u128) {
+		self.state().fee_estimates.insert(call_key(encoded_call), fee);
+	}
+
+	/// Take all remembered transactions.
+	pub fn take_pending(&self) -> Vec<PendingTransaction<C>> {
+		std::mem::take(&mut self.state().pending)
+	}
+
+	/// Set tip of the transaction that is about to be signed, according to the policy.
+	pub fn apply(
+		&self,
+		param: &SignParam<C>,
+		unsigned: UnsignedTransaction<C>,
+	) -> UnsignedTransaction<C> {
+		let mut state = self.state();
+		let estimated_fee = state.fee_estimates.get(&call_key(&unsigned.call.encode())).cloned();
+		let tip = state.policy.initial_tip(estimated_fee);
+		let unsigned = unsigned.tip(C::Balance::unique_saturated_from(tip));
+		if state.policy.tracks_transactions() && state.pending.len() < MAX_PENDING_TRANSACTIONS {
+			state.pending.push(PendingTransaction {
+				param: SignParam {
+					spec_version: param.spec_version,
+					transaction_version: param.transaction_version,
+					genesis_hash: param.genesis_hash,
+					signer: param.signer.clone(),
+				},
+				unsigned: unsigned.clone(),
+			});
+		}
+
+		unsigned
+	}
+
+	fn state(&self) -> MutexGuard<ChainTips<C>> {
+		// the state is always consistent, even if some thread has panicked while holding the lock
+		self.0.lock().unwrap_or_else(|e| e.into_inner())
+	}
+}
+
+/// Pallet and call indices of the encoded call.
+fn call_key(encoded_call: &[u8]) -> Vec<u8> {
+	encoded_call.iter().take(2).cloned().collect()
+}
+
+/// Hook of the RPC proxy that re-signs transactions of the relay signer with the tip, selected by
+/// the policy.
+struct TipsHook<C: ChainWithTransactions> {
+	tips: Tips<C>,
+	tx_params: TransactionParams<AccountKeyPairOf<C>>,
+}
+
+impl<C: ChainWithTips> SubmitHook for TipsHook<C>
+where
+	AccountIdOf<C>: From<<AccountKeyPairOf<C> as Pair>::Public>,
+{
+	fn rewrite_transaction(&self, transaction: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+		let transaction = C::SignedTransaction::decode(&mut &transaction[..])?;
+		let (signer, unsigned) = match C::parse_transaction(transaction) {
+			Some(parsed) => parsed,
+			None => return Ok(None),
+		};
+		// transactions of other signers and replacements of stuck transactions are left as is
+		let relay_signer: AccountIdOf<C> = self.tx_params.signer.public().into();
+		if signer != relay_signer || !unsigned.tip.is_zero() {
+			return Ok(None)
+		}
+
+		let (param, era) = {
+			let state = self.tips.state();
+			let context = state.context.as_ref().ok_or_else(|| {
+				anyhow::format_err!("Signing parameters of {} are not known yet", C::NAME)
+			})?;
+			(
+				SignParam {
+					spec_version: context.spec_version,
+					transaction_version: context.transaction_version,
+					genesis_hash: context.genesis_hash,
+					signer: self.tx_params.signer.clone(),
+				},
+				TransactionEra::new(context.best_block_id, self.tx_params.mortality),
+			)
+		};
+		let unsigned = self.tips.apply(&param, unsigned.era(era));
+		Ok(Some(C::sign_transaction(param, unsigned)?.encode()))
+	}
+}
+
+/// Start the proxy, applying tip policy to relay transactions at chain `C`, and redirect the
+/// relay connection to this proxy.
+fn start_tips_proxy<C: ChainWithTips>(
+	side: &str,
+	policy: Option<TipPolicy>,
+	connection: &mut impl NodeConnectionParams,
+	tx_params: TransactionParams<AccountKeyPairOf<C>>,
+) -> anyhow::Result<Option<Tips<C>>>
+where
+	AccountIdOf<C>: From<<AccountKeyPairOf<C> as Pair>::Public>,
+{
+	let policy = match policy {
+		Some(policy) => policy,
+		None => return Ok(None),
+	};
+	if !C::HAS_TRANSACTION_PAYMENT {
+		return Err(anyhow::format_err!("Transactions at {} have no tips", C::NAME))
+	}
+
+	let tips = Tips::<C>::new(policy);
+	let hook = TipsHook { tips: tips.clone(), tx_params };
+	let port = spawn_submit_proxy(format!("{}-tips", side), connection.upstream(), Arc::new(hook))?;
+	connection.redirect(port);
+	log::info!(target: LOG_TARGET, "Using {:?} tip policy at {}", policy, C::NAME);
+
+	Ok(Some(tips))
+}
+
+/// Relay transaction, tracked by the tips loop.
+struct TrackedTransaction<C: ChainWithBridgeTransactions> {
+	transaction: PendingTransaction<C>,
+	/// Best block number at the time when the transaction has been submitted.
+	submitted_at: u32,
+}
+
+/// Returns loop, refreshing signing parameters of the tips proxy and tracking relay transactions
+/// at chain `C`.
+pub fn tips_loop<C: ChainWithTips>(
+	client: impl Client<C>,
+	tips: Option<Tips<C>>,
+	registry: &Registry,
+) -> anyhow::Result<impl Future<Output = ()>>
+where
+	AccountIdOf<C>: From<<AccountKeyPairOf<C> as Pair>::Public>,
+{
+	let replacements = match tips.as_ref().map(|tips| tips.policy()) {
+		Some(TipPolicy::Fixed(_)) | None => None,
+		Some(_) => Some(replacements_metric::<C>(registry)?),
+	};
+
+	Ok(async move {
+		let tips = match tips {
+			Some(tips) => tips,
+			None => return,
+		};
+
+		let mut tracked = Vec::new();
+		loop {
+			if let Err(e) = update_tips(&client, &tips, &mut tracked, replacements.as_ref()).await {
+				log::warn!(
+					target: LOG_TARGET,
+					"Failed to update tips of relay transactions at {}: {}",
+					C::NAME,
+					e,
+				);
+			}
+			async_std::task::sleep(C::AVERAGE_BLOCK_INTERVAL).await;
+		}
+	})
+}
+
+/// Register the metric of relay transaction replacements at chain `C`.
+fn replacements_metric<C: ChainWithBridgeTransactions>(
+	registry: &Registry,
+) -> Result<Counter<U64>, PrometheusError> {
+	register(
+		Counter::new(
+			format!("at_{}_relay_transaction_replacements", C::NAME),
+			"Number of relay transactions, replaced with higher-tip versions.",
+		)?,
+		registry,
+	)
+}
+
+/// Refresh signing parameters of the tips proxy and update tips of all tracked transactions.
+///
+/// Transactions that have failed with a connection error are retried later. Transactions that
+/// have failed with other errors are no longer tracked, so they never block other transactions.
+async fn update_tips<C: ChainWithTips>(
+	client: &impl Client<C>,
+	tips: &Tips<C>,
+	tracked: &mut Vec<TrackedTransaction<C>>,
+	replacements: Option<&Counter<U64>>,
+) -> anyhow::Result<()>
+where
+	AccountIdOf<C>: From<<AccountKeyPairOf<C> as Pair>::Public>,
+{
+	let best_header = client.best_header().await?;
+	let best_hash = best_header.hash();
+	let best_number: u32 = (*best_header.number()).unique_saturated_into();
+	let runtime_version = client.simple_runtime_version().await?;
+	tips.state().context = Some(SigningContext {
+		spec_version: runtime_version.spec_version,
+		transaction_version: runtime_version.transaction_version,
+		genesis_hash: client.genesis_hash(),
+		best_block_id: best_header.id(),
+	});
+
+	let replacements = match replacements {
+		Some(replacements) => replacements,
+		None => return Ok(()),
+	};
+	tracked.extend(
+		tips.take_pending()
+			.into_iter()
+			.map(|transaction| TrackedTransaction { transaction, submitted_at: best_number }),
+	);
+
+	let mut still_pending = Vec::with_capacity(tracked.len());
+	let mut result = Ok(());
+	for mut tx in std::mem::take(tracked) {
+		let nonce = tx.transaction.unsigned.nonce;
+		match update_tip(client, tips, &mut tx, best_hash, best_number, replacements).await {
+			Ok(true) => still_pending.push(tx),
+			Ok(false) => (),
+			Err(e) if is_connection_error(&e) => {
+				still_pending.push(tx);
+				result = Err(e);
+			},
+			Err(e) => log::warn!(
+				target: LOG_TARGET,
+				nonce:? = nonce;
+				"Stopped tracking {} relay transaction with nonce {:?}: {}",
+				C::NAME,
+				nonce,
+				e,
+			),
+		}
+	}
+	*tracked = still_pending;
+
+	result
+}
+
+/// Returns true if the error is caused by the lost connection to the node.
+fn is_connection_error(e: &anyhow::Error) -> bool {
+	e.downcast_ref::<SubstrateError>().map_or(false, |e| e.is_connection_error())
+}
+
+/// Update tip of the tracked transaction. Returns false if transaction no longer needs to be
+/// tracked.
+async fn update_tip<C: ChainWithTips>(
+	client: &impl Client<C>,
+	tips: &Tips<C>,
+	tx: &mut TrackedTransaction<C>,
+	best_hash: HashOf<C>,
+	best_number: u32,
+	replacements: &Counter<U64>,
+) -> anyhow::Result<bool>
+where
+	AccountIdOf<C>: From<<AccountKeyPairOf<C> as Pair>::Public>,
+{
+	let policy = tips.policy();
+	let unsigned = &tx.transaction.unsigned;
+	if let TipPolicy::FeePercent(_) = policy {
+		let fee = estimate_call_fee(client, best_hash, unsigned.call.to_decoded()?).await?;
+		tips.set_fee_estimate(&unsigned.call.encode(), fee);
+		return Ok(false)
+	}
+
+	// the transaction (or some other transaction with the same nonce) is included
+	let signer: AccountIdOf<C> = tx.transaction.param.signer.public().into();
+	if account_nonce(client, &signer, best_hash).await? > unsigned.nonce {
+		return Ok(false)
+	}
+
+	let tip: u128 = unsigned.tip.unique_saturated_into();
+	let pending_blocks = best_number.saturating_sub(tx.submitted_at);
+	let new_tip = match policy.escalated_tip(tip, pending_blocks) {
+		Some(new_tip) => new_tip,
+		None => return Ok(true),
+	};
+
+	let param = &tx.transaction.param;
+	let replacement = unsigned.clone().tip(UniqueSaturatedFrom::unique_saturated_from(new_tip));
+	let signed = C::sign_transaction(
+		SignParam {
+			spec_version: param.spec_version,
+			transaction_version: param.transaction_version,
+			genesis_hash: param.genesis_hash,
+			signer: param.signer.clone(),
+		},
+		replacement.clone(),
+	)?;
+	let tx_hash = client.submit_unsigned_extrinsic(Bytes(signed.encode())).await?;
+
+	log::info!(
+		target: LOG_TARGET,
+		nonce:? = unsigned.nonce, tx_hash:? = tx_hash;
+		"Replaced {} relay transaction with nonce {:?}, pending for {} blocks: tip {} -> {} in \
+		transaction {:?}",
+		C::NAME,
+		unsigned.nonce,
+		pending_blocks,
+		tip,
+		new_tip,
+		tx_hash,
+	);
+
+	replacements.inc();
+	tx.transaction.unsigned = replacement;
+	tx.submitted_at = best_number;
+	Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn tips_are_selected_by_policy() {
+		let fixed: TipPolicy = "fixed:100".parse().unwrap();
+		assert_eq!(fixed.initial_tip(Some(1_000)), 100);
+		assert_eq!(fixed.escalated_tip(100, 1_000), None);
+
+		let fee_percent: TipPolicy = "fee-percent:20".parse().unwrap();
+		assert_eq!(fee_percent.initial_tip(None), 0);
+		assert_eq!(fee_percent.initial_tip(Some(1_000)), 200);
+
+		let escalating: TipPolicy = "escalating:10:50:3:100".parse().unwrap();
+		assert_eq!(
+			escalating,
+			TipPolicy::Escalating { initial: 10, step: 50, blocks: 3, cap: 100 }
+		);
+		assert_eq!(escalating.initial_tip(None), 10);
+		assert_eq!(escalating.escalated_tip(10, 2), None);
+		assert_eq!(escalating.escalated_tip(10, 3), Some(60));
+		assert_eq!(escalating.escalated_tip(60, 3), Some(100));
+		assert_eq!(escalating.escalated_tip(100, 3), None);
+
+		assert!("fixed".parse::<TipPolicy>().is_err());
+		assert!("escalating:10:50:3".parse::<TipPolicy>().is_err());
+		assert!("fee-percent:many".parse::<TipPolicy>().is_err());
+	}
+
+	#[test]
+	fn relay_transactions_are_signed_with_tips() {
+		use relay_substrate_client::CallOf;
+		use sp_keyring::Sr25519Keyring as AccountKeyring;
+
+		let tips = Tips::<BridgeHubPolkadot>::new("escalating:10:50:3:100".parse().unwrap());
+		let hook = TipsHook {
+			tips: tips.clone(),
+			tx_params: TransactionParams { signer: AccountKeyring::Alice.pair(), mortality: None },
+		};
+		let call: CallOf<BridgeHubPolkadot> = relay_bridge_hub_polkadot_client::RuntimeCall::System(
+			relay_bridge_hub_polkadot_client::runtime_types::frame_system::pallet::Call::remark {
+				remark: vec![42],
+			},
+		);
+		let sign = |signer: AccountKeyring, tip: u128| {
+			BridgeHubPolkadot::sign_transaction(
+				SignParam {
+					spec_version: 1,
+					transaction_version: 1,
+					genesis_hash: Default::default(),
+					signer: signer.pair(),
+				},
+				UnsignedTransaction::new(call.clone().into(), 7).tip(tip),
+			)
+			.unwrap()
+			.encode()
+		};
+
+		// signing parameters are refreshed by the tips loop
+		assert!(hook.rewrite_transaction(&sign(AccountKeyring::Alice, 0)).is_err());
+		tips.state().context = Some(SigningContext {
+			spec_version: 1,
+			transaction_version: 1,
+			genesis_hash: Default::default(),
+			best_block_id: relay_utils::HeaderId(0, Default::default()),
+		});
+
+		// transactions of other signers and replacements are not changed
+		assert_eq!(hook.rewrite_transaction(&sign(AccountKeyring::Bob, 0)).unwrap(), None);
+		assert_eq!(hook.rewrite_transaction(&sign(AccountKeyring::Alice, 60)).unwrap(), None);
+		assert!(tips.take_pending().is_empty());
+
+		// relay transactions get the initial tip and are tracked
+		let rewritten = hook.rewrite_transaction(&sign(AccountKeyring::Alice, 0)).unwrap().unwrap();
+		let (signer, unsigned) =
+			BridgeHubPolkadot::parse_transaction(Decode::decode(&mut &rewritten[..]).unwrap())
+				.unwrap();
+		assert_eq!(signer, AccountKeyring::Alice.to_account_id());
+		assert_eq!((unsigned.nonce, unsigned.tip), (7, 10));
+		assert_eq!(
+			tips.take_pending().into_iter().map(|tx| tx.unsigned.tip).collect::<Vec<_>>(),
+			vec![10],
+		);
+	}
+}
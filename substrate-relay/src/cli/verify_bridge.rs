@@ -0,0 +1,461 @@
+// Copyright 2019-2024 Parity Technologies (UK) Ltd.
+// This file is part of Parity Bridges Common.
+
+// Parity Bridges Common is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity Bridges Common is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity Bridges Common.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Verification of the bridge state at the target chain.
+//!
+//! The state of bridge pallets at the target chain is compared with the source chain:
+//!
+//! - the best finalized source header, known to the GRANDPA pallet, must be finalized and canonical
+//!   at the source chain;
+//! - the current authorities set of the GRANDPA pallet must match the set from the source chain
+//!   state at that header;
+//! - if the target chain also tracks the bridge hub of the source relay chain, the parachain head,
+//!   known to the parachains pallet, must match the `Paras::Heads` value at the relay block it has
+//!   been proven at.
+//!
+//! The command never submits transactions. All found divergences are printed and the command
+//! fails if there are any.
+
+use crate::{
+	bridges::{
+		kusama_polkadot::{
+			kusama_headers_to_bridge_hub_polkadot::KusamaToBridgeHubPolkadotCliBridge,
+			kusama_parachains_to_bridge_hub_polkadot::BridgeHubKusamaToBridgeHubPolkadot,
+			polkadot_headers_to_bridge_hub_kusama::PolkadotToBridgeHubKusamaCliBridge,
+			polkadot_parachains_to_bridge_hub_kusama::BridgeHubPolkadotToBridgeHubKusama,
+		},
+		polkadot_bulletin::{
+			polkadot_bulletin_headers_to_bridge_hub_polkadot::PolkadotBulletinToBridgeHubPolkadotCliBridge,
+			polkadot_headers_to_polkadot_bulletin::PolkadotToPolkadotBulletinCliBridge,
+			polkadot_parachains_to_polkadot_bulletin::PolkadotToPolkadotBulletin,
+		},
+		rococo_bulletin::{
+			rococo_bulletin_headers_to_bridge_hub_rococo::RococoBulletinToBridgeHubRococoCliBridge,
+			rococo_headers_to_rococo_bulletin::RococoToRococoBulletinCliBridge,
+			rococo_parachains_to_rococo_bulletin::RococoToRococoBulletin,
+		},
+		rococo_westend::{
+			rococo_headers_to_bridge_hub_westend::RococoToBridgeHubWestendCliBridge,
+			rococo_parachains_to_bridge_hub_westend::BridgeHubRococoToBridgeHubWestend,
+			westend_headers_to_bridge_hub_rococo::WestendToBridgeHubRococoCliBridge,
+			westend_parachains_to_bridge_hub_rococo::BridgeHubWestendToBridgeHubRococo,
+		},
+	},
+	cli::init_bridge::{authority_set_at, InitBridgeName},
+};
+
+use bp_header_chain::AuthoritySet;
+use bp_parachains::{ParaInfo, ParasInfoKeyProvider};
+use bp_polkadot_core::parachains::ParaId;
+use bp_runtime::StorageMapKeyProvider;
+use codec::{Decode, Encode};
+use frame_support::Twox64Concat;
+use relay_substrate_client::{Chain, ChainWithGrandpa, Client, HashOf, HeaderIdOf, RelayChain};
+use sp_core::{storage::StorageKey, H256};
+use sp_runtime::traits::Header as _;
+use std::fmt;
+use structopt::StructOpt;
+use strum::VariantNames;
+use substrate_relay_helper::{
+	cli::{
+		bridge::CliBridgeBase,
+		chain_schema::{SourceConnectionParams, TargetConnectionParams},
+	},
+	parachains::SubstrateParachainsPipeline,
+};
+
+/// Name of the GRANDPA pallet storage value with the current authorities set.
+const CURRENT_AUTHORITY_SET_VALUE_NAME: &str = "CurrentAuthoritySet";
+/// Name of the `Paras` pallet storage map with parachain heads.
+const PARA_HEADS_MAP_NAME: &str = "Heads";
+
+/// Compare the bridge state at the target chain with the source chain.
+#[derive(StructOpt)]
+pub struct VerifyBridge {
+	/// A bridge instance to verify.
+	#[structopt(possible_values = InitBridgeName::VARIANTS, case_insensitive = true)]
+	bridge: InitBridgeName,
+	#[structopt(flatten)]
+	source: SourceConnectionParams,
+	#[structopt(flatten)]
+	target: TargetConnectionParams,
+}
+
+/// Parachain, whose heads are tracked by the parachains pallet at the target chain.
+#[derive(Clone, Copy, Debug)]
+struct TrackedParachain {
+	/// Name of the parachains pallet at the target chain.
+	parachains_pallet: &'static str,
+	/// Name of the `Paras` pallet at the source relay chain.
+	paras_pallet: &'static str,
+	/// Parachain identifier.
+	parachain: u32,
+}
+
+impl TrackedParachain {
+	fn new<P: SubstrateParachainsPipeline>() -> Self
+	where
+		P::SourceRelayChain: RelayChain,
+	{
+		TrackedParachain {
+			parachains_pallet: P::SourceRelayChain::WITH_CHAIN_BRIDGE_PARACHAINS_PALLET_NAME,
+			paras_pallet: P::SourceRelayChain::PARAS_PALLET_NAME,
+			parachain: P::SourceParachain::PARACHAIN_ID,
+		}
+	}
+}
+
+/// Difference between the bridge state at the target chain and the source chain.
+#[derive(Debug, PartialEq)]
+pub enum Divergence {
+	/// The GRANDPA pallet has no finalized headers.
+	NotInitialized { source: &'static str, target: &'static str },
+	/// The best finalized header of the GRANDPA pallet is not finalized at the source chain.
+	HeaderNotFinalized { source: &'static str, header: (u32, H256), best_finalized: u32 },
+	/// The best finalized header of the GRANDPA pallet is not canonical at the source chain.
+	HeaderNotCanonical { source: &'static str, header: (u32, H256), canonical: H256 },
+	/// Authorities set ids are different.
+	AuthoritySetIdMismatch { source: &'static str, at_block: u32, target: u64, actual: u64 },
+	/// Authorities set ids are the same, but authorities are different.
+	AuthoritiesMismatch { source: &'static str, at_block: u32, set_id: u64 },
+	/// The parachain head is missing from the source relay chain state.
+	ParachainHeadMissing { parachain: u32, relay_block: u32 },
+	/// The parachain head does not match the source relay chain state.
+	ParachainHeadMismatch { parachain: u32, relay_block: u32, target: H256, actual: H256 },
+	/// The parachain head has been proven at relay block that is not yet known to the GRANDPA
+	/// pallet.
+	ParachainHeadAhead { parachain: u32, relay_block: u32, best_finalized: u32 },
+}
+
+impl fmt::Display for Divergence {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Self::NotInitialized { source, target } => write!(
+				f,
+				"{} headers are not finalized at {}: bridge is not initialized",
+				source, target,
+			),
+			Self::HeaderNotFinalized { source, header, best_finalized } => write!(
+				f,
+				"best finalized {} header {:?} is not finalized at {} (best finalized: {})",
+				source, header, source, best_finalized,
+			),
+			Self::HeaderNotCanonical { source, header, canonical } => write!(
+				f,
+				"best finalized {} header {:?} is not canonical at {} (canonical: {:?})",
+				source, header, source, canonical,
+			),
+			Self::AuthoritySetIdMismatch { source, at_block, target, actual } => write!(
+				f,
+				"{} authorities set id at header {} is {}, but the bridge has {}",
+				source, at_block, actual, target,
+			),
+			Self::AuthoritiesMismatch { source, at_block, set_id } => write!(
+				f,
+				"{} authorities of set {} at header {} are different from the bridge authorities",
+				source, set_id, at_block,
+			),
+			Self::ParachainHeadMissing { parachain, relay_block } => write!(
+				f,
+				"head of parachain {} is missing from the relay chain state at block {}",
+				parachain, relay_block,
+			),
+			Self::ParachainHeadMismatch { parachain, relay_block, target, actual } => write!(
+				f,
+				"head of parachain {} at relay block {} is {:?}, but the bridge has {:?}",
+				parachain, relay_block, actual, target,
+			),
+			Self::ParachainHeadAhead { parachain, relay_block, best_finalized } => write!(
+				f,
+				"head of parachain {} is proven at relay block {}, but the best finalized relay \
+				block is {}",
+				parachain, relay_block, best_finalized,
+			),
+		}
+	}
+}
+
+impl VerifyBridge {
+	/// Name of the bridge, served by this command.
+	pub fn bridge_name(&self) -> String {
+		format!("{:?}", self.bridge)
+	}
+
+	/// Run the command.
+	pub async fn run(self) -> anyhow::Result<()> {
+		let parachain = match self.bridge {
+			InitBridgeName::KusamaToBridgeHubPolkadot =>
+				Some(TrackedParachain::new::<BridgeHubKusamaToBridgeHubPolkadot>()),
+			InitBridgeName::PolkadotToBridgeHubKusama =>
+				Some(TrackedParachain::new::<BridgeHubPolkadotToBridgeHubKusama>()),
+			InitBridgeName::PolkadotToPolkadotBulletin =>
+				Some(TrackedParachain::new::<PolkadotToPolkadotBulletin>()),
+			InitBridgeName::RococoToRococoBulletin =>
+				Some(TrackedParachain::new::<RococoToRococoBulletin>()),
+			InitBridgeName::RococoToBridgeHubWestend =>
+				Some(TrackedParachain::new::<BridgeHubRococoToBridgeHubWestend>()),
+			InitBridgeName::WestendToBridgeHubRococo =>
+				Some(TrackedParachain::new::<BridgeHubWestendToBridgeHubRococo>()),
+			InitBridgeName::PolkadotBulletinToBridgeHubPolkadot |
+			InitBridgeName::RococoBulletinToBridgeHubRococo => None,
+		};
+
+		let divergences = match self.bridge {
+			InitBridgeName::KusamaToBridgeHubPolkadot =>
+				verify_bridge::<KusamaToBridgeHubPolkadotCliBridge>(self, parachain).await,
+			InitBridgeName::PolkadotToBridgeHubKusama =>
+				verify_bridge::<PolkadotToBridgeHubKusamaCliBridge>(self, parachain).await,
+			InitBridgeName::PolkadotToPolkadotBulletin =>
+				verify_bridge::<PolkadotToPolkadotBulletinCliBridge>(self, parachain).await,
+			InitBridgeName::PolkadotBulletinToBridgeHubPolkadot =>
+				verify_bridge::<PolkadotBulletinToBridgeHubPolkadotCliBridge>(self, parachain).await,
+			InitBridgeName::RococoToRococoBulletin =>
+				verify_bridge::<RococoToRococoBulletinCliBridge>(self, parachain).await,
+			InitBridgeName::RococoBulletinToBridgeHubRococo =>
+				verify_bridge::<RococoBulletinToBridgeHubRococoCliBridge>(self, parachain).await,
+			InitBridgeName::RococoToBridgeHubWestend =>
+				verify_bridge::<RococoToBridgeHubWestendCliBridge>(self, parachain).await,
+			InitBridgeName::WestendToBridgeHubRococo =>
+				verify_bridge::<WestendToBridgeHubRococoCliBridge>(self, parachain).await,
+		}?;
+
+		if divergences.is_empty() {
+			println!("Bridge state matches the source chain");
+			return Ok(())
+		}
+
+		println!("Bridge state diverges from the source chain:");
+		for (index, divergence) in divergences.iter().enumerate() {
+			println!("  {}. {}", index + 1, divergence);
+		}
+		Err(anyhow::format_err!("Found {} divergences", divergences.len()))
+	}
+}
+
+/// Compare the bridge state at the target chain with the source chain.
+async fn verify_bridge<Bridge: CliBridgeBase>(
+	params: VerifyBridge,
+	parachain: Option<TrackedParachain>,
+) -> anyhow::Result<Vec<Divergence>>
+where
+	Bridge::Source: ChainWithGrandpa,
+	HashOf<Bridge::Source>: From<H256> + Into<H256>,
+{
+	let source_client = params.source.into_client::<Bridge::Source>().await?;
+	let target_client = params.target.into_client::<Bridge::Target>().await?;
+	let at = target_client.best_finalized_header_hash().await?;
+	let mut divergences = Vec::new();
+
+	let best_finalized: Option<HeaderIdOf<Bridge::Source>> = target_client
+		.state_call(at, Bridge::Source::BEST_FINALIZED_HEADER_ID_METHOD.into(), ())
+		.await?;
+	let best_finalized = match best_finalized {
+		Some(best_finalized) => header_id::<Bridge::Source>(best_finalized)?,
+		None => {
+			divergences.push(Divergence::NotInitialized {
+				source: Bridge::Source::NAME,
+				target: Bridge::Target::NAME,
+			});
+			return Ok(divergences)
+		},
+	};
+
+	// the rest of checks are reading the source chain state at the best finalized header, so
+	// they only make sense if the header is canonical
+	let source_best_finalized = source_client
+		.header_by_hash(source_client.best_finalized_header_hash().await?)
+		.await?;
+	let source_best_finalized = block_number::<Bridge::Source>(*source_best_finalized.number())?;
+	if best_finalized.0 > source_best_finalized {
+		divergences.push(Divergence::HeaderNotFinalized {
+			source: Bridge::Source::NAME,
+			header: best_finalized,
+			best_finalized: source_best_finalized,
+		});
+		return Ok(divergences)
+	}
+	let canonical: H256 =
+		source_client.header_hash_by_number(best_finalized.0.into()).await?.into();
+	if canonical != best_finalized.1 {
+		divergences.push(Divergence::HeaderNotCanonical {
+			source: Bridge::Source::NAME,
+			header: best_finalized,
+			canonical,
+		});
+		return Ok(divergences)
+	}
+
+	let storage_key = StorageKey(
+		bp_runtime::storage_value_final_key(
+			Bridge::Source::WITH_CHAIN_GRANDPA_PALLET_NAME.as_bytes(),
+			CURRENT_AUTHORITY_SET_VALUE_NAME.as_bytes(),
+		)
+		.to_vec(),
+	);
+	let target_set = match target_client.raw_storage_value(at, storage_key).await? {
+		Some(set) => AuthoritySet::decode(&mut &set.0[..])?,
+		None => AuthoritySet::new(Vec::new(), 0),
+	};
+	let actual_set = authority_set_at(&source_client, best_finalized.1.into()).await?;
+	divergences.extend(compare_authority_sets(
+		Bridge::Source::NAME,
+		best_finalized.0,
+		&target_set,
+		&actual_set,
+	));
+
+	if let Some(parachain) = parachain {
+		divergences.extend(
+			verify_parachain_head(&source_client, &target_client, at, parachain, best_finalized.0)
+				.await?,
+		);
+	}
+
+	Ok(divergences)
+}
+
+/// Compare the parachain head, known to the target chain, with the `Paras::Heads` value at the
+/// source relay chain.
+async fn verify_parachain_head<Source: Chain, Target: Chain>(
+	source_client: &impl Client<Source>,
+	target_client: &impl Client<Target>,
+	at: HashOf<Target>,
+	parachain: TrackedParachain,
+	best_finalized: u32,
+) -> anyhow::Result<Option<Divergence>>
+where
+	HashOf<Source>: Into<H256>,
+{
+	let para_id = ParaId(parachain.parachain);
+	let para_info_key = ParasInfoKeyProvider::final_key(parachain.parachains_pallet, &para_id);
+	let para_info = match target_client.raw_storage_value(at, para_info_key).await? {
+		Some(para_info) => ParaInfo::decode(&mut &para_info.0[..])?,
+		// parachain heads are not relayed yet
+		None => return Ok(None),
+	};
+	let relay_block = para_info.best_head_hash.at_relay_block_number;
+	if relay_block > best_finalized {
+		return Ok(Some(Divergence::ParachainHeadAhead {
+			parachain: parachain.parachain,
+			relay_block,
+			best_finalized,
+		}))
+	}
+
+	let relay_hash = source_client.header_hash_by_number(relay_block.into()).await?;
+	let heads_key = bp_runtime::storage_map_final_key::<Twox64Concat>(
+		parachain.paras_pallet,
+		PARA_HEADS_MAP_NAME,
+		&para_id.encode(),
+	);
+	let actual = match source_client.raw_storage_value(relay_hash, heads_key).await? {
+		Some(head) => H256(sp_core::hashing::blake2_256(&Vec::<u8>::decode(&mut &head.0[..])?)),
+		None =>
+			return Ok(Some(Divergence::ParachainHeadMissing {
+				parachain: parachain.parachain,
+				relay_block,
+			})),
+	};
+	let target = para_info.best_head_hash.head_hash;
+	Ok((actual != target).then_some(Divergence::ParachainHeadMismatch {
+		parachain: parachain.parachain,
+		relay_block,
+		target,
+		actual,
+	}))
+}
+
+/// Compare the authorities set of the bridge with the set from the source chain state.
+fn compare_authority_sets(
+	source: &'static str,
+	at_block: u32,
+	target: &AuthoritySet,
+	actual: &AuthoritySet,
+) -> Option<Divergence> {
+	if target.set_id != actual.set_id {
+		return Some(Divergence::AuthoritySetIdMismatch {
+			source,
+			at_block,
+			target: target.set_id,
+			actual: actual.set_id,
+		})
+	}
+	(target.authorities != actual.authorities).then_some(Divergence::AuthoritiesMismatch {
+		source,
+		at_block,
+		set_id: actual.set_id,
+	})
+}
+
+/// Convert source header id to `(u32, H256)`. All chains with GRANDPA finality, that we are
+/// bridging with, have `u32` block numbers and `H256` hashes.
+fn header_id<C: Chain>(id: HeaderIdOf<C>) -> anyhow::Result<(u32, H256)>
+where
+	HashOf<C>: Into<H256>,
+{
+	Ok((block_number::<C>(id.number())?, id.hash().into()))
+}
+
+/// Convert source header number to `u32`.
+fn block_number<C: Chain>(number: C::BlockNumber) -> anyhow::Result<u32> {
+	number
+		.try_into()
+		.map_err(|_| anyhow::format_err!("Unexpected {} header number: {}", C::NAME, number))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn authority_sets_are_compared() {
+		let set = |set_id, authorities| AuthoritySet::new(authorities, set_id);
+		let authorities = bp_test_utils::authority_list();
+
+		assert_eq!(
+			compare_authority_sets(
+				"Rococo",
+				100,
+				&set(7, authorities.clone()),
+				&set(7, authorities.clone())
+			),
+			None,
+		);
+		assert_eq!(
+			compare_authority_sets(
+				"Rococo",
+				100,
+				&set(6, authorities.clone()),
+				&set(7, authorities.clone())
+			),
+			Some(Divergence::AuthoritySetIdMismatch {
+				source: "Rococo",
+				at_block: 100,
+				target: 6,
+				actual: 7,
+			}),
+		);
+		assert_eq!(
+			compare_authority_sets(
+				"Rococo",
+				100,
+				&set(7, authorities[1..].to_vec()),
+				&set(7, authorities)
+			),
+			Some(Divergence::AuthoritiesMismatch { source: "Rococo", at_block: 100, set_id: 7 }),
+		);
+	}
+}
@@ -22,7 +22,9 @@ mod bridges;
 mod cli;
 
 fn main() {
-	let command = cli::parse_args();
-	let run = command.run();
-	async_std::task::block_on(run);
+	let cli = cli::parse_args();
+	let run = cli.run();
+	let exit_code = async_std::task::block_on(run);
+	log::logger().flush();
+	std::process::exit(exit_code);
 }